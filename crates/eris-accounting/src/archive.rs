@@ -0,0 +1,169 @@
+use anyhow::Result;
+use chrono::NaiveDate;
+use thiserror::Error as ThisError;
+
+use eris_data::{
+    Delete,
+    DonationReceipt,
+    DonationReceiptFilter,
+    Member,
+    MemberFilter,
+    Query,
+    Retrieve,
+    Update,
+};
+use crate::gdpr::{PersonalData, PersonalRecords};
+
+#[derive(ThisError, Debug)]
+pub enum Error {
+    #[error("member {0} was already archived on {1}")]
+    Archived(u32, NaiveDate),
+    #[error("member {0} is not archived")]
+    NotArchived(u32),
+}
+
+/// Archive a former member. The membership ends at the
+/// date, unless it ended before. Archived members are not
+/// listed by default, but their ledger is kept.
+pub async fn archive<DB>(
+    db: &DB,
+    member_id: u32,
+    date: NaiveDate,
+) -> Result<Member>
+where
+    DB: Retrieve<Member, Key = u32> + Update<Member>,
+{
+    let member: Member = db.retrieve(member_id).await?;
+    if let Some(archived_at) = member.archived_at {
+        return Err(Error::Archived(member_id, archived_at).into());
+    }
+    let membership_end = match member.membership_end {
+        Some(end) if end <= date => end,
+        _ => date,
+    };
+    let member = db.update(Member {
+        membership_end: Some(membership_end),
+        archived_at: Some(date),
+        ..member
+    }).await?;
+    Ok(member)
+}
+
+/// Restore an archived member. The membership stays ended.
+pub async fn restore<DB>(db: &DB, member_id: u32) -> Result<Member>
+where
+    DB: Retrieve<Member, Key = u32> + Update<Member>,
+{
+    let member: Member = db.retrieve(member_id).await?;
+    if member.archived_at.is_none() {
+        return Err(Error::NotArchived(member_id).into());
+    }
+    let member = db.update(Member {
+        archived_at: None,
+        ..member
+    }).await?;
+    Ok(member)
+}
+
+/// Get the archived members whose ledger entries are
+/// past the retention period.
+pub async fn plan_purge<DB>(db: &DB, today: NaiveDate) -> Result<Vec<Member>>
+where
+    DB: PersonalRecords + Query<Member, Filter = MemberFilter>,
+{
+    let members: Vec<Member> = db.query(&MemberFilter {
+        include_archived: true,
+        ..Default::default()
+    }).await?;
+    let mut expired = vec![];
+    for member in members.into_iter().filter(|m| m.archived_at.is_some()) {
+        let data = PersonalData::fetch(db, member.id).await?;
+        match data.retained_until() {
+            Some(date) if date >= today => continue,
+            _ => expired.push(member),
+        }
+    }
+    Ok(expired)
+}
+
+/// Delete archived members with their ledger and receipts.
+pub async fn purge<DB>(db: &DB, members: &[Member]) -> Result<()>
+where
+    DB: Query<DonationReceipt, Filter = DonationReceiptFilter>
+        + Delete<DonationReceipt>
+        + Delete<Member>,
+{
+    for member in members {
+        let receipts: Vec<DonationReceipt> = db.query(&DonationReceiptFilter {
+            member_id: Some(member.id),
+            ..Default::default()
+        }).await?;
+        for receipt in receipts {
+            db.delete(receipt).await?;
+        }
+        db.delete(member.clone()).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use eris_data::{Insert, MemoryDb, Transaction};
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_archive_and_purge() {
+        let db = MemoryDb::new();
+        let member = db.insert(Member {
+            name: "Eris".to_string(),
+            membership_start: date(2020, 1, 1),
+            ..Default::default()
+        }).await.unwrap();
+        db.insert(Transaction {
+            member_id: member.id,
+            date: date(2023, 5, 10),
+            account_name: "Eris".to_string(),
+            description: "Mitgliedsbeitrag".to_string(),
+            amount: 20.0,
+            ..Default::default()
+        }).await.unwrap();
+        db.insert(DonationReceipt {
+            member_id: member.id,
+            year: 2023,
+            name: "Eris".to_string(),
+            amount: 20.0,
+            issued_at: date(2024, 1, 15),
+            ..Default::default()
+        }).await.unwrap();
+
+        let archived = archive(&db, member.id, date(2024, 3, 1)).await.unwrap();
+        assert_eq!(archived.membership_end, Some(date(2024, 3, 1)));
+        assert!(archive(&db, member.id, date(2024, 3, 2)).await.is_err());
+
+        // Archived members are hidden by default
+        let members: Vec<Member> = db.query(&MemberFilter::default())
+            .await.unwrap();
+        assert!(members.is_empty());
+        let member: Member = db.retrieve(member.id).await.unwrap();
+        assert_eq!(member.archived_at, Some(date(2024, 3, 1)));
+
+        let restored = restore(&db, member.id).await.unwrap();
+        assert_eq!(restored.archived_at, None);
+        assert_eq!(restored.membership_end, Some(date(2024, 3, 1)));
+        assert!(restore(&db, member.id).await.is_err());
+        archive(&db, member.id, date(2024, 3, 1)).await.unwrap();
+
+        // The receipt is kept until the end of 2034
+        assert!(plan_purge(&db, date(2034, 12, 31)).await.unwrap().is_empty());
+        let expired = plan_purge(&db, date(2035, 1, 1)).await.unwrap();
+        assert_eq!(expired.len(), 1);
+        purge(&db, &expired).await.unwrap();
+        assert!(db.retrieve(member.id).await
+            .map(|m: Member| m.id).is_err());
+    }
+}
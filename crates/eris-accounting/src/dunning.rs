@@ -0,0 +1,253 @@
+use anyhow::Result;
+use chrono::NaiveDate;
+use thiserror::Error as ThisError;
+
+use eris_data::{
+    DunningEvent,
+    DunningEventFilter,
+    DunningLevel,
+    Member,
+    MemberFilter,
+    Query,
+};
+
+#[derive(ThisError, Debug)]
+pub enum Error {
+    #[error("dunning thresholds must increase: {0} >= {1}")]
+    ThresholdsNotIncreasing(f64, f64),
+}
+
+/// The dunning policy defines when a level is reached:
+/// Each threshold is the number of monthly fees
+/// a member is in arrears.
+#[derive(Debug, Clone)]
+pub struct DunningPolicy {
+    pub reminder: f64,
+    pub first_notice: f64,
+    pub final_notice: f64,
+    pub board_review: f64,
+}
+
+impl Default for DunningPolicy {
+    fn default() -> Self {
+        Self {
+            reminder: 1.0,
+            first_notice: 3.0,
+            final_notice: 6.0,
+            board_review: 12.0,
+        }
+    }
+}
+
+impl DunningPolicy {
+    /// Make sure the levels are reached one after another
+    pub fn validate(&self) -> Result<(), Error> {
+        let thresholds = [
+            self.reminder,
+            self.first_notice,
+            self.final_notice,
+            self.board_review,
+        ];
+        for pair in thresholds.windows(2) {
+            if pair[0] >= pair[1] {
+                return Err(Error::ThresholdsNotIncreasing(pair[0], pair[1]));
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the level for the current balance of a member.
+    /// Members without a fee are never in arrears.
+    pub fn level_for(&self, member: &Member) -> DunningLevel {
+        if member.fee <= 0.0 || member.account >= 0.0 {
+            return DunningLevel::Cleared;
+        }
+        let arrears = -member.account / member.fee;
+        if arrears >= self.board_review {
+            DunningLevel::BoardReview
+        } else if arrears >= self.final_notice {
+            DunningLevel::FinalNotice
+        } else if arrears >= self.first_notice {
+            DunningLevel::FirstNotice
+        } else if arrears >= self.reminder {
+            DunningLevel::Reminder
+        } else {
+            DunningLevel::Cleared
+        }
+    }
+}
+
+/// The current level is the level of the most
+/// recent event.
+pub fn current_level(events: &[DunningEvent]) -> DunningLevel {
+    events
+        .iter()
+        .max_by_key(|e| (e.date, e.id))
+        .map(|e| e.level)
+        .unwrap_or_default()
+}
+
+/// Decide if a new event has to be recorded for a member.
+/// Levels only escalate; a member is cleared once the
+/// balance is no longer in arrears.
+pub fn next_event(
+    policy: &DunningPolicy,
+    member: &Member,
+    events: &[DunningEvent],
+    date: NaiveDate,
+) -> Option<DunningEvent> {
+    let current = current_level(events);
+    let level = policy.level_for(member);
+    let escalated = level > current;
+    let cleared = level == DunningLevel::Cleared && current != level;
+    if !escalated && !cleared {
+        return None;
+    }
+    Some(DunningEvent {
+        member_id: member.id,
+        level,
+        date,
+        balance: member.account,
+        ..Default::default()
+    })
+}
+
+/// Get the dunning events which are due for all members.
+/// The events are not recorded, so running this again
+/// after inserting the events will not yield any events.
+pub async fn plan_dunning<DB>(
+    db: &DB,
+    policy: &DunningPolicy,
+    date: NaiveDate,
+) -> Result<Vec<(Member, DunningEvent)>>
+where
+    DB: Query<Member, Filter = MemberFilter>
+        + Query<DunningEvent, Filter = DunningEventFilter>,
+{
+    policy.validate()?;
+    let members: Vec<Member> = db.query(&MemberFilter::default()).await?;
+    let mut due = vec![];
+    for member in members {
+        let events: Vec<DunningEvent> = db.query(&DunningEventFilter{
+            member_id: Some(member.id),
+            date_before: Some(date),
+            ..Default::default()
+        }).await?;
+        if let Some(event) = next_event(policy, &member, &events, date) {
+            due.push((member, event));
+        }
+    }
+    Ok(due)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eris_data::{Insert, MemoryDb};
+
+    fn member(account: f64) -> Member {
+        Member {
+            id: 1,
+            fee: 20.0,
+            account,
+            ..Default::default()
+        }
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_dunning_policy_level() {
+        let policy = DunningPolicy::default();
+        assert_eq!(policy.level_for(&member(10.0)), DunningLevel::Cleared);
+        assert_eq!(policy.level_for(&member(-19.0)), DunningLevel::Cleared);
+        assert_eq!(policy.level_for(&member(-20.0)), DunningLevel::Reminder);
+        assert_eq!(
+            policy.level_for(&member(-60.0)),
+            DunningLevel::FirstNotice
+        );
+        assert_eq!(
+            policy.level_for(&member(-130.0)),
+            DunningLevel::FinalNotice
+        );
+        assert_eq!(
+            policy.level_for(&member(-240.0)),
+            DunningLevel::BoardReview
+        );
+
+        // No fee, no arrears
+        let m = Member {
+            account: -100.0,
+            ..Default::default()
+        };
+        assert_eq!(policy.level_for(&m), DunningLevel::Cleared);
+    }
+
+    #[test]
+    fn test_dunning_policy_validate() {
+        assert!(DunningPolicy::default().validate().is_ok());
+        let policy = DunningPolicy {
+            first_notice: 1.0,
+            ..Default::default()
+        };
+        assert!(policy.validate().is_err());
+    }
+
+    #[test]
+    fn test_next_event() {
+        let policy = DunningPolicy::default();
+        let today = date(2023, 5, 1);
+
+        // Nothing to do
+        assert!(next_event(&policy, &member(0.0), &[], today).is_none());
+
+        // Escalate to first notice
+        let event = next_event(&policy, &member(-60.0), &[], today).unwrap();
+        assert_eq!(event.level, DunningLevel::FirstNotice);
+        assert_eq!(event.balance, -60.0);
+
+        // Running again does not create a new event
+        let events = vec![event];
+        assert!(next_event(&policy, &member(-60.0), &events, today).is_none());
+
+        // Partial payments do not deescalate
+        assert!(next_event(&policy, &member(-20.0), &events, today).is_none());
+
+        // Paying everything clears the member
+        let event = next_event(&policy, &member(0.0), &events, today).unwrap();
+        assert_eq!(event.level, DunningLevel::Cleared);
+    }
+
+    #[tokio::test]
+    async fn test_plan_dunning() {
+        let db = MemoryDb::new();
+        let policy = DunningPolicy::default();
+        let today = date(2023, 5, 1);
+        db.insert(Member {
+            name: "Payer".to_string(),
+            fee: 20.0,
+            account: 20.0,
+            ..Default::default()
+        }).await.unwrap();
+        db.insert(Member {
+            name: "Debtor".to_string(),
+            fee: 20.0,
+            account: -40.0,
+            ..Default::default()
+        }).await.unwrap();
+
+        let due = plan_dunning(&db, &policy, today).await.unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].0.name, "Debtor");
+        assert_eq!(due[0].1.level, DunningLevel::Reminder);
+
+        // Record the events, the next run is a no-op
+        for (_, event) in due {
+            db.insert(event).await.unwrap();
+        }
+        let due = plan_dunning(&db, &policy, today).await.unwrap();
+        assert!(due.is_empty());
+    }
+}
@@ -0,0 +1,321 @@
+use anyhow::Result;
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
+use serde::Serialize;
+use thiserror::Error as ThisError;
+
+use eris_data::{
+    AuditEntry,
+    AuditEntryFilter,
+    BankImportRule,
+    BankImportRuleFilter,
+    DonationReceipt,
+    DonationReceiptFilter,
+    DunningEvent,
+    DunningEventFilter,
+    Mandate,
+    MandateFilter,
+    Member,
+    Notification,
+    NotificationFilter,
+    Payout,
+    PayoutFilter,
+    PayoutState,
+    Pseudonymisation,
+    Pseudonymise,
+    Query,
+    Retrieve,
+    Transaction,
+    TransactionFilter,
+    Update,
+    MEMBERSHIP_FEE_ACCOUNT,
+};
+
+use crate::mandates::{MandateLifecycle, MandateState};
+
+/// Ledger entries are kept for ten years after the end of
+/// the year they were booked in (§ 147 AO, § 257 HGB).
+pub const RETENTION_YEARS: i32 = 10;
+
+#[derive(ThisError, Debug)]
+pub enum Error {
+    #[error(
+        "member {0} has ledger entries which must be kept until {1}, \
+         anonymise the member instead")]
+    Retained(u32, NaiveDate),
+    #[error("member {0} is still active, end the membership first")]
+    Active(u32),
+    #[error("member {0} has payouts which are not settled")]
+    UnsettledPayouts(u32),
+}
+
+/// Get the last day an entry booked at the date must be kept.
+pub fn retention_end(date: NaiveDate) -> NaiveDate {
+    NaiveDate::from_ymd_opt(date.year() + RETENTION_YEARS, 12, 31)
+        .unwrap()
+}
+
+/// A database with all records stored about a member
+pub trait PersonalRecords:
+    Retrieve<Member, Key = u32>
+    + Query<BankImportRule, Filter = BankImportRuleFilter>
+    + Query<Transaction, Filter = TransactionFilter>
+    + Query<Mandate, Filter = MandateFilter>
+    + Query<Payout, Filter = PayoutFilter>
+    + Query<DunningEvent, Filter = DunningEventFilter>
+    + Query<Notification, Filter = NotificationFilter>
+    + Query<DonationReceipt, Filter = DonationReceiptFilter>
+    + Query<AuditEntry, Filter = AuditEntryFilter>
+{
+}
+
+impl<DB> PersonalRecords for DB where
+    DB: Retrieve<Member, Key = u32>
+        + Query<BankImportRule, Filter = BankImportRuleFilter>
+        + Query<Transaction, Filter = TransactionFilter>
+        + Query<Mandate, Filter = MandateFilter>
+        + Query<Payout, Filter = PayoutFilter>
+        + Query<DunningEvent, Filter = DunningEventFilter>
+        + Query<Notification, Filter = NotificationFilter>
+        + Query<DonationReceipt, Filter = DonationReceiptFilter>
+        + Query<AuditEntry, Filter = AuditEntryFilter>
+{
+}
+
+/// All data stored about a member, as handed out on
+/// a data subject access request.
+#[derive(Debug, Clone, Serialize)]
+pub struct PersonalData {
+    pub exported_at: NaiveDateTime,
+    pub member: Member,
+    pub bank_import_rules: Vec<BankImportRule>,
+    pub transactions: Vec<Transaction>,
+    pub mandates: Vec<Mandate>,
+    pub payouts: Vec<Payout>,
+    pub dunning_events: Vec<DunningEvent>,
+    pub notifications: Vec<Notification>,
+    pub donation_receipts: Vec<DonationReceipt>,
+    pub audit_log: Vec<AuditEntry>,
+    /// The ledger entries are kept until this date
+    pub retained_until: Option<NaiveDate>,
+}
+
+impl PersonalData {
+    /// Collect all data of a member
+    pub async fn fetch<DB>(db: &DB, member_id: u32) -> Result<Self>
+    where
+        DB: PersonalRecords,
+    {
+        let member: Member = db.retrieve(member_id).await?;
+        let payouts: Vec<Payout> = db.query(&PayoutFilter {
+            member_id: Some(member_id),
+            ..Default::default()
+        }).await?;
+        let notifications: Vec<Notification> = db.query(&NotificationFilter {
+            member_id: Some(member_id),
+            ..Default::default()
+        }).await?;
+        let donation_receipts: Vec<DonationReceipt> =
+            db.query(&DonationReceiptFilter {
+                member_id: Some(member_id),
+                ..Default::default()
+            }).await?;
+        let audit_log: Vec<AuditEntry> = db.query(&AuditEntryFilter {
+            member_id: Some(member_id),
+            ..Default::default()
+        }).await?;
+
+        let mut data = PersonalData {
+            exported_at: chrono::Local::now().naive_local(),
+            bank_import_rules: member.get_bank_import_rules(db).await?,
+            transactions: member.get_transactions(db).await?,
+            mandates: member.get_mandates(db).await?,
+            dunning_events: member.get_dunning_events(db).await?,
+            member,
+            payouts,
+            notifications,
+            donation_receipts,
+            audit_log,
+            retained_until: None,
+        };
+        data.retained_until = data.retained_until();
+        Ok(data)
+    }
+
+    /// Get the date until the ledger entries of the member
+    /// must be kept, if there are any.
+    pub fn retained_until(&self) -> Option<NaiveDate> {
+        let transactions = self.transactions.iter().map(|tx| tx.date);
+        let payouts = self.payouts.iter()
+            .map(|p| p.settled_at.unwrap_or(p.created_at));
+        let receipts = self.donation_receipts.iter().map(|r| r.issued_at);
+        transactions.chain(payouts).chain(receipts)
+            .max()
+            .map(retention_end)
+    }
+
+    /// Get the text which identifies the member
+    fn identifying_values(&self) -> Vec<String> {
+        let member = &self.member;
+        let mut values = vec![
+            member.name.clone(),
+            member.email.clone(),
+            member.notes.clone(),
+        ];
+        values.extend(self.bank_import_rules.iter().map(|r| r.iban.clone()));
+        values.extend(self.transactions.iter()
+            .map(|tx| tx.account_name.clone())
+            .filter(|name| name != MEMBERSHIP_FEE_ACCOUNT));
+        for mandate in &self.mandates {
+            values.push(mandate.iban.clone());
+            values.push(mandate.account_holder.clone());
+            values.extend(mandate.original_iban.clone());
+        }
+        for payout in &self.payouts {
+            values.push(payout.name.clone());
+            values.push(payout.iban.clone());
+        }
+        values.extend(self.donation_receipts.iter().map(|r| r.name.clone()));
+        values.retain(|value| !value.is_empty());
+        values.sort();
+        values.dedup();
+        values
+    }
+}
+
+/// The name an anonymised member is listed as
+pub fn pseudonym(member_id: u32) -> String {
+    format!("Anonymised member {}", member_id)
+}
+
+/// Fail if the member can not be deleted, because the
+/// ledger entries must still be kept.
+pub async fn check_deletable<DB>(
+    db: &DB,
+    member: &Member,
+    today: NaiveDate,
+) -> Result<()>
+where
+    DB: PersonalRecords,
+{
+    let data = PersonalData::fetch(db, member.id).await?;
+    match data.retained_until() {
+        Some(date) if date >= today => {
+            Err(Error::Retained(member.id, date).into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Erase the personal data of a former member. The ledger
+/// is kept with the pseudonym instead of names and IBANs, so
+/// balances and annual reports do not change. Active mandates
+/// are revoked.
+pub async fn anonymise<DB>(
+    db: &DB,
+    member_id: u32,
+    today: NaiveDate,
+) -> Result<Member>
+where
+    DB: PersonalRecords + Update<Mandate> + Pseudonymise,
+{
+    let data = PersonalData::fetch(db, member_id).await?;
+    if data.member.is_active(today) {
+        return Err(Error::Active(member_id).into());
+    }
+    if data.payouts.iter().any(|p| p.state() != PayoutState::Settled) {
+        return Err(Error::UnsettledPayouts(member_id).into());
+    }
+    for mandate in &data.mandates {
+        if mandate.state(today) == MandateState::Active {
+            db.update(mandate.clone().revoke(today)?).await?;
+        }
+    }
+
+    let member = db.pseudonymise(&Pseudonymisation {
+        member_id,
+        pseudonym: pseudonym(member_id),
+        personal_data: data.identifying_values(),
+    }).await?;
+    Ok(member)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use eris_data::{Insert, MemoryDb, TransactionFilter};
+
+    use crate::mandates::new_mandate;
+
+    #[test]
+    fn test_retention_end() {
+        let date = NaiveDate::from_ymd_opt(2023, 2, 1).unwrap();
+        assert_eq!(retention_end(date), NaiveDate::from_ymd_opt(2033, 12, 31).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_anonymise() {
+        let db = MemoryDb::new();
+        let today = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let member = db.insert(Member {
+            name: "Eris".to_string(),
+            email: "eris@discordia.example".to_string(),
+            membership_start: NaiveDate::from_ymd_opt(2020, 1, 1).unwrap(),
+            ..Default::default()
+        }).await.unwrap();
+        db.insert(Transaction {
+            member_id: member.id,
+            date: NaiveDate::from_ymd_opt(2023, 5, 10).unwrap(),
+            account_name: "Eris Discordia".to_string(),
+            description: "Mitgliedsbeitrag".to_string(),
+            amount: 20.0,
+            ..Default::default()
+        }).await.unwrap();
+        db.insert(Transaction {
+            member_id: member.id,
+            date: NaiveDate::from_ymd_opt(2023, 6, 1).unwrap(),
+            account_name: MEMBERSHIP_FEE_ACCOUNT.to_string(),
+            description: "Monthly fee".to_string(),
+            amount: -20.0,
+            ..Default::default()
+        }).await.unwrap();
+        let mandate = new_mandate(
+            &member, &[], "DE12345678901234567890", "Eris Discordia",
+            NaiveDate::from_ymd_opt(2023, 1, 1).unwrap()).unwrap();
+        db.insert(mandate).await.unwrap();
+
+        // Ledger entries must be kept
+        let err = check_deletable(&db, &member, today).await.unwrap_err();
+        assert!(err.to_string().contains("2033-12-31"), "{}", err);
+
+        // Members must leave first
+        assert!(anonymise(&db, member.id, today).await.is_err());
+        let member = db.update(Member {
+            membership_end: Some(NaiveDate::from_ymd_opt(2023, 12, 31).unwrap()),
+            ..member
+        }).await.unwrap();
+
+        let anonymised = anonymise(&db, member.id, today).await.unwrap();
+        assert_eq!(anonymised.name, "Anonymised member 1");
+        assert_eq!(anonymised.email, "");
+
+        let data = PersonalData::fetch(&db, member.id).await.unwrap();
+        let json = serde_json::to_string(&data).unwrap();
+        assert!(!json.contains("Eris"), "{}", json);
+        assert!(!json.contains("DE1234"), "{}", json);
+        assert!(data.mandates[0].revoked_at.is_some());
+
+        // The ledger is kept
+        let transactions: Vec<Transaction> = db.query(&TransactionFilter {
+            member_id: Some(member.id),
+            ..Default::default()
+        }).await.unwrap();
+        let total: f64 = transactions.iter().map(|tx| tx.amount).sum();
+        assert_eq!(total, 0.0);
+        assert_eq!(transactions[1].account_name, MEMBERSHIP_FEE_ACCOUNT);
+
+        // After the retention period the member can be deleted
+        let later = NaiveDate::from_ymd_opt(2034, 1, 1).unwrap();
+        check_deletable(&db, &anonymised, later).await.unwrap();
+    }
+}
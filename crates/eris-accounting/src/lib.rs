@@ -1,3 +1,12 @@
+pub mod archive;
 pub mod datetime;
+pub mod dunning;
+pub mod gdpr;
+pub mod html;
+pub mod mandates;
 pub mod member_fees;
+pub mod pdf;
+pub mod receipts;
+pub mod reports;
+pub mod statements;
 pub mod transactions;
@@ -0,0 +1,327 @@
+use std::fmt;
+
+use chrono::{Months, NaiveDate};
+use thiserror::Error as ThisError;
+
+use eris_data::{Mandate, Member};
+
+/// A mandate expires if it was not used for 36 months.
+pub const MANDATE_EXPIRY_MONTHS: u32 = 36;
+
+#[derive(ThisError, Debug)]
+pub enum Error {
+    #[error("mandate {0} was revoked on {1}")]
+    Revoked(String, NaiveDate),
+    #[error("mandate {0} expired on {1}")]
+    Expired(String, NaiveDate),
+    #[error("mandate {0} already uses IBAN {1}")]
+    SameIban(String, String),
+    #[error("member {0} already has an active mandate {1}")]
+    ActiveMandatePresent(u32, String),
+}
+
+/// The sequence type of a direct debit collection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceType {
+    First,
+    Recurring,
+}
+
+impl SequenceType {
+    /// Get the SEPA code for the sequence type
+    pub fn code(&self) -> &'static str {
+        match self {
+            SequenceType::First => "FRST",
+            SequenceType::Recurring => "RCUR",
+        }
+    }
+}
+
+impl fmt::Display for SequenceType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+/// The state of a mandate at a given date.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MandateState {
+    Active,
+    Revoked(NaiveDate),
+    Expired(NaiveDate),
+}
+
+impl fmt::Display for MandateState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MandateState::Active => write!(f, "active"),
+            MandateState::Revoked(date) => write!(f, "revoked ({})", date),
+            MandateState::Expired(date) => write!(f, "expired ({})", date),
+        }
+    }
+}
+
+/// Get the part of the IBAN identifying the bank:
+/// The country code and the national bank code (the BLZ
+/// for german accounts).
+fn bank_code(iban: &str) -> String {
+    let iban = iban.replace(' ', "").to_uppercase();
+    let country = iban.get(..2).unwrap_or_default();
+    let bank = iban.get(4..12).unwrap_or_default();
+    format!("{}{}", country, bank)
+}
+
+pub trait MandateLifecycle: Sized {
+    /// The date after which the mandate can not be used anymore.
+    fn expires_at(&self) -> NaiveDate;
+
+    /// Get the state of the mandate at a date.
+    fn state(&self, date: NaiveDate) -> MandateState;
+
+    /// Get the sequence type for the next collection.
+    fn sequence_type(&self) -> SequenceType;
+
+    /// Change the account of the mandate.
+    fn amend(self, iban: &str, date: NaiveDate) -> Result<Self, Error>;
+
+    /// Revoke the mandate.
+    fn revoke(self, date: NaiveDate) -> Result<Self, Error>;
+
+    /// Record a collection and return the sequence type
+    /// to use for it.
+    fn record_collection(
+        self,
+        date: NaiveDate,
+    ) -> Result<(Self, SequenceType), Error>;
+}
+
+impl MandateLifecycle for Mandate {
+    fn expires_at(&self) -> NaiveDate {
+        let last_used = self.last_used_at.unwrap_or(self.signed_at);
+        last_used
+            .checked_add_months(Months::new(MANDATE_EXPIRY_MONTHS))
+            .unwrap()
+    }
+
+    fn state(&self, date: NaiveDate) -> MandateState {
+        if let Some(revoked_at) = self.revoked_at {
+            if revoked_at <= date {
+                return MandateState::Revoked(revoked_at);
+            }
+        }
+        let expires_at = self.expires_at();
+        if expires_at < date {
+            return MandateState::Expired(expires_at);
+        }
+        MandateState::Active
+    }
+
+    /// A mandate which was never used starts with a first
+    /// collection. The same applies if the account was moved
+    /// to a different bank after the last collection.
+    fn sequence_type(&self) -> SequenceType {
+        let last_used = match self.last_used_at {
+            Some(date) => date,
+            None => return SequenceType::First,
+        };
+        let amended = self.amended_at.map(|d| d > last_used);
+        if let (Some(true), Some(original)) = (amended, &self.original_iban) {
+            if bank_code(original) != bank_code(&self.iban) {
+                return SequenceType::First;
+            }
+        }
+        SequenceType::Recurring
+    }
+
+    fn amend(self, iban: &str, date: NaiveDate) -> Result<Self, Error> {
+        check_active(&self, date)?;
+        if self.iban == iban {
+            return Err(Error::SameIban(self.reference, iban.to_string()));
+        }
+        // Keep the IBAN of the last collection, in case
+        // the mandate is amended more than once in between.
+        let keep_original = match (self.amended_at, self.last_used_at) {
+            (Some(amended), Some(used)) => amended > used,
+            (Some(_), None) => true,
+            _ => false,
+        };
+        let original_iban = if keep_original {
+            self.original_iban.clone()
+        } else {
+            Some(self.iban.clone())
+        };
+
+        Ok(Mandate {
+            iban: iban.to_string(),
+            amended_at: Some(date),
+            original_iban,
+            ..self
+        })
+    }
+
+    fn revoke(self, date: NaiveDate) -> Result<Self, Error> {
+        check_active(&self, date)?;
+        Ok(Mandate {
+            revoked_at: Some(date),
+            ..self
+        })
+    }
+
+    fn record_collection(
+        self,
+        date: NaiveDate,
+    ) -> Result<(Self, SequenceType), Error> {
+        check_active(&self, date)?;
+        let sequence = self.sequence_type();
+        let mandate = Mandate {
+            last_used_at: Some(date),
+            ..self
+        };
+        Ok((mandate, sequence))
+    }
+}
+
+/// Fail if the mandate can not be used at the date.
+fn check_active(mandate: &Mandate, date: NaiveDate) -> Result<(), Error> {
+    match mandate.state(date) {
+        MandateState::Active => Ok(()),
+        MandateState::Revoked(revoked_at) => {
+            Err(Error::Revoked(mandate.reference.clone(), revoked_at))
+        }
+        MandateState::Expired(expires_at) => {
+            Err(Error::Expired(mandate.reference.clone(), expires_at))
+        }
+    }
+}
+
+/// Create a new mandate for a member. The reference is derived
+/// from the member id and the highest previous revision.
+/// There can only be one active mandate per member.
+pub fn new_mandate(
+    member: &Member,
+    mandates: &[Mandate],
+    iban: &str,
+    account_holder: &str,
+    signed_at: NaiveDate,
+) -> Result<Mandate, Error> {
+    let active = mandates
+        .iter()
+        .find(|m| m.state(signed_at) == MandateState::Active);
+    if let Some(active) = active {
+        return Err(Error::ActiveMandatePresent(
+            member.id,
+            active.reference.clone(),
+        ));
+    }
+    let revision = mandates.iter()
+        .filter_map(Mandate::revision)
+        .max()
+        .unwrap_or(0) + 1;
+    Ok(Mandate {
+        member_id: member.id,
+        reference: Mandate::make_reference(member.id, revision),
+        iban: iban.to_string(),
+        account_holder: account_holder.to_string(),
+        signed_at,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn mandate() -> Mandate {
+        Mandate {
+            member_id: 23,
+            reference: Mandate::make_reference(23, 1),
+            iban: "DE89370400440532013000".to_string(),
+            signed_at: date(2020, 1, 15),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_mandate_expiry() {
+        let m = mandate();
+        assert_eq!(m.expires_at(), date(2023, 1, 15));
+        assert_eq!(m.state(date(2023, 1, 15)), MandateState::Active);
+        assert_eq!(
+            m.state(date(2023, 1, 16)),
+            MandateState::Expired(date(2023, 1, 15))
+        );
+
+        // Using the mandate extends the expiry
+        let (m, _) = m.record_collection(date(2022, 6, 1)).unwrap();
+        assert_eq!(m.expires_at(), date(2025, 6, 1));
+        assert!(m.record_collection(date(2025, 6, 2)).is_err());
+    }
+
+    #[test]
+    fn test_mandate_sequence() {
+        let m = mandate();
+        assert_eq!(m.sequence_type(), SequenceType::First);
+
+        let (m, seq) = m.record_collection(date(2020, 2, 1)).unwrap();
+        assert_eq!(seq, SequenceType::First);
+        let (m, seq) = m.record_collection(date(2020, 3, 1)).unwrap();
+        assert_eq!(seq, SequenceType::Recurring);
+
+        // Same bank, different account
+        let m = m.amend("DE89370400440532013001", date(2020, 3, 5)).unwrap();
+        assert_eq!(m.sequence_type(), SequenceType::Recurring);
+
+        // Different bank
+        let m = m.amend("DE02120300000000202051", date(2020, 3, 6)).unwrap();
+        assert_eq!(m.original_iban, Some("DE89370400440532013000".into()));
+        assert_eq!(m.sequence_type(), SequenceType::First);
+
+        let (m, seq) = m.record_collection(date(2020, 4, 1)).unwrap();
+        assert_eq!(seq, SequenceType::First);
+        assert_eq!(m.sequence_type(), SequenceType::Recurring);
+    }
+
+    #[test]
+    fn test_mandate_revoke() {
+        let m = mandate().revoke(date(2021, 1, 1)).unwrap();
+        assert_eq!(m.state(date(2020, 12, 31)), MandateState::Active);
+        assert_eq!(
+            m.state(date(2021, 1, 1)),
+            MandateState::Revoked(date(2021, 1, 1))
+        );
+        assert!(m.clone().revoke(date(2021, 2, 1)).is_err());
+        assert!(m.amend("DE2342", date(2021, 2, 1)).is_err());
+    }
+
+    #[test]
+    fn test_new_mandate() {
+        let member = Member {
+            id: 23,
+            ..Default::default()
+        };
+        let first = new_mandate(
+            &member, &[], "DE2342", "Eris", date(2020, 1, 1)).unwrap();
+        assert_eq!(first.reference, "ERIS-000023-01");
+
+        // There is still an active mandate
+        let mandates = vec![first.clone()];
+        let res = new_mandate(
+            &member, &mandates, "DE4223", "Eris", date(2020, 2, 1));
+        assert!(res.is_err());
+
+        let mandates = vec![first.revoke(date(2020, 2, 1)).unwrap()];
+        let second = new_mandate(
+            &member, &mandates, "DE4223", "Eris", date(2020, 2, 1)).unwrap();
+        assert_eq!(second.reference, "ERIS-000023-02");
+
+        // The revision is not reused when a mandate was deleted
+        let mandates = vec![second.revoke(date(2020, 3, 1)).unwrap()];
+        let third = new_mandate(
+            &member, &mandates, "DE4223", "Eris", date(2020, 3, 1)).unwrap();
+        assert_eq!(third.reference, "ERIS-000023-03");
+    }
+}
@@ -137,52 +137,46 @@ mod tests {
             membership_start: NaiveDate::from_ymd_opt(2022, 2, 23).unwrap(),
             ..Default::default()
         };
-        assert_eq!(
-            is_member_active(
+        assert!(
+            !is_member_active(
                 &member,
                 NaiveDate::from_ymd_opt(2022, 1, 23).unwrap()
-            ),
-            false
+            )
         );
-        assert_eq!(
+        assert!(
             is_member_active(
                 &member,
                 NaiveDate::from_ymd_opt(2022, 2, 21).unwrap()
-            ),
-            true
+            )
         );
-        assert_eq!(
+        assert!(
             is_member_active(
                 &member,
                 NaiveDate::from_ymd_opt(2022, 4, 24).unwrap()
-            ),
-            true
+            )
         );
 
         let member = Member {
             membership_end: Some(NaiveDate::from_ymd_opt(2022, 2, 23).unwrap()),
             ..Default::default()
         };
-        assert_eq!(
+        assert!(
             is_member_active(
                 &member,
                 NaiveDate::from_ymd_opt(2022, 1, 22).unwrap()
-            ),
-            true
+            )
         );
-        assert_eq!(
+        assert!(
             is_member_active(
                 &member,
                 NaiveDate::from_ymd_opt(2022, 2, 25).unwrap()
-            ),
-            true
+            )
         );
-        assert_eq!(
-            is_member_active(
+        assert!(
+            !is_member_active(
                 &member,
                 NaiveDate::from_ymd_opt(2022, 3, 1).unwrap()
-            ),
-            false
+            )
         );
     }
 }
@@ -0,0 +1,254 @@
+use std::io::Write;
+
+/// A4 page size in points
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 56.0;
+
+/// A single line of text placed on a page
+#[derive(Debug, Clone)]
+struct Text {
+    x: f32,
+    y: f32,
+    size: f32,
+    bold: bool,
+    text: String,
+}
+
+/// A minimal PDF writer for simple text documents like
+/// receipts. Text is set top to bottom in Helvetica and
+/// wrapped at the page margins; new pages are started
+/// when needed.
+#[derive(Debug, Clone)]
+pub struct TextPdf {
+    pages: Vec<Vec<Text>>,
+    y: f32,
+}
+
+impl Default for TextPdf {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TextPdf {
+    pub fn new() -> Self {
+        Self {
+            pages: vec![vec![]],
+            y: PAGE_HEIGHT - MARGIN,
+        }
+    }
+
+    /// Move down to the next line, breaking the page
+    /// if the bottom margin is reached.
+    fn advance(&mut self, height: f32) {
+        self.y -= height;
+        if self.y < MARGIN {
+            self.pages.push(vec![]);
+            self.y = PAGE_HEIGHT - MARGIN - height;
+        }
+    }
+
+    fn place(&mut self, x: f32, size: f32, bold: bool, text: &str) {
+        let y = self.y;
+        self.pages.last_mut().unwrap().push(Text {
+            x,
+            y,
+            size,
+            bold,
+            text: text.to_string(),
+        });
+    }
+
+    /// Add a bold heading
+    pub fn heading(&mut self, text: &str, size: f32) -> &mut Self {
+        for line in wrap(text, size, PAGE_WIDTH - 2.0 * MARGIN) {
+            self.advance(size * 1.3);
+            self.place(MARGIN, size, true, &line);
+        }
+        self
+    }
+
+    /// Add a paragraph, wrapped at the page width
+    pub fn paragraph(&mut self, text: &str, size: f32) -> &mut Self {
+        for line in wrap(text, size, PAGE_WIDTH - 2.0 * MARGIN) {
+            self.advance(size * 1.3);
+            self.place(MARGIN, size, false, &line);
+        }
+        self
+    }
+
+    /// Add a row of columns. Columns are given as offsets
+    /// from the left margin and are not wrapped.
+    pub fn row(
+        &mut self,
+        columns: &[(f32, &str)],
+        size: f32,
+        bold: bool,
+    ) -> &mut Self {
+        self.advance(size * 1.3);
+        for (x, text) in columns {
+            self.place(MARGIN + x, size, bold, text);
+        }
+        self
+    }
+
+    /// Add vertical space
+    pub fn space(&mut self, height: f32) -> &mut Self {
+        self.advance(height);
+        self
+    }
+
+    /// Start a new page
+    pub fn page_break(&mut self) -> &mut Self {
+        self.pages.push(vec![]);
+        self.y = PAGE_HEIGHT - MARGIN;
+        self
+    }
+
+    /// Write the PDF document
+    pub fn render(&self) -> Vec<u8> {
+        // Objects 1 and 2 are the catalog and page tree,
+        // 3 and 4 the fonts, followed by a page and a
+        // content stream for each page.
+        let mut objects: Vec<Vec<u8>> = vec![];
+        let kids: Vec<String> = (0..self.pages.len())
+            .map(|i| format!("{} 0 R", 5 + 2 * i))
+            .collect();
+        objects.push(b"<< /Type /Catalog /Pages 2 0 R >>".to_vec());
+        objects.push(format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "),
+            self.pages.len(),
+        ).into_bytes());
+        for font in ["Helvetica", "Helvetica-Bold"] {
+            objects.push(format!(
+                "<< /Type /Font /Subtype /Type1 /BaseFont /{} \
+                 /Encoding /WinAnsiEncoding >>",
+                font,
+            ).into_bytes());
+        }
+        for (i, page) in self.pages.iter().enumerate() {
+            objects.push(format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+                 /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> \
+                 /Contents {} 0 R >>",
+                PAGE_WIDTH, PAGE_HEIGHT, 6 + 2 * i,
+            ).into_bytes());
+
+            let mut content: Vec<u8> = vec![];
+            for text in page {
+                write!(
+                    content,
+                    "BT /{} {} Tf {} {} Td (",
+                    if text.bold { "F2" } else { "F1" },
+                    text.size,
+                    text.x,
+                    text.y,
+                ).unwrap();
+                content.extend(encode(&text.text));
+                content.extend(b") Tj ET\n");
+            }
+            let mut stream = format!(
+                "<< /Length {} >>\nstream\n", content.len()).into_bytes();
+            stream.extend(content);
+            stream.extend(b"\nendstream");
+            objects.push(stream);
+        }
+
+        let mut pdf: Vec<u8> = b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec();
+        let mut offsets = vec![];
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            writeln!(pdf, "{} 0 obj", i + 1).unwrap();
+            pdf.extend(object);
+            pdf.extend(b"\nendobj\n");
+        }
+        let xref = pdf.len();
+        write!(pdf, "xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1)
+            .unwrap();
+        for offset in offsets {
+            writeln!(pdf, "{:010} 00000 n ", offset).unwrap();
+        }
+        write!(
+            pdf,
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref,
+        ).unwrap();
+        pdf
+    }
+}
+
+/// Wrap text into lines fitting the width. The width of
+/// a character is estimated as half the font size.
+fn wrap(text: &str, size: f32, width: f32) -> Vec<String> {
+    let max = (width / (size * 0.5)) as usize;
+    let mut lines = vec![];
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty()
+            && line.chars().count() + word.chars().count() + 1 > max
+        {
+            lines.push(line);
+            line = String::new();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    lines.push(line);
+    lines
+}
+
+/// Encode text as WinAnsi PDF string content
+fn encode(text: &str) -> Vec<u8> {
+    let mut bytes = vec![];
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                bytes.push(b'\\');
+                bytes.push(c as u8);
+            }
+            '€' => bytes.push(0x80),
+            '§' | 'Ä' | 'Ö' | 'Ü' | 'ß' | 'ä' | 'ö' | 'ü' | 'é' => {
+                bytes.push(c as u32 as u8)
+            }
+            c if c.is_ascii() => bytes.push(c as u8),
+            _ => bytes.push(b'?'),
+        }
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode("(ä) 5 €"), b"\\(\xE4\\) 5 \x80".to_vec());
+    }
+
+    #[test]
+    fn test_wrap() {
+        let lines = wrap("Hail Eris all hail Discordia", 10.0, 60.0);
+        assert_eq!(lines, vec!["Hail Eris", "all hail", "Discordia"]);
+    }
+
+    #[test]
+    fn test_render() {
+        let mut pdf = TextPdf::new();
+        pdf.heading("Bestätigung", 14.0)
+            .paragraph("Fnord", 10.0)
+            .page_break()
+            .row(&[(0.0, "Datum"), (100.0, "Betrag")], 10.0, false);
+        let bytes = pdf.render();
+        let text = String::from_utf8_lossy(&bytes);
+        assert!(text.starts_with("%PDF-1.4"));
+        assert!(text.contains("/Count 2"));
+        assert!(text.contains("(Fnord) Tj"));
+        assert!(text.ends_with("%%EOF\n"));
+    }
+}
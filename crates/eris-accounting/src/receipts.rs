@@ -0,0 +1,567 @@
+use std::fmt::Write;
+
+use anyhow::Result;
+use chrono::{Datelike, NaiveDate};
+
+use eris_data::{
+    DonationReceipt,
+    DonationReceiptFilter,
+    Insert,
+    Member,
+    Query,
+    Transaction,
+    TransactionFilter,
+    TransactionKind,
+};
+
+use crate::{html, pdf::TextPdf};
+
+/// The receiving organisation as printed on the receipt
+#[derive(Debug, Clone, Default)]
+pub struct Issuer {
+    pub name: String,
+    pub address: String,
+    /// Place of issue next to the signature
+    pub place: String,
+    /// The tax privileged purpose, completing the phrase
+    /// "Förderung ...", e.g. "der Volksbildung"
+    pub purpose: String,
+    pub tax_office: String,
+    pub tax_number: String,
+    /// Date of the latest exemption notice
+    pub exemption_date: NaiveDate,
+    /// The assessment period of the exemption notice
+    pub assessment_period: String,
+}
+
+/// The payments of a member in a year which can be
+/// confirmed as donations.
+#[derive(Debug, Clone, Default)]
+pub struct AnnualDonations {
+    pub member_id: u32,
+    pub year: i32,
+    /// Payments received from the member
+    pub payments: Vec<Transaction>,
+    /// Reversed payments and refunds, which are not donated
+    pub deductions: Vec<Transaction>,
+}
+
+impl AnnualDonations {
+    /// Collect the donations in a year from the ledger of a member.
+    /// Membership fees are confirmed as paid, so only payments
+    /// are counted; calculated fees and manual corrections are not.
+    pub fn from_transactions(
+        member_id: u32,
+        year: i32,
+        transactions: &[Transaction],
+    ) -> Self {
+        let mut payments = vec![];
+        let mut deductions = vec![];
+        let mut transactions: Vec<&Transaction> = transactions
+            .iter()
+            .filter(|tx| tx.member_id == member_id)
+            .filter(|tx| tx.date.year() == year)
+            .collect();
+        transactions.sort_by_key(|tx| (tx.date, tx.id));
+
+        for tx in transactions {
+            match tx.kind {
+                TransactionKind::Bank
+                | TransactionKind::Split
+                | TransactionKind::Overflow => {
+                    if tx.amount > 0.0 {
+                        payments.push(tx.clone());
+                    } else {
+                        deductions.push(tx.clone());
+                    }
+                }
+                TransactionKind::Refund => {
+                    deductions.push(tx.clone());
+                }
+                _ => {}
+            }
+        }
+        Self {
+            member_id,
+            year,
+            payments,
+            deductions,
+        }
+    }
+
+    /// Fetch the transactions of the member and collect
+    /// the donations in the year.
+    pub async fn fetch<DB>(db: &DB, member: &Member, year: i32) -> Result<Self>
+    where
+        DB: Query<Transaction, Filter = TransactionFilter>,
+    {
+        let transactions: Vec<Transaction> = db.query(&TransactionFilter {
+            member_id: Some(member.id),
+            date_after: NaiveDate::from_ymd_opt(year, 1, 1),
+            date_before: NaiveDate::from_ymd_opt(year, 12, 31),
+            ..Default::default()
+        }).await?;
+        Ok(Self::from_transactions(member.id, year, &transactions))
+    }
+
+    /// The total amount to confirm. This is never negative.
+    pub fn total(&self) -> f64 {
+        let total: f64 = self.payments.iter()
+            .chain(self.deductions.iter())
+            .map(|tx| tx.amount)
+            .sum();
+        (total.max(0.0) * 100.0).round() / 100.0
+    }
+}
+
+/// Get the receipt already issued to a member for a year
+pub async fn find_receipt<DB>(
+    db: &DB,
+    member_id: u32,
+    year: i32,
+) -> Result<Option<DonationReceipt>>
+where
+    DB: Query<DonationReceipt, Filter = DonationReceiptFilter>,
+{
+    let receipt = db.query(&DonationReceiptFilter {
+        member_id: Some(member_id),
+        year: Some(year),
+        ..Default::default()
+    }).await?.pop();
+    Ok(receipt)
+}
+
+/// Issue a receipt for the donations of a member. This
+/// assigns the next receipt number of the year.
+pub async fn issue_receipt<DB>(
+    db: &DB,
+    member: &Member,
+    donations: &AnnualDonations,
+    issued_at: NaiveDate,
+) -> Result<DonationReceipt>
+where
+    DB: Insert<DonationReceipt>,
+{
+    db.insert(DonationReceipt {
+        member_id: member.id,
+        year: donations.year,
+        name: member.name.clone(),
+        amount: donations.total(),
+        issued_at,
+        ..Default::default()
+    }).await
+}
+
+const TITLE: &str = "Sammelbestätigung über Geldzuwendungen/Mitgliedsbeiträge";
+
+const LEGAL_BASIS: &str = "im Sinne des § 10b des Einkommensteuergesetzes an \
+    eine der in § 5 Abs. 1 Nr. 9 des Körperschaftsteuergesetzes bezeichneten \
+    Körperschaften, Personenvereinigungen oder Vermögensmassen";
+
+const NO_WAIVER: &str = "Es wird bestätigt, dass es sich nicht um den \
+    Verzicht auf Erstattung von Aufwendungen handelt.";
+
+const NO_OTHER_RECEIPTS: &str = "Es wird bestätigt, dass über die in der \
+    Gesamtsumme enthaltenen Zuwendungen keine weiteren Bestätigungen, weder \
+    formelle Zuwendungsbestätigungen noch Beitragsquittungen oder ähnliches \
+    ausgestellt wurden und werden.";
+
+const NOTICE: &str = "Hinweis: Wer vorsätzlich oder grob fahrlässig eine \
+    unrichtige Zuwendungsbestätigung erstellt oder veranlasst, dass \
+    Zuwendungen nicht zu den in der Zuwendungsbestätigung angegebenen \
+    steuerbegünstigten Zwecken verwendet werden, haftet für die entgangene \
+    Steuer (§ 10b Abs. 4 EStG, § 9 Abs. 3 KStG, § 9 Nr. 5 GewStG). Diese \
+    Bestätigung wird nicht als Nachweis für die steuerliche \
+    Berücksichtigung der Zuwendung anerkannt, wenn das Datum des \
+    Freistellungsbescheides länger als 5 Jahre bzw. das Datum der \
+    Feststellung der Einhaltung der satzungsmäßigen Voraussetzungen nach \
+    § 60a Abs. 1 AO länger als 3 Jahre seit Ausstellung des Bescheides \
+    zurückliegt (§ 63 Abs. 5 AO).";
+
+/// A donation receipt in the official layout of a
+/// collective receipt (Sammelbestätigung) with the list
+/// of donations attached.
+pub struct ReceiptDocument<'a> {
+    pub issuer: &'a Issuer,
+    pub receipt: &'a DonationReceipt,
+    pub donations: &'a AnnualDonations,
+}
+
+impl ReceiptDocument<'_> {
+    fn exemption(&self) -> String {
+        format!(
+            "Wir sind wegen Förderung {} nach dem Freistellungsbescheid bzw. \
+             nach der Anlage zum Körperschaftsteuerbescheid des Finanzamtes \
+             {}, StNr. {}, vom {} für den letzten Veranlagungszeitraum {} \
+             nach § 5 Abs. 1 Nr. 9 des Körperschaftsteuergesetzes von der \
+             Körperschaftsteuer und nach § 3 Nr. 6 des \
+             Gewerbesteuergesetzes von der Gewerbesteuer befreit.",
+            self.issuer.purpose,
+            self.issuer.tax_office,
+            self.issuer.tax_number,
+            self.issuer.exemption_date.format("%d.%m.%Y"),
+            self.issuer.assessment_period,
+        )
+    }
+
+    fn usage(&self) -> String {
+        format!(
+            "Es wird bestätigt, dass die Zuwendung nur zur Förderung {} \
+             verwendet wird.",
+            self.issuer.purpose,
+        )
+    }
+
+    fn period(&self) -> String {
+        format!("01.01.{} bis 31.12.{}", self.receipt.year, self.receipt.year)
+    }
+
+    fn signature(&self) -> String {
+        format!(
+            "{}, {}",
+            self.issuer.place,
+            self.receipt.issued_at.format("%d.%m.%Y"),
+        )
+    }
+
+    /// The donations listed in the attachment
+    fn entries(&self) -> Vec<(String, &'static str, String)> {
+        self.donations.payments.iter()
+            .map(|tx| (tx, "Geldzuwendung/Mitgliedsbeitrag"))
+            .chain(self.donations.deductions.iter()
+                .map(|tx| (tx, "Rückbuchung/Erstattung")))
+            .map(|(tx, kind)| (
+                tx.date.format("%d.%m.%Y").to_string(),
+                kind,
+                euro(tx.amount),
+            ))
+            .collect()
+    }
+
+    /// Render the receipt as a printable HTML document
+    pub fn to_html(&self) -> String {
+        let mut out = String::new();
+        let number = self.receipt.number();
+        writeln!(out, "<!DOCTYPE html>").unwrap();
+        writeln!(out, "<html lang=\"de\">\n<head>\n<meta charset=\"utf-8\">")
+            .unwrap();
+        writeln!(out, "<title>Zuwendungsbestätigung {}</title>", number)
+            .unwrap();
+        writeln!(out, "<style>{}</style>", STYLE).unwrap();
+        writeln!(out, "</head>\n<body>").unwrap();
+        writeln!(out, "<p class=\"small\">Aussteller (Bezeichnung und \
+            Anschrift der steuerbegünstigten Einrichtung)</p>").unwrap();
+        writeln!(out, "<p>{}<br>{}</p>",
+            html::escape(&self.issuer.name),
+            html::escape(&self.issuer.address).replace('\n', "<br>"))
+            .unwrap();
+        writeln!(out, "<h1>{}</h1>", TITLE).unwrap();
+        writeln!(out, "<p class=\"small\">{}</p>", LEGAL_BASIS).unwrap();
+        writeln!(out, "<table class=\"fields\">").unwrap();
+        writeln!(out, "<tr><th>Name und Anschrift des Zuwendenden</th>\
+            <td>{}</td></tr>", html::escape(&self.receipt.name)).unwrap();
+        writeln!(out, "<tr><th>Gesamtbetrag der Zuwendung in Ziffern</th>\
+            <td>{}</td></tr>", euro(self.receipt.amount)).unwrap();
+        writeln!(out, "<tr><th>in Buchstaben</th><td>{}</td></tr>",
+            amount_in_words(self.receipt.amount)).unwrap();
+        writeln!(out, "<tr><th>Zeitraum der Sammelbestätigung</th>\
+            <td>{}</td></tr>", self.period()).unwrap();
+        writeln!(out, "<tr><th>Bestätigungsnummer</th><td>{}</td></tr>",
+            number).unwrap();
+        writeln!(out, "</table>").unwrap();
+        for text in [self.exemption(), self.usage()] {
+            writeln!(out, "<p>{}</p>", html::escape(&text)).unwrap();
+        }
+        writeln!(out, "<p>{}</p>\n<p>{}</p>", NO_WAIVER, NO_OTHER_RECEIPTS)
+            .unwrap();
+        writeln!(out, "<p class=\"signature\">{}</p>",
+            html::escape(&self.signature())).unwrap();
+        writeln!(out, "<p class=\"small\">(Unterschrift des \
+            Zuwendungsempfängers)</p>").unwrap();
+        writeln!(out, "<p class=\"small\">{}</p>", NOTICE).unwrap();
+
+        writeln!(out, "<h2>Anlage zur Sammelbestätigung {}</h2>", number)
+            .unwrap();
+        writeln!(out, "<table class=\"entries\">").unwrap();
+        writeln!(out, "<tr><th>Datum der Zuwendung</th>\
+            <th>Art der Zuwendung</th>\
+            <th>Verzicht auf die Erstattung von Aufwendungen</th>\
+            <th>Betrag</th></tr>").unwrap();
+        for (date, kind, amount) in self.entries() {
+            writeln!(out, "<tr><td>{}</td><td>{}</td><td>nein</td>\
+                <td>{}</td></tr>", date, kind, amount).unwrap();
+        }
+        writeln!(out, "<tr class=\"total\"><td colspan=\"3\">Gesamtsumme</td>\
+            <td>{}</td></tr>", euro(self.receipt.amount)).unwrap();
+        writeln!(out, "</table>").unwrap();
+        writeln!(out, "</body>\n</html>").unwrap();
+        out
+    }
+
+    /// Render the receipt as PDF
+    pub fn to_pdf(&self) -> Vec<u8> {
+        let number = self.receipt.number();
+        let mut pdf = TextPdf::new();
+        pdf.paragraph("Aussteller (Bezeichnung und Anschrift der \
+            steuerbegünstigten Einrichtung)", 8.0);
+        pdf.paragraph(&self.issuer.name, 10.0);
+        for line in self.issuer.address.lines() {
+            pdf.paragraph(line, 10.0);
+        }
+        pdf.space(12.0)
+            .heading(TITLE, 13.0)
+            .paragraph(LEGAL_BASIS, 8.0)
+            .space(12.0);
+
+        let fields = [
+            ("Name und Anschrift des Zuwendenden:", self.receipt.name.clone()),
+            ("Gesamtbetrag in Ziffern:", euro(self.receipt.amount)),
+            ("in Buchstaben:", amount_in_words(self.receipt.amount)),
+            ("Zeitraum der Sammelbestätigung:", self.period()),
+            ("Bestätigungsnummer:", number.clone()),
+        ];
+        for (label, value) in &fields {
+            pdf.row(&[(0.0, label), (190.0, value)], 10.0, false);
+        }
+        pdf.space(12.0)
+            .paragraph(&self.exemption(), 10.0)
+            .space(6.0)
+            .paragraph(&self.usage(), 10.0)
+            .space(6.0)
+            .paragraph(NO_WAIVER, 10.0)
+            .space(6.0)
+            .paragraph(NO_OTHER_RECEIPTS, 10.0)
+            .space(36.0)
+            .paragraph(&self.signature(), 10.0)
+            .paragraph("______________________________", 10.0)
+            .paragraph("(Unterschrift des Zuwendungsempfängers)", 8.0)
+            .space(12.0)
+            .paragraph(NOTICE, 7.0);
+
+        pdf.page_break()
+            .heading(&format!("Anlage zur Sammelbestätigung {}", number), 13.0)
+            .space(12.0)
+            .row(&[
+                (0.0, "Datum"),
+                (80.0, "Art der Zuwendung"),
+                (270.0, "Verzicht auf Erstattung"),
+                (410.0, "Betrag"),
+            ], 10.0, true);
+        for (date, kind, amount) in self.entries() {
+            pdf.row(&[
+                (0.0, &date),
+                (80.0, kind),
+                (270.0, "nein"),
+                (410.0, &amount),
+            ], 10.0, false);
+        }
+        pdf.space(6.0).row(&[
+            (0.0, "Gesamtsumme"),
+            (410.0, &euro(self.receipt.amount)),
+        ], 10.0, true);
+        pdf.render()
+    }
+}
+
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; max-width: 50em; } \
+.small { font-size: 0.8em; } \
+table { border-collapse: collapse; width: 100%; margin: 1em 0; } \
+th, td { border: 1px solid #999; padding: 0.3em; text-align: left; } \
+.entries td:last-child { text-align: right; } \
+tr.total { font-weight: bold; } \
+.signature { margin-top: 3em; border-bottom: 1px solid #000; width: 20em; } \
+h2 { page-break-before: always; }";
+
+/// Format an amount in the German notation
+fn euro(amount: f64) -> String {
+    format!("{:.2} €", amount).replace('.', ",")
+}
+
+const ONES: [&str; 20] = [
+    "null", "eins", "zwei", "drei", "vier", "fünf", "sechs", "sieben",
+    "acht", "neun", "zehn", "elf", "zwölf", "dreizehn", "vierzehn",
+    "fünfzehn", "sechzehn", "siebzehn", "achtzehn", "neunzehn",
+];
+
+const TENS: [&str; 10] = [
+    "", "", "zwanzig", "dreißig", "vierzig", "fünfzig", "sechzig",
+    "siebzig", "achtzig", "neunzig",
+];
+
+/// Spell out a number in German, e.g. 123 as
+/// "einhundertdreiundzwanzig".
+fn number_in_words(n: u64) -> String {
+    // In compounds "eins" is shortened to "ein"
+    let prefix = |n: u64| -> String {
+        if n == 1 {
+            "ein".to_string()
+        } else {
+            number_in_words(n)
+        }
+    };
+    match n {
+        0..=19 => ONES[n as usize].to_string(),
+        20..=99 => {
+            let (tens, ones) = (n / 10, n % 10);
+            if ones == 0 {
+                TENS[tens as usize].to_string()
+            } else {
+                format!("{}und{}", prefix(ones), TENS[tens as usize])
+            }
+        }
+        100..=999 => {
+            let rest = n % 100;
+            format!(
+                "{}hundert{}",
+                prefix(n / 100),
+                if rest > 0 { number_in_words(rest) } else { "".into() },
+            )
+        }
+        1000..=999_999 => {
+            let rest = n % 1000;
+            format!(
+                "{}tausend{}",
+                prefix(n / 1000),
+                if rest > 0 { number_in_words(rest) } else { "".into() },
+            )
+        }
+        _ => {
+            let millions = n / 1_000_000;
+            let rest = n % 1_000_000;
+            let millions = if millions == 1 {
+                "eine Million".to_string()
+            } else {
+                format!("{} Millionen", number_in_words(millions))
+            };
+            if rest > 0 {
+                format!("{} {}", millions, number_in_words(rest))
+            } else {
+                millions
+            }
+        }
+    }
+}
+
+/// Spell out an amount in Euro as required on receipts
+pub fn amount_in_words(amount: f64) -> String {
+    let cents = (amount.abs() * 100.0).round() as u64;
+    let (euros, cents) = (cents / 100, cents % 100);
+    // A single unit is "ein Euro", not "eins Euro"
+    let words = |n: u64| -> String {
+        if n == 1 {
+            "ein".to_string()
+        } else {
+            number_in_words(n)
+        }
+    };
+    if cents == 0 {
+        format!("{} Euro", words(euros))
+    } else {
+        format!("{} Euro und {} Cent", words(euros), words(cents))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use eris_data::{MemoryDb, TransactionKind, MEMBERSHIP_FEE_ACCOUNT};
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn tx(
+        date: NaiveDate,
+        kind: TransactionKind,
+        account_name: &str,
+        desc: &str,
+        amount: f64,
+    ) -> Transaction {
+        Transaction {
+            member_id: 1,
+            date,
+            account_name: account_name.to_string(),
+            description: desc.to_string(),
+            amount,
+            kind,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_amount_in_words() {
+        assert_eq!(amount_in_words(1.0), "ein Euro");
+        assert_eq!(amount_in_words(21.0), "einundzwanzig Euro");
+        assert_eq!(amount_in_words(240.0), "zweihundertvierzig Euro");
+        assert_eq!(
+            amount_in_words(1312.5),
+            "eintausenddreihundertzwölf Euro und fünfzig Cent",
+        );
+        assert_eq!(amount_in_words(101.01), "einhunderteins Euro und ein Cent");
+    }
+
+    #[test]
+    fn test_annual_donations() {
+        use TransactionKind::*;
+        let transactions = vec![
+            tx(date(2022, 12, 30), Bank, "Eris", "Beitrag 2022", 20.0),
+            tx(date(2023, 1, 1), Fee, MEMBERSHIP_FEE_ACCOUNT, "Fee", -20.0),
+            tx(date(2023, 1, 5), Bank, "Eris", "Beitrag", 240.0),
+            tx(date(2023, 2, 5), Split, "Eris", "Beitrag", 20.0),
+            tx(date(2023, 3, 5), Bank, "Eris", "Ruecklastschrift", -20.0),
+            tx(date(2023, 4, 1), Manual, "", "Manual account balance update", 50.0),
+            tx(date(2023, 5, 1), Refund, "Eris", "ERIS-PAYOUT-000001 refund: Overpaid", -40.0),
+            tx(date(2023, 6, 1), Payout, "Eris", "Reimbursement of expenses: Mate refund: x", 30.0),
+            tx(date(2023, 6, 1), Payout, "Eris", "ERIS-PAYOUT-000002 reimbursement: Mate refund: x", -30.0),
+        ];
+        let donations = AnnualDonations::from_transactions(1, 2023, &transactions);
+        assert_eq!(donations.payments.len(), 2);
+        assert_eq!(donations.deductions.len(), 2);
+        assert_eq!(donations.total(), 200.0);
+    }
+
+    #[tokio::test]
+    async fn test_issue_receipt() {
+        let db = MemoryDb::new();
+        let member = db.insert(Member {
+            name: "Eris Discordia".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        db.insert(Transaction {
+            member_id: member.id,
+            ..tx(date(2023, 1, 5), TransactionKind::Bank, "Eris", "Beitrag", 240.0)
+        }).await.unwrap();
+
+        let donations = AnnualDonations::fetch(&db, &member, 2023).await.unwrap();
+        assert!(find_receipt(&db, member.id, 2023).await.unwrap().is_none());
+        let receipt = issue_receipt(&db, &member, &donations, date(2024, 1, 15))
+            .await.unwrap();
+        assert_eq!(receipt.number(), "2023-0001");
+        assert_eq!(receipt.amount, 240.0);
+
+        // The receipt can not be issued again
+        assert!(find_receipt(&db, member.id, 2023).await.unwrap().is_some());
+        assert!(issue_receipt(&db, &member, &donations, date(2024, 1, 16))
+            .await.is_err());
+
+        let issuer = Issuer {
+            name: "Discordia e.V.".to_string(),
+            purpose: "der Volksbildung".to_string(),
+            ..Default::default()
+        };
+        let document = ReceiptDocument {
+            issuer: &issuer,
+            receipt: &receipt,
+            donations: &donations,
+        };
+        let html = document.to_html();
+        assert!(html.contains("<td>2023-0001</td>"));
+        assert!(html.contains("<td>zweihundertvierzig Euro</td>"));
+        assert!(html.contains("<td>05.01.2023</td>"));
+
+        let pdf = document.to_pdf();
+        assert!(pdf.starts_with(b"%PDF"));
+    }
+}
@@ -0,0 +1,310 @@
+use std::{collections::HashMap, fmt::Write};
+
+use anyhow::Result;
+use chrono::{Datelike, NaiveDate};
+use serde::Serialize;
+
+use eris_data::{
+    DonationReceipt,
+    DonationReceiptFilter,
+    Member,
+    MemberFilter,
+    Query,
+    Transaction,
+    TransactionFilter,
+    TransactionKind,
+};
+
+use crate::member_fees::is_member_active;
+
+/// The number of active members in a month
+#[derive(Debug, Clone, Serialize)]
+pub struct ActiveMembers {
+    pub month: NaiveDate,
+    pub active: usize,
+}
+
+/// Number and total of a kind of transactions
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TransactionStats {
+    pub count: usize,
+    pub total: f64,
+}
+
+impl TransactionStats {
+    fn add(&mut self, amount: f64) {
+        self.count += 1;
+        self.total = round(self.total + amount);
+    }
+}
+
+/// The annual financial report for the general assembly
+#[derive(Debug, Clone, Serialize)]
+pub struct AnnualReport {
+    pub year: i32,
+    pub active_members: Vec<ActiveMembers>,
+    pub joined: usize,
+    pub left: usize,
+    /// Membership fees charged to the member accounts
+    pub fees_billed: f64,
+    /// Payments received from members
+    pub fees_received: f64,
+    /// Sum of all negative balances at the end of the year
+    pub outstanding_receivables: f64,
+    pub members_in_arrears: usize,
+    pub split_payments: TransactionStats,
+    pub overflow_payments: TransactionStats,
+    pub payouts: TransactionStats,
+    pub donation_receipts: TransactionStats,
+}
+
+impl AnnualReport {
+    /// Aggregate the report for a year. The transactions must
+    /// include all transactions since the start of the year,
+    /// as the balances at the end of the year are derived from
+    /// the current balances.
+    pub fn new(
+        year: i32,
+        members: &[Member],
+        transactions: &[Transaction],
+        receipts: &[DonationReceipt],
+    ) -> Self {
+        let start = NaiveDate::from_ymd_opt(year, 1, 1).unwrap();
+        let end = NaiveDate::from_ymd_opt(year, 12, 31).unwrap();
+
+        let active_members = (1..=12)
+            .map(|m| NaiveDate::from_ymd_opt(year, m, 1).unwrap())
+            .map(|month| ActiveMembers {
+                month,
+                active: members.iter()
+                    .filter(|m| is_member_active(m, month))
+                    .count(),
+            })
+            .collect();
+        let joined = members.iter()
+            .filter(|m| m.membership_start.year() == year)
+            .count();
+        let left = members.iter()
+            .filter(|m| m.membership_end.map(|d| d.year()) == Some(year))
+            .count();
+
+        let mut fees_billed = 0.0;
+        let mut fees_received = 0.0;
+        let mut split_payments = TransactionStats::default();
+        let mut overflow_payments = TransactionStats::default();
+        let mut payouts = TransactionStats::default();
+        let mut after_year: HashMap<u32, f64> = HashMap::new();
+        for tx in transactions.iter().filter(|tx| tx.date >= start) {
+            if tx.date > end {
+                *after_year.entry(tx.member_id).or_default() += tx.amount;
+                continue;
+            }
+            match tx.kind {
+                TransactionKind::Fee => fees_billed -= tx.amount,
+                TransactionKind::Bank => fees_received += tx.amount,
+                TransactionKind::Split => {
+                    fees_received += tx.amount;
+                    split_payments.add(tx.amount);
+                }
+                TransactionKind::Overflow => {
+                    fees_received += tx.amount;
+                    overflow_payments.add(tx.amount);
+                }
+                TransactionKind::Refund => payouts.add(-tx.amount),
+                TransactionKind::Payout if tx.amount < 0.0 => {
+                    payouts.add(-tx.amount);
+                }
+                TransactionKind::Payout | TransactionKind::Manual => {}
+            }
+        }
+
+        // Balances at the end of the year
+        let balances: Vec<f64> = members.iter()
+            .map(|m| m.account - after_year.get(&m.id).unwrap_or(&0.0))
+            .map(round)
+            .collect();
+        let outstanding_receivables: f64 = balances.iter()
+            .filter(|b| **b < 0.0)
+            .map(|b| -b)
+            .sum();
+        let members_in_arrears = balances.iter()
+            .filter(|b| **b < 0.0)
+            .count();
+
+        let mut donation_receipts = TransactionStats::default();
+        for receipt in receipts.iter().filter(|r| r.year == year) {
+            donation_receipts.add(receipt.amount);
+        }
+
+        Self {
+            year,
+            active_members,
+            joined,
+            left,
+            fees_billed: round(fees_billed),
+            fees_received: round(fees_received),
+            outstanding_receivables: round(outstanding_receivables),
+            members_in_arrears,
+            split_payments,
+            overflow_payments,
+            payouts,
+            donation_receipts,
+        }
+    }
+
+    /// Fetch members, transactions and receipts and
+    /// aggregate the report for the year.
+    pub async fn fetch<DB>(db: &DB, year: i32) -> Result<Self>
+    where
+        DB: Query<Member, Filter = MemberFilter>
+            + Query<Transaction, Filter = TransactionFilter>
+            + Query<DonationReceipt, Filter = DonationReceiptFilter>,
+    {
+        // Former members are part of the report of the year
+        let members: Vec<Member> = db.query(&MemberFilter {
+            include_archived: true,
+            ..Default::default()
+        }).await?;
+        let transactions: Vec<Transaction> = db.query(&TransactionFilter {
+            date_after: NaiveDate::from_ymd_opt(year, 1, 1),
+            ..Default::default()
+        }).await?;
+        let receipts: Vec<DonationReceipt> = db.query(&DonationReceiptFilter {
+            year: Some(year),
+            ..Default::default()
+        }).await?;
+        Ok(Self::new(year, &members, &transactions, &receipts))
+    }
+
+    /// Render the report as Markdown
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "# Annual report {}", self.year).unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "## Members").unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "| Month | Active members |").unwrap();
+        writeln!(out, "|-------|---------------:|").unwrap();
+        for month in &self.active_members {
+            writeln!(out, "| {} | {} |",
+                month.month.format("%Y-%m"), month.active).unwrap();
+        }
+        writeln!(out).unwrap();
+        writeln!(out, "- Joined: {}", self.joined).unwrap();
+        writeln!(out, "- Left: {}", self.left).unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "## Finances").unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "| | Count | Amount |").unwrap();
+        writeln!(out, "|-|------:|-------:|").unwrap();
+        writeln!(out, "| Fees billed | | {:.2} |", self.fees_billed).unwrap();
+        writeln!(out, "| Payments received | | {:.2} |",
+            self.fees_received).unwrap();
+        writeln!(out, "| Outstanding receivables | {} | {:.2} |",
+            self.members_in_arrears, self.outstanding_receivables).unwrap();
+        for (label, stats) in [
+            ("Split payments", &self.split_payments),
+            ("Overflow payments", &self.overflow_payments),
+            ("Payouts", &self.payouts),
+            ("Donation receipts", &self.donation_receipts),
+        ] {
+            writeln!(out, "| {} | {} | {:.2} |",
+                label, stats.count, stats.total).unwrap();
+        }
+        out
+    }
+
+    /// Render the report as JSON
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+fn round(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use eris_data::{TransactionKind, MEMBERSHIP_FEE_ACCOUNT};
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn tx(
+        member_id: u32,
+        date: NaiveDate,
+        kind: TransactionKind,
+        account_name: &str,
+        desc: &str,
+        amount: f64,
+    ) -> Transaction {
+        Transaction {
+            member_id,
+            date,
+            account_name: account_name.to_string(),
+            description: desc.to_string(),
+            amount,
+            kind,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_annual_report() {
+        let members = vec![
+            Member {
+                id: 1,
+                membership_start: date(2020, 1, 1),
+                account: -10.0,
+                ..Default::default()
+            },
+            Member {
+                id: 2,
+                membership_start: date(2023, 4, 15),
+                membership_end: Some(date(2023, 9, 30)),
+                account: 0.0,
+                ..Default::default()
+            },
+        ];
+        use TransactionKind::*;
+        let transactions = vec![
+            tx(1, date(2023, 1, 1), Fee, MEMBERSHIP_FEE_ACCOUNT, "Fee", -20.0),
+            tx(1, date(2023, 1, 5), Split, "Eris", "Beitrag", 10.0),
+            tx(2, date(2023, 1, 5), Split, "Eris", "Beitrag", 10.0),
+            tx(2, date(2023, 1, 5), Overflow, "Eris", "Beitrag (overflow)", 5.0),
+            tx(2, date(2023, 6, 1), Refund, "Eris", "ERIS-PAYOUT-000001 refund: x", -5.0),
+            tx(1, date(2024, 1, 1), Fee, MEMBERSHIP_FEE_ACCOUNT, "Fee", -20.0),
+        ];
+        let receipts = vec![DonationReceipt {
+            year: 2023,
+            amount: 15.0,
+            ..Default::default()
+        }];
+        let report = AnnualReport::new(2023, &members, &transactions, &receipts);
+
+        assert_eq!(report.active_members[0].active, 1);
+        assert_eq!(report.active_members[3].active, 2);
+        assert_eq!(report.active_members[9].active, 1);
+        assert_eq!(report.joined, 1);
+        assert_eq!(report.left, 1);
+        assert_eq!(report.fees_billed, 20.0);
+        assert_eq!(report.fees_received, 25.0);
+        assert_eq!(report.split_payments.count, 2);
+        assert_eq!(report.overflow_payments.total, 5.0);
+        assert_eq!(report.payouts.total, 5.0);
+        assert_eq!(report.donation_receipts.count, 1);
+
+        // Member 1 had a balance of 10 at the end of the year
+        assert_eq!(report.members_in_arrears, 0);
+
+        let markdown = report.to_markdown();
+        assert!(markdown.contains("| 2023-04 | 2 |"));
+        assert!(markdown.contains("| Fees billed | | 20.00 |"));
+        let json = report.to_json().unwrap();
+        assert!(json.contains("\"fees_received\": 25.0"));
+    }
+}
@@ -0,0 +1,316 @@
+use std::fmt::Write;
+
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
+
+use eris_data::{
+    Member,
+    Query,
+    Transaction,
+    TransactionFilter,
+};
+
+use crate::html;
+
+/// A transaction on the statement with the balance
+/// after it was applied.
+#[derive(Debug, Clone)]
+pub struct StatementLine {
+    pub transaction: Transaction,
+    pub balance: f64,
+}
+
+/// The account statement of a member for a period
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub member: Member,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub opening_balance: f64,
+    pub lines: Vec<StatementLine>,
+    pub closing_balance: f64,
+}
+
+impl Statement {
+    /// Create the statement from the member's transactions.
+    /// The opening balance is derived backwards from the
+    /// current balance, so the transactions must include at
+    /// least all transactions since `from`.
+    pub fn new(
+        member: Member,
+        transactions: Vec<Transaction>,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Self> {
+        if from > to {
+            return Err(anyhow!(
+                "statement period starts after it ends: {} > {}", from, to));
+        }
+        let mut transactions: Vec<Transaction> = transactions
+            .into_iter()
+            .filter(|tx| tx.date >= from)
+            .collect();
+        transactions.sort_by_key(|tx| (tx.date, tx.id));
+
+        let since: f64 = transactions.iter().map(|tx| tx.amount).sum();
+        let opening_balance = round(member.account - since);
+
+        let mut balance = opening_balance;
+        let lines: Vec<StatementLine> = transactions
+            .into_iter()
+            .filter(|tx| tx.date <= to)
+            .map(|transaction| {
+                balance = round(balance + transaction.amount);
+                StatementLine {
+                    transaction,
+                    balance,
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            member,
+            from,
+            to,
+            opening_balance,
+            lines,
+            closing_balance: balance,
+        })
+    }
+
+    /// Fetch the transactions of a member and create the
+    /// statement for the period.
+    pub async fn fetch<DB>(
+        db: &DB,
+        member: Member,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Self>
+    where
+        DB: Query<Transaction, Filter = TransactionFilter>,
+    {
+        let transactions: Vec<Transaction> = db.query(&TransactionFilter {
+            member_id: Some(member.id),
+            date_after: Some(from),
+            ..Default::default()
+        }).await?;
+        Self::new(member, transactions, from, to)
+    }
+
+    /// Sum of all payments into the account
+    pub fn total_credit(&self) -> f64 {
+        round(self.lines.iter()
+            .map(|l| l.transaction.amount)
+            .filter(|a| *a > 0.0)
+            .sum())
+    }
+
+    /// Sum of all charges to the account
+    pub fn total_debit(&self) -> f64 {
+        round(self.lines.iter()
+            .map(|l| l.transaction.amount)
+            .filter(|a| *a < 0.0)
+            .sum())
+    }
+
+    /// Render the statement as plain text
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "Account statement for {} (#{})",
+            self.member.name, self.member.id).unwrap();
+        writeln!(out, "Period: {} to {}", self.from, self.to).unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "{:<10}  {:<8}  {:<50}  {:>10}  {:>10}",
+            "Date", "Kind", "Description", "Amount", "Balance").unwrap();
+        writeln!(out, "{:-<96}", "-").unwrap();
+        writeln!(out, "{:<10}  {:<8}  {:<50}  {:>10}  {:>10.2}",
+            self.from, "", "Opening balance", "", self.opening_balance)
+            .unwrap();
+        for line in &self.lines {
+            let tx = &line.transaction;
+            writeln!(out, "{:<10}  {:<8}  {:<50}  {:>10.2}  {:>10.2}",
+                tx.date, tx.kind, tx.description, tx.amount, line.balance)
+                .unwrap();
+        }
+        writeln!(out, "{:<10}  {:<8}  {:<50}  {:>10}  {:>10.2}",
+            self.to, "", "Closing balance", "", self.closing_balance)
+            .unwrap();
+        writeln!(out, "{:-<96}", "-").unwrap();
+        writeln!(out, "Payments: {:.2}  Charges: {:.2}",
+            self.total_credit(), self.total_debit()).unwrap();
+        out
+    }
+
+    /// Render the statement as CSV with the opening and
+    /// closing balance as first and last row.
+    pub fn to_csv(&self) -> Result<String> {
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.write_record(
+            ["date", "kind", "account_name", "description", "amount", "balance"])?;
+        writer.write_record([
+            &self.from.to_string(), "opening", "", "Opening balance", "",
+            &format!("{:.2}", self.opening_balance),
+        ])?;
+        for line in &self.lines {
+            let tx = &line.transaction;
+            writer.write_record([
+                &tx.date.to_string(),
+                &tx.kind.to_string(),
+                &tx.account_name,
+                &tx.description,
+                &format!("{:.2}", tx.amount),
+                &format!("{:.2}", line.balance),
+            ])?;
+        }
+        writer.write_record([
+            &self.to.to_string(), "closing", "", "Closing balance", "",
+            &format!("{:.2}", self.closing_balance),
+        ])?;
+        Ok(String::from_utf8(writer.into_inner()?)?)
+    }
+
+    /// Render the statement as a printable HTML document
+    pub fn to_html(&self) -> String {
+        let mut out = String::new();
+        let title = format!(
+            "Account statement for {}", html::escape(&self.member.name));
+        writeln!(out, "<!DOCTYPE html>").unwrap();
+        writeln!(out, "<html>\n<head>\n<meta charset=\"utf-8\">").unwrap();
+        writeln!(out, "<title>{}</title>", title).unwrap();
+        writeln!(out, "<style>{}</style>", STYLE).unwrap();
+        writeln!(out, "</head>\n<body>").unwrap();
+        writeln!(out, "<h1>{}</h1>", title).unwrap();
+        writeln!(out, "<p>Member #{}<br>Period: {} to {}</p>",
+            self.member.id, self.from, self.to).unwrap();
+        writeln!(out, "<table>").unwrap();
+        writeln!(out, "<tr><th>Date</th><th>Kind</th><th>Description</th>\
+            <th>Amount</th><th>Balance</th></tr>").unwrap();
+        writeln!(out, "<tr class=\"balance\"><td>{}</td><td></td>\
+            <td>Opening balance</td><td></td><td>{:.2}</td></tr>",
+            self.from, self.opening_balance).unwrap();
+        for line in &self.lines {
+            let tx = &line.transaction;
+            writeln!(out, "<tr><td>{}</td><td>{}</td><td>{}</td>\
+                <td>{:.2}</td><td>{:.2}</td></tr>",
+                tx.date,
+                tx.kind,
+                html::escape(&tx.description),
+                tx.amount,
+                line.balance).unwrap();
+        }
+        writeln!(out, "<tr class=\"balance\"><td>{}</td><td></td>\
+            <td>Closing balance</td><td></td><td>{:.2}</td></tr>",
+            self.to, self.closing_balance).unwrap();
+        writeln!(out, "</table>").unwrap();
+        writeln!(out, "<p>Payments: {:.2} &euro;<br>Charges: {:.2} &euro;</p>",
+            self.total_credit(), self.total_debit()).unwrap();
+        writeln!(out, "</body>\n</html>").unwrap();
+        out
+    }
+}
+
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; } \
+table { border-collapse: collapse; width: 100%; } \
+th, td { border-bottom: 1px solid #ccc; padding: 0.3em; text-align: left; } \
+td:nth-child(4), td:nth-child(5) { text-align: right; } \
+tr.balance { font-weight: bold; }";
+
+/// Round to cents to avoid accumulating float errors
+fn round(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use eris_data::{Insert, MemoryDb, TransactionKind, MEMBERSHIP_FEE_ACCOUNT};
+
+    use crate::transactions::ApplyTransaction;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    async fn member_with_transactions(db: &MemoryDb) -> Member {
+        let mut member = db.insert(Member {
+            name: "Eris".to_string(),
+            account: 10.0,
+            ..Default::default()
+        }).await.unwrap();
+        let txs = [
+            (date(2022, 12, 1), MEMBERSHIP_FEE_ACCOUNT, "Fee 12/2022", -20.0),
+            (date(2023, 1, 1), MEMBERSHIP_FEE_ACCOUNT, "Fee 01/2023", -20.0),
+            (date(2023, 1, 5), "Eris", "Mitgliedsbeitrag", 40.0),
+            (date(2023, 2, 1), MEMBERSHIP_FEE_ACCOUNT, "Fee 02/2023", -20.0),
+            (date(2023, 3, 1), MEMBERSHIP_FEE_ACCOUNT, "Fee 03/2023", -20.0),
+        ];
+        for (date, account_name, description, amount) in txs {
+            let kind = if account_name == MEMBERSHIP_FEE_ACCOUNT {
+                TransactionKind::Fee
+            } else {
+                TransactionKind::Bank
+            };
+            member = member.apply_transaction(db, Transaction {
+                date,
+                account_name: account_name.to_string(),
+                description: description.to_string(),
+                amount,
+                kind,
+                ..Default::default()
+            }).await.unwrap();
+        }
+        member
+    }
+
+    #[tokio::test]
+    async fn test_statement_balances() {
+        let db = MemoryDb::new();
+        let member = member_with_transactions(&db).await;
+        assert_eq!(member.account, -30.0);
+
+        let statement = Statement::fetch(
+            &db, member, date(2023, 1, 1), date(2023, 2, 28),
+        ).await.unwrap();
+        assert_eq!(statement.opening_balance, -10.0);
+        assert_eq!(statement.lines.len(), 3);
+        assert_eq!(statement.lines[0].balance, -30.0);
+        assert_eq!(statement.lines[1].transaction.kind, TransactionKind::Bank);
+        assert_eq!(statement.lines[1].balance, 10.0);
+        assert_eq!(statement.closing_balance, -10.0);
+        assert_eq!(statement.total_credit(), 40.0);
+        assert_eq!(statement.total_debit(), -40.0);
+    }
+
+    #[tokio::test]
+    async fn test_statement_render() {
+        let db = MemoryDb::new();
+        let member = member_with_transactions(&db).await;
+        let statement = Statement::fetch(
+            &db, member, date(2023, 1, 1), date(2023, 12, 31),
+        ).await.unwrap();
+
+        let text = statement.to_text();
+        assert!(text.contains("Opening balance"));
+        assert!(text.lines().any(|l| {
+            l.contains("Closing balance") && l.ends_with("-30.00")
+        }));
+
+        let csv = statement.to_csv().unwrap();
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows.len(), 7);
+        assert_eq!(rows[1], "2023-01-01,opening,,Opening balance,,-10.00");
+        assert_eq!(rows[3], "2023-01-05,bank,Eris,Mitgliedsbeitrag,40.00,10.00");
+
+        let html = statement.to_html();
+        assert!(html.contains("<td>Closing balance</td><td></td><td>-30.00</td>"));
+    }
+
+    #[test]
+    fn test_statement_invalid_period() {
+        let result = Statement::new(
+            Member::default(), vec![], date(2023, 2, 1), date(2023, 1, 1));
+        assert!(result.is_err());
+    }
+}
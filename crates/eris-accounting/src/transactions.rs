@@ -1,15 +1,23 @@
 use async_trait::async_trait;
 use anyhow::Result;
+use chrono::NaiveDate;
+use serde::Serialize;
 
-use eris_db::Connection;
 use eris_data::{
+    Book,
+    Booking,
     Update,
     Insert,
     Member,
+    MEMBERSHIP_FEE_ACCOUNT,
     Transaction,
+    TransactionKind,
 };
 
-use crate::member_fees::MemberFee;
+use crate::{
+    datetime::AlignStart,
+    member_fees::{CalculateFees, MemberFee},
+};
 
 impl From<MemberFee> for Transaction {
     /// Convert a member fee into a transaction.
@@ -17,8 +25,9 @@ impl From<MemberFee> for Transaction {
         Transaction{
             amount: -fee.amount,
             date: fee.date,
-            account_name: "memberhip fee".to_string(),
+            account_name: MEMBERSHIP_FEE_ACCOUNT.to_string(),
             description: fee.describe(),
+            kind: TransactionKind::Fee,
             ..Default::default()
         }
     }
@@ -26,22 +35,27 @@ impl From<MemberFee> for Transaction {
 
 #[async_trait]
 pub trait ApplyTransaction {
-    async fn apply_transaction(
+    async fn apply_transaction<DB>(
         self,
-        db: &Connection,
+        db: &DB,
         tx: Transaction,
-    ) -> Result<Member>;
+    ) -> Result<Member>
+    where
+        DB: Insert<Transaction> + Update<Member> + Sync;
 }
 
 #[async_trait]
 impl ApplyTransaction for Member {
     /// Apply a transaction and update the member's
     /// account balance.
-    async fn apply_transaction(
+    async fn apply_transaction<DB>(
         self,
-        db: &Connection,
+        db: &DB,
         tx: Transaction,
-    ) -> Result<Member> {
+    ) -> Result<Member>
+    where
+        DB: Insert<Transaction> + Update<Member> + Sync,
+    {
         let mut member = self; 
         let tx = Transaction{
             member_id: member.id,
@@ -56,15 +70,51 @@ impl ApplyTransaction for Member {
     }
 }
 
+/// The fees booked for a member by an account calculation
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountCalculation {
+    pub member: Member,
+    pub fees: Vec<Transaction>,
+}
+
+/// Book the membership fees of a member until the month
+/// of the end date and update the account balance. Members
+/// without fees due are not changed. The fees and the
+/// balance are booked together, and fail if the account
+/// was calculated concurrently.
+pub async fn calculate_account<DB>(
+    db: &DB,
+    member: Member,
+    end: NaiveDate,
+) -> Result<AccountCalculation>
+where
+    DB: Book + Sync,
+{
+    let end = end.align_start();
+    let fees = member.calculate_fees(end);
+    if fees.is_empty() {
+        return Ok(AccountCalculation { member, fees: vec![] });
+    }
+
+    let (member, fees) = db.book(Booking {
+        member_id: member.id,
+        transactions: fees.into_iter().map(Transaction::from).collect(),
+        account_calculated_at: Some(end),
+        ..Default::default()
+    }).await?;
+
+    Ok(AccountCalculation { member, fees })
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use chrono::NaiveDate;
+    use eris_data::{MemoryDb, Retrieve};
 
     #[tokio::test]
     async fn test_apply_transaction() {
-        let db = Connection::open_test().await;
+        let db = MemoryDb::new();
         let member = db.insert(Member{
             account: 100.0,
             name: "test".to_string(),
@@ -73,7 +123,7 @@ mod tests {
 
         let tx = Transaction{
             amount: -23.42,
-            account_name: "memberhip fee".to_string(),
+            account_name: MEMBERSHIP_FEE_ACCOUNT.to_string(),
             description: "monthly membership fee for ...".to_string(),
             ..Default::default()
         };
@@ -89,7 +139,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_tx_from_fee() {
-        let db = Connection::open_test().await;
+        let db = MemoryDb::new();
         let member = db.insert(Member{
             account: 100.0,
             name: "test".to_string(),
@@ -109,4 +159,79 @@ mod tests {
         assert_eq!(txs.len(), 1);
         println!("txs: {:?}", txs);
     }
+    #[tokio::test]
+    async fn test_calculate_account() {
+        let db = MemoryDb::new();
+        let member = db.insert(Member{
+            name: "test".to_string(),
+            fee: 20.0,
+            membership_start: NaiveDate::from_ymd_opt(2023, 1, 15).unwrap(),
+            ..Default::default()
+        }).await.unwrap();
+
+        let end = NaiveDate::from_ymd_opt(2023, 3, 31).unwrap();
+        let calculation = calculate_account(&db, member, end).await.unwrap();
+        assert_eq!(calculation.fees.len(), 3);
+        assert_eq!(calculation.member.account, -60.0);
+        assert_eq!(
+            calculation.member.account_calculated_at,
+            NaiveDate::from_ymd_opt(2023, 3, 1).unwrap());
+
+        // Fees are only booked once
+        let calculation = calculate_account(&db, calculation.member, end)
+            .await.unwrap();
+        assert!(calculation.fees.is_empty());
+        assert_eq!(calculation.member.account, -60.0);
+    }
+
+    #[tokio::test]
+    async fn test_calculate_account_concurrently() {
+        let db = MemoryDb::new();
+        let member = db.insert(Member{
+            name: "test".to_string(),
+            fee: 20.0,
+            membership_start: NaiveDate::from_ymd_opt(2023, 1, 15).unwrap(),
+            ..Default::default()
+        }).await.unwrap();
+
+        // The balance changed since the member was read
+        let tx = Transaction {
+            amount: 50.0,
+            ..Default::default()
+        };
+        member.clone().apply_transaction(&db, tx).await.unwrap();
+        let end = NaiveDate::from_ymd_opt(2023, 3, 31).unwrap();
+        let calculation = calculate_account(&db, member.clone(), end)
+            .await.unwrap();
+        assert_eq!(calculation.member.account, -10.0);
+
+        // The fees were booked by another calculation
+        let err = calculate_account(&db, member, end).await.unwrap_err();
+        assert!(err.is::<eris_data::AlreadyCalculated>());
+        let member: Member = db.retrieve(calculation.member.id).await.unwrap();
+        assert_eq!(member.account, -10.0);
+    }
+
+    #[tokio::test]
+    async fn test_calculate_account_dry_run() {
+        let db = MemoryDb::new();
+        let member = db.insert(Member{
+            name: "test".to_string(),
+            fee: 20.0,
+            membership_start: NaiveDate::from_ymd_opt(2023, 1, 15).unwrap(),
+            ..Default::default()
+        }).await.unwrap();
+
+        // Fees are booked on a copy of the database
+        let dry_run = MemoryDb::load(&db).await.unwrap();
+        let end = NaiveDate::from_ymd_opt(2023, 3, 31).unwrap();
+        let calculation = calculate_account(&dry_run, member.clone(), end)
+            .await.unwrap();
+        assert_eq!(calculation.fees.len(), 3);
+        assert_eq!(calculation.member.account, -60.0);
+
+        let member: Member = db.retrieve(member.id).await.unwrap();
+        assert_eq!(member.account, 0.0);
+        assert!(member.get_transactions(&db).await.unwrap().is_empty());
+    }
 }
@@ -1,21 +1,32 @@
 use anyhow::Result;
 use chrono::NaiveDate;
+use serde::Serialize;
 use thiserror::Error as ThisError;
 
-use eris_db::Connection;
 use eris_data::{
+    Book,
+    Booking,
     Query,
     Insert,
+    Retrieve,
     Update,
     Transaction,
+    TransactionKind,
     BankImportRule,
     BankImportRuleFilter,
+    IbanHasher,
     Member,
+    find_payment_reference,
     MemberFilter,
+    Mandate,
+    MandateFilter,
+    Payout,
+    PayoutFilter,
+    PayoutState,
 };
-use eris_accounting::transactions::ApplyTransaction;
+use eris_accounting::mandates::MandateLifecycle;
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize)]
 pub struct BankTransaction {
     pub num: u32,
     pub date: NaiveDate,
@@ -35,6 +46,12 @@ pub enum BankImportError {
     #[error("insufficient amount for split transaction")]
     InsufficientAmountForSplit(BankTransaction),
 
+    #[error("no pending payout matches the outgoing transaction")]
+    PayoutMatchFailed(BankTransaction),
+
+    #[error("several pending payouts match the outgoing transaction")]
+    AmbiguousPayoutMatch(BankTransaction),
+
     #[error("a more recent transaction ({0}) is present in database")]
     MoreRecentTransactionPresent(String),
 
@@ -62,15 +79,29 @@ impl BankTransaction {
         Ok(())
     }
     
-    /// Lookup member by account name and create a default rule
-    async fn make_default_rule(
+    /// Lookup member by the payment reference in the subject
+    /// or by account name and create a default rule
+    async fn make_default_rule<DB>(
         &self,
-        db: &Connection,
-    ) -> Result<BankImportRule, BankImportError> {
-        let members: Vec<Member> = db.query(&MemberFilter{
-            name: Some(self.name.clone()),
-            ..Default::default()
-        }).await?;
+        db: &DB,
+        hasher: &IbanHasher,
+    ) -> Result<BankImportRule, BankImportError>
+    where
+        DB: Query<Member, Filter = MemberFilter> + Insert<BankImportRule>,
+    {
+        let mut members: Vec<Member> = vec![];
+        if let Some(id) = find_payment_reference(&self.subject) {
+            members = db.query(&MemberFilter{
+                id: Some(id),
+                ..Default::default()
+            }).await?;
+        }
+        if members.is_empty() {
+            members = db.query(&MemberFilter{
+                name: Some(self.name.clone()),
+                ..Default::default()
+            }).await?;
+        }
         let member = if members.len() == 1 {
             Ok(members[0].clone())
         } else {
@@ -84,26 +115,99 @@ impl BankTransaction {
         // Create bank import rule
         let rule = db.insert(BankImportRule{
             member_id: member.id,
-            iban: self.iban.clone(),
+            iban: hasher.protect(&self.iban, &self.name),
             ..Default::default()
         }).await?;
 
         Ok(rule)
     }
 
-    /// Import bank transaction into database
-    pub async fn import(self, db: &Connection) -> Result<(), BankImportError>
+    /// Find the bank import rules for the account. Rules
+    /// may store the IBAN in plain text or hashed.
+    async fn find_rules<DB>(
+        &self,
+        db: &DB,
+        hasher: &IbanHasher,
+    ) -> Result<Vec<BankImportRule>, BankImportError>
+    where
+        DB: Query<BankImportRule, Filter = BankImportRuleFilter>,
     {
-        // Check if there is are bank import rules for the iban
-        let rules: Vec<BankImportRule> = db.query(&BankImportRuleFilter{
-            iban: Some(self.iban.clone()),
+        for iban in hasher.candidates(&self.iban, &self.name) {
+            let rules: Vec<BankImportRule> = db.query(&BankImportRuleFilter{
+                iban: Some(iban),
+                ..Default::default()
+            }).await?;
+            if !rules.is_empty() {
+                return Ok(rules);
+            }
+        }
+        Ok(vec![])
+    }
+
+    /// Record the collection of the direct debit mandate
+    /// referenced in the subject, so the mandate does not
+    /// expire and the next collection is recurring. Only
+    /// mandates of the members the transaction was booked
+    /// for are used.
+    async fn record_mandate_collection<DB>(
+        &self,
+        db: &DB,
+        member_ids: &[u32],
+    ) -> Result<Option<Mandate>, BankImportError>
+    where
+        DB: Query<Mandate, Filter = MandateFilter> + Update<Mandate>,
+    {
+        let Some(reference) = Mandate::find_reference(&self.subject) else {
+            return Ok(None);
+        };
+        let mandates: Vec<Mandate> = db.query(&MandateFilter{
+            reference: Some(reference),
             ..Default::default()
-        }).await?; 
+        }).await?;
+        let Some(mandate) = mandates.into_iter()
+            .find(|m| member_ids.contains(&m.member_id))
+        else {
+            return Ok(None);
+        };
+        if mandate.last_used_at.is_some_and(|date| date >= self.date) {
+            return Ok(Some(mandate));
+        }
+        match mandate.record_collection(self.date) {
+            Ok((mandate, _)) => Ok(Some(db.update(mandate).await?)),
+            Err(err) => {
+                println!("not recording the collection of {}: {}",
+                    self.subject, err);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Import bank transaction into database. New rules
+    /// are stored as configured by the hasher. Collections
+    /// of direct debit mandates are recorded. Each transaction
+    /// is booked together with the account of the member.
+    pub async fn import<DB>(
+        self,
+        db: &DB,
+        hasher: &IbanHasher,
+    ) -> Result<(), BankImportError>
+    where
+        DB: Query<Member, Filter = MemberFilter>
+            + Retrieve<Member, Key = u32>
+            + Book
+            + Query<BankImportRule, Filter = BankImportRuleFilter>
+            + Insert<BankImportRule>
+            + Query<Mandate, Filter = MandateFilter>
+            + Update<Mandate>
+            + Sync,
+    {
+        // Check if there is are bank import rules for the iban
+        let rules = self.find_rules(db, hasher).await?;
         
         // If there are no rules, we make up a default rule
         // for a member with the same name as the account.
         let rules = if rules.is_empty() {
-            vec![self.make_default_rule(db).await?]
+            vec![self.make_default_rule(db, hasher).await?]
         } else {
             rules
         };
@@ -134,35 +238,43 @@ impl BankTransaction {
             // In case we have a split transaction, we have to deduce
             // the amount from the total amount
             let mut amount = total_amount;
-            let mut subject = self.subject.clone();
+            let mut kind = TransactionKind::Bank;
             if let Some(split_amount) = rule.split_amount {
                 if split_amount > total_amount {
                     return Err(BankImportError::InsufficientAmountForSplit(
                         self.clone()));
                 }
                 amount = split_amount;
-                subject += " (split)";
+                kind = TransactionKind::Split;
             }
 
             // Make transaction and queue application
             let tx = Transaction{
                 date: self.date,
-                amount: amount,
+                amount,
                 account_name: self.name.clone(),
                 description: self.subject.clone(),
+                kind,
                 ..Default::default()
             };
             transactions.push((member, tx, self.num));
             total_amount -= amount;
         }
     
-        // Apply transactions to member accounts
+        // Record the collection of a direct debit mandate
+        let member_ids: Vec<u32> = transactions.iter()
+            .map(|(member, _, _)| member.id)
+            .collect();
+        self.record_mandate_collection(db, &member_ids).await?;
+
+        // Book transactions on member accounts
         for (member, tx, num) in transactions {
-            let mut member = member.apply_transaction(
-                db, tx.clone()).await?;
-            member.last_bank_transaction_at = tx.date;
-            member.last_bank_transaction_number = num;
-            db.update(member).await?;
+            db.book(Booking {
+                member_id: member.id,
+                last_bank_transaction: Some((tx.date, num)),
+                transactions: vec![tx],
+                ..Default::default()
+            }).await?;
         }
     
         if total_amount <= 0.0 {
@@ -173,31 +285,81 @@ impl BankTransaction {
         // apply to the first rule.
         if let Some(rule) = rules.first() {
             let subject = format!("{} (overflow)", self.subject);
-            let member = rule.get_member(db).await?;
             let tx = Transaction{
                 date: self.date,
                 amount: total_amount,
                 account_name: self.name.clone(),
-                description: subject, 
+                description: subject,
+                kind: TransactionKind::Overflow,
                 ..Default::default()
             };
-            member.apply_transaction(db, tx).await?;
+            db.book(Booking {
+                member_id: rule.member_id,
+                transactions: vec![tx],
+                ..Default::default()
+            }).await?;
         }
 
         Ok(())
     }
+
+    /// Match an outgoing bank transaction with a pending payout
+    /// and mark the payout as settled. Outgoing transactions
+    /// which are not payouts are ignored. If several payouts
+    /// match and none is referenced in the subject, the match
+    /// is ambiguous and nothing is settled.
+    pub async fn settle_payout<DB>(
+        self,
+        db: &DB,
+    ) -> Result<Option<Payout>, BankImportError>
+    where
+        DB: Query<Payout, Filter = PayoutFilter> + Update<Payout>,
+    {
+        let payouts: Vec<Payout> = db.query(&PayoutFilter{
+            iban: Some(self.iban.clone()),
+            state: Some(PayoutState::Pending),
+            ..Default::default()
+        }).await?;
+        let amount = -self.amount;
+        let candidates: Vec<Payout> = payouts.into_iter()
+            .filter(|p| (p.amount - amount).abs() < 0.005)
+            .collect();
+
+        // Prefer the payout referenced in the subject
+        let referenced = candidates.iter()
+            .find(|p| self.subject.contains(&p.end_to_end_id()));
+        let payout = match (referenced, candidates.as_slice()) {
+            (Some(payout), _) | (None, [payout]) => payout.clone(),
+            (None, []) => return Ok(None),
+            (None, _) => {
+                return Err(BankImportError::AmbiguousPayoutMatch(self));
+            }
+        };
+
+        let payout = db.update(Payout{
+            settled_at: Some(self.date),
+            ..payout
+        }).await?;
+        Ok(Some(payout))
+    }
 }
 
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use eris_data::{TransactionFilter, Retrieve};
-    use eris_db::Connection;
+    use eris_accounting::mandates::SequenceType;
+    use eris_data::{
+        hash_iban,
+        IbanStorage,
+        MemoryDb,
+        PayoutKind,
+        TransactionFilter,
+    };
 
     #[tokio::test]
     async fn test_make_default_rule_member_match() {
-        let db = Connection::open_test().await;
+        let db = MemoryDb::new();
         // Insert test member and try to derive a rule
         let member = db.insert(Member{
             name: "Test Member".to_string(),
@@ -209,14 +371,36 @@ mod tests {
             ..Default::default()
         };
         // This should work because we have a matching member
-        let rule = tx.make_default_rule(&db).await.unwrap();
+        let rule = tx.make_default_rule(&db, &IbanHasher::default()).await.unwrap();
         assert_eq!(rule.member_id, member.id);
         assert_eq!(rule.iban, tx.iban);
     }
 
+    #[tokio::test]
+    async fn test_make_default_rule_payment_reference() {
+        let db = MemoryDb::new();
+        db.insert(Member{
+            name: "Test Member".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        let member = db.insert(Member{
+            name: "Other Member".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        // The account name does not match, the reference does
+        let tx = BankTransaction{
+            name: "Test Member".to_string(),
+            iban: "DE1231231111111111".to_string(),
+            subject: member.transfer_subject(),
+            ..Default::default()
+        };
+        let rule = tx.make_default_rule(&db, &IbanHasher::default()).await.unwrap();
+        assert_eq!(rule.member_id, member.id);
+    }
+
     #[tokio::test]
     async fn test_make_default_rule_member_no_match() {
-        let db = Connection::open_test().await;
+        let db = MemoryDb::new();
         // Insert test member and try to derive a rule
         db.insert(Member{
             name: "Test Member".to_string(),
@@ -228,7 +412,7 @@ mod tests {
             ..Default::default()
         };
         // This should work because we have a matching member
-        let rule = tx.make_default_rule(&db).await;
+        let rule = tx.make_default_rule(&db, &IbanHasher::default()).await;
         assert!(rule.is_err());
         match rule {
             Err(BankImportError::AccountMatchFailed(tx)) => {
@@ -240,7 +424,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_check_last_member_tx() {
-        let db = Connection::open_test().await;
+        let db = MemoryDb::new();
         let member = db.insert(Member{
             name: "Test Member".to_string(),
             last_bank_transaction_at: NaiveDate::from_ymd_opt(2023, 2, 3).unwrap(),
@@ -289,7 +473,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_import_bank_transaction() {
-        let db = Connection::open_test().await;
+        let db = MemoryDb::new();
         // Insert a testmember and a transaction
         let member = db.insert(Member{
             name: "Test Member".to_string(),
@@ -307,7 +491,7 @@ mod tests {
         };
 
         // Import the transaction
-        tx.clone().import(&db).await.unwrap();
+        tx.clone().import(&db, &IbanHasher::default()).await.unwrap();
 
         let member: Member = db.retrieve(member.id).await.unwrap();
         assert_eq!(member.account, 23.0);
@@ -315,9 +499,36 @@ mod tests {
         assert_eq!(member.last_bank_transaction_number, tx.num);
     }
 
+    #[tokio::test]
+    async fn test_import_bank_transaction_twice() {
+        let db = MemoryDb::new();
+        let member = db.insert(Member{
+            name: "Test Member".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        let tx = BankTransaction{
+            num: 42,
+            name: "Test Member".to_string(),
+            iban: "DE1111111111111".to_string(),
+            amount: 23.0,
+            date: NaiveDate::from_ymd_opt(2023, 5, 10).unwrap(),
+            subject: "Test Transaction".to_string(),
+        };
+        tx.clone().import(&db, &IbanHasher::default()).await.unwrap();
+
+        let member: Member = db.retrieve(member.id).await.unwrap();
+        assert_eq!(member.account, 23.0);
+        assert_eq!(member.last_bank_transaction_number, tx.num);
+        let rules = member.get_bank_import_rules(&db).await.unwrap();
+        assert_eq!(rules.len(), 1);
+
+        // The same transaction is not imported twice
+        assert!(tx.import(&db, &IbanHasher::default()).await.is_err());
+    }
+
     #[tokio::test]
     async fn test_import_bank_transaction_split_iban() {
-        let db = Connection::open_test().await;
+        let db = MemoryDb::new();
         let m1 = db.insert(Member{
             name: "Test Member".to_string(),
             ..Default::default()
@@ -353,7 +564,7 @@ mod tests {
         };
 
         // Import the transaction
-        tx.import(&db).await.unwrap();
+        tx.import(&db, &IbanHasher::default()).await.unwrap();
 
         // There should now be three transactions:
         let tx: Vec<Transaction> = db.query(&TransactionFilter{
@@ -361,6 +572,15 @@ mod tests {
         }).await.unwrap();
         assert_eq!(tx.len(), 3);
 
+        // The kind is stored, the subject is kept
+        let kinds: Vec<TransactionKind> = tx.iter().map(|tx| tx.kind).collect();
+        assert_eq!(kinds, vec![
+            TransactionKind::Split,
+            TransactionKind::Split,
+            TransactionKind::Overflow,
+        ]);
+        assert_eq!(tx[0].description, "Mitgliedsbeitrag fuer beide");
+
         // M1 balance should be 10 + 2 overflow
         let m1: Member = db.retrieve(m1.id).await.unwrap();
         assert_eq!(m1.account, 12.0);
@@ -369,4 +589,166 @@ mod tests {
         let m2: Member = db.retrieve(m2.id).await.unwrap();
         assert_eq!(m2.account, 20.0);
     }
+
+    #[tokio::test]
+    async fn test_import_bank_transaction_hashed_iban() {
+        let db = MemoryDb::new();
+        let member = db.insert(Member{
+            name: "Eris".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        db.insert(BankImportRule{
+            member_id: member.id,
+            iban: hash_iban("DE2342", "Eris Discordia"),
+            ..Default::default()
+        }).await.unwrap();
+
+        // The account name does not match the member, so
+        // only the hashed rule can resolve the member.
+        let tx = BankTransaction{
+            num: 1,
+            name: "Eris Discordia".to_string(),
+            iban: "DE2342".to_string(),
+            amount: 23.0,
+            date: NaiveDate::from_ymd_opt(2023, 5, 10).unwrap(),
+            subject: "Mitgliedsbeitrag".to_string(),
+        };
+        tx.import(&db, &IbanHasher::default()).await.unwrap();
+
+        let member: Member = db.retrieve(member.id).await.unwrap();
+        assert_eq!(member.account, 23.0);
+    }
+
+    #[tokio::test]
+    async fn test_import_records_mandate_collection() {
+        let db = MemoryDb::new();
+        let member = db.insert(Member{
+            name: "Eris".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        let reference = Mandate::make_reference(member.id, 1);
+        let mandate = db.insert(Mandate{
+            member_id: member.id,
+            reference: reference.clone(),
+            iban: "DE2342".to_string(),
+            signed_at: NaiveDate::from_ymd_opt(2023, 1, 1).unwrap(),
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(mandate.sequence_type(), SequenceType::First);
+
+        let date = NaiveDate::from_ymd_opt(2023, 5, 10).unwrap();
+        let tx = BankTransaction{
+            num: 1,
+            name: "Eris".to_string(),
+            iban: "DE2342".to_string(),
+            amount: 23.0,
+            date,
+            subject: format!("Mitgliedsbeitrag MREF {}", reference),
+        };
+        tx.import(&db, &IbanHasher::default()).await.unwrap();
+
+        let mandate: Mandate = db.retrieve(mandate.id).await.unwrap();
+        assert_eq!(mandate.last_used_at, Some(date));
+        assert_eq!(mandate.sequence_type(), SequenceType::Recurring);
+    }
+
+    #[tokio::test]
+    async fn test_import_bank_transaction_hmac_rule() {
+        let db = MemoryDb::new();
+        let hasher = IbanHasher::new(IbanStorage::Hmac, Some("fnord"))
+            .unwrap();
+        let member = db.insert(Member{
+            name: "Eris".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        let tx = BankTransaction{
+            num: 1,
+            name: "Eris".to_string(),
+            iban: "DE2342".to_string(),
+            amount: 23.0,
+            date: NaiveDate::from_ymd_opt(2023, 5, 10).unwrap(),
+            subject: "Mitgliedsbeitrag".to_string(),
+        };
+        tx.clone().import(&db, &hasher).await.unwrap();
+
+        // The rule only stores the HMAC of the IBAN
+        let rules = member.get_bank_import_rules(&db).await.unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].iban, hasher.protect("DE2342", "Eris"));
+
+        // The next transaction is matched by the rule, even
+        // if the account name differs.
+        let tx = BankTransaction{
+            num: 2,
+            name: "E. Discordia".to_string(),
+            ..tx
+        };
+        tx.import(&db, &hasher).await.unwrap();
+        let member: Member = db.retrieve(member.id).await.unwrap();
+        assert_eq!(member.account, 46.0);
+    }
+
+    #[tokio::test]
+    async fn test_settle_payout() {
+        let db = MemoryDb::new();
+        let member = db.insert(Member{
+            name: "Test Member".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        let date = NaiveDate::from_ymd_opt(2023, 5, 10).unwrap();
+        let mut payouts = vec![];
+        for _ in 0..2 {
+            let payout = db.insert(Payout{
+                member_id: member.id,
+                kind: PayoutKind::Refund,
+                iban: "DE2342".to_string(),
+                amount: 23.0,
+                exported_at: Some(date),
+                ..Default::default()
+            }).await.unwrap();
+            payouts.push(payout);
+        }
+
+        // Unrelated outgoing transaction
+        let tx = BankTransaction{
+            iban: "DE2342".to_string(),
+            amount: -42.0,
+            ..Default::default()
+        };
+        assert!(tx.settle_payout(&db).await.unwrap().is_none());
+
+        // Both payouts match, but none is referenced
+        let tx = BankTransaction{
+            iban: "DE2342".to_string(),
+            amount: -23.0,
+            subject: "refund".to_string(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            tx.settle_payout(&db).await,
+            Err(BankImportError::AmbiguousPayoutMatch(_))));
+
+        // The second payout is referenced in the subject
+        let tx = BankTransaction{
+            iban: "DE2342".to_string(),
+            amount: -23.0,
+            date: NaiveDate::from_ymd_opt(2023, 5, 12).unwrap(),
+            subject: format!("{} refund", payouts[1].end_to_end_id()),
+            ..Default::default()
+        };
+        let payout = tx.settle_payout(&db).await.unwrap().unwrap();
+        assert_eq!(payout.id, payouts[1].id);
+        assert_eq!(payout.state(), PayoutState::Settled);
+        assert_eq!(payout.settled_at, NaiveDate::from_ymd_opt(2023, 5, 12));
+
+        // The remaining payout is the only match
+        let tx = BankTransaction{
+            iban: "DE2342".to_string(),
+            amount: -23.0,
+            subject: "refund".to_string(),
+            ..Default::default()
+        };
+        let payout = tx.settle_payout(&db).await.unwrap().unwrap();
+        assert_eq!(payout.id, payouts[0].id);
+    }
 }
@@ -0,0 +1,304 @@
+use std::fmt::Write;
+
+use anyhow::Result;
+use chrono::{NaiveDate, NaiveDateTime};
+
+use eris_data::{
+    Book,
+    Booking,
+    Payout,
+    PayoutKind,
+    Transaction,
+    TransactionKind,
+    Update,
+};
+
+/// The account of the association, money is
+/// transferred from this account.
+#[derive(Debug, Default, Clone)]
+pub struct Debtor {
+    pub name: String,
+    pub iban: String,
+    pub bic: Option<String>,
+}
+
+/// A batch of SEPA credit transfers (pain.001)
+#[derive(Debug, Clone)]
+pub struct CreditTransferBatch {
+    pub message_id: String,
+    pub created_at: NaiveDateTime,
+    pub execution_date: NaiveDate,
+    pub debtor: Debtor,
+    pub payouts: Vec<Payout>,
+}
+
+/// Escape a string for use in XML text nodes and limit
+/// its length to what the SEPA schema allows.
+fn xml_text(text: &str, max_len: usize) -> String {
+    text.chars()
+        .take(max_len)
+        .collect::<String>()
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Format an amount with exactly two decimals
+fn xml_amount(amount: f64) -> String {
+    format!("{:.2}", amount)
+}
+
+impl CreditTransferBatch {
+    /// Create a new batch. The message id is derived
+    /// from the creation time.
+    pub fn new(
+        debtor: Debtor,
+        created_at: NaiveDateTime,
+        execution_date: NaiveDate,
+        payouts: Vec<Payout>,
+    ) -> Self {
+        let message_id =
+            format!("ERIS-{}", created_at.format("%Y%m%d%H%M%S"));
+        Self {
+            message_id,
+            created_at,
+            execution_date,
+            debtor,
+            payouts,
+        }
+    }
+
+    /// The sum of all transfers in the batch
+    pub fn control_sum(&self) -> f64 {
+        self.payouts.iter().map(|p| p.amount).sum()
+    }
+
+    /// Render the batch as pain.001.001.09 XML document
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::new();
+        let num = self.payouts.len();
+        let sum = xml_amount(self.control_sum());
+        let debtor_name = xml_text(&self.debtor.name, 70);
+
+        // These writes into a string can not fail.
+        writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+        writeln!(xml, concat!(
+            r#"<Document xmlns="urn:iso:std:iso:20022:tech:xsd:"#,
+            r#"pain.001.001.09">"#,
+        )).unwrap();
+        writeln!(xml, "  <CstmrCdtTrfInitn>").unwrap();
+        writeln!(xml, "    <GrpHdr>").unwrap();
+        writeln!(xml, "      <MsgId>{}</MsgId>",
+            xml_text(&self.message_id, 35)).unwrap();
+        writeln!(xml, "      <CreDtTm>{}</CreDtTm>",
+            self.created_at.format("%Y-%m-%dT%H:%M:%S")).unwrap();
+        writeln!(xml, "      <NbOfTxs>{}</NbOfTxs>", num).unwrap();
+        writeln!(xml, "      <CtrlSum>{}</CtrlSum>", sum).unwrap();
+        writeln!(xml, "      <InitgPty><Nm>{}</Nm></InitgPty>",
+            debtor_name).unwrap();
+        writeln!(xml, "    </GrpHdr>").unwrap();
+        writeln!(xml, "    <PmtInf>").unwrap();
+        writeln!(xml, "      <PmtInfId>{}</PmtInfId>",
+            xml_text(&self.message_id, 35)).unwrap();
+        writeln!(xml, "      <PmtMtd>TRF</PmtMtd>").unwrap();
+        writeln!(xml, "      <BtchBookg>true</BtchBookg>").unwrap();
+        writeln!(xml, "      <NbOfTxs>{}</NbOfTxs>", num).unwrap();
+        writeln!(xml, "      <CtrlSum>{}</CtrlSum>", sum).unwrap();
+        writeln!(xml,
+            "      <PmtTpInf><SvcLvl><Cd>SEPA</Cd></SvcLvl></PmtTpInf>"
+        ).unwrap();
+        writeln!(xml, "      <ReqdExctnDt><Dt>{}</Dt></ReqdExctnDt>",
+            self.execution_date.format("%Y-%m-%d")).unwrap();
+        writeln!(xml, "      <Dbtr><Nm>{}</Nm></Dbtr>", debtor_name).unwrap();
+        writeln!(xml, "      <DbtrAcct><Id><IBAN>{}</IBAN></Id></DbtrAcct>",
+            xml_text(&self.debtor.iban, 34)).unwrap();
+        match &self.debtor.bic {
+            Some(bic) => writeln!(xml, concat!(
+                "      <DbtrAgt><FinInstnId><BICFI>{}</BICFI>",
+                "</FinInstnId></DbtrAgt>"), xml_text(bic, 11)).unwrap(),
+            None => writeln!(xml, concat!(
+                "      <DbtrAgt><FinInstnId><Othr><Id>NOTPROVIDED</Id>",
+                "</Othr></FinInstnId></DbtrAgt>")).unwrap(),
+        }
+        writeln!(xml, "      <ChrgBr>SLEV</ChrgBr>").unwrap();
+
+        for payout in &self.payouts {
+            writeln!(xml, "      <CdtTrfTxInf>").unwrap();
+            writeln!(xml, "        <PmtId><EndToEndId>{}</EndToEndId></PmtId>",
+                xml_text(&payout.end_to_end_id(), 35)).unwrap();
+            writeln!(xml, concat!(
+                r#"        <Amt><InstdAmt Ccy="EUR">{}</InstdAmt>"#,
+                "</Amt>"), xml_amount(payout.amount)).unwrap();
+            writeln!(xml, "        <Cdtr><Nm>{}</Nm></Cdtr>",
+                xml_text(&payout.name, 70)).unwrap();
+            writeln!(xml,
+                "        <CdtrAcct><Id><IBAN>{}</IBAN></Id></CdtrAcct>",
+                xml_text(&payout.iban, 34)).unwrap();
+            writeln!(xml, "        <RmtInf><Ustrd>{}</Ustrd></RmtInf>",
+                xml_text(&payout.subject, 140)).unwrap();
+            writeln!(xml, "      </CdtTrfTxInf>").unwrap();
+        }
+
+        writeln!(xml, "    </PmtInf>").unwrap();
+        writeln!(xml, "  </CstmrCdtTrfInitn>").unwrap();
+        writeln!(xml, "</Document>").unwrap();
+        xml
+    }
+
+    /// Book the payouts on the member accounts and mark
+    /// them as pending until the transfer shows up in a
+    /// bank import. The transactions of a payout are booked
+    /// together with the account balance.
+    pub async fn book<DB>(self, db: &DB) -> Result<Self>
+    where
+        DB: Book + Update<Payout> + Sync,
+    {
+        let date = self.created_at.date();
+        let mut payouts = vec![];
+        for payout in self.payouts {
+            let mut transactions = vec![];
+
+            // Expenses are credited first, so the payout
+            // does not affect the membership fee balance.
+            if payout.kind == PayoutKind::Reimbursement {
+                transactions.push(Transaction {
+                    date,
+                    amount: payout.amount,
+                    account_name: payout.name.clone(),
+                    description: format!(
+                        "Reimbursement of expenses: {}", payout.subject),
+                    kind: TransactionKind::Payout,
+                    ..Default::default()
+                });
+            }
+            let kind = match payout.kind {
+                PayoutKind::Refund => TransactionKind::Refund,
+                PayoutKind::Reimbursement => TransactionKind::Payout,
+            };
+            transactions.push(Transaction {
+                date,
+                amount: -payout.amount,
+                account_name: payout.name.clone(),
+                description: format!(
+                    "{} {}: {}",
+                    payout.end_to_end_id(), payout.kind, payout.subject),
+                kind,
+                ..Default::default()
+            });
+            db.book(Booking {
+                member_id: payout.member_id,
+                transactions,
+                ..Default::default()
+            }).await?;
+
+            let payout = db.update(Payout {
+                batch_id: Some(self.message_id.clone()),
+                exported_at: Some(date),
+                ..payout
+            }).await?;
+            payouts.push(payout);
+        }
+        Ok(Self { payouts, ..self })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eris_data::{Insert, Member, MemoryDb, PayoutState, Retrieve};
+
+    fn batch(payouts: Vec<Payout>) -> CreditTransferBatch {
+        let created_at = NaiveDate::from_ymd_opt(2023, 5, 10)
+            .unwrap()
+            .and_hms_opt(12, 23, 42)
+            .unwrap();
+        CreditTransferBatch::new(
+            Debtor {
+                name: "Chaos & Co e.V.".to_string(),
+                iban: "DE02120300000000202051".to_string(),
+                bic: None,
+            },
+            created_at,
+            NaiveDate::from_ymd_opt(2023, 5, 11).unwrap(),
+            payouts,
+        )
+    }
+
+    #[test]
+    fn test_credit_transfer_xml() {
+        let batch = batch(vec![
+            Payout {
+                id: 1,
+                name: "Eris Discordia".to_string(),
+                iban: "DE89370400440532013000".to_string(),
+                amount: 12.5,
+                subject: "Refund <balance>".to_string(),
+                ..Default::default()
+            },
+            Payout {
+                id: 2,
+                name: "Test Member".to_string(),
+                iban: "DE89370400440532013001".to_string(),
+                amount: 30.0,
+                subject: "Mate".to_string(),
+                ..Default::default()
+            },
+        ]);
+        assert_eq!(batch.message_id, "ERIS-20230510122342");
+        assert_eq!(batch.control_sum(), 42.5);
+
+        let xml = batch.to_xml();
+        assert!(xml.contains("<NbOfTxs>2</NbOfTxs>"));
+        assert!(xml.contains("<CtrlSum>42.50</CtrlSum>"));
+        assert!(xml.contains("<Nm>Chaos &amp; Co e.V.</Nm>"));
+        assert!(xml.contains("<ReqdExctnDt><Dt>2023-05-11</Dt>"));
+        assert!(xml.contains("<EndToEndId>ERIS-PAYOUT-000001</EndToEndId>"));
+        assert!(xml.contains(r#"<InstdAmt Ccy="EUR">12.50</InstdAmt>"#));
+        assert!(xml.contains("<Ustrd>Refund &lt;balance&gt;</Ustrd>"));
+        assert!(xml.contains("<Id>NOTPROVIDED</Id>"));
+    }
+
+    #[tokio::test]
+    async fn test_credit_transfer_book() {
+        let db = MemoryDb::new();
+        let m1 = db.insert(Member {
+            name: "Test Member".to_string(),
+            account: 20.0,
+            ..Default::default()
+        }).await.unwrap();
+        let m2 = db.insert(Member {
+            name: "Best Member".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+
+        let refund = db.insert(Payout {
+            member_id: m1.id,
+            kind: PayoutKind::Refund,
+            amount: 20.0,
+            ..Default::default()
+        }).await.unwrap();
+        let reimbursement = db.insert(Payout {
+            member_id: m2.id,
+            kind: PayoutKind::Reimbursement,
+            amount: 42.0,
+            ..Default::default()
+        }).await.unwrap();
+
+        let batch = batch(vec![refund, reimbursement]);
+        let batch = batch.book(&db).await.unwrap();
+        for payout in &batch.payouts {
+            assert_eq!(payout.state(), PayoutState::Pending);
+            assert_eq!(payout.batch_id, Some(batch.message_id.clone()));
+        }
+
+        // The refund clears the balance, the reimbursement
+        // does not change it.
+        let m1: Member = db.retrieve(m1.id).await.unwrap();
+        assert_eq!(m1.account, 0.0);
+        let m2: Member = db.retrieve(m2.id).await.unwrap();
+        assert_eq!(m2.account, 0.0);
+        assert_eq!(m2.get_transactions(&db).await.unwrap().len(), 2);
+    }
+}
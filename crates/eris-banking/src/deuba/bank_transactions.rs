@@ -8,12 +8,21 @@ use encoding_rs_io::DecodeReaderBytesBuilder;
 
 use crate::{deuba::Language, BankTransaction};
 
+/// Incoming transactions are credited, outgoing
+/// transactions are debited from the account.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Direction {
+    Incoming,
+    Outgoing,
+}
+
 impl BankTransaction {
     /// Decode a CSV row into a BankTransaction.
     fn from_record(
         num: u32,
         lang: &Language,
         record: &StringRecord,
+        direction: Direction,
     ) -> Result<Option<Self>> {
         if record.len() < 18 {
             return Ok(None);
@@ -41,19 +50,23 @@ impl BankTransaction {
         let subject = &record[4];
         let iban = &record[5];
 
-        if &record[16] == "" {
+        let amount = match direction {
+            Direction::Incoming => &record[16],
+            Direction::Outgoing => &record[15],
+        };
+        if amount.is_empty() {
             return Ok(None);
         }
 
-        let amount = lang.parse_number(&record[16])?;
+        let amount = lang.parse_number(amount)?;
 
         Ok(Some(Self {
-            num: num,
+            num,
             date: booking_date,
             name: name.to_string(),
             iban: iban.to_string(),
             subject: subject.to_string(),
-            amount: amount,
+            amount,
         }))
     }
 }
@@ -61,6 +74,19 @@ impl BankTransaction {
 /// Parse a Deutsche Bank CSV export.
 /// Only incoming transactions are considered.
 pub fn parse(file: &mut File) -> Result<Vec<BankTransaction>> {
+    parse_records(file, Direction::Incoming)
+}
+
+/// Parse the outgoing transactions of a Deutsche Bank
+/// CSV export. The amounts are negative.
+pub fn parse_outgoing(file: &mut File) -> Result<Vec<BankTransaction>> {
+    parse_records(file, Direction::Outgoing)
+}
+
+fn parse_records(
+    file: &mut File,
+    direction: Direction,
+) -> Result<Vec<BankTransaction>> {
     let lang = Language::from_file(file)?;
     let transcoder = DecodeReaderBytesBuilder::new()
         .encoding(Some(WINDOWS_1252))
@@ -76,7 +102,8 @@ pub fn parse(file: &mut File) -> Result<Vec<BankTransaction>> {
     // I'm sure there is a more elegant way to do this
     for result in rdr.records() {
         counter += 1;
-        let tx = BankTransaction::from_record(counter, &lang, &result?)?;
+        let tx = BankTransaction::from_record(
+            counter, &lang, &result?, direction)?;
         if let Some(tx) = tx {
             transactions.push(tx);
         }
@@ -101,4 +128,17 @@ mod tests {
         let txs = parse(&mut file).unwrap();
         assert_eq!(txs.len(), 5);
     }
+
+    #[test]
+    fn test_parse_outgoing() {
+        let mut file = File::open("test/konto_en.csv").unwrap();
+        let txs = parse_outgoing(&mut file).unwrap();
+        assert_eq!(txs.len(), 2);
+        assert_eq!(txs[0].amount, -1111.11);
+
+        let mut file = File::open("test/konto_de.csv").unwrap();
+        let txs = parse_outgoing(&mut file).unwrap();
+        assert_eq!(txs.len(), 2);
+        assert_eq!(txs[1].amount, -20.11);
+    }
 }
@@ -0,0 +1,216 @@
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+
+use eris_data::{
+    is_hashed_iban,
+    BankImportRule,
+    BankImportRuleFilter,
+    Delete,
+    IbanHasher,
+    IbanStorage,
+    Insert,
+    Member,
+    Query,
+    Retrieve,
+    Transaction,
+    TransactionFilter,
+    TransactionKind,
+};
+
+/// A plain text rule and the value it will be stored as
+#[derive(Debug, Clone)]
+pub struct ProtectedRule {
+    pub rule: BankImportRule,
+    pub iban: String,
+}
+
+/// The plain text rules which can be protected, and those
+/// whose account holder is not known.
+#[derive(Debug, Clone, Default)]
+pub struct ProtectionPlan {
+    pub protected: Vec<ProtectedRule>,
+    pub unknown_holder: Vec<BankImportRule>,
+}
+
+/// Get the account name the IBAN of a rule is hashed with.
+/// Bank transactions do not record the IBAN, so the name is
+/// only known if the member has this single rule and all of
+/// their bank transactions come from one account.
+async fn account_name<DB>(
+    db: &DB,
+    member: &Member,
+    rules: &[BankImportRule],
+) -> Result<Option<String>>
+where
+    DB: Query<Transaction, Filter = TransactionFilter>,
+{
+    if rules.iter().filter(|r| r.member_id == member.id).count() != 1 {
+        return Ok(None);
+    }
+    let transactions = member.get_transactions(db).await?;
+    let mut names: BTreeSet<String> = transactions.into_iter()
+        .filter(|tx| matches!(
+            tx.kind,
+            TransactionKind::Bank
+                | TransactionKind::Split
+                | TransactionKind::Overflow))
+        .map(|tx| tx.account_name)
+        .collect();
+    if names.len() != 1 {
+        return Ok(None);
+    }
+    Ok(names.pop_first())
+}
+
+/// Find all rules storing a plain IBAN and derive the value
+/// they should be stored as. Rules which need the name of
+/// an unknown account holder are not protected.
+pub async fn plan_protection<DB>(
+    db: &DB,
+    hasher: &IbanHasher,
+) -> Result<ProtectionPlan>
+where
+    DB: Query<BankImportRule, Filter = BankImportRuleFilter>
+        + Query<Transaction, Filter = TransactionFilter>
+        + Retrieve<Member, Key = u32>,
+{
+    let mut plan = ProtectionPlan::default();
+    if hasher.storage() == IbanStorage::Plain {
+        return Ok(plan);
+    }
+    let rules: Vec<BankImportRule> =
+        db.query(&BankImportRuleFilter::default()).await?;
+
+    for rule in rules.iter().filter(|r| !is_hashed_iban(&r.iban)) {
+        let name = if hasher.needs_account_name() {
+            let member: Member = db.retrieve(rule.member_id).await?;
+            account_name(db, &member, &rules).await?
+        } else {
+            Some(String::new())
+        };
+        match name {
+            Some(name) => plan.protected.push(ProtectedRule {
+                iban: hasher.protect(&rule.iban, &name),
+                rule: rule.clone(),
+            }),
+            None => plan.unknown_holder.push(rule.clone()),
+        }
+    }
+    Ok(plan)
+}
+
+/// Replace the plain text rules
+pub async fn apply_protection<DB>(
+    db: &DB,
+    rules: &[ProtectedRule],
+) -> Result<()>
+where
+    DB: Query<BankImportRule, Filter = BankImportRuleFilter>
+        + Insert<BankImportRule>
+        + Delete<BankImportRule>,
+{
+    for ProtectedRule { rule, iban } in rules {
+        let existing: Vec<BankImportRule> = db.query(&BankImportRuleFilter {
+            member_id: Some(rule.member_id),
+            iban: Some(iban.clone()),
+        }).await?;
+        if existing.is_empty() {
+            db.insert(BankImportRule {
+                iban: iban.clone(),
+                ..rule.clone()
+            }).await?;
+        }
+        db.delete(rule.clone()).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::NaiveDate;
+    use eris_data::{hash_iban, MemoryDb};
+
+    #[tokio::test]
+    async fn test_protect_rules() {
+        let db = MemoryDb::new();
+        let member = db.insert(Member {
+            name: "Eris".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        db.insert(Transaction {
+            member_id: member.id,
+            date: NaiveDate::from_ymd_opt(2023, 5, 10).unwrap(),
+            account_name: "Eris Discordia".to_string(),
+            description: "Mitgliedsbeitrag".to_string(),
+            amount: 20.0,
+            kind: TransactionKind::Bank,
+            ..Default::default()
+        }).await.unwrap();
+        db.insert(BankImportRule {
+            member_id: member.id,
+            iban: "DE12345678901234567890".to_string(),
+            split_amount: Some(10.0),
+            ..Default::default()
+        }).await.unwrap();
+
+        // Nothing to do when storing plain IBANs
+        let plain = IbanHasher::default();
+        assert!(plan_protection(&db, &plain).await.unwrap().protected.is_empty());
+
+        let hasher = IbanHasher::new(IbanStorage::Hash, None).unwrap();
+        let plan = plan_protection(&db, &hasher).await.unwrap();
+        assert_eq!(plan.protected.len(), 1);
+        assert_eq!(
+            plan.protected[0].iban,
+            hash_iban("DE12345678901234567890", "Eris Discordia"));
+        apply_protection(&db, &plan.protected).await.unwrap();
+
+        let rules = member.get_bank_import_rules(&db).await.unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].iban, "448a2be23338");
+        assert_eq!(rules[0].split_amount, Some(10.0));
+
+        // Hashed rules are kept
+        let plan = plan_protection(&db, &hasher).await.unwrap();
+        assert!(plan.protected.is_empty());
+        assert!(plan.unknown_holder.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_protect_rules_unknown_holder() {
+        let db = MemoryDb::new();
+        let member = db.insert(Member {
+            name: "Eris".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        db.insert(Transaction {
+            member_id: member.id,
+            account_name: "Eris Discordia".to_string(),
+            amount: 20.0,
+            kind: TransactionKind::Bank,
+            ..Default::default()
+        }).await.unwrap();
+        // The account of a partner
+        for iban in ["DE1111", "DE2222"] {
+            db.insert(BankImportRule {
+                member_id: member.id,
+                iban: iban.to_string(),
+                ..Default::default()
+            }).await.unwrap();
+        }
+
+        let hasher = IbanHasher::new(IbanStorage::Hash, None).unwrap();
+        let plan = plan_protection(&db, &hasher).await.unwrap();
+        assert!(plan.protected.is_empty());
+        assert_eq!(plan.unknown_holder.len(), 2);
+
+        // HMACs do not need the account holder
+        let hasher = IbanHasher::new(IbanStorage::Hmac, Some("fnord")).unwrap();
+        let plan = plan_protection(&db, &hasher).await.unwrap();
+        assert_eq!(plan.protected.len(), 2);
+        assert!(plan.unknown_holder.is_empty());
+    }
+}
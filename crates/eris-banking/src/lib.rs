@@ -1,4 +1,9 @@
 mod bank_transaction;
 pub use bank_transaction::{BankImportError, BankTransaction};
 
+mod credit_transfer;
+pub use credit_transfer::{CreditTransferBatch, Debtor};
+
 pub mod deuba;
+
+pub mod iban_rules;
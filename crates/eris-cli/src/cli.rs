@@ -1,16 +1,65 @@
-use clap::{Parser, Subcommand};
-use anyhow::Result;
+use std::{path::PathBuf, sync::Arc};
 
-use eris_db::Connection;
+use clap::{builder::BoolishValueParser, ArgAction, Parser, Subcommand};
+use anyhow::{anyhow, Result};
+use inquire::Password;
 
-use crate::commands::{Accounting, Bank, Members};
+use eris_data::{
+    Access,
+    Authorized,
+    Principal,
+    Query,
+    Resource,
+    Role,
+    User,
+    UserFilter,
+};
+use eris_db::{Connection, DatabaseKey};
+
+use crate::{
+    commands::{Accounting, Audit, Bank, Members, Users},
+    output::{Context, OutputFormat},
+};
 
 #[derive(Parser, Debug)]
 #[clap(name = "eris", version=env!("CARGO_PKG_VERSION"))]
 pub struct Cli {
+    /// The SQLite database file or a postgres:// URL
     #[clap(long, default_value = "members.sqlite3")]
     pub members_db: String,
 
+    /// Passphrase of an encrypted database
+    #[clap(long, env = "ERIS_DB_KEY", hide = true, hide_env_values = true)]
+    pub db_key: Option<String>,
+
+    /// Read the passphrase of an encrypted database from a file
+    #[clap(long, env = "ERIS_DB_KEYFILE")]
+    pub db_keyfile: Option<PathBuf>,
+
+    /// Ask for the passphrase of an encrypted database
+    #[clap(long)]
+    pub encrypted: bool,
+
+    /// One of table, json, csv or yaml
+    #[clap(long, global = true, env = "ERIS_OUTPUT",
+           default_value_t = OutputFormat::Table)]
+    pub output: OutputFormat,
+
+    /// Name recorded in the audit log, defaults to the login name
+    #[clap(long, global = true, env = "ERIS_ACTOR")]
+    pub actor: Option<String>,
+
+    /// Run with the permissions of this user. Without a
+    /// user, all commands are allowed.
+    #[clap(long, global = true, env = "ERIS_USER")]
+    pub user: Option<String>,
+
+    /// Do not ask for confirmations
+    #[clap(short, long, global = true, visible_alias = "non-interactive",
+           env = "ERIS_NON_INTERACTIVE", action = ArgAction::SetTrue,
+           value_parser = BoolishValueParser::new())]
+    pub yes: bool,
+
     #[clap(subcommand)]
     pub command: Command,
 }
@@ -20,11 +69,58 @@ impl Cli {
         Self::parse()
     }
 
-    pub async fn run(self, db: &Connection) -> Result<()> {
+    /// Get the database key from the keyfile, the
+    /// environment or a prompt.
+    pub fn database_key(&self) -> Result<Option<DatabaseKey>> {
+        let key = DatabaseKey::resolve(
+            self.db_key.as_deref(), self.db_keyfile.as_deref())?;
+        if key.is_some() || !self.encrypted {
+            return Ok(key);
+        }
+        let passphrase = Password::new("Database passphrase:")
+            .without_confirmation()
+            .prompt()?;
+        Ok(Some(DatabaseKey::new(&passphrase)?))
+    }
+
+    /// Check the user may run the command and get the
+    /// principal its operations are checked against.
+    /// Without a user, everything is allowed.
+    pub async fn authorize(&self, db: &Connection) -> Result<Principal> {
+        let Some(name) = &self.user else {
+            return Ok(Principal {
+                role: Role::Treasurer,
+                member_id: None,
+            });
+        };
+        let user: User = db.query(&UserFilter {
+            name: Some(name.clone()),
+            ..Default::default()
+        }).await?.pop().ok_or_else(|| anyhow!("unknown user: {}", name))?;
+        if user.role == Role::Member {
+            return Err(anyhow!(
+                "user {} is a member and can only use the member portal",
+                user.name));
+        }
+        let principal = Principal::from(&user);
+        let (resource, access) = self.command.access();
+        principal.check(resource, access)?;
+        Ok(principal)
+    }
+
+    pub async fn run(self, conn: Arc<Connection>) -> Result<()> {
+        let principal = self.authorize(&conn).await?;
+        let db = &Authorized::new(conn, principal);
+        let ctx = Context {
+            output: self.output,
+            yes: self.yes,
+        };
         match self.command {
-            Command::Members(cmd) => cmd.run(&db).await,
-            Command::Accounting(cmd) => cmd.run(&db).await,
-            Command::Bank(cmd) => cmd.run(&db).await,
+            Command::Members(cmd) => cmd.run(db, &ctx).await,
+            Command::Accounting(cmd) => cmd.run(db, &ctx).await,
+            Command::Bank(cmd) => cmd.run(db, &ctx).await,
+            Command::Audit(cmd) => cmd.run(db, &ctx).await,
+            Command::Users(cmd) => cmd.run(db, &ctx).await,
         }
     }
 }
@@ -42,4 +138,82 @@ pub enum Command {
     #[clap(subcommand, name = "bank")]
     /// Import bank transactions and manage IBAN rules
     Bank(Bank),
+
+    #[clap(subcommand, name = "audit")]
+    /// Show the audit log of changes
+    Audit(Audit),
+
+    #[clap(subcommand, name = "users")]
+    /// Manage users and their roles
+    Users(Users),
+}
+
+impl Command {
+    /// The access needed to run the command
+    pub fn access(&self) -> (Resource, Access) {
+        match self {
+            Command::Members(cmd) => cmd.access(),
+            Command::Accounting(cmd) => cmd.access(),
+            Command::Bank(cmd) => cmd.access(),
+            Command::Audit(cmd) => cmd.access(),
+            Command::Users(cmd) => cmd.access(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use eris_data::{Insert, Member, PermissionDenied};
+
+    #[test]
+    fn test_non_interactive_env() {
+        let parse = || Cli::try_parse_from(["eris", "members", "list"]).unwrap();
+        for (value, yes) in [("1", true), ("true", true), ("0", false), ("no", false)] {
+            std::env::set_var("ERIS_NON_INTERACTIVE", value);
+            assert_eq!(parse().yes, yes, "ERIS_NON_INTERACTIVE={}", value);
+        }
+        std::env::remove_var("ERIS_NON_INTERACTIVE");
+        assert!(!parse().yes);
+        assert!(Cli::try_parse_from(["eris", "-y", "members", "list"]).unwrap().yes);
+    }
+
+    #[tokio::test]
+    async fn test_board_gets_no_audit_log() {
+        let conn = Arc::new(Connection::open_test().await);
+        let member = conn.insert(Member {
+            name: "Eris".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        conn.insert(User {
+            name: "board".to_string(),
+            role: Role::Board,
+            ..Default::default()
+        }).await.unwrap();
+
+        // The board may read members, but the export includes
+        // the audit log of the member
+        let file = std::env::temp_dir()
+            .join(format!("discordia_gdpr_{}.json", member.id));
+        let id = member.id.to_string();
+        let args = [
+            "eris", "--user", "board", "members", "gdpr", "export",
+            "--id", &id, "--file", file.to_str().unwrap(),
+        ];
+        let err = Cli::try_parse_from(args).unwrap()
+            .run(conn.clone()).await.unwrap_err();
+        assert!(err.is::<PermissionDenied>(), "{}", err);
+        assert!(!file.exists());
+
+        // Without a user, the audit log is exported
+        let cli = Cli::try_parse_from(
+            args.iter().filter(|arg| !["--user", "board"].contains(arg)))
+            .unwrap();
+        cli.run(conn).await.unwrap();
+        let json = std::fs::read_to_string(&file).unwrap();
+        std::fs::remove_file(&file).unwrap();
+        let data: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert!(!data["audit_log"].as_array().unwrap().is_empty());
+    }
 }
@@ -4,23 +4,23 @@ use chrono::NaiveDate;
 use inquire::Confirm;
 use clap::{Subcommand, Args};
 
-use eris_db::Connection;
+use super::Database;
 use eris_data::{
-    Update,
-    Transaction,
+    Access,
+    Member,
     MemberFilter,
     Query,
-    Member,
+    Resource,
 };
 use eris_accounting::{
-    transactions::ApplyTransaction,
-    member_fees::{
-        CalculateFees,
-    },
+    transactions::{calculate_account, AccountCalculation},
     datetime::{AlignStart, last_month},
 };
 
-use crate::commands::Transactions;
+use crate::{
+    commands::{Dunning, Receipts, Reports, ShowStatement, Transactions},
+    output::Context,
+};
 
 
 #[derive(Subcommand, Debug)]
@@ -32,13 +32,45 @@ pub enum Accounting {
     /// Manage transactions
     #[clap(subcommand)]
     Transactions(Transactions),
+
+    /// Dunning of members in arrears
+    #[clap(subcommand)]
+    Dunning(Dunning),
+
+    /// Show the account statement of a member
+    #[clap(name = "statement")]
+    Statement(ShowStatement),
+
+    /// Donation receipts (Zuwendungsbestätigungen)
+    #[clap(subcommand)]
+    Receipts(Receipts),
+
+    /// Financial reports
+    #[clap(subcommand, name = "report")]
+    Report(Reports),
 }
 
 impl Accounting {
-    pub async fn run(self, db: &Connection) -> Result<()> {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
         match self {
-            Accounting::Calculate(cmd) => cmd.run(db).await,
-            Accounting::Transactions(cmd) => cmd.run(db).await,
+            Accounting::Calculate(cmd) => cmd.run(db, ctx).await,
+            Accounting::Transactions(cmd) => cmd.run(db, ctx).await,
+            Accounting::Dunning(cmd) => cmd.run(db, ctx).await,
+            Accounting::Statement(cmd) => cmd.run(db).await,
+            Accounting::Receipts(cmd) => cmd.run(db, ctx).await,
+            Accounting::Report(cmd) => cmd.run(db).await,
+        }
+    }
+
+    /// The access needed to run the command
+    pub fn access(&self) -> (Resource, Access) {
+        match self {
+            Accounting::Calculate(_) => (Resource::Transaction, Access::Write),
+            Accounting::Transactions(_)
+            | Accounting::Statement(_)
+            | Accounting::Report(_) => (Resource::Transaction, Access::Read),
+            Accounting::Dunning(cmd) => cmd.access(),
+            Accounting::Receipts(cmd) => cmd.access(),
         }
     }
 }
@@ -53,15 +85,14 @@ pub struct CalculateAccounts {
 
 impl CalculateAccounts {
     /// Run the account calculations
-    pub async fn run(self, db: &Connection) -> Result<()> {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
         // Get current state
         let end = self.until.align_start();
 
         // Confirm calculation
-        let ok = Confirm::new(&format!(
+        let ok = ctx.confirm(Confirm::new(&format!(
                 "Calculate account balances until {}?",
-                end.format("%Y-%m")))
-            .prompt()?;
+                end.format("%Y-%m"))))?;
         if !ok {
             return Ok(());
         }
@@ -69,41 +100,27 @@ impl CalculateAccounts {
         // Calculate fees for each members
         let members: Vec<Member> = db.query(
             &MemberFilter::default()).await?;
-        for mut member in members {
-            let fees = member.calculate_fees(end);
-            if fees.is_empty() {
-                continue; // nothing to do here.
-            }
-
-            let transactions: Vec<Transaction> = fees.into_iter()
-                .map(|fee| fee.into())
-                .collect();
-            let num = transactions.len();
-            let total = transactions.iter()
-                .map(|t| t.amount)
-                .sum::<f64>();
-
-
+        for member in members {
             let start = std::cmp::max(
                 member.account_calculated_at,
                 member.membership_start,
             );
             let start = start.align_start().format("%Y-%m");
+
+            let AccountCalculation { member, fees } =
+                calculate_account(db, member, end).await?;
+            if fees.is_empty() {
+                continue; // nothing to do here.
+            }
+            let total = fees.iter()
+                .map(|t| t.amount)
+                .sum::<f64>();
             println!(
                 "{}: fees since {} for {} month: {}€",
                 member.name,
                 start,
-                num,
+                fees.len(),
                 total);
-
-            // Apply transactions
-            for tx in transactions {
-                member = member.apply_transaction(db, tx).await?;
-            }
-            // Update state
-            member.account_calculated_at = end;
-            member = db.update(member).await?;
-
             println!("Current balance: {}€", member.account);
             println!();
         }
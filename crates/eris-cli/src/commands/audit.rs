@@ -0,0 +1,58 @@
+use anyhow::Result;
+use clap::{Args, Subcommand};
+
+use eris_data::{
+    Access,
+    AuditAction,
+    AuditEntity,
+    AuditEntry,
+    AuditEntryFilter,
+    Query,
+    Resource,
+};
+use super::Database;
+
+use crate::output::Context;
+
+#[derive(Subcommand, Debug)]
+pub enum Audit {
+    /// List changes to members, rules and transactions
+    List(ListAuditEntries),
+}
+
+impl Audit {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        match self {
+            Audit::List(cmd) => cmd.run(db, ctx).await,
+        }
+    }
+
+    /// The access needed to run the command
+    pub fn access(&self) -> (Resource, Access) {
+        (Resource::AuditLog, Access::Read)
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ListAuditEntries {
+    #[clap(long)]
+    pub member_id: Option<u32>,
+    /// One of member, bank_import_rule, transaction or user
+    #[clap(long)]
+    pub entity: Option<AuditEntity>,
+    /// One of insert, update or delete
+    #[clap(long)]
+    pub action: Option<AuditAction>,
+}
+
+impl ListAuditEntries {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        let entries: Vec<AuditEntry> = db.query(&AuditEntryFilter {
+            member_id: self.member_id,
+            entity: self.entity,
+            action: self.action,
+            ..Default::default()
+        }).await?;
+        ctx.print_list(&entries)
+    }
+}
@@ -1,9 +1,10 @@
 use std::fs::File; 
 
-use anyhow::{Result};
+use anyhow::{anyhow, Result};
 use chrono::{NaiveDate};
 use clap::{Args, Subcommand};
 use inquire::Confirm;
+use serde::Serialize;
 
 use eris_data::{
     Query,
@@ -13,15 +14,26 @@ use eris_data::{
     Delete,
     BankImportRule,
     BankImportRuleFilter,
+    IbanHasher,
+    IbanStorage,
+    Member,
+    Payout,
+    Access,
+    Resource,
 };
-use eris_db::Connection;
+use super::Database;
 use eris_banking::{
     deuba::bank_transactions,
+    iban_rules,
     BankTransaction,
     BankImportError,
 };
 
-use crate::formatting::PrintFormatted;
+use crate::{
+    commands::Payouts,
+    formatting::PrintFormatted,
+    output::{Context, OutputFormat, PartialFailure},
+};
 
 #[derive(Subcommand, Debug)]
 pub enum Bank {
@@ -30,22 +42,58 @@ pub enum Bank {
 
     /// IBAN rules
     #[clap(subcommand)]
-    Iban(Iban)
+    Iban(Iban),
+
+    /// Refunds and reimbursements
+    #[clap(subcommand)]
+    Payout(Payouts),
 }
 
 impl Bank {
-    pub async fn run(self, conn: &Connection) -> Result<()> {
+    pub async fn run(self, conn: &Database, ctx: &Context) -> Result<()> {
+        match self {
+            Bank::Import(import) => import.run(conn, ctx).await,
+            Bank::Iban(iban) => iban.run(conn, ctx).await,
+            Bank::Payout(payout) => payout.run(conn, ctx).await,
+        }
+    }
+
+    /// The access needed to run the command
+    pub fn access(&self) -> (Resource, Access) {
         match self {
-            Bank::Import(import) => import.run(conn).await,
-            Bank::Iban(iban) => iban.run(conn).await,
+            Bank::Import(_) => (Resource::Transaction, Access::Write),
+            Bank::Iban(cmd) => cmd.access(),
+            Bank::Payout(cmd) => cmd.access(),
         }
     }
 }
 
+/// How the IBANs of new bank import rules are stored
+#[derive(Args, Debug)]
+pub struct IbanArgs {
+    /// One of plain, hash or hmac. Defaults to the storage
+    /// of the database.
+    #[clap(long, env = "ERIS_IBAN_STORAGE")]
+    pub iban_storage: Option<IbanStorage>,
+    /// The secret for storing IBANs as HMAC
+    #[clap(long, env = "ERIS_IBAN_SECRET", hide_env_values = true)]
+    pub iban_secret: Option<String>,
+}
+
+impl IbanArgs {
+    pub async fn hasher(&self, db: &Database) -> Result<IbanHasher> {
+        db.database()
+            .iban_hasher(self.iban_storage, self.iban_secret.as_deref())
+            .await
+    }
+}
+
 #[derive(Args, Debug)]
 pub struct BankImport {
     #[clap(short, long)]
     pub file: String,
+    #[clap(flatten)]
+    pub iban: IbanArgs,
 }
 
 /// Get first and last date from transactions
@@ -55,7 +103,7 @@ fn get_first_and_last_date(
     let mut first = NaiveDate::from_ymd_opt(9999, 1, 1).unwrap();
     let mut last = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
     for tx in transactions {
-        let date = tx.date.clone();
+        let date = tx.date;
         if last < date {
             last = date;
         }
@@ -66,29 +114,53 @@ fn get_first_and_last_date(
     Ok((first, last))
 }
 
+/// A transaction which could not be imported
+#[derive(Serialize, Debug)]
+struct FailedImport {
+    transaction: BankTransaction,
+    error: String,
+}
+
+/// The outcome of an import for machine-readable output
+#[derive(Serialize, Debug, Default)]
+struct ImportReport {
+    imported: Vec<BankTransaction>,
+    settled: Vec<Payout>,
+    failed: Vec<FailedImport>,
+}
+
 impl BankImport {
-    pub async fn run(self, db: &Connection) -> Result<()> {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        let hasher = self.iban.hasher(db).await?;
+
         // Open CSV file
         let mut file = File::open(&self.file)?; 
         let transactions = bank_transactions::parse(&mut file)?;
+        let outgoing = bank_transactions::parse_outgoing(&mut file)?;
 
         // Get first and last date from transactions
-        let (first_date, last_date) = get_first_and_last_date(&transactions)?;
-        let ok = Confirm::new(&format!(
+        let (first_date, last_date) = get_first_and_last_date(
+            &[transactions.as_slice(), outgoing.as_slice()].concat())?;
+        let ok = ctx.confirm(Confirm::new(&format!(
             "Import transactions from {} to {}?",
             first_date,
             last_date,
-        )).prompt()?;
+        )))?;
         if !ok {
             return Ok(());
         }
 
         // Run import
+        let total = transactions.len() + outgoing.len();
+        let mut report = ImportReport::default();
         let mut failed_tx: Vec<(BankTransaction, BankImportError)> = vec![];
         for tx in transactions {
-            match tx.clone().import(db).await {
+            match tx.clone().import(db, &hasher).await {
                 Ok(()) => {
-                    tx.print_formatted();
+                    if ctx.is_table() {
+                        tx.print_formatted();
+                    }
+                    report.imported.push(tx);
                 },
                 Err(e) => {
                     failed_tx.push((tx, e));
@@ -96,16 +168,50 @@ impl BankImport {
             } 
         }
 
-        if !failed_tx.is_empty() {
+        // Settle pending payouts
+        for tx in outgoing {
+            match tx.clone().settle_payout(db).await {
+                Ok(Some(payout)) => {
+                    if ctx.is_table() {
+                        tx.print_formatted();
+                        println!("Settled payout {}", payout.end_to_end_id());
+                    }
+                    report.settled.push(payout);
+                },
+                Ok(None) => {}, // not a payout
+                Err(e) => {
+                    failed_tx.push((tx, e));
+                }
+            }
+        }
+
+        if !ctx.is_table() {
+            report.failed = failed_tx.iter()
+                .map(|(transaction, e)| FailedImport {
+                    transaction: transaction.clone(),
+                    error: e.to_string(),
+                })
+                .collect();
+            match ctx.output {
+                OutputFormat::Csv => ctx.print_records(&report.imported)?,
+                _ => ctx.print_serialized(&report)?,
+            };
+        } else if !failed_tx.is_empty() {
             println!();
             println!("Failed to import transactions:");
-            for (tx, e) in failed_tx {
+            for (tx, e) in &failed_tx {
                 println!();
                 tx.print_formatted();
                 println!("{}", e);
             }
         }
-        
+
+        if !failed_tx.is_empty() {
+            return Err(PartialFailure {
+                failed: failed_tx.len(),
+                total,
+            }.into());
+        }
         Ok(())
     }
 }
@@ -126,15 +232,28 @@ pub enum Iban {
     /// Remove a rule
     #[clap(name = "delete")]
     Delete(IbanRemove),
+
+    /// Convert plain text IBANs to the configured storage
+    #[clap(name = "protect")]
+    Protect(IbanProtect),
 }
 
 impl Iban {
-    pub async fn run(self, conn: &Connection) -> Result<()> {
+    pub async fn run(self, conn: &Database, ctx: &Context) -> Result<()> {
+        match self {
+            Iban::List(list) => list.run(conn, ctx).await,
+            Iban::Add(add) => add.run(conn, ctx).await,
+            Iban::Update(update) => update.run(conn, ctx).await,
+            Iban::Delete(delete) => delete.run(conn, ctx).await,
+            Iban::Protect(protect) => protect.run(conn, ctx).await,
+        }
+    }
+
+    /// The access needed to run the command
+    pub fn access(&self) -> (Resource, Access) {
         match self {
-            Iban::List(list) => list.run(conn).await,
-            Iban::Add(add) => add.run(conn).await,
-            Iban::Update(update) => update.run(conn).await,
-            Iban::Delete(delete) => delete.run(conn).await,
+            Iban::List(_) => (Resource::BankImportRule, Access::Read),
+            _ => (Resource::BankImportRule, Access::Write),
         }
     }
 }
@@ -149,14 +268,13 @@ pub struct IbanList {
 }
 
 impl IbanList {
-    pub async fn run(self, db: &Connection) -> Result<()> {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
         let rules: Vec<BankImportRule> = db.query(&BankImportRuleFilter{
             member_id: self.member_id,
             iban: self.iban,
-            ..Default::default()
         }).await?;
 
-        rules.print_formatted();
+        ctx.print_list(&rules)?;
 
         Ok(())
     }
@@ -175,20 +293,31 @@ pub struct IbanAdd {
 
     #[clap(short, long)]
     pub match_subject: Option<String>,
+
+    /// Name of the account holder, used for hashing the IBAN.
+    /// Defaults to the name of the member.
+    #[clap(long)]
+    pub account_name: Option<String>,
+
+    #[clap(flatten)]
+    pub storage: IbanArgs,
 }
 
 impl IbanAdd {
-    pub async fn run(self, db: &Connection) -> Result<()> {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        let hasher = self.storage.hasher(db).await?;
+        let member: Member = db.retrieve(self.member_id).await?;
+        let account_name = self.account_name.unwrap_or(member.name);
         let rule = BankImportRule {
             member_id: self.member_id,
-            iban: self.iban,
+            iban: hasher.protect(&self.iban, &account_name),
             split_amount: self.split_amount,
             match_subject: self.match_subject,
         };
         println!();
         rule.print_formatted();
         println!();
-        let ok = Confirm::new("Add this rule?").prompt()?;
+        let ok = ctx.confirm(Confirm::new("Add this rule?"))?;
         if !ok {
             return Ok(());
         }
@@ -222,7 +351,7 @@ pub struct IbanUpdate {
 }
 
 impl IbanUpdate {
-    pub async fn run(self, db: &Connection) -> Result<()> {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
         // Get rule
         let rule: BankImportRule = db.retrieve(
             (self.member_id, self.iban)
@@ -241,7 +370,7 @@ impl IbanUpdate {
             }
         }
         if let Some(match_subject) = self.match_subject {
-            if match_subject == "" {
+            if match_subject.is_empty() {
                 update.match_subject = None;
             } else {
                 update.match_subject = Some(match_subject);
@@ -252,7 +381,7 @@ impl IbanUpdate {
         update.print_formatted();
         println!();
 
-        let ok = Confirm::new("Apply this update?").prompt()?;
+        let ok = ctx.confirm(Confirm::new("Apply this update?"))?;
         if !ok {
             return Ok(());
         }
@@ -274,7 +403,7 @@ pub struct IbanRemove {
 }
 
 impl IbanRemove {
-    pub async fn run(self, db: &Connection) -> Result<()> {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
         let rule: BankImportRule = db.retrieve(
             (self.member_id, self.iban)
         ).await?;
@@ -283,7 +412,7 @@ impl IbanRemove {
         rule.print_formatted();
         println!();
 
-        let ok = Confirm::new("Delete this rule?").prompt()?;
+        let ok = ctx.confirm(Confirm::new("Delete this rule?"))?;
         if !ok {
             return Ok(());
         }
@@ -293,3 +422,61 @@ impl IbanRemove {
         Ok(())
     }
 }
+
+
+#[derive(Args, Debug)]
+pub struct IbanProtect {
+    #[clap(flatten)]
+    pub storage: IbanArgs,
+}
+
+impl IbanProtect {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        let storage = match self.storage.iban_storage {
+            Some(storage) => storage,
+            None => db.database().iban_storage().await?,
+        };
+        let hasher = IbanHasher::new(
+            storage, self.storage.iban_secret.as_deref())?;
+        if hasher.storage() == IbanStorage::Plain {
+            return Err(anyhow!(
+                "set --iban-storage to hash or hmac to protect IBANs"));
+        }
+        let plan = iban_rules::plan_protection(db, &hasher).await?;
+        for rule in &plan.unknown_holder {
+            println!(
+                "Member {}: {} is kept, the account holder is not known",
+                rule.member_id,
+                rule.iban);
+        }
+        if !plan.unknown_holder.is_empty() {
+            println!("Add these rules again with --account-name to protect them.");
+            println!();
+        }
+        let plan = plan.protected;
+        if plan.is_empty() {
+            db.database().set_iban_storage(storage).await?;
+            println!("No more IBANs can be protected.");
+            return Ok(());
+        }
+        for protected in &plan {
+            println!(
+                "Member {}: {} -> {}",
+                protected.rule.member_id,
+                protected.rule.iban,
+                protected.iban);
+        }
+        println!();
+
+        let ok = ctx.confirm(Confirm::new(&format!(
+            "Replace {} plain text IBANs?", plan.len())))?;
+        if !ok {
+            return Ok(());
+        }
+        iban_rules::apply_protection(db, &plan).await?;
+        db.database().set_iban_storage(storage).await?;
+        println!("Protected {} rules.", plan.len());
+
+        Ok(())
+    }
+}
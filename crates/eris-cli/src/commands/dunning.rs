@@ -0,0 +1,134 @@
+use anyhow::Result;
+use chrono::NaiveDate;
+use clap::{Args, Subcommand};
+use inquire::Confirm;
+
+use eris_accounting::{
+    datetime,
+    dunning::{plan_dunning, DunningPolicy},
+};
+use eris_data::{
+    Access,
+    DunningEvent,
+    DunningEventFilter,
+    DunningLevel,
+    Insert,
+    Query,
+    Resource,
+};
+use super::Database;
+
+use crate::output::Context;
+
+#[derive(Subcommand, Debug)]
+pub enum Dunning {
+    /// Escalate members in arrears to the next dunning level
+    #[clap(name = "run")]
+    Run(RunDunning),
+    /// List recorded dunning events
+    #[clap(name = "list")]
+    List(ListDunning),
+}
+
+impl Dunning {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        match self {
+            Dunning::Run(cmd) => cmd.run(db, ctx).await,
+            Dunning::List(cmd) => cmd.run(db, ctx).await,
+        }
+    }
+
+    /// The access needed to run the command
+    pub fn access(&self) -> (Resource, Access) {
+        match self {
+            Dunning::Run(_) => (Resource::DunningEvent, Access::Write),
+            Dunning::List(_) => (Resource::DunningEvent, Access::Read),
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct RunDunning {
+    #[clap(short, long)]
+    pub date: Option<NaiveDate>,
+    /// Send a reminder when in arrears for this many monthly fees
+    #[clap(long, default_value_t = 1.0)]
+    pub reminder: f64,
+    /// Send the first notice when in arrears for this many monthly fees
+    #[clap(long, default_value_t = 3.0)]
+    pub first_notice: f64,
+    /// Send the final notice when in arrears for this many monthly fees
+    #[clap(long, default_value_t = 6.0)]
+    pub final_notice: f64,
+    /// Hand over to the board when in arrears for this many monthly fees
+    #[clap(long, default_value_t = 12.0)]
+    pub board_review: f64,
+}
+
+impl RunDunning {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        let date = self.date.unwrap_or(datetime::today());
+        let policy = DunningPolicy {
+            reminder: self.reminder,
+            first_notice: self.first_notice,
+            final_notice: self.final_notice,
+            board_review: self.board_review,
+        };
+
+        let due = plan_dunning(db, &policy, date).await?;
+        if due.is_empty() {
+            println!("No dunning levels changed.");
+            return Ok(());
+        }
+
+        for (member, event) in &due {
+            println!(
+                "{:>4}\t{:<24}\t{:>10.2}\t{}",
+                member.id, member.name, event.balance, event.level,
+            );
+        }
+        println!();
+
+        let ok = ctx.confirm(Confirm::new(&format!(
+            "Record {} dunning events for {}?", due.len(), date,
+        )))?;
+        if !ok {
+            return Ok(());
+        }
+
+        for (_, event) in due {
+            db.insert(event).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ListDunning {
+    #[clap(short, long)]
+    pub member_id: Option<u32>,
+    /// One of cleared, reminder, first_notice, final_notice
+    /// or board_review
+    #[clap(short, long)]
+    pub level: Option<DunningLevel>,
+    #[clap(short, long)]
+    pub after_date: Option<NaiveDate>,
+    #[clap(short, long)]
+    pub before_date: Option<NaiveDate>,
+}
+
+impl ListDunning {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        let events: Vec<DunningEvent> = db.query(&DunningEventFilter {
+            member_id: self.member_id,
+            level: self.level,
+            date_after: self.after_date,
+            date_before: self.before_date,
+            ..Default::default()
+        }).await?;
+        ctx.message(&format!("{} dunning events.", events.len()));
+        ctx.print_list(&events)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use inquire::Confirm;
+
+use eris_accounting::{datetime, gdpr::{self, PersonalData}};
+use eris_data::{Access, Member, Resource, Retrieve};
+use super::Database;
+
+use crate::{formatting::PrintFormatted, output::Context};
+
+#[derive(Subcommand, Debug)]
+pub enum Gdpr {
+    /// Export all personal data of a member as JSON
+    #[clap(name = "export")]
+    Export(ExportPersonalData),
+    /// Erase the personal data of a former member, keeping
+    /// the pseudonymised ledger
+    #[clap(name = "anonymise")]
+    Anonymise(AnonymiseMember),
+}
+
+impl Gdpr {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        match self {
+            Gdpr::Export(cmd) => cmd.run(db).await,
+            Gdpr::Anonymise(cmd) => cmd.run(db, ctx).await,
+        }
+    }
+
+    /// The access needed to run the command
+    pub fn access(&self) -> (Resource, Access) {
+        match self {
+            Gdpr::Export(_) => (Resource::Member, Access::Read),
+            Gdpr::Anonymise(_) => (Resource::Member, Access::Write),
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ExportPersonalData {
+    #[clap(short, long)]
+    pub id: u32,
+    /// Write the export to a file instead of stdout
+    #[clap(long)]
+    pub file: Option<PathBuf>,
+}
+
+impl ExportPersonalData {
+    pub async fn run(self, db: &Database) -> Result<()> {
+        let data = PersonalData::fetch(db, self.id).await?;
+        let json = serde_json::to_string_pretty(&data)?;
+        match self.file {
+            Some(file) => fs::write(file, json)?,
+            None => println!("{}", json),
+        }
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct AnonymiseMember {
+    #[clap(short, long)]
+    pub id: u32,
+}
+
+impl AnonymiseMember {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        let member: Member = db.retrieve(self.id).await?;
+        if ctx.is_table() {
+            println!();
+            member.print_formatted();
+            println!();
+        }
+        let confirm = Confirm::new(
+            "Erase the personal data of the member? This can not be undone.")
+            .with_default(false);
+        if !ctx.confirm(confirm)? {
+            return Ok(());
+        }
+        let member = gdpr::anonymise(db, self.id, datetime::today()).await?;
+        ctx.message(&format!(
+            "Member {} was anonymised as \"{}\"", member.id, member.name));
+        Ok(())
+    }
+}
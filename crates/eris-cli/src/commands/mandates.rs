@@ -0,0 +1,197 @@
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
+use clap::{Args, Subcommand};
+use inquire::Confirm;
+
+use eris_accounting::{
+    datetime,
+    mandates::{new_mandate, MandateLifecycle, MandateState},
+};
+use eris_data::{
+    Access,
+    Insert,
+    Mandate,
+    Member,
+    Resource,
+    Retrieve,
+    Update,
+};
+use super::Database;
+
+use crate::{formatting::PrintFormatted, output::Context};
+
+#[derive(Subcommand, Debug)]
+pub enum Mandates {
+    /// Add a direct debit mandate for a member
+    #[clap(name = "add")]
+    Add(AddMandate),
+    /// Show the mandates of a member
+    #[clap(name = "show")]
+    Show(ShowMandates),
+    /// Change the IBAN of the active mandate
+    #[clap(name = "amend")]
+    Amend(AmendMandate),
+    /// Revoke the active mandate
+    #[clap(name = "revoke")]
+    Revoke(RevokeMandate),
+}
+
+impl Mandates {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        match self {
+            Mandates::Add(cmd) => cmd.run(db, ctx).await,
+            Mandates::Show(cmd) => cmd.run(db, ctx).await,
+            Mandates::Amend(cmd) => cmd.run(db, ctx).await,
+            Mandates::Revoke(cmd) => cmd.run(db, ctx).await,
+        }
+    }
+
+    /// The access needed to run the command
+    pub fn access(&self) -> (Resource, Access) {
+        match self {
+            Mandates::Show(_) => (Resource::Mandate, Access::Read),
+            _ => (Resource::Mandate, Access::Write),
+        }
+    }
+}
+
+/// Get the mandate of a member which is active at a date
+async fn get_active_mandate(
+    db: &Database,
+    member: &Member,
+    date: NaiveDate,
+) -> Result<Mandate> {
+    member
+        .get_mandates(db)
+        .await?
+        .into_iter()
+        .find(|m| m.state(date) == MandateState::Active)
+        .ok_or(anyhow!("Member {} has no active mandate.", member.id))
+}
+
+#[derive(Args, Debug)]
+pub struct AddMandate {
+    #[clap(short, long)]
+    pub member_id: u32,
+    #[clap(short, long)]
+    pub iban: String,
+    /// Name of the account holder, defaults to the member name
+    #[clap(short, long)]
+    pub account_holder: Option<String>,
+    #[clap(short, long)]
+    pub signed_at: Option<NaiveDate>,
+}
+
+impl AddMandate {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        let member: Member = db.retrieve(self.member_id).await?;
+        let mandates = member.get_mandates(db).await?;
+        let signed_at = self.signed_at.unwrap_or(datetime::today());
+        let account_holder =
+            self.account_holder.unwrap_or(member.name.clone());
+
+        let mandate = new_mandate(
+            &member,
+            &mandates,
+            &self.iban,
+            &account_holder,
+            signed_at,
+        )?;
+
+        println!();
+        mandate.print_formatted();
+        println!();
+        let ok = ctx.confirm(Confirm::new("Add this mandate?")
+            .with_default(true))?;
+        if !ok {
+            return Ok(());
+        }
+
+        let mandate = db.insert(mandate).await?;
+        println!("Created mandate {}.", mandate.reference);
+
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ShowMandates {
+    #[clap(short, long)]
+    pub member_id: u32,
+}
+
+impl ShowMandates {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        let member: Member = db.retrieve(self.member_id).await?;
+        let mandates = member.get_mandates(db).await?;
+        ctx.message(&format!("{} mandates.", mandates.len()));
+        ctx.print_list(&mandates)?;
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct AmendMandate {
+    #[clap(short, long)]
+    pub member_id: u32,
+    #[clap(short, long)]
+    pub iban: String,
+    #[clap(short, long)]
+    pub date: Option<NaiveDate>,
+}
+
+impl AmendMandate {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        let date = self.date.unwrap_or(datetime::today());
+        let member: Member = db.retrieve(self.member_id).await?;
+        let mandate = get_active_mandate(db, &member, date).await?;
+        let update = mandate.clone().amend(&self.iban, date)?;
+
+        println!();
+        update.print_formatted();
+        println!();
+        let ok = ctx.confirm(Confirm::new(&format!(
+            "Change IBAN of mandate {} from {} to {}?",
+            mandate.reference, mandate.iban, update.iban,
+        )))?;
+        if !ok {
+            return Ok(());
+        }
+
+        db.update(update).await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct RevokeMandate {
+    #[clap(short, long)]
+    pub member_id: u32,
+    #[clap(short, long)]
+    pub date: Option<NaiveDate>,
+}
+
+impl RevokeMandate {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        let date = self.date.unwrap_or(datetime::today());
+        let member: Member = db.retrieve(self.member_id).await?;
+        let mandate = get_active_mandate(db, &member, date).await?;
+
+        println!();
+        mandate.print_formatted();
+        println!();
+        let ok = ctx.confirm(Confirm::new(&format!(
+            "Revoke mandate {} as of {}?",
+            mandate.reference, date,
+        )))?;
+        if !ok {
+            return Ok(());
+        }
+
+        let mandate = mandate.revoke(date)?;
+        db.update(mandate).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,422 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
+use clap::Args;
+use inquire::Confirm;
+use serde::{Deserialize, Serialize};
+
+use eris_accounting::datetime;
+use eris_data::{
+    is_hashed_iban,
+    AddMember,
+    BankImportRule,
+    BankImportRuleFilter,
+    Member,
+    MemberFilter,
+    MemberIbanRule,
+    MemberRecord,
+    Query,
+};
+use super::Database;
+
+use crate::{
+    commands::IbanArgs,
+    formatting::PrintFormatted,
+    output::{Context, OutputFormat, PartialFailure},
+};
+
+/// File format of member imports and exports
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RecordFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+impl RecordFormat {
+    /// Guess the format from the file extension
+    fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => RecordFormat::Json,
+            _ => RecordFormat::Csv,
+        }
+    }
+}
+
+impl fmt::Display for RecordFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordFormat::Csv => write!(f, "csv"),
+            RecordFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+impl FromStr for RecordFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "csv" => Ok(RecordFormat::Csv),
+            "json" => Ok(RecordFormat::Json),
+            _ => Err(anyhow!("unknown format: {}", s)),
+        }
+    }
+}
+
+/// A member as a flat CSV row. The bank import rules
+/// are encoded in a single column.
+#[derive(Debug, Serialize, Deserialize)]
+struct MemberRow {
+    #[serde(default)]
+    id: Option<u32>,
+    name: String,
+    email: String,
+    #[serde(default)]
+    notes: String,
+    #[serde(default)]
+    membership_start: Option<NaiveDate>,
+    #[serde(default)]
+    membership_end: Option<NaiveDate>,
+    #[serde(default)]
+    fee: Option<f64>,
+    #[serde(default)]
+    interval: Option<u8>,
+    #[serde(default)]
+    account: Option<f64>,
+    #[serde(default)]
+    archived_at: Option<NaiveDate>,
+    #[serde(default)]
+    bank_import_rules: String,
+}
+
+impl From<MemberRecord> for MemberRow {
+    fn from(record: MemberRecord) -> Self {
+        Self {
+            id: record.id,
+            name: record.name,
+            email: record.email,
+            notes: record.notes,
+            membership_start: record.membership_start,
+            membership_end: record.membership_end,
+            fee: record.fee,
+            interval: record.interval,
+            account: record.account,
+            archived_at: record.archived_at,
+            bank_import_rules: MemberIbanRule::format_list(
+                &record.bank_import_rules),
+        }
+    }
+}
+
+impl TryFrom<MemberRow> for MemberRecord {
+    type Error = anyhow::Error;
+
+    fn try_from(row: MemberRow) -> Result<Self> {
+        Ok(Self {
+            id: row.id,
+            name: row.name,
+            email: row.email,
+            notes: row.notes,
+            membership_start: row.membership_start,
+            membership_end: row.membership_end,
+            fee: row.fee,
+            interval: row.interval,
+            account: row.account,
+            archived_at: row.archived_at,
+            bank_import_rules: MemberIbanRule::parse_list(
+                &row.bank_import_rules)?,
+        })
+    }
+}
+
+/// Read the records of a file. Rows which can not be
+/// parsed are returned as errors, so they can be reported
+/// with the invalid records.
+fn read_records(
+    path: &Path,
+    format: RecordFormat,
+) -> Result<Vec<Result<MemberRecord>>> {
+    match format {
+        RecordFormat::Csv => {
+            let mut reader = csv::Reader::from_path(path)?;
+            Ok(reader.deserialize::<MemberRow>()
+                .map(|row| row
+                    .map_err(anyhow::Error::from)
+                    .and_then(MemberRecord::try_from))
+                .collect())
+        }
+        RecordFormat::Json => {
+            let records: Vec<MemberRecord> =
+                serde_json::from_str(&fs::read_to_string(path)?)?;
+            Ok(records.into_iter().map(Ok).collect())
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ImportMembers {
+    /// CSV or JSON file as written by the export
+    #[clap(short, long)]
+    pub file: PathBuf,
+    /// One of csv or json, defaults to the file extension
+    #[clap(long)]
+    pub format: Option<RecordFormat>,
+    /// Only validate the file
+    #[clap(long)]
+    pub dry_run: bool,
+    #[clap(flatten)]
+    pub iban: IbanArgs,
+}
+
+/// A row which was not imported
+#[derive(Serialize, Debug)]
+struct FailedRow {
+    /// Position in the file, starting at 1
+    row: usize,
+    email: String,
+    errors: Vec<String>,
+}
+
+/// A rule stored in plain text, as hashing its IBAN
+/// needs the unknown name of the account holder
+#[derive(Serialize, Debug)]
+struct UnprotectedRule {
+    row: usize,
+    iban: String,
+}
+
+/// The outcome of an import for machine-readable output
+#[derive(Serialize, Debug, Default)]
+struct ImportReport {
+    dry_run: bool,
+    imported: Vec<Member>,
+    failed: Vec<FailedRow>,
+    unprotected: Vec<UnprotectedRule>,
+}
+
+impl ImportMembers {
+    /// Run the command and import members from a file
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        let hasher = self.iban.hasher(db).await?;
+        let format = self.format
+            .unwrap_or_else(|| RecordFormat::from_path(&self.file));
+        let records = read_records(&self.file, format)?;
+        let total = records.len();
+
+        // Like when adding a single member, email addresses
+        // must be unique. This includes the file itself.
+        let members: Vec<Member> = db.query(&MemberFilter {
+            include_archived: true,
+            ..Default::default()
+        }).await?;
+        let mut emails: HashSet<String> = members.iter()
+            .map(|m| m.email.to_lowercase())
+            .collect();
+
+        let today = datetime::today();
+        let mut report = ImportReport {
+            dry_run: self.dry_run,
+            ..Default::default()
+        };
+        let mut valid: Vec<(usize, Member, Vec<BankImportRule>)> = vec![];
+        for (i, record) in records.into_iter().enumerate() {
+            let row = i + 1;
+            let record = match record {
+                Ok(record) => record,
+                Err(e) => {
+                    report.failed.push(FailedRow {
+                        row,
+                        email: "".to_string(),
+                        errors: vec![e.to_string()],
+                    });
+                    continue;
+                }
+            };
+            let mut errors = record.validate();
+            let email = record.email.trim().to_lowercase();
+            if !email.is_empty() && !emails.insert(email) {
+                errors.push(format!(
+                    "Member with email {} already exists.", record.email));
+            }
+            if !errors.is_empty() {
+                report.failed.push(FailedRow {
+                    row,
+                    email: record.email,
+                    errors,
+                });
+                continue;
+            }
+            let (member, rules) = record.into_member(today);
+            let rules = rules.into_iter()
+                .map(|(rule, holder)| match holder {
+                    Some(holder) => BankImportRule {
+                        iban: hasher.protect(&rule.iban, &holder),
+                        ..rule
+                    },
+                    None if !hasher.needs_account_name()
+                        || is_hashed_iban(&rule.iban) => BankImportRule {
+                        iban: hasher.protect(&rule.iban, ""),
+                        ..rule
+                    },
+                    // Kept until the holder is known
+                    None => {
+                        report.unprotected.push(UnprotectedRule {
+                            row,
+                            iban: rule.iban.clone(),
+                        });
+                        rule
+                    }
+                })
+                .collect();
+            valid.push((row, member, rules));
+        }
+
+        if ctx.is_table() {
+            for FailedRow { row, email, errors } in &report.failed {
+                println!("Row {} ({}): {}", row, email, errors.join("; "));
+            }
+            for UnprotectedRule { row, iban } in &report.unprotected {
+                println!(
+                    "Row {}: {} is stored in plain text, the account \
+                     holder is not known", row, iban);
+            }
+            println!(
+                "{} of {} members are valid.", valid.len(), total);
+        }
+
+        if self.dry_run {
+            report.imported = valid.into_iter()
+                .map(|(_, member, _)| member)
+                .collect();
+            if ctx.is_table() {
+                println!();
+                report.imported.print_formatted();
+            } else {
+                print_report(ctx, &report)?;
+            }
+            return finish(report.failed.len(), total);
+        }
+
+        if !valid.is_empty() {
+            let ok = ctx.confirm(Confirm::new(&format!(
+                "Import {} members?", valid.len()))
+                .with_default(true))?;
+            if !ok {
+                return Ok(());
+            }
+        }
+
+        for (row, member, rules) in valid {
+            let email = member.email.clone();
+            match db.add_member(member, rules).await {
+                Ok(member) => {
+                    if ctx.is_table() {
+                        println!("Member added with id {}.", member.id);
+                    }
+                    report.imported.push(member);
+                }
+                Err(e) => report.failed.push(FailedRow {
+                    row,
+                    email,
+                    errors: vec![e.to_string()],
+                }),
+            }
+        }
+        report.failed.sort_by_key(|f| f.row);
+
+        if !ctx.is_table() {
+            print_report(ctx, &report)?;
+        }
+        finish(report.failed.len(), total)
+    }
+}
+
+fn print_report(ctx: &Context, report: &ImportReport) -> Result<()> {
+    match ctx.output {
+        OutputFormat::Csv => ctx.print_records(&report.imported),
+        _ => ctx.print_serialized(report),
+    }
+}
+
+fn finish(failed: usize, total: usize) -> Result<()> {
+    if failed > 0 {
+        return Err(PartialFailure { failed, total }.into());
+    }
+    Ok(())
+}
+
+#[derive(Args, Debug)]
+pub struct ExportMembers {
+    /// One of csv or json, defaults to the file extension
+    /// or csv when writing to stdout
+    #[clap(long)]
+    pub format: Option<RecordFormat>,
+    /// Write the export to a file instead of stdout
+    #[clap(long)]
+    pub file: Option<PathBuf>,
+}
+
+impl ExportMembers {
+    /// Run the command and export all members, including
+    /// archived members, with their bank import rules
+    pub async fn run(self, db: &Database) -> Result<()> {
+        let format = match (self.format, &self.file) {
+            (Some(format), _) => format,
+            (None, Some(file)) => RecordFormat::from_path(file),
+            (None, None) => RecordFormat::default(),
+        };
+
+        let members: Vec<Member> = db.query(&MemberFilter {
+            include_archived: true,
+            ..Default::default()
+        }).await?;
+        let rules: Vec<BankImportRule> =
+            db.query(&BankImportRuleFilter::default()).await?;
+        let mut rules_by_member: HashMap<u32, Vec<BankImportRule>> =
+            HashMap::new();
+        for rule in rules {
+            rules_by_member.entry(rule.member_id).or_default().push(rule);
+        }
+        let records: Vec<MemberRecord> = members.into_iter()
+            .map(|member| {
+                let rules = rules_by_member.remove(&member.id)
+                    .unwrap_or_default();
+                MemberRecord::new(member, rules)
+            })
+            .collect();
+
+        let writer: Box<dyn io::Write> = match &self.file {
+            Some(file) => Box::new(File::create(file)?),
+            None => Box::new(io::stdout()),
+        };
+        match format {
+            RecordFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(writer);
+                for record in records.iter().cloned() {
+                    writer.serialize(MemberRow::from(record))?;
+                }
+                writer.flush()?;
+            }
+            RecordFormat::Json => {
+                let mut writer = writer;
+                serde_json::to_writer_pretty(&mut writer, &records)?;
+                writeln!(writer)?;
+            }
+        }
+
+        if let Some(file) = &self.file {
+            eprintln!(
+                "Exported {} members to {}.", records.len(), file.display());
+        }
+        Ok(())
+    }
+}
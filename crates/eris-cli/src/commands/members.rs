@@ -4,11 +4,27 @@ use chrono::NaiveDate;
 use clap::{Subcommand, Args};
 use inquire::Confirm;
 
-use eris_data::{Member, MemberFilter, Query, Insert, Retrieve, Delete, Update, Transaction};
-use eris_accounting::{datetime};
-use eris_db::Connection;
+use eris_data::{
+    Access,
+    Insert,
+    Member,
+    MemberFilter,
+    MemberSort,
+    Query,
+    Resource,
+    Retrieve,
+    Transaction,
+    TransactionKind,
+    Update,
+};
+use eris_accounting::{archive, datetime};
+use super::Database;
 
-use crate::formatting::PrintFormatted;
+use crate::{
+    commands::{ExportMembers, Gdpr, ImportMembers, Mandates, Notify},
+    formatting::PrintFormatted,
+    output::Context,
+};
 
 #[derive(Subcommand, Debug)]
 pub enum Members {
@@ -24,21 +40,62 @@ pub enum Members {
     /// Update a member
     #[clap(name="set")]
     Update(UpdateMember),
-    /// Delete a member
-    #[clap(name="delete")]
-    Delete(DeleteMember),
+    /// Archive a former member
+    #[clap(name="archive", alias="delete")]
+    Archive(ArchiveMember),
+    /// Restore an archived member
+    #[clap(name="restore")]
+    Restore(RestoreMember),
+    /// Delete archived members after the retention period
+    #[clap(name="purge")]
+    Purge(PurgeMembers),
+    /// Import members from a CSV or JSON file
+    #[clap(name="import")]
+    Import(ImportMembers),
+    /// Export all members, including archived members, with their IBAN rules
+    #[clap(name="export")]
+    Export(ExportMembers),
+    /// Export or erase the personal data of a member
+    #[clap(subcommand, name="gdpr")]
+    Gdpr(Gdpr),
+    /// Manage direct debit mandates
+    #[clap(subcommand, name="mandate")]
+    Mandate(Mandates),
+    /// Notify members by email
+    #[clap(subcommand, name="notify")]
+    Notify(Notify),
 }
 
 impl Members {
-    pub async fn run(self, db: &Connection) -> Result<()> {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
         match self {
-            Members::Show(cmd) => cmd.run(db).await,
-            Members::List(cmd) => cmd.run(db).await,
-            Members::Add(cmd) => cmd.run(db).await,
-            Members::Update(cmd) => cmd.run(db).await,
-            Members::Delete(cmd) => cmd.run(db).await,
+            Members::Show(cmd) => cmd.run(db, ctx).await,
+            Members::List(cmd) => cmd.run(db, ctx).await,
+            Members::Add(cmd) => cmd.run(db, ctx).await,
+            Members::Update(cmd) => cmd.run(db, ctx).await,
+            Members::Archive(cmd) => cmd.run(db, ctx).await,
+            Members::Restore(cmd) => cmd.run(db, ctx).await,
+            Members::Purge(cmd) => cmd.run(db, ctx).await,
+            Members::Import(cmd) => cmd.run(db, ctx).await,
+            Members::Export(cmd) => cmd.run(db).await,
+            Members::Gdpr(cmd) => cmd.run(db, ctx).await,
+            Members::Mandate(cmd) => cmd.run(db, ctx).await,
+            Members::Notify(cmd) => cmd.run(db, ctx).await,
         } 
     }
+
+    /// The access needed to run the command
+    pub fn access(&self) -> (Resource, Access) {
+        match self {
+            Members::Show(_) | Members::List(_) | Members::Export(_) => {
+                (Resource::Member, Access::Read)
+            }
+            Members::Gdpr(cmd) => cmd.access(),
+            Members::Mandate(cmd) => cmd.access(),
+            Members::Notify(cmd) => cmd.access(),
+            _ => (Resource::Member, Access::Write),
+        }
+    }
 }
 
 #[derive(Args, Debug)]
@@ -49,11 +106,9 @@ pub struct ShowMember {
 
 impl ShowMember {
     /// Run the command and show a member
-    pub async fn run(self, db: &Connection) -> Result<()> {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
         let member: Member = db.retrieve(self.id).await?;
-        println!("");
-        member.print_formatted();
-        println!("");
+        ctx.print(&member)?;
         Ok(())
     }
 }
@@ -66,22 +121,102 @@ pub struct ListMembers {
     pub name: Option<String>,
     #[clap(short, long)]
     pub email: Option<String>,
+    /// Include archived members
+    #[clap(long)]
+    pub archived: bool,
+    /// Only members with an active membership today
+    #[clap(long, conflicts_with_all = ["inactive", "active_at"])]
+    pub active: bool,
+    /// Only members without an active membership today
+    #[clap(long, conflicts_with = "active_at")]
+    pub inactive: bool,
+    /// Only members with an active membership at the date
+    #[clap(long)]
+    pub active_at: Option<NaiveDate>,
+    /// Only members with a negative balance
+    #[clap(long, conflicts_with = "balance_below")]
+    pub in_arrears: bool,
+    /// Only members with a balance below the amount
+    #[clap(long)]
+    pub balance_below: Option<f64>,
+    /// Only members with a balance above the amount
+    #[clap(long)]
+    pub balance_above: Option<f64>,
+    #[clap(long)]
+    pub fee: Option<f64>,
+    #[clap(long)]
+    pub interval: Option<u8>,
+    /// Only members who joined on or after the date
+    #[clap(long)]
+    pub start_after: Option<NaiveDate>,
+    /// Only members who joined on or before the date
+    #[clap(long)]
+    pub start_before: Option<NaiveDate>,
+    /// Only members whose membership ends on or after the date
+    #[clap(long)]
+    pub end_after: Option<NaiveDate>,
+    /// Only members whose membership ends on or before the date
+    #[clap(long)]
+    pub end_before: Option<NaiveDate>,
+    /// Only members with bank import rules
+    #[clap(long, conflicts_with = "without_iban")]
+    pub with_iban: bool,
+    /// Only members without bank import rules
+    #[clap(long)]
+    pub without_iban: bool,
+    /// Search the notes
+    #[clap(long)]
+    pub notes: Option<String>,
+    /// Sort by id, name, balance, fee, membership_start
+    /// or last_payment
+    #[clap(long, default_value_t = MemberSort::Id)]
+    pub sort: MemberSort,
+    /// Sort in descending order
+    #[clap(long)]
+    pub desc: bool,
+    #[clap(long)]
+    pub limit: Option<u32>,
+    #[clap(long)]
+    pub offset: Option<u32>,
 }
 
 impl ListMembers {
     /// Run the command and list members
-    pub async fn run(self, db: &Connection) -> Result<()> {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        let today = datetime::today();
+        let has_iban_rule = match (self.with_iban, self.without_iban) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        };
+
         // Create member filter
         let filter = MemberFilter{
             id: self.id,
             name: self.name,
             email: self.email,
-            ..Default::default()
+            include_archived: self.archived,
+            active_at: self.active_at.or(self.active.then_some(today)),
+            inactive_at: self.inactive.then_some(today),
+            balance_below: self.balance_below.or(self.in_arrears.then_some(0.0)),
+            balance_above: self.balance_above,
+            fee: self.fee,
+            interval: self.interval,
+            membership_start_after: self.start_after,
+            membership_start_before: self.start_before,
+            membership_end_after: self.end_after,
+            membership_end_before: self.end_before,
+            has_iban_rule,
+            notes: self.notes,
+            sort: self.sort,
+            descending: self.desc,
+            limit: self.limit,
+            offset: self.offset,
         };
 
         let members: Vec<Member> = db.query(&filter).await?;
-        println!("{} members.", members.len());
-        members.print_formatted();
+        ctx.message(&format!("{} members.", members.len()));
+        ctx.print_list(&members)?;
 
         Ok(())
     }
@@ -107,16 +242,17 @@ pub struct AddMember{
 
 impl AddMember {
     /// Run the command and add a member to the database
-    pub async fn run(self, db: &Connection) -> Result<()>
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()>
     {
         let membership_start = self.membership_start.unwrap_or(datetime::today());
 
         // Check if a member with this email already exists
         let members: Vec<Member> = db.query(&MemberFilter{
             email: Some(self.email.clone()),
+            include_archived: true,
             ..Default::default()
         }).await?;
-        if members.len() > 0 {
+        if !members.is_empty() {
             return Err(anyhow!(
                 "Member with email {} already exists.", self.email));
         }
@@ -126,20 +262,20 @@ impl AddMember {
             name: self.name,
             email: self.email,
             notes: self.notes.unwrap_or("".to_string()),
-            membership_start: membership_start,
+            membership_start,
             fee: self.fee,
             interval: self.interval,
-            account: account,
+            account,
             ..Default::default()
         };
 
-        println!("");
+        println!();
         member.print_formatted();
-        println!("");
+        println!();
 
         // Confirm adding member
         let confirm = Confirm::new("Add member?").with_default(true);
-        if !confirm.prompt()? {
+        if !ctx.confirm(confirm)? {
             return Ok(());
         }
 
@@ -175,7 +311,7 @@ pub struct UpdateMember{
 
 impl UpdateMember {
     /// Run command and update a member
-    pub async fn run(self, db: &Connection) -> Result<()> {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
         let member: Member = db.retrieve(self.id).await?;
         let mut update = member.clone();
     
@@ -204,11 +340,11 @@ impl UpdateMember {
             update.account = account;
         }
 
-        println!("");
+        println!();
         (member.clone(), update.clone()).print_formatted();
-        println!("");
+        println!();
         let confirm = Confirm::new("Update member?").with_default(true);
-        if !confirm.prompt()? {
+        if !ctx.confirm(confirm)? {
             return Ok(());
         }
     
@@ -216,9 +352,10 @@ impl UpdateMember {
             // Check if a member with this email already exists
             let members: Vec<Member> = db.query(&MemberFilter{
                 email: Some(update.email.clone()),
+                include_archived: true,
                 ..Default::default()
             }).await?;
-            if members.len() > 0 {
+            if !members.is_empty() {
                 return Err(anyhow!(
                     "Member with email {} already exists.", update.email));
             }
@@ -232,7 +369,8 @@ impl UpdateMember {
                 member_id: update.id,
                 date: datetime::today(),
                 amount: update.account - member.account,
-                description: format!("Manual account balance update"),
+                description: "Manual account balance update".to_string(),
+                kind: TransactionKind::Manual,
                 ..Default::default()
             };
             db.insert(transaction).await?;
@@ -244,24 +382,70 @@ impl UpdateMember {
 
 
 #[derive(Args, Debug)]
-pub struct DeleteMember{
+pub struct ArchiveMember{
     #[clap(short, long)]
     pub id: u32,
+    /// End of the membership, defaults to today
+    #[clap(long)]
+    pub date: Option<NaiveDate>,
 }
 
 
-impl DeleteMember {
-    pub async fn run(&self, db: &Connection) -> Result<()> {
+impl ArchiveMember {
+    pub async fn run(&self, db: &Database, ctx: &Context) -> Result<()> {
         let member: Member = db.retrieve(self.id).await?;
-        println!("");
+        println!();
         member.print_formatted();
-        println!("");
-        let confirm = Confirm::new("Delete member from database?")
+        println!();
+        let confirm = Confirm::new("Archive member?")
             .with_default(true);
-        if !confirm.prompt()? {
+        if !ctx.confirm(confirm)? {
+            return Ok(());
+        }
+        let date = self.date.unwrap_or(datetime::today());
+        archive::archive(db, self.id, date).await?;
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct RestoreMember{
+    #[clap(short, long)]
+    pub id: u32,
+}
+
+impl RestoreMember {
+    pub async fn run(&self, db: &Database, ctx: &Context) -> Result<()> {
+        let member = archive::restore(db, self.id).await?;
+        ctx.message(&format!("Member {} was restored.", member.id));
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct PurgeMembers {}
+
+impl PurgeMembers {
+    /// Run the command and delete the archived members
+    /// whose ledger is past the retention period
+    pub async fn run(&self, db: &Database, ctx: &Context) -> Result<()> {
+        let members = archive::plan_purge(db, datetime::today()).await?;
+        if members.is_empty() {
+            ctx.message("No archived members are past the retention period.");
+            return Ok(());
+        }
+        if ctx.is_table() {
+            members.print_formatted();
+            println!();
+        }
+        let prompt = format!(
+            "Delete {} members with their transactions?", members.len());
+        let confirm = Confirm::new(&prompt).with_default(false);
+        if !ctx.confirm(confirm)? {
             return Ok(());
         }
-        db.delete(member).await?;
+        archive::purge(db, &members).await?;
+        ctx.message(&format!("{} members were purged.", members.len()));
         Ok(())
     }
 }
@@ -1,8 +1,37 @@
+use eris_data::Authorized;
+use eris_db::Connection;
+
+/// The database commands run on. Every operation is checked
+/// against the permissions of the user running the command.
+pub type Database = Authorized<Connection>;
+
 mod members;
 pub use members::Members;
+mod member_records;
+pub use member_records::{ExportMembers, ImportMembers};
+mod gdpr;
+pub use gdpr::Gdpr;
 mod accounting;
 pub use accounting::Accounting;
 mod transactions;
 pub use transactions::Transactions;
 mod bank;
-pub use bank::Bank;
+pub use bank::{Bank, IbanArgs};
+mod mandates;
+pub use mandates::Mandates;
+mod payouts;
+pub use payouts::Payouts;
+mod dunning;
+pub use dunning::Dunning;
+mod notify;
+pub use notify::Notify;
+mod statements;
+pub use statements::{DocumentFormat, ShowStatement};
+mod receipts;
+pub use receipts::Receipts;
+mod reports;
+pub use reports::Reports;
+mod audit;
+pub use audit::Audit;
+mod users;
+pub use users::Users;
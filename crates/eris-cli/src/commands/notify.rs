@@ -0,0 +1,228 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use chrono::NaiveDate;
+use clap::{Args, Subcommand};
+use inquire::Confirm;
+
+use eris_accounting::datetime;
+use eris_data::{
+    Access,
+    Member,
+    MemberFilter,
+    Notification,
+    NotificationEvent,
+    NotificationFilter,
+    Query,
+    Resource,
+    Retrieve,
+};
+use super::Database;
+use eris_notify::{
+    outbox::{self, MAX_ATTEMPTS},
+    Mailer,
+    SmtpConfig,
+    Templates,
+    TlsMode,
+};
+
+use crate::{
+    formatting::PrintFormatted,
+    output::{Context, PartialFailure},
+};
+
+#[derive(Subcommand, Debug)]
+pub enum Notify {
+    /// Render notifications and put them into the outbox
+    #[clap(name = "queue")]
+    Queue(QueueNotifications),
+    /// Send pending notifications from the outbox
+    #[clap(name = "send")]
+    Send(SendNotifications),
+    /// List notifications in the outbox
+    #[clap(name = "list")]
+    List(ListNotifications),
+}
+
+impl Notify {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        match self {
+            Notify::Queue(cmd) => cmd.run(db).await,
+            Notify::Send(cmd) => cmd.run(db, ctx).await,
+            Notify::List(cmd) => cmd.run(db, ctx).await,
+        }
+    }
+
+    /// The access needed to run the command
+    pub fn access(&self) -> (Resource, Access) {
+        match self {
+            Notify::List(_) => (Resource::Notification, Access::Read),
+            _ => (Resource::Notification, Access::Write),
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct QueueNotifications {
+    /// One of welcome, balance_reminder or fee_change
+    #[clap(short, long)]
+    pub event: NotificationEvent,
+    /// Only notify this member. Balance reminders are
+    /// otherwise queued for all members with a negative
+    /// balance, other events for all members.
+    #[clap(short, long)]
+    pub id: Option<u32>,
+    /// Defaults to today. Fee changes are notified once
+    /// per date, so use the date the fee changes at.
+    #[clap(short, long)]
+    pub date: Option<NaiveDate>,
+    /// Load templates from this directory instead of the
+    /// built-in ones
+    #[clap(short, long, env = "ERIS_TEMPLATES")]
+    pub templates: Option<PathBuf>,
+}
+
+impl QueueNotifications {
+    pub async fn run(self, db: &Database) -> Result<()> {
+        let date = self.date.unwrap_or(datetime::today());
+        let templates = match &self.templates {
+            Some(path) => Templates::from_dir(path)?,
+            None => Templates::builtin()?,
+        };
+
+        let members: Vec<Member> = match self.id {
+            Some(id) => vec![db.retrieve(id).await?],
+            None => db.query(&MemberFilter::default()).await?,
+        };
+        let members: Vec<Member> = members.into_iter()
+            .filter(|m| self.id.is_some()
+                || self.event != NotificationEvent::BalanceReminder
+                || m.account < 0.0)
+            .collect();
+
+        let mut queued = 0;
+        for member in members {
+            match outbox::queue(db, &templates, self.event, &member, date).await {
+                Ok(Some(_)) => queued += 1,
+                Ok(None) => {}
+                Err(err) => println!("Skipping {}: {}", member.name, err),
+            }
+        }
+        println!("{} notifications queued.", queued);
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct SmtpArgs {
+    #[clap(long, env = "ERIS_SMTP_HOST")]
+    pub smtp_host: String,
+    #[clap(long, env = "ERIS_SMTP_PORT", default_value_t = 587)]
+    pub smtp_port: u16,
+    /// One of none, starttls or tls
+    #[clap(long, env = "ERIS_SMTP_TLS", default_value_t = TlsMode::StartTls)]
+    pub smtp_tls: TlsMode,
+    #[clap(long, env = "ERIS_SMTP_USER")]
+    pub smtp_user: Option<String>,
+    #[clap(long, env = "ERIS_SMTP_PASSWORD", hide_env_values = true)]
+    pub smtp_password: Option<String>,
+    /// The sender address, e.g. "Treasurer <kasse@example.org>"
+    #[clap(long, env = "ERIS_SMTP_FROM")]
+    pub smtp_from: String,
+}
+
+impl From<SmtpArgs> for SmtpConfig {
+    fn from(args: SmtpArgs) -> Self {
+        SmtpConfig {
+            host: args.smtp_host,
+            port: args.smtp_port,
+            tls: args.smtp_tls,
+            username: args.smtp_user,
+            password: args.smtp_password,
+            from: args.smtp_from,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct SendNotifications {
+    #[clap(flatten)]
+    pub smtp: SmtpArgs,
+    /// Give up on a notification after this many failed attempts
+    #[clap(long, default_value_t = MAX_ATTEMPTS)]
+    pub max_attempts: u32,
+}
+
+impl SendNotifications {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        let pending: Vec<Notification> = db.query(&NotificationFilter {
+            sent: Some(false),
+            ..Default::default()
+        }).await?;
+        let pending: Vec<Notification> = pending.into_iter()
+            .filter(|n| n.attempts < self.max_attempts)
+            .collect();
+        if pending.is_empty() {
+            println!("No pending notifications.");
+            return Ok(());
+        }
+        pending.print_formatted();
+        println!();
+
+        let ok = ctx.confirm(Confirm::new(&format!(
+            "Send {} notifications?", pending.len(),
+        )))?;
+        if !ok {
+            return Ok(());
+        }
+
+        let mailer = Mailer::new(&self.smtp.into())?;
+        let report = outbox::deliver(db, &mailer, self.max_attempts).await?;
+        for failed in &report.failed {
+            println!(
+                "Failed to send {} to {}: {}",
+                failed.id,
+                failed.recipient,
+                failed.last_error.clone().unwrap_or_default(),
+            );
+        }
+        println!(
+            "{} notifications sent, {} failed.",
+            report.sent.len(),
+            report.failed.len(),
+        );
+        if !report.failed.is_empty() {
+            return Err(PartialFailure {
+                failed: report.failed.len(),
+                total: report.sent.len() + report.failed.len(),
+            }.into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ListNotifications {
+    #[clap(short, long)]
+    pub member_id: Option<u32>,
+    /// One of welcome, balance_reminder or fee_change
+    #[clap(short, long)]
+    pub event: Option<NotificationEvent>,
+    /// Only list notifications not sent yet
+    #[clap(short, long)]
+    pub pending: bool,
+}
+
+impl ListNotifications {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        let notifications: Vec<Notification> = db.query(&NotificationFilter {
+            member_id: self.member_id,
+            event: self.event,
+            sent: if self.pending { Some(false) } else { None },
+            ..Default::default()
+        }).await?;
+        ctx.message(&format!("{} notifications.", notifications.len()));
+        ctx.print_list(&notifications)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,257 @@
+use std::fs;
+
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
+use clap::{Args, Subcommand};
+use inquire::Confirm;
+
+use eris_accounting::datetime;
+use eris_banking::{CreditTransferBatch, Debtor};
+use eris_data::{
+    Access,
+    Delete,
+    Insert,
+    Member,
+    Payout,
+    PayoutFilter,
+    PayoutKind,
+    PayoutState,
+    Query,
+    Resource,
+    Retrieve,
+};
+use super::Database;
+
+use crate::{formatting::PrintFormatted, output::Context};
+
+#[derive(Subcommand, Debug)]
+pub enum Payouts {
+    /// List payouts
+    #[clap(name = "list")]
+    List(ListPayouts),
+    /// Queue a refund or reimbursement
+    #[clap(name = "add")]
+    Add(AddPayout),
+    /// Remove a queued payout
+    #[clap(name = "delete")]
+    Delete(DeletePayout),
+    /// Export queued payouts as SEPA credit transfer (pain.001)
+    #[clap(name = "export")]
+    Export(ExportPayouts),
+}
+
+impl Payouts {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        match self {
+            Payouts::List(cmd) => cmd.run(db, ctx).await,
+            Payouts::Add(cmd) => cmd.run(db, ctx).await,
+            Payouts::Delete(cmd) => cmd.run(db, ctx).await,
+            Payouts::Export(cmd) => cmd.run(db, ctx).await,
+        }
+    }
+
+    /// The access needed to run the command. Exporting
+    /// marks the payouts as pending.
+    pub fn access(&self) -> (Resource, Access) {
+        match self {
+            Payouts::List(_) => (Resource::Payout, Access::Read),
+            _ => (Resource::Payout, Access::Write),
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ListPayouts {
+    #[clap(short, long)]
+    pub member_id: Option<u32>,
+    /// One of queued, pending or settled
+    #[clap(short, long)]
+    pub state: Option<PayoutState>,
+    #[clap(short, long)]
+    pub batch_id: Option<String>,
+}
+
+impl ListPayouts {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        let payouts: Vec<Payout> = db.query(&PayoutFilter {
+            member_id: self.member_id,
+            state: self.state,
+            batch_id: self.batch_id,
+            ..Default::default()
+        }).await?;
+        ctx.message(&format!("{} payouts.", payouts.len()));
+        ctx.print_list(&payouts)?;
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct AddPayout {
+    #[clap(short, long)]
+    pub member_id: u32,
+    /// Either refund or reimbursement
+    #[clap(short, long, default_value_t = PayoutKind::Refund)]
+    pub kind: PayoutKind,
+    /// Defaults to the account balance for refunds
+    #[clap(short, long)]
+    pub amount: Option<f64>,
+    /// Defaults to the IBAN of the first bank import rule
+    #[clap(short, long)]
+    pub iban: Option<String>,
+    /// Name of the account holder, defaults to the member name
+    #[clap(short, long)]
+    pub name: Option<String>,
+    #[clap(short, long)]
+    pub subject: Option<String>,
+}
+
+impl AddPayout {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        let member: Member = db.retrieve(self.member_id).await?;
+
+        let amount = match (self.amount, self.kind) {
+            (Some(amount), _) => amount,
+            (None, PayoutKind::Refund) => member.account,
+            (None, PayoutKind::Reimbursement) => {
+                return Err(anyhow!("An amount is required."));
+            }
+        };
+        if amount <= 0.0 {
+            return Err(anyhow!("Can not pay out {}€.", amount));
+        }
+
+        let iban = match self.iban {
+            Some(iban) => iban,
+            None => member
+                .get_bank_import_rules(db)
+                .await?
+                .first()
+                .map(|rule| rule.iban.clone())
+                .ok_or(anyhow!(
+                    "Member {} has no known IBAN.", member.id))?,
+        };
+        let subject = self.subject.unwrap_or(match self.kind {
+            PayoutKind::Refund => "Refund of account balance".to_string(),
+            PayoutKind::Reimbursement => "Reimbursement".to_string(),
+        });
+
+        let payout = Payout {
+            member_id: member.id,
+            kind: self.kind,
+            name: self.name.unwrap_or(member.name.clone()),
+            iban,
+            amount,
+            subject,
+            created_at: datetime::today(),
+            ..Default::default()
+        };
+
+        println!();
+        payout.print_formatted();
+        println!();
+        let ok = ctx.confirm(Confirm::new("Queue this payout?")
+            .with_default(true))?;
+        if !ok {
+            return Ok(());
+        }
+
+        let payout = db.insert(payout).await?;
+        println!("Payout queued with id {}.", payout.id);
+
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct DeletePayout {
+    #[clap(short, long)]
+    pub id: u32,
+}
+
+impl DeletePayout {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        let payout: Payout = db.retrieve(self.id).await?;
+        if payout.state() != PayoutState::Queued {
+            return Err(anyhow!(
+                "Payout {} is {} and can not be removed.",
+                payout.id, payout.state()));
+        }
+
+        println!();
+        payout.print_formatted();
+        println!();
+        let ok = ctx.confirm(Confirm::new("Remove this payout?"))?;
+        if !ok {
+            return Ok(());
+        }
+
+        db.delete(payout).await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ExportPayouts {
+    /// Write the pain.001 XML to this file
+    #[clap(short, long)]
+    pub output: String,
+    /// Requested execution date, defaults to today
+    #[clap(short, long)]
+    pub execution_date: Option<NaiveDate>,
+    #[clap(long, env = "ERIS_DEBTOR_NAME")]
+    pub debtor_name: String,
+    #[clap(long, env = "ERIS_DEBTOR_IBAN")]
+    pub debtor_iban: String,
+    #[clap(long, env = "ERIS_DEBTOR_BIC")]
+    pub debtor_bic: Option<String>,
+}
+
+impl ExportPayouts {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        let payouts: Vec<Payout> = db.query(&PayoutFilter {
+            state: Some(PayoutState::Queued),
+            ..Default::default()
+        }).await?;
+        if payouts.is_empty() {
+            println!("There are no queued payouts.");
+            return Ok(());
+        }
+
+        let batch = CreditTransferBatch::new(
+            Debtor {
+                name: self.debtor_name,
+                iban: self.debtor_iban,
+                bic: self.debtor_bic,
+            },
+            chrono::Local::now().naive_local(),
+            self.execution_date.unwrap_or(datetime::today()),
+            payouts,
+        );
+
+        batch.payouts.print_formatted();
+        println!();
+        let ok = ctx.confirm(Confirm::new(&format!(
+            "Book {} payouts with a total of {:.2}€ and write {}?",
+            batch.payouts.len(),
+            batch.control_sum(),
+            self.output,
+        )))?;
+        if !ok {
+            return Ok(());
+        }
+
+        // Write the file first, so nothing is booked
+        // in case this fails.
+        fs::write(&self.output, batch.to_xml())?;
+        let batch = batch.book(db).await?;
+        println!(
+            "Exported batch {} to {}. The payouts are pending until \
+            they show up in a bank import.",
+            batch.message_id,
+            self.output,
+        );
+
+        Ok(())
+    }
+}
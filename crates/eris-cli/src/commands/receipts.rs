@@ -0,0 +1,220 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::{anyhow, Result};
+use chrono::{Datelike, NaiveDate};
+use clap::{Args, Subcommand};
+use inquire::Confirm;
+
+use eris_accounting::{
+    datetime,
+    receipts::{
+        find_receipt,
+        issue_receipt,
+        AnnualDonations,
+        Issuer,
+        ReceiptDocument,
+    },
+};
+use eris_data::{
+    Access,
+    DonationReceipt,
+    DonationReceiptFilter,
+    Member,
+    MemberFilter,
+    Query,
+    Resource,
+    Retrieve,
+};
+use super::Database;
+
+use crate::{commands::DocumentFormat, output::Context};
+
+#[derive(Subcommand, Debug)]
+pub enum Receipts {
+    /// Issue donation receipts for a year
+    #[clap(name = "issue")]
+    Issue(Box<IssueReceipts>),
+    /// List issued donation receipts
+    #[clap(name = "list")]
+    List(ListReceipts),
+}
+
+impl Receipts {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        match self {
+            Receipts::Issue(cmd) => cmd.run(db, ctx).await,
+            Receipts::List(cmd) => cmd.run(db, ctx).await,
+        }
+    }
+
+    /// The access needed to run the command
+    pub fn access(&self) -> (Resource, Access) {
+        match self {
+            Receipts::Issue(_) => (Resource::DonationReceipt, Access::Write),
+            Receipts::List(_) => (Resource::DonationReceipt, Access::Read),
+        }
+    }
+}
+
+/// The issuing organisation as printed on receipts
+#[derive(Args, Debug)]
+pub struct IssuerArgs {
+    #[clap(long, env = "ERIS_ISSUER_NAME")]
+    pub issuer_name: String,
+    /// The postal address, lines separated by newlines
+    #[clap(long, env = "ERIS_ISSUER_ADDRESS")]
+    pub issuer_address: String,
+    #[clap(long, env = "ERIS_ISSUER_PLACE")]
+    pub issuer_place: String,
+    /// Completes "Förderung ...", e.g. "der Volksbildung"
+    #[clap(long, env = "ERIS_ISSUER_PURPOSE")]
+    pub issuer_purpose: String,
+    #[clap(long, env = "ERIS_TAX_OFFICE")]
+    pub tax_office: String,
+    #[clap(long, env = "ERIS_TAX_NUMBER")]
+    pub tax_number: String,
+    /// Date of the latest exemption notice
+    #[clap(long, env = "ERIS_EXEMPTION_DATE")]
+    pub exemption_date: NaiveDate,
+    /// Assessment period of the exemption notice
+    #[clap(long, env = "ERIS_ASSESSMENT_PERIOD")]
+    pub assessment_period: String,
+}
+
+impl From<IssuerArgs> for Issuer {
+    fn from(args: IssuerArgs) -> Self {
+        Issuer {
+            name: args.issuer_name,
+            address: args.issuer_address,
+            place: args.issuer_place,
+            purpose: args.issuer_purpose,
+            tax_office: args.tax_office,
+            tax_number: args.tax_number,
+            exemption_date: args.exemption_date,
+            assessment_period: args.assessment_period,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct IssueReceipts {
+    /// The year to confirm, defaults to last year
+    #[clap(short, long)]
+    pub year: Option<i32>,
+    /// Only issue the receipt for this member
+    #[clap(short, long)]
+    pub member_id: Option<u32>,
+    /// One of html or pdf
+    #[clap(long, default_value_t = DocumentFormat::Pdf)]
+    pub format: DocumentFormat,
+    /// Write the receipts to this directory
+    #[clap(short, long, default_value = ".")]
+    pub dir: PathBuf,
+    #[clap(flatten)]
+    pub issuer: IssuerArgs,
+}
+
+impl IssueReceipts {
+    /// Run the command and write donation receipts. Receipts
+    /// issued before are written again with their number.
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        if !matches!(self.format, DocumentFormat::Html | DocumentFormat::Pdf) {
+            return Err(anyhow!("receipts can only be rendered as html or pdf"));
+        }
+        let today = datetime::today();
+        let year = self.year.unwrap_or(today.year() - 1);
+        let members: Vec<Member> = match self.member_id {
+            Some(id) => vec![db.retrieve(id).await?],
+            None => db.query(&MemberFilter {
+                include_archived: true,
+                ..Default::default()
+            }).await?,
+        };
+
+        let mut issued = vec![];
+        let mut new = vec![];
+        for member in members {
+            let donations = AnnualDonations::fetch(db, &member, year).await?;
+            match find_receipt(db, member.id, year).await? {
+                Some(receipt) => {
+                    if receipt.amount != donations.total() {
+                        println!(
+                            "Warning: receipt {} for {} confirms {:.2}, \
+                             the ledger now shows {:.2}.",
+                            receipt.number(),
+                            member.name,
+                            receipt.amount,
+                            donations.total(),
+                        );
+                    }
+                    issued.push((receipt, donations));
+                }
+                None if donations.total() > 0.0 => {
+                    println!(
+                        "{:>4}\t{:<30}\t{:>10.2}",
+                        member.id, member.name, donations.total(),
+                    );
+                    new.push((member, donations));
+                }
+                None => {}
+            }
+        }
+
+        if !new.is_empty() {
+            let ok = ctx.confirm(Confirm::new(&format!(
+                "Issue {} new donation receipts for {}?", new.len(), year,
+            )))?;
+            if !ok {
+                return Ok(());
+            }
+            for (member, donations) in new {
+                let receipt = issue_receipt(db, &member, &donations, today)
+                    .await?;
+                issued.push((receipt, donations));
+            }
+        }
+
+        let issuer: Issuer = self.issuer.into();
+        fs::create_dir_all(&self.dir)?;
+        for (receipt, donations) in &issued {
+            let document = ReceiptDocument {
+                issuer: &issuer,
+                receipt,
+                donations,
+            };
+            let path = self.dir.join(format!(
+                "zuwendungsbestaetigung-{}.{}", receipt.number(), self.format));
+            match self.format {
+                DocumentFormat::Pdf => fs::write(&path, document.to_pdf())?,
+                _ => fs::write(&path, document.to_html())?,
+            }
+        }
+        println!(
+            "{} donation receipts written to {}.",
+            issued.len(),
+            self.dir.display(),
+        );
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ListReceipts {
+    #[clap(short, long)]
+    pub year: Option<i32>,
+    #[clap(short, long)]
+    pub member_id: Option<u32>,
+}
+
+impl ListReceipts {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        let receipts: Vec<DonationReceipt> = db.query(&DonationReceiptFilter {
+            year: self.year,
+            member_id: self.member_id,
+            ..Default::default()
+        }).await?;
+        ctx.message(&format!("{} donation receipts.", receipts.len()));
+        ctx.print_list(&receipts)?;
+        Ok(())
+    }
+}
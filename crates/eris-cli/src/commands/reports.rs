@@ -0,0 +1,84 @@
+use std::{fmt, fs, path::PathBuf, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use chrono::Datelike;
+use clap::{Args, Subcommand};
+
+use eris_accounting::{datetime, reports::AnnualReport};
+use super::Database;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReportFormat {
+    #[default]
+    Markdown,
+    Json,
+}
+
+impl fmt::Display for ReportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReportFormat::Markdown => write!(f, "markdown"),
+            ReportFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+impl FromStr for ReportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "markdown" | "md" => Ok(ReportFormat::Markdown),
+            "json" => Ok(ReportFormat::Json),
+            _ => Err(anyhow!("unknown report format: {}", s)),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Reports {
+    /// Annual financial report for the general assembly
+    #[clap(name = "annual")]
+    Annual(AnnualReportCmd),
+}
+
+impl Reports {
+    pub async fn run(self, db: &Database) -> Result<()> {
+        match self {
+            Reports::Annual(cmd) => cmd.run(db).await,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct AnnualReportCmd {
+    /// The year of the report, defaults to last year
+    #[clap(short, long)]
+    pub year: Option<i32>,
+    /// One of markdown or json
+    #[clap(long, default_value_t = ReportFormat::Markdown)]
+    pub format: ReportFormat,
+    /// Write the report to a file instead of stdout
+    #[clap(long)]
+    pub file: Option<PathBuf>,
+}
+
+impl AnnualReportCmd {
+    /// Run the command and render the annual report
+    pub async fn run(self, db: &Database) -> Result<()> {
+        let year = self.year.unwrap_or(datetime::today().year() - 1);
+        let report = AnnualReport::fetch(db, year).await?;
+        let document = match self.format {
+            ReportFormat::Markdown => report.to_markdown(),
+            ReportFormat::Json => report.to_json()? + "\n",
+        };
+        match self.file {
+            Some(path) => {
+                fs::write(&path, document)?;
+                println!("Report written to {}.", path.display());
+            }
+            None => print!("{}", document),
+        }
+        Ok(())
+    }
+}
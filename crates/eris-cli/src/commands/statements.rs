@@ -0,0 +1,93 @@
+use std::{fmt, fs, path::PathBuf, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use chrono::{Datelike, NaiveDate};
+use clap::Args;
+
+use eris_accounting::{datetime, statements::Statement};
+use eris_data::{Member, Retrieve};
+use super::Database;
+
+/// How to render a document
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DocumentFormat {
+    #[default]
+    Text,
+    Csv,
+    Html,
+    Pdf,
+}
+
+impl fmt::Display for DocumentFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DocumentFormat::Text => write!(f, "text"),
+            DocumentFormat::Csv => write!(f, "csv"),
+            DocumentFormat::Html => write!(f, "html"),
+            DocumentFormat::Pdf => write!(f, "pdf"),
+        }
+    }
+}
+
+impl FromStr for DocumentFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(DocumentFormat::Text),
+            "csv" => Ok(DocumentFormat::Csv),
+            "html" => Ok(DocumentFormat::Html),
+            "pdf" => Ok(DocumentFormat::Pdf),
+            _ => Err(anyhow!("unknown format: {}", s)),
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ShowStatement {
+    #[clap(short, long)]
+    pub member_id: u32,
+    /// Start of the period, defaults to the start of
+    /// the current year
+    #[clap(short, long)]
+    pub from: Option<NaiveDate>,
+    /// End of the period, defaults to today
+    #[clap(short, long)]
+    pub to: Option<NaiveDate>,
+    /// One of text, csv or html
+    #[clap(long, default_value_t = DocumentFormat::Text)]
+    pub format: DocumentFormat,
+    /// Write the statement to a file instead of stdout
+    #[clap(long)]
+    pub file: Option<PathBuf>,
+}
+
+impl ShowStatement {
+    /// Run the command and render the account statement
+    pub async fn run(self, db: &Database) -> Result<()> {
+        let today = datetime::today();
+        let from = self.from.unwrap_or(
+            NaiveDate::from_ymd_opt(today.year(), 1, 1).unwrap());
+        let to = self.to.unwrap_or(today);
+
+        let member: Member = db.retrieve(self.member_id).await?;
+        let statement = Statement::fetch(db, member, from, to).await?;
+        let document = match self.format {
+            DocumentFormat::Text => statement.to_text(),
+            DocumentFormat::Csv => statement.to_csv()?,
+            DocumentFormat::Html => statement.to_html(),
+            DocumentFormat::Pdf => {
+                return Err(anyhow!("statements can not be rendered as pdf"));
+            }
+        };
+
+        match self.file {
+            Some(path) => {
+                fs::write(&path, document)?;
+                println!("Statement written to {}.", path.display());
+            }
+            None => print!("{}", document),
+        }
+        Ok(())
+    }
+}
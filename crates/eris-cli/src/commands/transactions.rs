@@ -2,25 +2,41 @@ use anyhow::{anyhow, Result};
 use chrono::NaiveDate;
 use clap::{Args, Subcommand};
 
-use eris_data::{Member, MemberFilter, Query, Transaction, TransactionFilter,  Retrieve};
-use eris_db::Connection;
+use eris_data::{
+    Member,
+    MemberFilter,
+    MemberTransaction,
+    Query,
+    TransactionFilter,
+    TransactionGroup,
+    TransactionKind,
+    TransactionTotal,
+    TransactionTotalFilter,
+};
+use super::Database;
+
+use crate::output::Context;
 
 #[derive(Subcommand, Debug)]
 pub enum Transactions {
     /// List transactions
     List(ListTransactions),
+    /// Count and sum up transactions by month, member or kind
+    Totals(TransactionTotals),
 }
 
 impl Transactions {
-    pub async fn run(self, conn: &Connection) -> Result<()> {
+    pub async fn run(self, conn: &Database, ctx: &Context) -> Result<()> {
         match self {
-            Transactions::List(cmd) => cmd.run(conn).await,
+            Transactions::List(cmd) => cmd.run(conn, ctx).await,
+            Transactions::Totals(cmd) => cmd.run(conn, ctx).await,
         }
     }
 }
 
+/// Select transactions
 #[derive(Args, Debug)]
-pub struct ListTransactions {
+pub struct FilterArgs {
     #[clap(long)]
     pub member_id: Option<u32>,
     #[clap(long)]
@@ -29,16 +45,37 @@ pub struct ListTransactions {
     pub after_date: Option<NaiveDate>,
     #[clap(short, long)]
     pub before_date: Option<NaiveDate>,
+    /// Transactions of at least this amount
+    #[clap(long)]
+    pub min_amount: Option<f64>,
+    /// Transactions of at most this amount
+    #[clap(long)]
+    pub max_amount: Option<f64>,
+    /// The account name, where % matches any text
+    #[clap(long)]
+    pub account_name: Option<String>,
+    /// Part of the description
+    #[clap(long)]
+    pub description: Option<String>,
+    /// One of fee, bank, split, overflow, payout, refund or manual
+    #[clap(long)]
+    pub kind: Option<TransactionKind>,
 }
 
-impl ListTransactions {
-    pub async fn run(self, db: &Connection) -> Result<()> {
-        // Build filter
-        let mut filter = TransactionFilter::default();
-
-        if let Some(id) = self.member_id {
-            filter.member_id = Some(id);
-        }
+impl FilterArgs {
+    /// Build the filter, looking up the member by name
+    pub async fn filter(self, db: &Database) -> Result<TransactionFilter> {
+        let mut filter = TransactionFilter {
+            member_id: self.member_id,
+            date_after: self.after_date,
+            date_before: self.before_date,
+            amount_min: self.min_amount,
+            amount_max: self.max_amount,
+            account_name: self.account_name,
+            description: self.description,
+            kind: self.kind,
+            ..Default::default()
+        };
         if let Some(name) = self.member_name {
             let members: Vec<Member> = db.query(&MemberFilter{
                 name: Some(name),
@@ -47,26 +84,71 @@ impl ListTransactions {
             let member = members.first().ok_or(anyhow!("member not found"))?;
             filter.member_id = Some(member.id);
         }
+        Ok(filter)
+    }
+}
 
-        if let Some(date) = self.after_date {
-            filter.date_after = Some(date);
-        }
-        if let Some(date) = self.before_date {
-            filter.date_before = Some(date);
-        }
+#[derive(Args, Debug)]
+pub struct ListTransactions {
+    #[clap(flatten)]
+    pub filter: FilterArgs,
+}
+
+impl ListTransactions {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        let filter = self.filter.filter(db).await?;
 
-        // Query and print transctions
-        let transactions: Vec<Transaction> = db.query(&filter).await?;
+        // Query and print transactions with the member names
+        let transactions: Vec<MemberTransaction> = db.query(&filter).await?;
+        if !ctx.is_table() {
+            return ctx.print_records(&transactions);
+        }
         println!(
-            "{:>4}\t{:<15}\t{:<30}\t{:<40}\t{:<12}\t{}",
-            "ID", "Date", "Member", "Account", "Amount", "Description"
+            "{:>4}\t{:<15}\t{:<30}\t{:<40}\t{:<12}\tDescription",
+            "ID", "Date", "Member", "Account", "Amount"
         );
         println!("{:-<180}", "-");
         for tx in transactions {
-            let member: Member = db.retrieve(tx.member_id).await?;
             println!(
                 "{:>4}\t{:<15}\t{:<30}\t{:<40}\t{:<12.2}\t{}",
-                tx.id, tx.date, member.name, tx.account_name, tx.amount, tx.description
+                tx.id, tx.date, tx.member_name, tx.account_name, tx.amount, tx.description
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct TransactionTotals {
+    /// One of month, member or kind
+    #[clap(long, default_value_t = TransactionGroup::Month)]
+    pub group_by: TransactionGroup,
+    #[clap(flatten)]
+    pub filter: FilterArgs,
+}
+
+impl TransactionTotals {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        let filter = TransactionTotalFilter {
+            group: self.group_by,
+            transactions: self.filter.filter(db).await?,
+        };
+        let totals: Vec<TransactionTotal> = db.query(&filter).await?;
+        if !ctx.is_table() {
+            return ctx.print_records(&totals);
+        }
+        let group = match self.group_by {
+            TransactionGroup::Month => "Month",
+            TransactionGroup::Member => "Member",
+            TransactionGroup::Kind => "Kind",
+        };
+        println!("{:<15}\t{:>8}\t{:>12}", group, "Count", "Sum");
+        println!("{:-<40}", "-");
+        for total in totals {
+            println!(
+                "{:<15}\t{:>8}\t{:>12.2}",
+                total.label, total.count, total.sum
             );
         }
 
@@ -0,0 +1,144 @@
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use inquire::Confirm;
+
+use eris_data::{
+    Access,
+    Delete,
+    Insert,
+    Member,
+    Query,
+    Resource,
+    Retrieve,
+    Role,
+    Update,
+    User,
+    UserFilter,
+};
+use super::Database;
+
+use crate::output::Context;
+
+#[derive(Subcommand, Debug)]
+pub enum Users {
+    /// List users
+    #[clap(name = "list")]
+    List(ListUsers),
+    /// Add a user
+    #[clap(name = "add")]
+    Add(AddUser),
+    /// Change the role of a user
+    #[clap(name = "set")]
+    Update(UpdateUser),
+    /// Delete a user
+    #[clap(name = "delete")]
+    Delete(DeleteUser),
+}
+
+impl Users {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        match self {
+            Users::List(cmd) => cmd.run(db, ctx).await,
+            Users::Add(cmd) => cmd.run(db, ctx).await,
+            Users::Update(cmd) => cmd.run(db, ctx).await,
+            Users::Delete(cmd) => cmd.run(db, ctx).await,
+        }
+    }
+
+    /// The access needed to run the command
+    pub fn access(&self) -> (Resource, Access) {
+        match self {
+            Users::List(_) => (Resource::User, Access::Read),
+            _ => (Resource::User, Access::Write),
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ListUsers {
+    /// One of treasurer, board, auditor or member
+    #[clap(short, long)]
+    pub role: Option<Role>,
+}
+
+impl ListUsers {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        let users: Vec<User> = db.query(&UserFilter {
+            role: self.role,
+            ..Default::default()
+        }).await?;
+        ctx.print_list(&users)
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct AddUser {
+    #[clap(short, long)]
+    pub name: String,
+    /// One of treasurer, board, auditor or member
+    #[clap(short, long)]
+    pub role: Role,
+    /// The member record of a user with the member role
+    #[clap(short, long)]
+    pub member_id: Option<u32>,
+}
+
+impl AddUser {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        if let Some(member_id) = self.member_id {
+            // Make sure the member exists
+            let _: Member = db.retrieve(member_id).await?;
+        }
+        let user = db.insert(User {
+            name: self.name,
+            role: self.role,
+            member_id: self.member_id,
+            ..Default::default()
+        }).await?;
+        ctx.print(&user)
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct UpdateUser {
+    #[clap(short, long)]
+    pub name: String,
+    /// One of treasurer, board, auditor or member
+    #[clap(short, long)]
+    pub role: Option<Role>,
+    #[clap(short, long)]
+    pub member_id: Option<u32>,
+}
+
+impl UpdateUser {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        let user: User = db.retrieve(self.name).await?;
+        if let Some(member_id) = self.member_id {
+            let _: Member = db.retrieve(member_id).await?;
+        }
+        let user = db.update(User {
+            role: self.role.unwrap_or(user.role),
+            member_id: self.member_id.or(user.member_id),
+            ..user
+        }).await?;
+        ctx.print(&user)
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct DeleteUser {
+    #[clap(short, long)]
+    pub name: String,
+}
+
+impl DeleteUser {
+    pub async fn run(self, db: &Database, ctx: &Context) -> Result<()> {
+        let user: User = db.retrieve(self.name).await?;
+        let prompt = format!("Delete user {} ({})?", user.name, user.role);
+        if !ctx.confirm(Confirm::new(&prompt).with_default(false))? {
+            return Ok(());
+        }
+        db.delete(user).await?;
+        Ok(())
+    }
+}
@@ -1,6 +1,9 @@
-use eris_accounting::datetime;
+use eris_accounting::{datetime, mandates::MandateLifecycle};
 use eris_banking::BankTransaction;
-use eris_data::{BankImportRule, Member};
+use eris_data::{
+    AuditAction, AuditEntry, BankImportRule, DonationReceipt, DunningEvent, Mandate, Member,
+    Notification, Payout, User,
+};
 
 pub trait PrintFormatted {
     fn print_formatted(&self);
@@ -42,6 +45,9 @@ impl PrintFormatted for Member {
             "Account Balance:\t{}\t({})",
             self.account, self.account_calculated_at
         );
+        if let Some(archived_at) = self.archived_at {
+            println!("Archived:\t\t{}", archived_at);
+        }
     }
 }
 
@@ -89,16 +95,12 @@ impl PrintFormatted for Vec<Member> {
     fn print_formatted(&self) {
         let today = datetime::today();
         println!(
-            "{:>4}\t{:<24}\t{:<30}\t{:<24}\t{:>12}\t{}\t{}\t{}\t{}",
+            "{:>4}\t{:<24}\t{:<30}\t{:<24}\t{:>12}\tLast Payment\tInterval\tFee\tInacive",
             "ID",
             "Name",
             "Email",
             "Notes",
-            "Account",
-            "Last Payment",
-            "Interval",
-            "Fee",
-            "Inacive"
+            "Account"
         );
         println!("{:-<180}", "-");
 
@@ -167,3 +169,224 @@ impl PrintFormatted for BankTransaction {
         );
     }
 }
+
+impl PrintFormatted for Mandate {
+    fn print_formatted(&self) {
+        let today = datetime::today();
+        let last_used = match self.last_used_at {
+            Some(date) => date.to_string(),
+            None => "Never".to_string(),
+        };
+        println!("Reference:\t\t{}", self.reference);
+        println!("Member:\t\t\t{}", self.member_id);
+        println!("IBAN:\t\t\t{}", self.iban);
+        if let Some(original_iban) = &self.original_iban {
+            println!("Original IBAN:\t\t{}", original_iban);
+        }
+        println!("Account Holder:\t\t{}", self.account_holder);
+        println!("Signed:\t\t\t{}", self.signed_at);
+        println!("Last Used:\t\t{}", last_used);
+        println!("Expires:\t\t{}", self.expires_at());
+        println!("State:\t\t\t{}", self.state(today));
+        println!("Next Sequence:\t\t{}", self.sequence_type());
+    }
+}
+
+impl PrintFormatted for Vec<Mandate> {
+    fn print_formatted(&self) {
+        let today = datetime::today();
+        println!(
+            "{:<16}\t{:<24}\t{:<24}\t{:<10}\t{:<10}\t{:<8}\tState",
+            "Reference", "IBAN", "Account Holder", "Signed", "Last Used",
+            "Sequence",
+        );
+        println!("{:-<180}", "-");
+        for mandate in self {
+            let last_used = match mandate.last_used_at {
+                Some(date) => date.to_string(),
+                None => "Never".to_string(),
+            };
+            println!(
+                "{:<16}\t{:<24}\t{:<24}\t{:<10}\t{:<10}\t{:<8}\t{}",
+                mandate.reference,
+                mandate.iban,
+                mandate.account_holder,
+                mandate.signed_at,
+                last_used,
+                mandate.sequence_type(),
+                mandate.state(today),
+            );
+        }
+    }
+}
+
+impl PrintFormatted for Payout {
+    fn print_formatted(&self) {
+        println!("Member:\t\t\t{}", self.member_id);
+        println!("Kind:\t\t\t{}", self.kind);
+        println!("Name:\t\t\t{}", self.name);
+        println!("IBAN:\t\t\t{}", self.iban);
+        println!("Amount:\t\t\t{:.2}", self.amount);
+        println!("Subject:\t\t{}", self.subject);
+        println!("State:\t\t\t{}", self.state());
+    }
+}
+
+impl PrintFormatted for Vec<Payout> {
+    fn print_formatted(&self) {
+        println!(
+            "{:>4}\t{:>6}\t{:<14}\t{:<24}\t{:<24}\t{:>10}\t{:<8}\tSubject",
+            "ID", "Member", "Kind", "Name", "IBAN", "Amount", "State",
+        );
+        println!("{:-<180}", "-");
+        for payout in self {
+            println!(
+                "{:>4}\t{:>6}\t{:<14}\t{:<24}\t{:<24}\t{:>10.2}\t{:<8}\t{}",
+                payout.id,
+                payout.member_id,
+                payout.kind,
+                payout.name,
+                payout.iban,
+                payout.amount,
+                payout.state(),
+                payout.subject,
+            );
+        }
+    }
+}
+
+impl PrintFormatted for Vec<DunningEvent> {
+    fn print_formatted(&self) {
+        println!(
+            "{:>4}\t{:>6}\t{:<10}\t{:>10}\tLevel",
+            "ID", "Member", "Date", "Balance",
+        );
+        println!("{:-<180}", "-");
+        for event in self {
+            println!(
+                "{:>4}\t{:>6}\t{:<10}\t{:>10.2}\t{}",
+                event.id,
+                event.member_id,
+                event.date,
+                event.balance,
+                event.level,
+            );
+        }
+    }
+}
+
+impl PrintFormatted for Vec<Notification> {
+    fn print_formatted(&self) {
+        println!(
+            "{:>4}\t{:>6}\t{:<16}\t{:<19}\t{:>8}\t{:<32}\tSubject",
+            "ID", "Member", "Event", "Sent", "Attempts", "Recipient",
+        );
+        println!("{:-<180}", "-");
+        for n in self {
+            let sent = match n.sent_at {
+                Some(sent) => sent.format("%Y-%m-%d %H:%M:%S").to_string(),
+                None => "pending".to_string(),
+            };
+            println!(
+                "{:>4}\t{:>6}\t{:<16}\t{:<19}\t{:>8}\t{:<32}\t{}",
+                n.id,
+                n.member_id,
+                n.event,
+                sent,
+                n.attempts,
+                n.recipient,
+                n.subject,
+            );
+        }
+    }
+}
+
+impl PrintFormatted for Vec<DonationReceipt> {
+    fn print_formatted(&self) {
+        println!(
+            "{:<9}\t{:>6}\t{:<30}\t{:>10}\tIssued",
+            "Number", "Member", "Name", "Amount",
+        );
+        println!("{:-<180}", "-");
+        for receipt in self {
+            println!(
+                "{:<9}\t{:>6}\t{:<30}\t{:>10.2}\t{}",
+                receipt.number(),
+                receipt.member_id,
+                receipt.name,
+                receipt.amount,
+                receipt.issued_at,
+            );
+        }
+    }
+}
+
+impl PrintFormatted for Vec<AuditEntry> {
+    fn print_formatted(&self) {
+        println!(
+            "{:>6}\t{:<19}\t{:<12}\t{:<6}\t{:<16}\t{:<24}\t{:>6}\tChanges",
+            "ID", "Time", "Actor", "Action", "Entity", "Entity ID", "Member",
+        );
+        println!("{:-<180}", "-");
+        for entry in self {
+            let member = match entry.member_id {
+                Some(id) => id.to_string(),
+                None => "".to_string(),
+            };
+            let changes: Vec<String> = entry.changes().into_iter()
+                .filter(|c| c.field != "id")
+                .map(|c| match entry.action {
+                    AuditAction::Update => {
+                        format!("{}: {} -> {}", c.field, c.old, c.new)
+                    }
+                    AuditAction::Insert => format!("{}: {}", c.field, c.new),
+                    AuditAction::Delete => format!("{}: {}", c.field, c.old),
+                })
+                .collect();
+            println!(
+                "{:>6}\t{:<19}\t{:<12}\t{:<6}\t{:<16}\t{:<24}\t{:>6}\t{}",
+                entry.id,
+                entry.created_at.format("%Y-%m-%d %H:%M:%S"),
+                entry.actor,
+                entry.action.to_string(),
+                entry.entity.to_string(),
+                entry.entity_id,
+                member,
+                changes.join(", "),
+            );
+        }
+    }
+}
+
+impl PrintFormatted for User {
+    fn print_formatted(&self) {
+        let member = match self.member_id {
+            Some(id) => id.to_string(),
+            None => "-".to_string(),
+        };
+        println!("Name:\t\t\t{}", self.name);
+        println!("Role:\t\t\t{}", self.role);
+        println!("Member:\t\t\t{}", member);
+        println!("Created:\t\t{}", self.created_at.format("%Y-%m-%d %H:%M:%S"));
+    }
+}
+
+impl PrintFormatted for Vec<User> {
+    fn print_formatted(&self) {
+        println!("{:<24}\t{:<10}\t{:>6}\tCreated", "Name", "Role", "Member");
+        println!("{:-<80}", "-");
+        for user in self {
+            let member = match user.member_id {
+                Some(id) => id.to_string(),
+                None => "".to_string(),
+            };
+            println!(
+                "{:<24}\t{:<10}\t{:>6}\t{}",
+                user.name,
+                user.role.to_string(),
+                member,
+                user.created_at.format("%Y-%m-%d %H:%M:%S"),
+            );
+        }
+    }
+}
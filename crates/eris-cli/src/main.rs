@@ -1,16 +1,29 @@
 
+use std::{process::ExitCode, sync::Arc};
+
 use anyhow::Result;
 
 use eris_db::Connection;
-use eris_cli::cli::Cli;
+use eris_cli::{cli::Cli, output::PartialFailure};
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> Result<ExitCode> {
     let cli = Cli::init();
 
-    let conn = Connection::open(&cli.members_db).await?;
-    cli.run(&conn).await?;
-
-    Ok(())
+    let key = cli.database_key()?;
+    let mut conn = Connection::open_with_key(&cli.members_db, key.as_ref()).await?;
+    if let Some(actor) = cli.actor.as_ref().or(cli.user.as_ref()) {
+        conn = conn.with_actor(actor);
+    }
+    let conn = Arc::new(conn);
+    let result = cli.run(conn.clone()).await;
+    conn.close().await;
+    match result {
+        Err(err) if err.is::<PartialFailure>() => {
+            eprintln!("Error: {}", err);
+            Ok(ExitCode::from(2))
+        }
+        result => result.map(|_| ExitCode::SUCCESS),
+    }
 }
 
@@ -0,0 +1,165 @@
+use std::{error, fmt, io, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use inquire::{Confirm, InquireError};
+use serde::Serialize;
+
+use crate::formatting::PrintFormatted;
+
+/// How listings are printed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Aligned columns for humans
+    #[default]
+    Table,
+    Json,
+    Csv,
+    Yaml,
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OutputFormat::Table => write!(f, "table"),
+            OutputFormat::Json => write!(f, "json"),
+            OutputFormat::Csv => write!(f, "csv"),
+            OutputFormat::Yaml => write!(f, "yaml"),
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            "yaml" => Ok(OutputFormat::Yaml),
+            _ => Err(anyhow!("unknown output format: {}", s)),
+        }
+    }
+}
+
+/// Options shared by all commands
+#[derive(Debug, Clone, Default)]
+pub struct Context {
+    pub output: OutputFormat,
+    /// Answer all confirmations with yes
+    pub yes: bool,
+}
+
+impl Context {
+    /// Ask for confirmation, unless running non-interactively.
+    pub fn confirm(&self, prompt: Confirm) -> Result<bool> {
+        if self.yes {
+            self.message(&format!("{} yes", prompt.message));
+            return Ok(true);
+        }
+        match prompt.prompt() {
+            Ok(ok) => Ok(ok),
+            Err(InquireError::NotTTY) => Err(anyhow!(
+                "can not ask for confirmation without a terminal, \
+                 use --yes to run non-interactively")),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Is the output meant for humans?
+    pub fn is_table(&self) -> bool {
+        self.output == OutputFormat::Table
+    }
+
+    /// Print a message. With machine-readable output the
+    /// message goes to stderr, so stdout can be piped.
+    pub fn message(&self, message: &str) {
+        if self.is_table() {
+            println!("{}", message);
+        } else {
+            eprintln!("{}", message);
+        }
+    }
+
+    /// Print a list of records
+    pub fn print_list<T>(&self, records: &Vec<T>) -> Result<()>
+    where
+        T: Serialize,
+        Vec<T>: PrintFormatted,
+    {
+        match self.output {
+            OutputFormat::Table => records.print_formatted(),
+            _ => self.print_records(records)?,
+        }
+        Ok(())
+    }
+
+    /// Print a list of records in a machine-readable format.
+    /// This is used by commands with their own table layout.
+    pub fn print_records<T: Serialize>(&self, records: &[T]) -> Result<()> {
+        match self.output {
+            OutputFormat::Csv => write_csv(records.iter()),
+            _ => self.print_serialized(records),
+        }
+    }
+
+    /// Print a single record
+    pub fn print<T>(&self, record: &T) -> Result<()>
+    where
+        T: Serialize + PrintFormatted,
+    {
+        match self.output {
+            OutputFormat::Table => {
+                println!();
+                record.print_formatted();
+                println!();
+            }
+            OutputFormat::Csv => write_csv([record].into_iter())?,
+            _ => self.print_serialized(record)?,
+        }
+        Ok(())
+    }
+
+    /// Print any serializable value as JSON or YAML
+    pub fn print_serialized<T: Serialize + ?Sized>(&self, value: &T) -> Result<()> {
+        match self.output {
+            OutputFormat::Json => {
+                println!("{}", serde_json::to_string_pretty(value)?);
+            }
+            OutputFormat::Yaml => print!("{}", serde_yaml::to_string(value)?),
+            format => {
+                return Err(anyhow!("output format {} is not supported here", format));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_csv<'a, T, I>(records: I) -> Result<()>
+where
+    T: Serialize + 'a,
+    I: Iterator<Item = &'a T>,
+{
+    let mut writer = csv::Writer::from_writer(io::stdout());
+    for record in records {
+        writer.serialize(record)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Returned by commands which completed, but failed for
+/// some of the records. The process exits with status 2.
+#[derive(Debug)]
+pub struct PartialFailure {
+    pub failed: usize,
+    pub total: usize,
+}
+
+impl fmt::Display for PartialFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} of {} records failed", self.failed, self.total)
+    }
+}
+
+impl error::Error for PartialFailure {}
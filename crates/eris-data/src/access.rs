@@ -0,0 +1,373 @@
+use std::{fmt, sync::Arc};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use thiserror::Error as ThisError;
+
+use crate::{
+    AddMember,
+    AuditEntry,
+    BankImportRule,
+    Book,
+    Booking,
+    ChangeMember,
+    ClaimNotification,
+    Delete,
+    DonationReceipt,
+    DunningEvent,
+    Insert,
+    Mandate,
+    Member,
+    MemberChanges,
+    MemberTransaction,
+    Notification,
+    Payout,
+    Pseudonymisation,
+    Pseudonymise,
+    Query,
+    Retrieve,
+    Role,
+    Transaction,
+    TransactionTotal,
+    Update,
+    User,
+};
+
+/// Reading or changing records. Write access includes
+/// read access, so they are ordered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+        }
+    }
+}
+
+/// The kinds of records access is granted for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resource {
+    Member,
+    Transaction,
+    BankImportRule,
+    Mandate,
+    Payout,
+    DunningEvent,
+    Notification,
+    DonationReceipt,
+    AuditLog,
+    User,
+}
+
+impl Resource {
+    /// Records belonging to a single member
+    pub fn is_personal(&self) -> bool {
+        !matches!(self, Resource::AuditLog | Resource::User)
+    }
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Resource::Member => "members",
+            Resource::Transaction => "transactions",
+            Resource::BankImportRule => "bank import rules",
+            Resource::Mandate => "mandates",
+            Resource::Payout => "payouts",
+            Resource::DunningEvent => "dunning events",
+            Resource::Notification => "notifications",
+            Resource::DonationReceipt => "donation receipts",
+            Resource::AuditLog => "the audit log",
+            Resource::User => "users",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(ThisError, Debug)]
+#[error("permission denied: {role} may not {access} {resource}")]
+pub struct PermissionDenied {
+    pub role: Role,
+    pub access: Access,
+    pub resource: Resource,
+}
+
+/// The role and member on whose behalf records are accessed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Principal {
+    pub role: Role,
+    pub member_id: Option<u32>,
+}
+
+impl Principal {
+    /// A member accessing their own records
+    pub fn member(member_id: u32) -> Self {
+        Self {
+            role: Role::Member,
+            member_id: Some(member_id),
+        }
+    }
+
+    /// Check the role grants the access
+    pub fn check(&self, resource: Resource, access: Access) -> Result<()> {
+        if !self.role.allows(resource, access) {
+            return Err(self.denied(resource, access).into());
+        }
+        Ok(())
+    }
+
+    /// Check the role grants the access to a record
+    /// belonging to the owner.
+    pub fn check_record<T: Protected>(&self, record: &T, access: Access) -> Result<()> {
+        self.check(T::RESOURCE, access)?;
+        if !self.owns(record.owner()) {
+            return Err(self.denied(T::RESOURCE, access).into());
+        }
+        Ok(())
+    }
+
+    /// Members may only see their own records
+    fn owns(&self, owner: Option<u32>) -> bool {
+        self.role != Role::Member || (owner.is_some() && owner == self.member_id)
+    }
+
+    fn denied(&self, resource: Resource, access: Access) -> PermissionDenied {
+        PermissionDenied {
+            role: self.role,
+            access,
+            resource,
+        }
+    }
+}
+
+impl From<&User> for Principal {
+    fn from(user: &User) -> Self {
+        Self {
+            role: user.role,
+            member_id: user.member_id,
+        }
+    }
+}
+
+/// Records protected by the permission checks
+pub trait Protected {
+    const RESOURCE: Resource;
+
+    /// The member the record belongs to
+    fn owner(&self) -> Option<u32>;
+}
+
+macro_rules! protected {
+    ($type:ty, $resource:expr, $record:ident => $owner:expr) => {
+        impl Protected for $type {
+            const RESOURCE: Resource = $resource;
+
+            fn owner(&self) -> Option<u32> {
+                let $record = self;
+                $owner
+            }
+        }
+    };
+}
+
+protected!(Member, Resource::Member, m => Some(m.id));
+protected!(Transaction, Resource::Transaction, t => Some(t.member_id));
+protected!(MemberTransaction, Resource::Transaction, t => Some(t.member_id));
+// Totals span members, so members do not get them
+protected!(TransactionTotal, Resource::Transaction, _t => None);
+protected!(BankImportRule, Resource::BankImportRule, r => Some(r.member_id));
+protected!(Mandate, Resource::Mandate, m => Some(m.member_id));
+protected!(Payout, Resource::Payout, p => Some(p.member_id));
+protected!(DunningEvent, Resource::DunningEvent, e => Some(e.member_id));
+protected!(Notification, Resource::Notification, n => Some(n.member_id));
+protected!(DonationReceipt, Resource::DonationReceipt, r => Some(r.member_id));
+protected!(AuditEntry, Resource::AuditLog, e => e.member_id);
+protected!(User, Resource::User, _u => None);
+
+/// Wraps a database and checks every operation against
+/// the permissions of the principal. Members only get
+/// their own records from queries.
+pub struct Authorized<DB> {
+    db: Arc<DB>,
+    principal: Principal,
+}
+
+impl<DB> Authorized<DB> {
+    pub fn new(db: Arc<DB>, principal: Principal) -> Self {
+        Self { db, principal }
+    }
+
+    pub fn principal(&self) -> &Principal {
+        &self.principal
+    }
+
+    /// The wrapped database, for settings which are not
+    /// records. Operations on it are not checked.
+    pub fn database(&self) -> &DB {
+        &self.db
+    }
+}
+
+impl<DB> Clone for Authorized<DB> {
+    fn clone(&self) -> Self {
+        Self {
+            db: self.db.clone(),
+            principal: self.principal,
+        }
+    }
+}
+
+#[async_trait]
+impl<T, DB> Query<T> for Authorized<DB>
+where
+    T: Protected + Send + 'static,
+    DB: Query<T> + Send + Sync,
+    DB::Filter: Sync,
+{
+    type Filter = DB::Filter;
+
+    async fn query(&self, filter: &Self::Filter) -> Result<Vec<T>> {
+        self.principal.check(T::RESOURCE, Access::Read)?;
+        let records = self.db.query(filter).await?;
+        Ok(records.into_iter()
+            .filter(|r| self.principal.owns(r.owner()))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl<T, DB> Retrieve<T> for Authorized<DB>
+where
+    T: Protected + Send + 'static,
+    DB: Retrieve<T> + Send + Sync,
+    DB::Key: Send + 'static,
+{
+    type Key = DB::Key;
+
+    async fn retrieve(&self, key: Self::Key) -> Result<T> {
+        self.principal.check(T::RESOURCE, Access::Read)?;
+        let record = self.db.retrieve(key).await?;
+        self.principal.check_record(&record, Access::Read)?;
+        Ok(record)
+    }
+}
+
+#[async_trait]
+impl<T, DB> Insert<T> for Authorized<DB>
+where
+    T: Protected + Send + 'static,
+    DB: Insert<T> + Send + Sync,
+{
+    async fn insert(&self, record: T) -> Result<T> {
+        self.principal.check_record(&record, Access::Write)?;
+        self.db.insert(record).await
+    }
+}
+
+#[async_trait]
+impl<T, DB> Update<T> for Authorized<DB>
+where
+    T: Protected + Send + 'static,
+    DB: Update<T> + Send + Sync,
+{
+    async fn update(&self, record: T) -> Result<T> {
+        self.principal.check_record(&record, Access::Write)?;
+        self.db.update(record).await
+    }
+}
+
+#[async_trait]
+impl<DB> Book for Authorized<DB>
+where
+    DB: Book + Send + Sync,
+{
+    async fn book(&self, booking: Booking) -> Result<(Member, Vec<Transaction>)> {
+        self.principal.check(Resource::Member, Access::Write)?;
+        self.principal.check(Resource::Transaction, Access::Write)?;
+        if !self.principal.owns(Some(booking.member_id)) {
+            return Err(self.principal.denied(Resource::Member, Access::Write).into());
+        }
+        self.db.book(booking).await
+    }
+}
+
+#[async_trait]
+impl<DB> ChangeMember for Authorized<DB>
+where
+    DB: ChangeMember + Send + Sync,
+{
+    async fn change_member(&self, changes: MemberChanges) -> Result<Member> {
+        self.principal.check(Resource::Member, Access::Write)?;
+        if !self.principal.owns(Some(changes.id)) {
+            return Err(self.principal.denied(Resource::Member, Access::Write).into());
+        }
+        self.db.change_member(changes).await
+    }
+}
+
+#[async_trait]
+impl<DB> AddMember for Authorized<DB>
+where
+    DB: AddMember + Send + Sync,
+{
+    async fn add_member(
+        &self,
+        member: Member,
+        rules: Vec<BankImportRule>,
+    ) -> Result<Member> {
+        self.principal.check_record(&member, Access::Write)?;
+        if !rules.is_empty() {
+            self.principal.check(Resource::BankImportRule, Access::Write)?;
+        }
+        self.db.add_member(member, rules).await
+    }
+}
+
+#[async_trait]
+impl<DB> ClaimNotification for Authorized<DB>
+where
+    DB: ClaimNotification + Send + Sync,
+{
+    async fn claim(
+        &self,
+        notification: &Notification,
+        now: NaiveDateTime,
+        stale_before: NaiveDateTime,
+    ) -> Result<Option<Notification>> {
+        self.principal.check_record(notification, Access::Write)?;
+        self.db.claim(notification, now, stale_before).await
+    }
+}
+
+#[async_trait]
+impl<DB> Pseudonymise for Authorized<DB>
+where
+    DB: Pseudonymise + Send + Sync,
+{
+    async fn pseudonymise(&self, p: &Pseudonymisation) -> Result<Member> {
+        self.principal.check(Resource::Member, Access::Write)?;
+        if !self.principal.owns(Some(p.member_id)) {
+            return Err(self.principal.denied(Resource::Member, Access::Write).into());
+        }
+        self.db.pseudonymise(p).await
+    }
+}
+
+#[async_trait]
+impl<T, DB> Delete<T> for Authorized<DB>
+where
+    T: Protected + Send + 'static,
+    DB: Delete<T> + Send + Sync,
+{
+    async fn delete(&self, record: T) -> Result<()> {
+        self.principal.check_record(&record, Access::Write)?;
+        self.db.delete(record).await
+    }
+}
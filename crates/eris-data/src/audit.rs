@@ -0,0 +1,186 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+
+use crate::OptionalId;
+
+/// The kind of change recorded in the audit log
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum AuditAction {
+    #[default]
+    Insert,
+    Update,
+    Delete,
+}
+
+impl fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuditAction::Insert => write!(f, "insert"),
+            AuditAction::Update => write!(f, "update"),
+            AuditAction::Delete => write!(f, "delete"),
+        }
+    }
+}
+
+impl FromStr for AuditAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "insert" => Ok(AuditAction::Insert),
+            "update" => Ok(AuditAction::Update),
+            "delete" => Ok(AuditAction::Delete),
+            _ => Err(anyhow!("unknown audit action: {}", s)),
+        }
+    }
+}
+
+/// The kind of record changed
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum AuditEntity {
+    #[default]
+    Member,
+    BankImportRule,
+    Transaction,
+    User,
+}
+
+impl fmt::Display for AuditEntity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuditEntity::Member => write!(f, "member"),
+            AuditEntity::BankImportRule => write!(f, "bank_import_rule"),
+            AuditEntity::Transaction => write!(f, "transaction"),
+            AuditEntity::User => write!(f, "user"),
+        }
+    }
+}
+
+impl FromStr for AuditEntity {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.replace('-', "_").as_str() {
+            "member" => Ok(AuditEntity::Member),
+            "bank_import_rule" | "rule" => Ok(AuditEntity::BankImportRule),
+            "transaction" => Ok(AuditEntity::Transaction),
+            "user" => Ok(AuditEntity::User),
+            _ => Err(anyhow!("unknown audit entity: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct AuditEntryFilter {
+    pub member_id: Option<u32>,
+    pub entity: Option<AuditEntity>,
+    pub entity_id: Option<String>,
+    pub action: Option<AuditAction>,
+}
+
+/// A change to the database. The old and new state of
+/// the record are stored as JSON. Entries are only ever
+/// appended.
+#[derive(Debug, Clone, Default, FromRow, Serialize, Deserialize)]
+pub struct AuditEntry {
+    #[sqlx(try_from = "i64")]
+    pub id: u32,
+    pub created_at: NaiveDateTime,
+    /// Who made the change, e.g. the login name
+    pub actor: String,
+    /// The command line which made the change
+    pub command: String,
+    pub action: AuditAction,
+    pub entity: AuditEntity,
+    pub entity_id: String,
+    #[sqlx(try_from = "OptionalId")]
+    pub member_id: Option<u32>,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+}
+
+/// A field changed by an update
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: String,
+    pub new: String,
+}
+
+impl AuditEntry {
+    /// Get the fields which were changed. Inserted and
+    /// deleted records list all their fields.
+    pub fn changes(&self) -> Vec<FieldChange> {
+        let parse = |value: &Option<String>| -> serde_json::Map<String, Value> {
+            value.as_deref()
+                .and_then(|v| serde_json::from_str::<Value>(v).ok())
+                .and_then(|v| v.as_object().cloned())
+                .unwrap_or_default()
+        };
+        let old = parse(&self.old_value);
+        let new = parse(&self.new_value);
+
+        let mut fields: Vec<&String> = old.keys().chain(new.keys()).collect();
+        fields.sort();
+        fields.dedup();
+        fields.into_iter()
+            .filter(|field| old.get(*field) != new.get(*field))
+            .map(|field| FieldChange {
+                field: field.to_string(),
+                old: old.get(field).map(format_value).unwrap_or_default(),
+                new: new.get(field).map(format_value).unwrap_or_default(),
+            })
+            .collect()
+    }
+}
+
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => "null".to_string(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changes() {
+        let entry = AuditEntry {
+            action: AuditAction::Update,
+            old_value: Some(r#"{"id":1,"name":"Eris","account":-20.0}"#.into()),
+            new_value: Some(r#"{"id":1,"name":"Eris","account":0.0}"#.into()),
+            ..Default::default()
+        };
+        assert_eq!(entry.changes(), vec![FieldChange {
+            field: "account".to_string(),
+            old: "-20.0".to_string(),
+            new: "0.0".to_string(),
+        }]);
+
+        let entry = AuditEntry {
+            action: AuditAction::Insert,
+            new_value: Some(r#"{"id":1,"name":"Eris"}"#.into()),
+            ..Default::default()
+        };
+        let changes = entry.changes();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[1].old, "");
+        assert_eq!(changes[1].new, "Eris");
+    }
+}
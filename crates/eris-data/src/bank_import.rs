@@ -1,4 +1,7 @@
-use anyhow::Result;
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use hmac::{Hmac, Mac};
 use serde::{Deserialize, Serialize};
 use sha2::Sha256;
 use sqlx::FromRow;
@@ -16,8 +19,121 @@ pub fn hash_iban(iban: &str, name: &str) -> String {
 
     pbkdf2::pbkdf2_hmac::<Sha256>(name_bytes, iban_bytes, 1000, &mut key);
     // Hexdigest the key
-    let hash = hex::encode(key);
-    hash
+    hex::encode(key)
+}
+
+/// How the IBANs of bank import rules are stored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IbanStorage {
+    /// The IBAN in plain text
+    #[default]
+    Plain,
+    /// `hash_iban` of the IBAN and the account name, as
+    /// stored by the legacy implementation
+    Hash,
+    /// A HMAC-SHA256 of the IBAN, keyed with a secret
+    Hmac,
+}
+
+impl fmt::Display for IbanStorage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IbanStorage::Plain => write!(f, "plain"),
+            IbanStorage::Hash => write!(f, "hash"),
+            IbanStorage::Hmac => write!(f, "hmac"),
+        }
+    }
+}
+
+impl FromStr for IbanStorage {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "plain" => Ok(IbanStorage::Plain),
+            "hash" => Ok(IbanStorage::Hash),
+            "hmac" => Ok(IbanStorage::Hmac),
+            _ => Err(anyhow!("unknown IBAN storage: {}", s)),
+        }
+    }
+}
+
+/// Check if a stored value is a hash or HMAC rather
+/// than an IBAN. IBANs start with a country code and are
+/// never 12 or 64 characters long.
+pub fn is_hashed_iban(value: &str) -> bool {
+    (value.len() == 12 || value.len() == 64)
+        && value.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f'))
+}
+
+/// Derives the value stored in bank import rules
+/// for the IBAN of an account.
+#[derive(Debug, Clone, Default)]
+pub struct IbanHasher {
+    storage: IbanStorage,
+    secret: Option<Vec<u8>>,
+}
+
+impl IbanHasher {
+    /// Create a hasher. Storing IBANs as HMAC requires
+    /// a secret.
+    pub fn new(storage: IbanStorage, secret: Option<&str>) -> Result<Self> {
+        let secret = secret
+            .filter(|s| !s.is_empty())
+            .map(|s| s.as_bytes().to_vec());
+        if storage == IbanStorage::Hmac && secret.is_none() {
+            return Err(anyhow!("storing IBANs as HMAC requires a secret"));
+        }
+        Ok(Self { storage, secret })
+    }
+
+    pub fn storage(&self) -> IbanStorage {
+        self.storage
+    }
+
+    /// Check if protecting an IBAN needs the name of the
+    /// account holder. Only hashes include the name.
+    pub fn needs_account_name(&self) -> bool {
+        self.storage == IbanStorage::Hash
+    }
+
+    fn hmac(&self, iban: &str) -> Option<String> {
+        let secret = self.secret.as_ref()?;
+        let iban: String = iban.chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_uppercase();
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret)
+            .expect("HMAC can take a key of any size");
+        mac.update(iban.as_bytes());
+        Some(hex::encode(mac.finalize().into_bytes()))
+    }
+
+    /// Get the value to store in a rule for an account.
+    /// Values which are hashed already are kept.
+    pub fn protect(&self, iban: &str, name: &str) -> String {
+        if is_hashed_iban(iban) {
+            return iban.to_string();
+        }
+        match self.storage {
+            IbanStorage::Plain => iban.to_string(),
+            IbanStorage::Hash => hash_iban(iban, name),
+            IbanStorage::Hmac => self.hmac(iban).unwrap(),
+        }
+    }
+
+    /// Get all values a rule for an account may be stored
+    /// as, regardless of the configured storage. This way
+    /// existing rules keep matching when the storage is
+    /// changed.
+    pub fn candidates(&self, iban: &str, name: &str) -> Vec<String> {
+        let mut candidates = vec![iban.to_string(), hash_iban(iban, name)];
+        if let Some(hmac) = self.hmac(iban) {
+            candidates.push(hmac);
+        }
+        candidates
+    }
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -28,6 +144,7 @@ pub struct BankImportRuleFilter {
 
 #[derive(Debug, Clone, Default, FromRow, Serialize, Deserialize)]
 pub struct BankImportRule {
+    #[sqlx(try_from = "i64")]
     pub member_id: u32,
     pub iban: String,
     pub split_amount: Option<f64>,
@@ -60,13 +177,10 @@ impl BankImportRule {
     /// substring of the transaction subject it will be true.
     /// Comparison is case insensitive.
     pub fn match_subject(&self, subject: &str) -> Option<bool> {
-        if self.match_subject.is_none() {
-            return None
-        }
-        let match_subject = self.match_subject.clone().unwrap();
-        let subject = subject.clone().to_lowercase();
+        let match_subject = self.match_subject.as_ref()?;
+        let subject = subject.to_lowercase();
         
-        Some(subject.contains(&match_subject))
+        Some(subject.contains(match_subject.as_str()))
     }
 }
 
@@ -83,6 +197,35 @@ mod tests {
         assert_eq!(hash.len(), 12);
     }
 
+    #[test]
+    fn test_iban_hasher() {
+        let iban = "DE12345678901234567890";
+        let name = "Eris Discordia";
+
+        let plain = IbanHasher::default();
+        assert_eq!(plain.protect(iban, name), iban);
+        assert_eq!(plain.candidates(iban, name), vec![
+            iban.to_string(),
+            "448a2be23338".to_string(),
+        ]);
+
+        let hash = IbanHasher::new(IbanStorage::Hash, None).unwrap();
+        assert_eq!(hash.protect(iban, name), "448a2be23338");
+        assert_eq!(hash.protect("448a2be23338", name), "448a2be23338");
+
+        assert!(IbanHasher::new(IbanStorage::Hmac, None).is_err());
+        let hmac = IbanHasher::new(IbanStorage::Hmac, Some("fnord")).unwrap();
+        let value = hmac.protect(iban, name);
+        assert!(is_hashed_iban(&value));
+        assert_eq!(value.len(), 64);
+        assert_eq!(hmac.protect("de12 3456 7890 1234 5678 90", ""), value);
+        assert_eq!(hmac.protect(&value, name), value);
+        assert_eq!(hmac.candidates(iban, name)[2], value);
+
+        let other = IbanHasher::new(IbanStorage::Hmac, Some("23")).unwrap();
+        assert_ne!(other.protect(iban, name), value);
+    }
+
     #[test]
     fn test_match_subject() {
         let rule = BankImportRule{
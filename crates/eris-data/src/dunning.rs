@@ -0,0 +1,81 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Escalation levels for members in arrears. A member
+/// is cleared when the arrears were paid.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    sqlx::Type,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum DunningLevel {
+    #[default]
+    Cleared,
+    Reminder,
+    FirstNotice,
+    FinalNotice,
+    BoardReview,
+}
+
+impl fmt::Display for DunningLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DunningLevel::Cleared => write!(f, "cleared"),
+            DunningLevel::Reminder => write!(f, "reminder"),
+            DunningLevel::FirstNotice => write!(f, "first_notice"),
+            DunningLevel::FinalNotice => write!(f, "final_notice"),
+            DunningLevel::BoardReview => write!(f, "board_review"),
+        }
+    }
+}
+
+impl FromStr for DunningLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "cleared" => Ok(DunningLevel::Cleared),
+            "reminder" => Ok(DunningLevel::Reminder),
+            "first_notice" => Ok(DunningLevel::FirstNotice),
+            "final_notice" => Ok(DunningLevel::FinalNotice),
+            "board_review" => Ok(DunningLevel::BoardReview),
+            _ => Err(anyhow!("unknown dunning level: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct DunningEventFilter {
+    pub id: Option<u32>,
+    pub member_id: Option<u32>,
+    pub level: Option<DunningLevel>,
+    pub date_after: Option<NaiveDate>,
+    pub date_before: Option<NaiveDate>,
+}
+
+/// A dunning event records the level a member
+/// reached at a date and the balance at that time.
+#[derive(Debug, Clone, Default, FromRow, Serialize, Deserialize)]
+pub struct DunningEvent {
+    #[sqlx(try_from = "i64")]
+    pub id: u32,
+    #[sqlx(try_from = "i64")]
+    pub member_id: u32,
+    pub level: DunningLevel,
+    pub date: NaiveDate,
+    pub balance: f64,
+}
@@ -0,0 +1,46 @@
+use std::num::TryFromIntError;
+
+use sqlx::{
+    database::{Database, HasValueRef},
+    decode::Decode,
+    error::BoxDynError,
+    types::Type,
+};
+
+/// An optional reference to a record, like the member of
+/// a user. Ids are stored as BIGINT, as PostgreSQL has no
+/// unsigned integers, and read with `try_from`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OptionalId(pub Option<i64>);
+
+impl TryFrom<OptionalId> for Option<u32> {
+    type Error = TryFromIntError;
+
+    fn try_from(id: OptionalId) -> Result<Self, Self::Error> {
+        id.0.map(u32::try_from).transpose()
+    }
+}
+
+impl<DB: Database> Type<DB> for OptionalId
+where
+    i64: Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo {
+        <i64 as Type<DB>>::type_info()
+    }
+
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        <i64 as Type<DB>>::compatible(ty)
+    }
+}
+
+impl<'r, DB: Database> Decode<'r, DB> for OptionalId
+where
+    Option<i64>: Decode<'r, DB>,
+{
+    fn decode(
+        value: <DB as HasValueRef<'r>>::ValueRef,
+    ) -> Result<Self, BoxDynError> {
+        Ok(Self(<Option<i64> as Decode<'r, DB>>::decode(value)?))
+    }
+}
@@ -11,3 +11,42 @@ pub use transactions::*;
 
 mod bank_import;
 pub use bank_import::*;
+
+mod mandates;
+pub use mandates::*;
+
+mod payouts;
+pub use payouts::*;
+
+mod dunning;
+pub use dunning::*;
+
+mod notifications;
+pub use notifications::*;
+
+mod receipts;
+pub use receipts::*;
+
+mod member_records;
+pub use member_records::*;
+
+mod audit;
+pub use audit::*;
+
+mod users;
+pub use users::*;
+
+mod pseudonymise;
+pub use pseudonymise::*;
+
+// Database types
+mod ids;
+pub use ids::*;
+
+// Permissions
+pub mod access;
+pub use access::{Access, Authorized, PermissionDenied, Principal, Resource};
+
+// In-memory database
+pub mod memory;
+pub use memory::MemoryDb;
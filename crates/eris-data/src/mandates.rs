@@ -0,0 +1,118 @@
+use anyhow::Result;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use crate::{Member, Retrieve};
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct MandateFilter {
+    pub id: Option<u32>,
+    pub member_id: Option<u32>,
+    pub reference: Option<String>,
+}
+
+/// A SEPA direct debit mandate signed by a member.
+#[derive(Debug, Clone, Default, FromRow, Serialize, Deserialize)]
+pub struct Mandate {
+    #[sqlx(try_from = "i64")]
+    pub id: u32,
+    #[sqlx(try_from = "i64")]
+    pub member_id: u32,
+    pub reference: String,
+    pub iban: String,
+    pub account_holder: String,
+    pub signed_at: NaiveDate,
+    pub amended_at: Option<NaiveDate>,
+    pub original_iban: Option<String>,
+    pub last_used_at: Option<NaiveDate>,
+    pub revoked_at: Option<NaiveDate>,
+}
+
+impl Mandate {
+    /// Create the mandate reference for a member. The revision
+    /// is increased whenever a member signs a new mandate.
+    pub fn make_reference(member_id: u32, revision: u32) -> String {
+        format!("ERIS-{:06}-{:02}", member_id, revision)
+    }
+
+    /// The revision of a reference made by `make_reference`
+    pub fn revision(&self) -> Option<u32> {
+        let (_, revision) = self.reference.strip_prefix("ERIS-")?
+            .split_once('-')?;
+        revision.parse().ok()
+    }
+
+    /// Find a mandate reference in the subject of a bank
+    /// transaction, as made by `make_reference`. Member ids
+    /// are padded to at least six digits.
+    pub fn find_reference(subject: &str) -> Option<String> {
+        let subject = subject.to_uppercase();
+        subject.match_indices("ERIS-").find_map(|(pos, prefix)| {
+            let rest = &subject[pos + prefix.len()..];
+            let member: String = rest.chars()
+                .take_while(|c| c.is_ascii_digit())
+                .collect();
+            let revision: String = rest[member.len()..]
+                .strip_prefix('-')?
+                .chars()
+                .take_while(|c| c.is_ascii_digit())
+                .collect();
+            if member.len() < 6 || revision.len() < 2 {
+                return None;
+            }
+            Some(format!("ERIS-{}-{}", member, revision))
+        })
+    }
+
+    /// Get associated member
+    pub async fn get_member<DB>(&self, db: &DB) -> Result<Member>
+    where
+        DB: Retrieve<Member, Key = u32>,
+    {
+        db.retrieve(self.member_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_make_reference() {
+        assert_eq!(Mandate::make_reference(23, 1), "ERIS-000023-01");
+        assert_eq!(Mandate::make_reference(4223, 12), "ERIS-004223-12");
+        assert_eq!(Mandate::make_reference(1234567, 3), "ERIS-1234567-03");
+    }
+
+    #[test]
+    fn test_revision() {
+        let mandate = Mandate {
+            reference: Mandate::make_reference(1234567, 12),
+            ..Default::default()
+        };
+        assert_eq!(mandate.revision(), Some(12));
+        let mandate = Mandate {
+            reference: "MANUAL-1".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(mandate.revision(), None);
+    }
+
+    #[test]
+    fn test_find_reference() {
+        assert_eq!(
+            Mandate::find_reference("SEPA-Lastschrift MREF: eris-000023-01 Beitrag"),
+            Some("ERIS-000023-01".to_string()));
+        assert_eq!(
+            Mandate::find_reference("ERIS-M-23 ERIS-000023-01"),
+            Some("ERIS-000023-01".to_string()));
+        assert_eq!(
+            Mandate::find_reference("MREF ERIS-1234567-03"),
+            Some("ERIS-1234567-03".to_string()));
+        assert_eq!(Mandate::find_reference("ERIS-PAYOUT-000001"), None);
+        assert_eq!(Mandate::find_reference("ERIS-00023-01"), None);
+        assert_eq!(Mandate::find_reference("ERIS-000023"), None);
+        assert_eq!(Mandate::find_reference("Mitgliedsbeitrag"), None);
+    }
+}
@@ -0,0 +1,317 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::{BankImportRule, Member};
+
+/// A bank import rule of an exported member. In CSV files
+/// the rules are written as `IBAN[:split_amount[:match_subject]]`
+/// and separated by `;`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MemberIbanRule {
+    pub iban: String,
+    #[serde(default)]
+    pub split_amount: Option<f64>,
+    #[serde(default)]
+    pub match_subject: Option<String>,
+    /// The name of the account holder, used for hashing
+    /// the IBAN. It is only read from JSON files.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_holder: Option<String>,
+}
+
+impl MemberIbanRule {
+    /// Parse a `;` separated list of rules
+    pub fn parse_list(rules: &str) -> Result<Vec<Self>> {
+        rules.split(';')
+            .map(str::trim)
+            .filter(|r| !r.is_empty())
+            .map(str::parse)
+            .collect()
+    }
+
+    /// Format rules as `;` separated list
+    pub fn format_list(rules: &[Self]) -> String {
+        rules.iter()
+            .map(|r| r.to_string())
+            .collect::<Vec<_>>()
+            .join(";")
+    }
+}
+
+impl fmt::Display for MemberIbanRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.iban)?;
+        if self.split_amount.is_none() && self.match_subject.is_none() {
+            return Ok(());
+        }
+        write!(f, ":")?;
+        if let Some(amount) = self.split_amount {
+            write!(f, "{}", amount)?;
+        }
+        if let Some(subject) = &self.match_subject {
+            write!(f, ":{}", subject)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for MemberIbanRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.splitn(3, ':');
+        let iban = parts.next().unwrap_or("").trim().to_string();
+        let split_amount = match parts.next().map(str::trim) {
+            None | Some("") => None,
+            Some(amount) => Some(amount.parse::<f64>().map_err(|_| {
+                anyhow!("invalid split amount in rule {}: {}", s, amount)
+            })?),
+        };
+        let match_subject = parts.next()
+            .map(|subject| subject.trim().to_string())
+            .filter(|subject| !subject.is_empty());
+        Ok(Self {
+            iban,
+            split_amount,
+            match_subject,
+            account_holder: None,
+        })
+    }
+}
+
+impl From<BankImportRule> for MemberIbanRule {
+    fn from(rule: BankImportRule) -> Self {
+        Self {
+            iban: rule.iban,
+            split_amount: rule.split_amount,
+            match_subject: rule.match_subject,
+            account_holder: None,
+        }
+    }
+}
+
+/// A member with its bank import rules as used in bulk
+/// imports and exports. Optional fields are filled with
+/// the same defaults as when adding a single member.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MemberRecord {
+    /// The id in the exporting database. It is ignored
+    /// when importing, as new members get new ids.
+    #[serde(default)]
+    pub id: Option<u32>,
+    pub name: String,
+    pub email: String,
+    #[serde(default)]
+    pub notes: String,
+    #[serde(default)]
+    pub membership_start: Option<NaiveDate>,
+    #[serde(default)]
+    pub membership_end: Option<NaiveDate>,
+    #[serde(default)]
+    pub fee: Option<f64>,
+    #[serde(default)]
+    pub interval: Option<u8>,
+    #[serde(default)]
+    pub account: Option<f64>,
+    /// Archived members are imported archived
+    #[serde(default)]
+    pub archived_at: Option<NaiveDate>,
+    #[serde(default)]
+    pub bank_import_rules: Vec<MemberIbanRule>,
+}
+
+impl MemberRecord {
+    /// Create the record of an existing member
+    pub fn new(member: Member, rules: Vec<BankImportRule>) -> Self {
+        Self {
+            id: Some(member.id),
+            name: member.name,
+            email: member.email,
+            notes: member.notes,
+            membership_start: Some(member.membership_start),
+            membership_end: member.membership_end,
+            fee: Some(member.fee),
+            interval: Some(member.interval),
+            account: Some(member.account),
+            archived_at: member.archived_at,
+            bank_import_rules: rules.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Check the record and return all problems found
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = vec![];
+        if self.name.trim().is_empty() {
+            errors.push("name is empty".to_string());
+        }
+        if !is_valid_email(&self.email) {
+            errors.push(format!("invalid email address: {:?}", self.email));
+        }
+        if let Some(fee) = self.fee {
+            if fee < 0.0 || !fee.is_finite() {
+                errors.push(format!("invalid fee: {}", fee));
+            }
+        }
+        if self.interval == Some(0) {
+            errors.push("interval must be at least one month".to_string());
+        }
+        if let (Some(start), Some(end)) =
+            (self.membership_start, self.membership_end)
+        {
+            if end < start {
+                errors.push(format!(
+                    "membership ends before it starts: {} < {}", end, start));
+            }
+        }
+        for rule in &self.bank_import_rules {
+            if rule.iban.is_empty()
+                || !rule.iban.chars().all(|c| c.is_ascii_alphanumeric())
+            {
+                errors.push(format!("invalid IBAN: {:?}", rule.iban));
+            }
+            if let Some(amount) = rule.split_amount {
+                if amount <= 0.0 {
+                    errors.push(format!(
+                        "invalid split amount for {}: {}", rule.iban, amount));
+                }
+            }
+        }
+        errors
+    }
+
+    /// Create the member and its rules with the account
+    /// holder, if known. The rules do not have a member id
+    /// until the member is inserted.
+    pub fn into_member(
+        self,
+        today: NaiveDate,
+    ) -> (Member, Vec<(BankImportRule, Option<String>)>) {
+        let member = Member {
+            name: self.name.trim().to_string(),
+            email: self.email.trim().to_string(),
+            notes: self.notes,
+            membership_start: self.membership_start.unwrap_or(today),
+            membership_end: self.membership_end,
+            fee: self.fee.unwrap_or(20.0),
+            interval: self.interval.unwrap_or(1),
+            account: self.account.unwrap_or(0.0),
+            archived_at: self.archived_at,
+            ..Default::default()
+        };
+        let rules = self.bank_import_rules
+            .into_iter()
+            .map(|rule| (BankImportRule {
+                iban: rule.iban,
+                split_amount: rule.split_amount,
+                match_subject: rule.match_subject,
+                ..Default::default()
+            }, rule.account_holder))
+            .collect();
+        (member, rules)
+    }
+}
+
+/// A plausibility check, not a full validation of
+/// the address.
+fn is_valid_email(email: &str) -> bool {
+    let email = email.trim();
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !email.contains(char::is_whitespace)
+                && !domain.contains('@')
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iban_rule_roundtrip() {
+        let rules = MemberIbanRule::parse_list(
+            "DE1111;DE2222:10.5; DE3333::mitgliedsbeitrag: eris").unwrap();
+        assert_eq!(rules.len(), 3);
+        assert_eq!(rules[0].split_amount, None);
+        assert_eq!(rules[1].split_amount, Some(10.5));
+        assert_eq!(rules[2].split_amount, None);
+        assert_eq!(
+            rules[2].match_subject.as_deref(),
+            Some("mitgliedsbeitrag: eris"),
+        );
+        let formatted = MemberIbanRule::format_list(&rules);
+        assert_eq!(
+            formatted, "DE1111;DE2222:10.5;DE3333::mitgliedsbeitrag: eris");
+        assert_eq!(MemberIbanRule::parse_list(&formatted).unwrap(), rules);
+
+        assert!(MemberIbanRule::parse_list("DE1111:ten").is_err());
+        assert!(MemberIbanRule::parse_list("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_validate() {
+        let record = MemberRecord {
+            name: "Eris".to_string(),
+            email: "eris@discordia.ccc".to_string(),
+            ..Default::default()
+        };
+        assert!(record.validate().is_empty());
+
+        let record = MemberRecord {
+            name: " ".to_string(),
+            email: "eris@localhost".to_string(),
+            fee: Some(-1.0),
+            interval: Some(0),
+            bank_import_rules: vec![MemberIbanRule {
+                iban: "DE 1111".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert_eq!(record.validate().len(), 5);
+    }
+
+    #[test]
+    fn test_into_member() {
+        let today = NaiveDate::from_ymd_opt(2023, 5, 1).unwrap();
+        let record = MemberRecord {
+            id: Some(23),
+            name: "Eris".to_string(),
+            email: "eris@discordia.ccc".to_string(),
+            bank_import_rules: vec![MemberIbanRule {
+                iban: "DE1111".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let (member, rules) = record.into_member(today);
+        assert_eq!(member.id, 0);
+        assert_eq!(member.membership_start, today);
+        assert_eq!(member.fee, 20.0);
+        assert_eq!(member.interval, 1);
+        assert_eq!(rules[0].0.iban, "DE1111");
+        let rules = rules.into_iter().map(|(rule, _)| rule).collect();
+
+        let record = MemberRecord::new(member, rules);
+        assert_eq!(record.fee, Some(20.0));
+        assert_eq!(record.bank_import_rules[0].iban, "DE1111");
+        assert_eq!(record.archived_at, None);
+
+        // Archived members stay archived
+        let record = MemberRecord {
+            archived_at: Some(today),
+            ..record
+        };
+        let (member, _) = record.into_member(today);
+        assert_eq!(member.archived_at, Some(today));
+        assert_eq!(MemberRecord::new(member, vec![]).archived_at, Some(today));
+    }
+}
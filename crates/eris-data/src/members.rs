@@ -1,4 +1,7 @@
-use anyhow::Result;
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
 use chrono::NaiveDate;
 use sqlx::FromRow;
 use serde::{Serialize, Deserialize};
@@ -6,20 +9,114 @@ use serde::{Serialize, Deserialize};
 use crate::{
     BankImportRuleFilter,
     BankImportRule,
+    DunningEvent,
+    DunningEventFilter,
+    Mandate,
+    MandateFilter,
     Query,
     Transaction,
     TransactionFilter,
 };
 
+/// Payment references start with this prefix, followed
+/// by the member id.
+pub const PAYMENT_REFERENCE_PREFIX: &str = "ERIS-M-";
+
+/// Find a payment reference in the subject of a bank
+/// transaction and return the member id.
+pub fn find_payment_reference(subject: &str) -> Option<u32> {
+    let subject = subject.to_uppercase();
+    subject.match_indices(PAYMENT_REFERENCE_PREFIX)
+        .find_map(|(pos, prefix)| {
+            let digits: String = subject[pos + prefix.len()..]
+                .chars()
+                .take_while(|c| c.is_ascii_digit())
+                .collect();
+            digits.parse().ok()
+        })
+}
+
+/// The order members are listed in. Members with
+/// the same value are ordered by id.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemberSort {
+    #[default]
+    Id,
+    Name,
+    Balance,
+    Fee,
+    MembershipStart,
+    LastPayment,
+}
+
+impl fmt::Display for MemberSort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MemberSort::Id => write!(f, "id"),
+            MemberSort::Name => write!(f, "name"),
+            MemberSort::Balance => write!(f, "balance"),
+            MemberSort::Fee => write!(f, "fee"),
+            MemberSort::MembershipStart => write!(f, "membership_start"),
+            MemberSort::LastPayment => write!(f, "last_payment"),
+        }
+    }
+}
+
+impl FromStr for MemberSort {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "id" => Ok(MemberSort::Id),
+            "name" => Ok(MemberSort::Name),
+            "balance" => Ok(MemberSort::Balance),
+            "fee" => Ok(MemberSort::Fee),
+            "membership_start" => Ok(MemberSort::MembershipStart),
+            "last_payment" => Ok(MemberSort::LastPayment),
+            _ => Err(anyhow!("unknown member sort order: {}", s)),
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct MemberFilter {
     pub id: Option<u32>,
     pub name: Option<String>,
     pub email: Option<String>,
+    /// Archived members are only listed if set
+    pub include_archived: bool,
+    /// Members whose membership includes the date
+    pub active_at: Option<NaiveDate>,
+    /// Members whose membership does not include the date
+    pub inactive_at: Option<NaiveDate>,
+    /// Members with an account balance below the amount
+    pub balance_below: Option<f64>,
+    /// Members with an account balance above the amount
+    pub balance_above: Option<f64>,
+    pub fee: Option<f64>,
+    pub interval: Option<u8>,
+    pub membership_start_after: Option<NaiveDate>,
+    pub membership_start_before: Option<NaiveDate>,
+    /// Only members with an end of membership are
+    /// matched by the end dates.
+    pub membership_end_after: Option<NaiveDate>,
+    pub membership_end_before: Option<NaiveDate>,
+    /// Members with or without bank import rules
+    pub has_iban_rule: Option<bool>,
+    /// Text in the notes, the case is ignored
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub sort: MemberSort,
+    #[serde(default)]
+    pub descending: bool,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
 }
 
 #[derive(Debug, Clone, Default, FromRow, Serialize, Deserialize)]
 pub struct Member {
+    #[sqlx(try_from = "i64")]
     pub id: u32,
     pub name: String,
     pub email: String,
@@ -27,12 +124,70 @@ pub struct Member {
     pub membership_start: NaiveDate,
     pub membership_end: Option<NaiveDate>,
     pub fee: f64,
+    #[sqlx(try_from = "i64")]
     pub interval: u8,
     pub last_payment_at: NaiveDate,
     pub last_bank_transaction_at: NaiveDate,
+    #[sqlx(try_from = "i64")]
     pub last_bank_transaction_number: u32,
     pub account_calculated_at: NaiveDate,
     pub account: f64,
+    /// Former members are archived instead of deleted
+    pub archived_at: Option<NaiveDate>,
+}
+
+/// Changes to the attributes of a member which are edited
+/// by hand. Attributes which are not set are kept, as are
+/// the balance and the dates maintained by bookings.
+#[derive(Debug, Clone, Default)]
+pub struct MemberChanges {
+    pub id: u32,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub notes: Option<String>,
+    pub membership_start: Option<NaiveDate>,
+    /// Set to `Some(None)` to clear the end of membership
+    pub membership_end: Option<Option<NaiveDate>>,
+    pub fee: Option<f64>,
+    pub interval: Option<u8>,
+}
+
+impl MemberChanges {
+    /// Apply the changes to a member
+    pub fn apply(&self, member: Member) -> Member {
+        Member {
+            name: self.name.clone().unwrap_or(member.name),
+            email: self.email.clone().unwrap_or(member.email),
+            notes: self.notes.clone().unwrap_or(member.notes),
+            membership_start: self.membership_start
+                .unwrap_or(member.membership_start),
+            membership_end: self.membership_end
+                .unwrap_or(member.membership_end),
+            fee: self.fee.unwrap_or(member.fee),
+            interval: self.interval.unwrap_or(member.interval),
+            ..member
+        }
+    }
+}
+
+/// Change only the edited attributes of a member, so
+/// bookings made since the member was read are kept.
+/// Returns the updated member.
+#[async_trait]
+pub trait ChangeMember {
+    async fn change_member(&self, changes: MemberChanges) -> Result<Member>;
+}
+
+/// Insert a new member together with their bank import
+/// rules, so either all or none are stored. The member id
+/// of the rules is set. Returns the inserted member.
+#[async_trait]
+pub trait AddMember {
+    async fn add_member(
+        &self,
+        member: Member,
+        rules: Vec<BankImportRule>,
+    ) -> Result<Member>;
 }
 
 impl Member {
@@ -66,6 +221,47 @@ impl Member {
         Ok(transactions)
     }
 
+    /// Get all direct debit mandates signed by the member
+    pub async fn get_mandates<DB>(
+        &self,
+        db: &DB,
+    ) -> Result<Vec<Mandate>>
+    where
+         DB: Query<Mandate, Filter=MandateFilter>,
+    {
+        let mandates = db.query(&MandateFilter{
+            member_id: Some(self.id),
+            ..Default::default()
+        }).await?;
+        Ok(mandates)
+    }
+
+    /// Get the dunning history of the member
+    pub async fn get_dunning_events<DB>(
+        &self,
+        db: &DB,
+    ) -> Result<Vec<DunningEvent>>
+    where
+         DB: Query<DunningEvent, Filter=DunningEventFilter>,
+    {
+        let events = db.query(&DunningEventFilter{
+            member_id: Some(self.id),
+            ..Default::default()
+        }).await?;
+        Ok(events)
+    }
+
+    /// The reference members put into the subject of
+    /// their transfers, so the bank import can match them.
+    pub fn payment_reference(&self) -> String {
+        format!("{}{:06}", PAYMENT_REFERENCE_PREFIX, self.id)
+    }
+
+    /// Suggested subject for a membership fee transfer
+    pub fn transfer_subject(&self) -> String {
+        format!("{} Mitgliedsbeitrag", self.payment_reference())
+    }
+
     // Check if member is active
     pub fn is_active(&self, date: NaiveDate) -> bool {
         if date < self.membership_start {
@@ -80,3 +276,25 @@ impl Member {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payment_reference() {
+        let member = Member {
+            id: 23,
+            ..Default::default()
+        };
+        assert_eq!(member.payment_reference(), "ERIS-M-000023");
+        assert_eq!(
+            find_payment_reference(&member.transfer_subject()),
+            Some(23));
+        assert_eq!(
+            find_payment_reference("Beitrag eris-m-42 Juni"),
+            Some(42));
+        assert_eq!(find_payment_reference("ERIS-M- 42"), None);
+        assert_eq!(find_payment_reference("Mitgliedsbeitrag"), None);
+    }
+}
@@ -0,0 +1,1425 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Mutex, MutexGuard},
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use thiserror::Error as ThisError;
+
+use crate::{
+    AddMember,
+    AlreadyCalculated,
+    AuditEntry,
+    AuditEntryFilter,
+    BankImportRule,
+    BankImportRuleFilter,
+    Book,
+    Booking,
+    ChangeMember,
+    ClaimNotification,
+    Delete,
+    DonationReceipt,
+    DonationReceiptFilter,
+    DunningEvent,
+    DunningEventFilter,
+    Insert,
+    Mandate,
+    MandateFilter,
+    Member,
+    MemberChanges,
+    MemberFilter,
+    MemberSort,
+    MemberTransaction,
+    Notification,
+    NotificationFilter,
+    Payout,
+    PayoutFilter,
+    PayoutState,
+    Pseudonymisation,
+    Pseudonymise,
+    Query,
+    Retrieve,
+    Transaction,
+    TransactionFilter,
+    TransactionGroup,
+    TransactionTotal,
+    TransactionTotalFilter,
+    Update,
+    User,
+    UserFilter,
+};
+
+/// Errors of the in-memory database. They correspond to
+/// the constraints of the database schema.
+#[derive(ThisError, Debug)]
+pub enum Error {
+    #[error("{0} {1} not found")]
+    NotFound(&'static str, String),
+    #[error("{0} {1} already exists")]
+    Duplicate(&'static str, String),
+    #[error("member {0} does not exist")]
+    UnknownMember(u32),
+    #[error("member {0} has donation receipts, which must be deleted first")]
+    Restricted(u32),
+}
+
+/// Records identified by a numeric id
+trait Row: Clone {
+    const NAME: &'static str;
+
+    fn id(&self) -> u32;
+    fn set_id(&mut self, id: u32);
+}
+
+macro_rules! row {
+    ($type:ty, $name:expr) => {
+        impl Row for $type {
+            const NAME: &'static str = $name;
+
+            fn id(&self) -> u32 {
+                self.id
+            }
+
+            fn set_id(&mut self, id: u32) {
+                self.id = id;
+            }
+        }
+    };
+}
+
+row!(Member, "member");
+row!(Transaction, "transaction");
+row!(Mandate, "mandate");
+row!(Payout, "payout");
+row!(DunningEvent, "dunning event");
+row!(Notification, "notification");
+row!(DonationReceipt, "donation receipt");
+row!(User, "user");
+
+/// The rows of a table ordered by id. Ids are assigned
+/// on insert and are not reused after a delete.
+#[derive(Debug, Clone)]
+struct Table<T> {
+    rows: Vec<T>,
+    last_id: u32,
+}
+
+impl<T> Default for Table<T> {
+    fn default() -> Self {
+        Self {
+            rows: vec![],
+            last_id: 0,
+        }
+    }
+}
+
+impl<T: Row> Table<T> {
+    fn get(&self, id: u32) -> Result<T> {
+        let row = self.rows.iter()
+            .find(|r| r.id() == id)
+            .ok_or(Error::NotFound(T::NAME, id.to_string()))?;
+        Ok(row.clone())
+    }
+
+    fn filter<F>(&self, f: F) -> Vec<T>
+    where
+        F: Fn(&T) -> bool,
+    {
+        self.rows.iter().filter(|r| f(r)).cloned().collect()
+    }
+
+    fn insert(&mut self, mut row: T) -> T {
+        self.last_id += 1;
+        row.set_id(self.last_id);
+        self.rows.push(row.clone());
+        row
+    }
+
+    /// Add a row with the id it has in another database
+    fn load(&mut self, row: T) {
+        self.last_id = self.last_id.max(row.id());
+        self.rows.push(row);
+    }
+
+    /// Replace the row with the same id. Fields which can
+    /// not be changed are taken from the stored row.
+    fn update<F>(&mut self, row: T, merge: F) -> Result<T>
+    where
+        F: Fn(T, &T) -> T,
+    {
+        let stored = self.rows.iter_mut()
+            .find(|r| r.id() == row.id())
+            .ok_or(Error::NotFound(T::NAME, row.id().to_string()))?;
+        *stored = merge(row, stored);
+        Ok(stored.clone())
+    }
+
+    fn delete(&mut self, id: u32) {
+        self.rows.retain(|r| r.id() != id);
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Tables {
+    members: Table<Member>,
+    bank_import_rules: Vec<BankImportRule>,
+    transactions: Table<Transaction>,
+    mandates: Table<Mandate>,
+    payouts: Table<Payout>,
+    dunning_events: Table<DunningEvent>,
+    notifications: Table<Notification>,
+    donation_receipts: Table<DonationReceipt>,
+    users: Table<User>,
+}
+
+impl Tables {
+    /// Check the member referenced by a record exists
+    fn check_member(&self, member_id: u32) -> Result<()> {
+        if !self.members.rows.iter().any(|m| m.id == member_id) {
+            return Err(Error::UnknownMember(member_id).into());
+        }
+        Ok(())
+    }
+}
+
+/// Match a pattern like SQL `LIKE` does, ignoring case:
+/// `%` matches any text and `_` a single character.
+fn like(pattern: &str, value: &str) -> bool {
+    fn matches(pattern: &[char], value: &[char]) -> bool {
+        match pattern.split_first() {
+            None => value.is_empty(),
+            Some(('%', rest)) => {
+                (0..=value.len()).any(|i| matches(rest, &value[i..]))
+            }
+            Some(('_', rest)) => {
+                !value.is_empty() && matches(rest, &value[1..])
+            }
+            Some((c, rest)) => {
+                value.first() == Some(c) && matches(rest, &value[1..])
+            }
+        }
+    }
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let value: Vec<char> = value.to_lowercase().chars().collect();
+    matches(&pattern, &value)
+}
+
+/// Check if the value contains the text, ignoring the case
+fn contains(text: &str, value: &str) -> bool {
+    value.to_lowercase().contains(&text.to_lowercase())
+}
+
+/// A database keeping all records in memory. It implements
+/// the same operations as the SQL database with the same
+/// filters, order and constraints, so the accounting can be
+/// tested and tried without changing the database.
+///
+/// Changes are not recorded in an audit log.
+#[derive(Debug, Default)]
+pub struct MemoryDb {
+    tables: Mutex<Tables>,
+}
+
+impl MemoryDb {
+    /// Create an empty database
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Copy all records except for the users from another
+    /// database. The records keep their ids, so changes made
+    /// in memory can be compared with the original.
+    pub async fn load<DB>(db: &DB) -> Result<Self>
+    where
+        DB: Query<Member, Filter = MemberFilter>
+            + Query<BankImportRule, Filter = BankImportRuleFilter>
+            + Query<Transaction, Filter = TransactionFilter>
+            + Query<Mandate, Filter = MandateFilter>
+            + Query<Payout, Filter = PayoutFilter>
+            + Query<DunningEvent, Filter = DunningEventFilter>
+            + Query<Notification, Filter = NotificationFilter>
+            + Query<DonationReceipt, Filter = DonationReceiptFilter>,
+    {
+        let members: Vec<Member> = db.query(&MemberFilter {
+            include_archived: true,
+            ..Default::default()
+        }).await?;
+        let bank_import_rules: Vec<BankImportRule> =
+            db.query(&BankImportRuleFilter::default()).await?;
+        let transactions: Vec<Transaction> =
+            db.query(&TransactionFilter::default()).await?;
+        let mandates: Vec<Mandate> =
+            db.query(&MandateFilter::default()).await?;
+        let payouts: Vec<Payout> =
+            db.query(&PayoutFilter::default()).await?;
+        let dunning_events: Vec<DunningEvent> =
+            db.query(&DunningEventFilter::default()).await?;
+        let notifications: Vec<Notification> =
+            db.query(&NotificationFilter::default()).await?;
+        let donation_receipts: Vec<DonationReceipt> =
+            db.query(&DonationReceiptFilter::default()).await?;
+
+        let mut tables = Tables {
+            bank_import_rules,
+            ..Default::default()
+        };
+        members.into_iter().for_each(|r| tables.members.load(r));
+        transactions.into_iter().for_each(|r| tables.transactions.load(r));
+        mandates.into_iter().for_each(|r| tables.mandates.load(r));
+        payouts.into_iter().for_each(|r| tables.payouts.load(r));
+        dunning_events.into_iter()
+            .for_each(|r| tables.dunning_events.load(r));
+        notifications.into_iter()
+            .for_each(|r| tables.notifications.load(r));
+        donation_receipts.into_iter()
+            .for_each(|r| tables.donation_receipts.load(r));
+
+        // Rows are kept in the order of their ids
+        tables.dunning_events.rows.sort_by_key(|r| r.id);
+        tables.donation_receipts.rows.sort_by_key(|r| r.id);
+
+        Ok(Self {
+            tables: Mutex::new(tables),
+        })
+    }
+
+    fn tables(&self) -> MutexGuard<'_, Tables> {
+        self.tables.lock().unwrap()
+    }
+}
+
+// Members
+
+#[async_trait]
+impl Query<Member> for MemoryDb {
+    type Filter = MemberFilter;
+    async fn query(&self, filter: &Self::Filter) -> Result<Vec<Member>> {
+        let tables = self.tables();
+        let has_iban_rule = |m: &Member| {
+            tables.bank_import_rules.iter().any(|r| r.member_id == m.id)
+        };
+        let mut members = tables.members.filter(|m| {
+            filter.id.is_none_or(|id| m.id == id)
+                && filter.name.as_ref()
+                    .is_none_or(|name| contains(name, &m.name))
+                && filter.email.as_ref()
+                    .is_none_or(|email| like(email, &m.email))
+                && (filter.include_archived || m.archived_at.is_none())
+                && filter.active_at.is_none_or(|date| m.is_active(date))
+                && filter.inactive_at.is_none_or(|date| !m.is_active(date))
+                && filter.balance_below.is_none_or(|amount| m.account < amount)
+                && filter.balance_above.is_none_or(|amount| m.account > amount)
+                && filter.fee.is_none_or(|fee| m.fee == fee)
+                && filter.interval.is_none_or(|interval| m.interval == interval)
+                && filter.membership_start_after
+                    .is_none_or(|date| m.membership_start >= date)
+                && filter.membership_start_before
+                    .is_none_or(|date| m.membership_start <= date)
+                && filter.membership_end_after
+                    .is_none_or(|date| m.membership_end.is_some_and(|end| end >= date))
+                && filter.membership_end_before
+                    .is_none_or(|date| m.membership_end.is_some_and(|end| end <= date))
+                && filter.has_iban_rule.is_none_or(|has| has_iban_rule(m) == has)
+                && filter.notes.as_ref()
+                    .is_none_or(|notes| contains(notes, &m.notes))
+        });
+
+        let order = |a: &Member, b: &Member| match filter.sort {
+            MemberSort::Id => a.id.cmp(&b.id),
+            MemberSort::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            MemberSort::Balance => a.account.total_cmp(&b.account),
+            MemberSort::Fee => a.fee.total_cmp(&b.fee),
+            MemberSort::MembershipStart => a.membership_start.cmp(&b.membership_start),
+            MemberSort::LastPayment => a.last_payment_at.cmp(&b.last_payment_at),
+        };
+        members.sort_by(|a, b| {
+            let ord = order(a, b);
+            let ord = if filter.descending { ord.reverse() } else { ord };
+            ord.then(a.id.cmp(&b.id))
+        });
+
+        let offset = filter.offset.unwrap_or(0) as usize;
+        let limit = filter.limit.map_or(usize::MAX, |limit| limit as usize);
+        Ok(members.into_iter().skip(offset).take(limit).collect())
+    }
+}
+
+#[async_trait]
+impl Retrieve<Member> for MemoryDb {
+    type Key = u32;
+    async fn retrieve(&self, member_id: Self::Key) -> Result<Member> {
+        self.tables().members.get(member_id)
+    }
+}
+
+#[async_trait]
+impl Insert<Member> for MemoryDb {
+    async fn insert(&self, member: Member) -> Result<Member> {
+        Ok(self.tables().members.insert(member))
+    }
+}
+
+#[async_trait]
+impl Update<Member> for MemoryDb {
+    async fn update(&self, member: Member) -> Result<Member> {
+        self.tables().members.update(member, |member, _| member)
+    }
+}
+
+#[async_trait]
+impl ChangeMember for MemoryDb {
+    async fn change_member(&self, changes: MemberChanges) -> Result<Member> {
+        let mut tables = self.tables();
+        let member = tables.members.get(changes.id)?;
+        tables.members.update(member, |_, stored| changes.apply(stored.clone()))
+    }
+}
+
+#[async_trait]
+impl AddMember for MemoryDb {
+    async fn add_member(
+        &self,
+        member: Member,
+        rules: Vec<BankImportRule>,
+    ) -> Result<Member> {
+        let mut tables = self.tables();
+        for (i, rule) in rules.iter().enumerate() {
+            if rules[..i].iter().any(|r| r.iban == rule.iban) {
+                return Err(Error::Duplicate(
+                    "bank import rule", rule.iban.clone()).into());
+            }
+        }
+        let member = tables.members.insert(member);
+        for rule in rules {
+            tables.bank_import_rules.push(BankImportRule {
+                member_id: member.id,
+                ..rule
+            });
+        }
+        Ok(member)
+    }
+}
+
+#[async_trait]
+impl Delete<Member> for MemoryDb {
+    /// Delete member with their records. Members with
+    /// donation receipts can not be deleted.
+    async fn delete(&self, member: Member) -> Result<()> {
+        let mut tables = self.tables();
+        let id = member.id;
+        if tables.donation_receipts.rows.iter().any(|r| r.member_id == id) {
+            return Err(Error::Restricted(id).into());
+        }
+        tables.bank_import_rules.retain(|r| r.member_id != id);
+        tables.transactions.rows.retain(|r| r.member_id != id);
+        tables.mandates.rows.retain(|r| r.member_id != id);
+        tables.payouts.rows.retain(|r| r.member_id != id);
+        tables.dunning_events.rows.retain(|r| r.member_id != id);
+        tables.notifications.rows.retain(|r| r.member_id != id);
+        tables.users.rows.retain(|r| r.member_id != Some(id));
+        tables.members.delete(id);
+        Ok(())
+    }
+}
+
+// Transactions
+
+#[async_trait]
+impl Query<Transaction> for MemoryDb {
+    type Filter = TransactionFilter;
+    async fn query(&self, filter: &Self::Filter) -> Result<Vec<Transaction>> {
+        let transactions = self.tables().transactions
+            .filter(|tx| transaction_matches(filter, tx));
+        Ok(transactions)
+    }
+}
+
+fn transaction_matches(filter: &TransactionFilter, tx: &Transaction) -> bool {
+    filter.id.is_none_or(|id| tx.id == id)
+        && filter.member_id.is_none_or(|id| tx.member_id == id)
+        && filter.date.is_none_or(|date| tx.date == date)
+        && filter.date_before.is_none_or(|date| tx.date <= date)
+        && filter.date_after.is_none_or(|date| tx.date >= date)
+        && filter.amount_min.is_none_or(|amount| tx.amount >= amount)
+        && filter.amount_max.is_none_or(|amount| tx.amount <= amount)
+        && filter.account_name.as_ref()
+            .is_none_or(|name| like(name, &tx.account_name))
+        && filter.description.as_ref()
+            .is_none_or(|text| contains(text, &tx.description))
+        && filter.kind.is_none_or(|kind| tx.kind == kind)
+}
+
+#[async_trait]
+impl Query<MemberTransaction> for MemoryDb {
+    type Filter = TransactionFilter;
+    async fn query(
+        &self,
+        filter: &Self::Filter,
+    ) -> Result<Vec<MemberTransaction>> {
+        let tables = self.tables();
+        let transactions = tables.transactions.rows.iter()
+            .filter(|tx| transaction_matches(filter, tx))
+            .filter_map(|tx| {
+                let member = tables.members.rows.iter()
+                    .find(|m| m.id == tx.member_id)?;
+                Some(MemberTransaction {
+                    id: tx.id,
+                    member_id: tx.member_id,
+                    member_name: member.name.clone(),
+                    date: tx.date,
+                    account_name: tx.account_name.clone(),
+                    amount: tx.amount,
+                    description: tx.description.clone(),
+                    kind: tx.kind,
+                })
+            })
+            .collect();
+        Ok(transactions)
+    }
+}
+
+#[async_trait]
+impl Query<TransactionTotal> for MemoryDb {
+    type Filter = TransactionTotalFilter;
+    /// Sum up the transactions per group. Members are
+    /// ordered by id, months and kinds by their label.
+    async fn query(
+        &self,
+        filter: &Self::Filter,
+    ) -> Result<Vec<TransactionTotal>> {
+        let mut groups: BTreeMap<(u32, String), TransactionTotal> =
+            BTreeMap::new();
+        let transactions = self.tables().transactions
+            .filter(|tx| transaction_matches(&filter.transactions, tx));
+        for tx in transactions {
+            let key = match filter.group {
+                TransactionGroup::Month => (0, tx.date.format("%Y-%m").to_string()),
+                TransactionGroup::Member => (tx.member_id, tx.member_id.to_string()),
+                TransactionGroup::Kind => (0, tx.kind.to_string()),
+            };
+            let total = groups.entry(key.clone()).or_insert(TransactionTotal {
+                label: key.1,
+                ..Default::default()
+            });
+            total.count += 1;
+            total.sum += tx.amount;
+        }
+        Ok(groups.into_values().collect())
+    }
+}
+
+#[async_trait]
+impl Retrieve<Transaction> for MemoryDb {
+    type Key = u32;
+    async fn retrieve(&self, id: Self::Key) -> Result<Transaction> {
+        self.tables().transactions.get(id)
+    }
+}
+
+#[async_trait]
+impl Insert<Transaction> for MemoryDb {
+    async fn insert(&self, tx: Transaction) -> Result<Transaction> {
+        let mut tables = self.tables();
+        tables.check_member(tx.member_id)?;
+        Ok(tables.transactions.insert(tx))
+    }
+}
+
+#[async_trait]
+impl Delete<Transaction> for MemoryDb {
+    async fn delete(&self, tx: Transaction) -> Result<()> {
+        self.tables().transactions.delete(tx.id);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Book for MemoryDb {
+    async fn book(&self, booking: Booking) -> Result<(Member, Vec<Transaction>)> {
+        let mut tables = self.tables();
+        let mut member = tables.members.get(booking.member_id)?;
+        if let Some(date) = booking.account_calculated_at {
+            if member.account_calculated_at >= date {
+                return Err(AlreadyCalculated {
+                    member_id: member.id,
+                    date: member.account_calculated_at,
+                }.into());
+            }
+            member.account_calculated_at = date;
+        }
+        if let Some((date, number)) = booking.last_bank_transaction {
+            member.last_bank_transaction_at = date;
+            member.last_bank_transaction_number = number;
+        }
+        let booked: Vec<Transaction> = booking.transactions.into_iter()
+            .map(|tx| tables.transactions.insert(Transaction {
+                member_id: member.id,
+                ..tx
+            }))
+            .collect();
+        member.account += booked.iter().map(|tx| tx.amount).sum::<f64>();
+        let member = tables.members.update(member, |member, _| member)?;
+        Ok((member, booked))
+    }
+}
+
+// Bank import rules
+
+#[async_trait]
+impl Query<BankImportRule> for MemoryDb {
+    type Filter = BankImportRuleFilter;
+    async fn query(
+        &self,
+        filter: &Self::Filter,
+    ) -> Result<Vec<BankImportRule>> {
+        let mut rules: Vec<BankImportRule> = self.tables()
+            .bank_import_rules
+            .iter()
+            .filter(|r| {
+                filter.member_id.is_none_or(|id| r.member_id == id)
+                    && filter.iban.as_ref().is_none_or(|iban| &r.iban == iban)
+            })
+            .cloned()
+            .collect();
+        rules.sort_by(|a, b| {
+            (a.member_id, &a.iban).cmp(&(b.member_id, &b.iban))
+        });
+        Ok(rules)
+    }
+}
+
+#[async_trait]
+impl Retrieve<BankImportRule> for MemoryDb {
+    type Key = (u32, String);
+    async fn retrieve(&self, key: Self::Key) -> Result<BankImportRule> {
+        let (member_id, iban) = key;
+        let rule = self.tables()
+            .bank_import_rules
+            .iter()
+            .find(|r| r.member_id == member_id && r.iban == iban)
+            .cloned()
+            .ok_or(Error::NotFound(
+                "bank import rule",
+                format!("{}:{}", member_id, iban)))?;
+        Ok(rule)
+    }
+}
+
+#[async_trait]
+impl Insert<BankImportRule> for MemoryDb {
+    async fn insert(&self, rule: BankImportRule) -> Result<BankImportRule> {
+        let mut tables = self.tables();
+        tables.check_member(rule.member_id)?;
+        let exists = tables.bank_import_rules.iter()
+            .any(|r| r.member_id == rule.member_id && r.iban == rule.iban);
+        if exists {
+            return Err(Error::Duplicate(
+                "bank import rule",
+                format!("{}:{}", rule.member_id, rule.iban)).into());
+        }
+        tables.bank_import_rules.push(rule.clone());
+        Ok(rule)
+    }
+}
+
+#[async_trait]
+impl Update<BankImportRule> for MemoryDb {
+    /// Update the split amount and subject match of a rule
+    async fn update(&self, rule: BankImportRule) -> Result<BankImportRule> {
+        let mut tables = self.tables();
+        let stored = tables.bank_import_rules.iter_mut()
+            .find(|r| r.member_id == rule.member_id && r.iban == rule.iban)
+            .ok_or(Error::NotFound(
+                "bank import rule",
+                format!("{}:{}", rule.member_id, rule.iban)))?;
+        stored.split_amount = rule.split_amount;
+        stored.match_subject = rule.match_subject;
+        Ok(stored.clone())
+    }
+}
+
+#[async_trait]
+impl Delete<BankImportRule> for MemoryDb {
+    async fn delete(&self, rule: BankImportRule) -> Result<()> {
+        self.tables().bank_import_rules
+            .retain(|r| !(r.member_id == rule.member_id && r.iban == rule.iban));
+        Ok(())
+    }
+}
+
+// Mandates
+
+#[async_trait]
+impl Query<Mandate> for MemoryDb {
+    type Filter = MandateFilter;
+    async fn query(&self, filter: &Self::Filter) -> Result<Vec<Mandate>> {
+        let mandates = self.tables().mandates.filter(|m| {
+            filter.id.is_none_or(|id| m.id == id)
+                && filter.member_id.is_none_or(|id| m.member_id == id)
+                && filter.reference.as_ref()
+                    .is_none_or(|reference| &m.reference == reference)
+        });
+        Ok(mandates)
+    }
+}
+
+#[async_trait]
+impl Retrieve<Mandate> for MemoryDb {
+    type Key = u32;
+    async fn retrieve(&self, id: Self::Key) -> Result<Mandate> {
+        self.tables().mandates.get(id)
+    }
+}
+
+#[async_trait]
+impl Insert<Mandate> for MemoryDb {
+    async fn insert(&self, mandate: Mandate) -> Result<Mandate> {
+        let mut tables = self.tables();
+        tables.check_member(mandate.member_id)?;
+        if tables.mandates.rows.iter().any(|m| m.reference == mandate.reference) {
+            return Err(Error::Duplicate("mandate", mandate.reference).into());
+        }
+        Ok(tables.mandates.insert(mandate))
+    }
+}
+
+#[async_trait]
+impl Update<Mandate> for MemoryDb {
+    /// Update a mandate. The reference and member
+    /// can not be changed.
+    async fn update(&self, mandate: Mandate) -> Result<Mandate> {
+        self.tables().mandates.update(mandate, |mandate, stored| Mandate {
+            member_id: stored.member_id,
+            reference: stored.reference.clone(),
+            ..mandate
+        })
+    }
+}
+
+#[async_trait]
+impl Delete<Mandate> for MemoryDb {
+    async fn delete(&self, mandate: Mandate) -> Result<()> {
+        self.tables().mandates.delete(mandate.id);
+        Ok(())
+    }
+}
+
+// Payouts
+
+#[async_trait]
+impl Query<Payout> for MemoryDb {
+    type Filter = PayoutFilter;
+    async fn query(&self, filter: &Self::Filter) -> Result<Vec<Payout>> {
+        let payouts = self.tables().payouts.filter(|p| {
+            filter.id.is_none_or(|id| p.id == id)
+                && filter.member_id.is_none_or(|id| p.member_id == id)
+                && filter.iban.as_ref().is_none_or(|iban| &p.iban == iban)
+                && filter.batch_id.as_ref()
+                    .is_none_or(|batch_id| p.batch_id.as_ref() == Some(batch_id))
+                && filter.state.is_none_or(|state| match state {
+                    PayoutState::Queued => p.exported_at.is_none(),
+                    PayoutState::Pending => {
+                        p.exported_at.is_some() && p.settled_at.is_none()
+                    }
+                    PayoutState::Settled => p.settled_at.is_some(),
+                })
+        });
+        Ok(payouts)
+    }
+}
+
+#[async_trait]
+impl Retrieve<Payout> for MemoryDb {
+    type Key = u32;
+    async fn retrieve(&self, id: Self::Key) -> Result<Payout> {
+        self.tables().payouts.get(id)
+    }
+}
+
+#[async_trait]
+impl Insert<Payout> for MemoryDb {
+    async fn insert(&self, payout: Payout) -> Result<Payout> {
+        let mut tables = self.tables();
+        tables.check_member(payout.member_id)?;
+        Ok(tables.payouts.insert(payout))
+    }
+}
+
+#[async_trait]
+impl Update<Payout> for MemoryDb {
+    /// Update a payout. The member and creation
+    /// date can not be changed.
+    async fn update(&self, payout: Payout) -> Result<Payout> {
+        self.tables().payouts.update(payout, |payout, stored| Payout {
+            member_id: stored.member_id,
+            created_at: stored.created_at,
+            ..payout
+        })
+    }
+}
+
+#[async_trait]
+impl Delete<Payout> for MemoryDb {
+    async fn delete(&self, payout: Payout) -> Result<()> {
+        self.tables().payouts.delete(payout.id);
+        Ok(())
+    }
+}
+
+// Dunning events
+
+#[async_trait]
+impl Query<DunningEvent> for MemoryDb {
+    type Filter = DunningEventFilter;
+    async fn query(&self, filter: &Self::Filter) -> Result<Vec<DunningEvent>> {
+        let mut events = self.tables().dunning_events.filter(|e| {
+            filter.id.is_none_or(|id| e.id == id)
+                && filter.member_id.is_none_or(|id| e.member_id == id)
+                && filter.level.is_none_or(|level| e.level == level)
+                && filter.date_before.is_none_or(|date| e.date <= date)
+                && filter.date_after.is_none_or(|date| e.date >= date)
+        });
+        events.sort_by_key(|e| (e.date, e.id));
+        Ok(events)
+    }
+}
+
+#[async_trait]
+impl Retrieve<DunningEvent> for MemoryDb {
+    type Key = u32;
+    async fn retrieve(&self, id: Self::Key) -> Result<DunningEvent> {
+        self.tables().dunning_events.get(id)
+    }
+}
+
+#[async_trait]
+impl Insert<DunningEvent> for MemoryDb {
+    async fn insert(&self, event: DunningEvent) -> Result<DunningEvent> {
+        let mut tables = self.tables();
+        tables.check_member(event.member_id)?;
+        Ok(tables.dunning_events.insert(event))
+    }
+}
+
+// Notifications
+
+#[async_trait]
+impl Query<Notification> for MemoryDb {
+    type Filter = NotificationFilter;
+    async fn query(&self, filter: &Self::Filter) -> Result<Vec<Notification>> {
+        let notifications = self.tables().notifications.filter(|n| {
+            filter.id.is_none_or(|id| n.id == id)
+                && filter.member_id.is_none_or(|id| n.member_id == id)
+                && filter.event.is_none_or(|event| n.event == event)
+                && filter.dedup_key.as_ref()
+                    .is_none_or(|key| &n.dedup_key == key)
+                && filter.sent.is_none_or(|sent| n.sent_at.is_some() == sent)
+        });
+        Ok(notifications)
+    }
+}
+
+#[async_trait]
+impl Retrieve<Notification> for MemoryDb {
+    type Key = u32;
+    async fn retrieve(&self, id: Self::Key) -> Result<Notification> {
+        self.tables().notifications.get(id)
+    }
+}
+
+#[async_trait]
+impl Insert<Notification> for MemoryDb {
+    /// Queue a notification. This fails if a notification
+    /// with the same dedup key exists.
+    async fn insert(
+        &self,
+        notification: Notification,
+    ) -> Result<Notification> {
+        let mut tables = self.tables();
+        tables.check_member(notification.member_id)?;
+        let exists = tables.notifications.rows.iter()
+            .any(|n| n.dedup_key == notification.dedup_key);
+        if exists {
+            return Err(Error::Duplicate(
+                "notification", notification.dedup_key).into());
+        }
+        Ok(tables.notifications.insert(notification))
+    }
+}
+
+#[async_trait]
+impl Update<Notification> for MemoryDb {
+    /// Update the delivery state of a notification.
+    /// The content can not be changed.
+    async fn update(
+        &self,
+        notification: Notification,
+    ) -> Result<Notification> {
+        self.tables().notifications.update(notification, |n, stored| {
+            Notification {
+                sent_at: n.sent_at,
+                attempts: n.attempts,
+                last_error: n.last_error,
+                claimed_at: n.claimed_at,
+                ..stored.clone()
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl ClaimNotification for MemoryDb {
+    async fn claim(
+        &self,
+        notification: &Notification,
+        now: NaiveDateTime,
+        stale_before: NaiveDateTime,
+    ) -> Result<Option<Notification>> {
+        let mut tables = self.tables();
+        let stored = tables.notifications.get(notification.id)?;
+        let claimable = stored.sent_at.is_none()
+            && stored.attempts == notification.attempts
+            && stored.claimed_at.is_none_or(|at| at < stale_before);
+        if !claimable {
+            return Ok(None);
+        }
+        let claimed = tables.notifications.update(Notification {
+            attempts: stored.attempts + 1,
+            claimed_at: Some(now),
+            ..stored
+        }, |n, _| n)?;
+        Ok(Some(claimed))
+    }
+}
+
+// Donation receipts
+
+#[async_trait]
+impl Query<DonationReceipt> for MemoryDb {
+    type Filter = DonationReceiptFilter;
+    async fn query(
+        &self,
+        filter: &Self::Filter,
+    ) -> Result<Vec<DonationReceipt>> {
+        let mut receipts = self.tables().donation_receipts.filter(|r| {
+            filter.id.is_none_or(|id| r.id == id)
+                && filter.member_id.is_none_or(|id| r.member_id == id)
+                && filter.year.is_none_or(|year| r.year == year)
+        });
+        receipts.sort_by_key(|r| (r.year, r.sequence));
+        Ok(receipts)
+    }
+}
+
+#[async_trait]
+impl Retrieve<DonationReceipt> for MemoryDb {
+    type Key = u32;
+    async fn retrieve(&self, id: Self::Key) -> Result<DonationReceipt> {
+        self.tables().donation_receipts.get(id)
+    }
+}
+
+#[async_trait]
+impl Insert<DonationReceipt> for MemoryDb {
+    /// Issue a donation receipt. The sequence number is
+    /// assigned as the next free number of the year; a
+    /// second receipt for the same member and year fails.
+    async fn insert(
+        &self,
+        receipt: DonationReceipt,
+    ) -> Result<DonationReceipt> {
+        let mut tables = self.tables();
+        tables.check_member(receipt.member_id)?;
+        let issued = tables.donation_receipts.rows.iter()
+            .filter(|r| r.year == receipt.year);
+        if issued.clone().any(|r| r.member_id == receipt.member_id) {
+            return Err(Error::Duplicate(
+                "donation receipt",
+                format!("{} of member {}", receipt.year, receipt.member_id),
+            ).into());
+        }
+        let sequence = issued.map(|r| r.sequence).max().unwrap_or(0) + 1;
+        Ok(tables.donation_receipts.insert(DonationReceipt {
+            sequence,
+            ..receipt
+        }))
+    }
+}
+
+#[async_trait]
+impl Delete<DonationReceipt> for MemoryDb {
+    async fn delete(&self, receipt: DonationReceipt) -> Result<()> {
+        self.tables().donation_receipts.delete(receipt.id);
+        Ok(())
+    }
+}
+
+// Users
+
+#[async_trait]
+impl Query<User> for MemoryDb {
+    type Filter = UserFilter;
+    async fn query(&self, filter: &Self::Filter) -> Result<Vec<User>> {
+        let mut users = self.tables().users.filter(|u| {
+            filter.id.is_none_or(|id| u.id == id)
+                && filter.name.as_ref().is_none_or(|name| &u.name == name)
+                && filter.role.is_none_or(|role| u.role == role)
+                && filter.member_id.is_none_or(|id| u.member_id == Some(id))
+        });
+        users.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(users)
+    }
+}
+
+#[async_trait]
+impl Retrieve<User> for MemoryDb {
+    type Key = String;
+    async fn retrieve(&self, name: Self::Key) -> Result<User> {
+        let user = self.tables().users.rows.iter()
+            .find(|u| u.name == name)
+            .cloned()
+            .ok_or(Error::NotFound("user", name))?;
+        Ok(user)
+    }
+}
+
+#[async_trait]
+impl Insert<User> for MemoryDb {
+    /// Create a user. Names are unique.
+    async fn insert(&self, user: User) -> Result<User> {
+        user.validate()?;
+        let mut tables = self.tables();
+        if let Some(member_id) = user.member_id {
+            tables.check_member(member_id)?;
+        }
+        if tables.users.rows.iter().any(|u| u.name == user.name) {
+            return Err(Error::Duplicate("user", user.name).into());
+        }
+        Ok(tables.users.insert(User {
+            created_at: chrono::Local::now().naive_local(),
+            ..user
+        }))
+    }
+}
+
+#[async_trait]
+impl Update<User> for MemoryDb {
+    /// Change the role or member of a user. The name
+    /// can not be changed.
+    async fn update(&self, user: User) -> Result<User> {
+        user.validate()?;
+        let mut tables = self.tables();
+        if let Some(member_id) = user.member_id {
+            tables.check_member(member_id)?;
+        }
+        let stored = tables.users.rows.iter_mut()
+            .find(|u| u.name == user.name)
+            .ok_or(Error::NotFound("user", user.name.clone()))?;
+        stored.role = user.role;
+        stored.member_id = user.member_id;
+        Ok(stored.clone())
+    }
+}
+
+#[async_trait]
+impl Delete<User> for MemoryDb {
+    async fn delete(&self, user: User) -> Result<()> {
+        self.tables().users.rows.retain(|u| u.name != user.name);
+        Ok(())
+    }
+}
+
+// Pseudonymisation
+
+#[async_trait]
+impl Pseudonymise for MemoryDb {
+    async fn pseudonymise(&self, p: &Pseudonymisation) -> Result<Member> {
+        let values = p.replaced_values();
+        let scrub = |text: &str| {
+            values.iter().fold(text.to_string(), |text, value| {
+                text.replace(value, &p.pseudonym)
+            })
+        };
+
+        let mut tables = self.tables();
+        let id = p.member_id;
+        let member = tables.members.update(
+            Member { id, ..Default::default() },
+            |_, member| Member {
+                name: p.pseudonym.clone(),
+                email: String::new(),
+                notes: String::new(),
+                ..member.clone()
+            },
+        )?;
+        tables.bank_import_rules.retain(|r| r.member_id != id);
+        tables.notifications.rows.retain(|n| n.member_id != id);
+        tables.users.rows.retain(|u| u.member_id != Some(id));
+        for mandate in tables.mandates.rows.iter_mut()
+            .filter(|m| m.member_id == id)
+        {
+            mandate.account_holder = p.pseudonym.clone();
+            mandate.iban = String::new();
+            mandate.original_iban = None;
+        }
+        for payout in tables.payouts.rows.iter_mut()
+            .filter(|payout| payout.member_id == id)
+        {
+            payout.name = p.pseudonym.clone();
+            payout.iban = String::new();
+            payout.subject = scrub(&payout.subject);
+        }
+        for receipt in tables.donation_receipts.rows.iter_mut()
+            .filter(|r| r.member_id == id)
+        {
+            receipt.name = p.pseudonym.clone();
+        }
+        for tx in tables.transactions.rows.iter_mut()
+            .filter(|tx| tx.member_id == id)
+        {
+            tx.account_name = scrub(&tx.account_name);
+            tx.description = scrub(&tx.description);
+        }
+        Ok(member)
+    }
+}
+
+// Audit log
+
+#[async_trait]
+impl Query<AuditEntry> for MemoryDb {
+    type Filter = AuditEntryFilter;
+    /// Changes are not audited, so there are no entries
+    async fn query(&self, _filter: &Self::Filter) -> Result<Vec<AuditEntry>> {
+        Ok(vec![])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::Role;
+
+    #[test]
+    fn test_like() {
+        assert!(like("%member%", "Test Member"));
+        assert!(like("mail@eris.%", "MAIL@eris.example"));
+        assert!(like("e_is", "Eris"));
+        assert!(!like("e_is", "Eeris"));
+        assert!(!like("member", "Test Member"));
+    }
+
+    #[tokio::test]
+    async fn test_member_query() {
+        let db = MemoryDb::new();
+        let m1 = db.insert(Member {
+            name: "Test Member".to_string(),
+            email: "test@eris.example".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        let m2 = db.insert(Member {
+            name: "Best Member".to_string(),
+            archived_at: NaiveDate::from_ymd_opt(2023, 1, 1),
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!((m1.id, m2.id), (1, 2));
+
+        let members: Vec<Member> = db.query(&MemberFilter {
+            name: Some("member".to_string()),
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].id, m1.id);
+
+        let members: Vec<Member> = db.query(&MemberFilter {
+            name: Some("member".to_string()),
+            include_archived: true,
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(members.len(), 2);
+
+        let members: Vec<Member> = db.query(&MemberFilter {
+            email: Some("TEST@%".to_string()),
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(members.len(), 1);
+
+        // Wildcards in names are matched literally
+        for name in ["T_st", "%"] {
+            let members: Vec<Member> = db.query(&MemberFilter {
+                name: Some(name.to_string()),
+                include_archived: true,
+                ..Default::default()
+            }).await.unwrap();
+            assert!(members.is_empty(), "{}", name);
+        }
+
+        // Archived members can be retrieved
+        let member: Member = db.retrieve(m2.id).await.unwrap();
+        assert_eq!(member.name, "Best Member");
+    }
+
+    #[tokio::test]
+    async fn test_member_query_filters() {
+        let db = MemoryDb::new();
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        for (name, start, end, fee, account) in [
+            ("Eris", date(2020, 1, 1), None, 20.0, -40.0),
+            ("aneris", date(2021, 6, 1), Some(date(2022, 12, 31)), 10.0, 5.0),
+            ("Discordia", date(2023, 1, 1), None, 20.0, 60.0),
+        ] {
+            db.insert(Member {
+                name: name.to_string(),
+                membership_start: start,
+                membership_end: end,
+                fee,
+                account,
+                ..Default::default()
+            }).await.unwrap();
+        }
+        let member: Member = db.retrieve(3).await.unwrap();
+        db.insert(BankImportRule::new(&member, "DE2342")).await.unwrap();
+
+        let ids = |filter: MemberFilter| {
+            let db = &db;
+            async move {
+                let result: Vec<Member> = db.query(&filter).await.unwrap();
+                result.iter().map(|m| m.id).collect::<Vec<u32>>()
+            }
+        };
+        assert_eq!(ids(MemberFilter {
+            active_at: Some(date(2022, 1, 1)),
+            ..Default::default()
+        }).await, vec![1, 2]);
+        assert_eq!(ids(MemberFilter {
+            inactive_at: Some(date(2023, 1, 1)),
+            ..Default::default()
+        }).await, vec![2]);
+        assert_eq!(ids(MemberFilter {
+            balance_below: Some(0.0),
+            ..Default::default()
+        }).await, vec![1]);
+        assert_eq!(ids(MemberFilter {
+            has_iban_rule: Some(false),
+            ..Default::default()
+        }).await, vec![1, 2]);
+        assert_eq!(ids(MemberFilter {
+            membership_end_before: Some(date(2023, 1, 1)),
+            ..Default::default()
+        }).await, vec![2]);
+
+        // Members with the same value are ordered by id
+        assert_eq!(ids(MemberFilter {
+            sort: MemberSort::Fee,
+            descending: true,
+            ..Default::default()
+        }).await, vec![1, 3, 2]);
+        assert_eq!(ids(MemberFilter {
+            sort: MemberSort::Name,
+            offset: Some(1),
+            limit: Some(1),
+            ..Default::default()
+        }).await, vec![3]);
+    }
+
+    #[tokio::test]
+    async fn test_transaction_query() {
+        let db = MemoryDb::new();
+        let m1 = db.insert(Member {
+            name: "Eris".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        let m2 = db.insert(Member {
+            name: "Discordia".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        let date = |m, d| NaiveDate::from_ymd_opt(2023, m, d).unwrap();
+        use crate::TransactionKind::*;
+        let txs = [
+            (m1.id, date(1, 1), crate::MEMBERSHIP_FEE_ACCOUNT, -23.0, "Monthly fee", Fee),
+            (m2.id, date(1, 5), "Eris Discordia", 40.0, "Beitrag", Split),
+            (m2.id, date(2, 3), "Eris Discordia", 2.0, "Beitrag (overflow)", Overflow),
+            (m1.id, date(2, 10), "", 5.0, "Manual account balance update", Manual),
+        ];
+        for (member_id, date, account_name, amount, description, kind) in txs {
+            db.insert(Transaction {
+                member_id,
+                date,
+                account_name: account_name.to_string(),
+                amount,
+                description: description.to_string(),
+                kind,
+                ..Default::default()
+            }).await.unwrap();
+        }
+
+        let txs: Vec<MemberTransaction> = db.query(&TransactionFilter {
+            amount_min: Some(2.0),
+            description: Some("beitrag".to_string()),
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(txs.len(), 2);
+        assert_eq!(txs[0].member_name, "Discordia");
+
+        let txs: Vec<Transaction> = db.query(&TransactionFilter {
+            kind: Some(Manual),
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(txs.len(), 1);
+        assert_eq!(txs[0].amount, 5.0);
+
+        let totals: Vec<TransactionTotal> = db.query(&TransactionTotalFilter {
+            group: TransactionGroup::Month,
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(totals, vec![
+            TransactionTotal { label: "2023-01".to_string(), count: 2, sum: 17.0 },
+            TransactionTotal { label: "2023-02".to_string(), count: 2, sum: 7.0 },
+        ]);
+        let totals: Vec<TransactionTotal> = db.query(&TransactionTotalFilter {
+            group: TransactionGroup::Member,
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(totals[0].label, m1.id.to_string());
+        assert_eq!(totals[1].sum, 42.0);
+    }
+
+    #[tokio::test]
+    async fn test_member_delete() {
+        let db = MemoryDb::new();
+        let member = db.insert(Member {
+            name: "Test Member".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        db.insert(Transaction {
+            member_id: member.id,
+            amount: 23.0,
+            ..Default::default()
+        }).await.unwrap();
+        db.insert(BankImportRule::new(&member, "DE2342")).await.unwrap();
+        db.insert(User {
+            name: "eris".to_string(),
+            role: Role::Member,
+            member_id: Some(member.id),
+            ..Default::default()
+        }).await.unwrap();
+        let receipt = db.insert(DonationReceipt {
+            member_id: member.id,
+            year: 2023,
+            ..Default::default()
+        }).await.unwrap();
+
+        // Receipts must be deleted first
+        assert!(db.delete(member.clone()).await.is_err());
+        db.delete(receipt).await.unwrap();
+        db.delete(member.clone()).await.unwrap();
+
+        let transactions: Vec<Transaction> =
+            db.query(&TransactionFilter::default()).await.unwrap();
+        assert!(transactions.is_empty());
+        assert!(member.get_bank_import_rules(&db).await.unwrap().is_empty());
+        let users: Vec<User> = db.query(&UserFilter::default()).await.unwrap();
+        assert!(users.is_empty());
+
+        // Ids are not reused
+        let member = db.insert(Member::default()).await.unwrap();
+        assert_eq!(member.id, 2);
+    }
+
+    #[tokio::test]
+    async fn test_constraints() {
+        let db = MemoryDb::new();
+        let res = db.insert(Transaction {
+            member_id: 42,
+            ..Default::default()
+        }).await;
+        assert!(res.is_err());
+
+        let member = db.insert(Member::default()).await.unwrap();
+        let notification = Notification {
+            member_id: member.id,
+            dedup_key: "fnord".to_string(),
+            ..Default::default()
+        };
+        db.insert(notification.clone()).await.unwrap();
+        assert!(db.insert(notification).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_donation_receipt_sequence() {
+        let db = MemoryDb::new();
+        let m1 = db.insert(Member::default()).await.unwrap();
+        let m2 = db.insert(Member::default()).await.unwrap();
+        let receipt = |member_id, year| DonationReceipt {
+            member_id,
+            year,
+            ..Default::default()
+        };
+        let r1 = db.insert(receipt(m1.id, 2022)).await.unwrap();
+        let r2 = db.insert(receipt(m2.id, 2022)).await.unwrap();
+        let r3 = db.insert(receipt(m1.id, 2023)).await.unwrap();
+        assert_eq!(r1.sequence, 1);
+        assert_eq!(r2.sequence, 2);
+        assert_eq!(r3.sequence, 1);
+        assert!(db.insert(receipt(m1.id, 2022)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_payout_state() {
+        let db = MemoryDb::new();
+        let member = db.insert(Member::default()).await.unwrap();
+        let date = NaiveDate::from_ymd_opt(2023, 5, 10);
+        for (exported_at, settled_at) in [
+            (None, None),
+            (date, None),
+            (date, date),
+        ] {
+            db.insert(Payout {
+                member_id: member.id,
+                exported_at,
+                settled_at,
+                ..Default::default()
+            }).await.unwrap();
+        }
+        for (state, id) in [
+            (PayoutState::Queued, 1),
+            (PayoutState::Pending, 2),
+            (PayoutState::Settled, 3),
+        ] {
+            let payouts: Vec<Payout> = db.query(&PayoutFilter {
+                state: Some(state),
+                ..Default::default()
+            }).await.unwrap();
+            assert_eq!(payouts.len(), 1);
+            assert_eq!(payouts[0].id, id);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_load() {
+        let db = MemoryDb::new();
+        let m1 = db.insert(Member::default()).await.unwrap();
+        let m2 = db.insert(Member::default()).await.unwrap();
+        db.delete(m1).await.unwrap();
+        db.insert(Transaction {
+            member_id: m2.id,
+            amount: 23.0,
+            ..Default::default()
+        }).await.unwrap();
+
+        let copy = MemoryDb::load(&db).await.unwrap();
+        let member: Member = copy.retrieve(m2.id).await.unwrap();
+        assert_eq!(member.get_transactions(&copy).await.unwrap().len(), 1);
+
+        // Changes to the copy do not touch the original
+        let member = copy.insert(Member::default()).await.unwrap();
+        assert_eq!(member.id, 3);
+        let members: Vec<Member> =
+            db.query(&MemberFilter::default()).await.unwrap();
+        assert_eq!(members.len(), 1);
+    }
+}
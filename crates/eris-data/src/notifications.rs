@@ -0,0 +1,97 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Events members are notified about by email.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum NotificationEvent {
+    #[default]
+    Welcome,
+    BalanceReminder,
+    FeeChange,
+}
+
+impl NotificationEvent {
+    /// Get the name of the event as used in templates
+    pub fn name(&self) -> &'static str {
+        match self {
+            NotificationEvent::Welcome => "welcome",
+            NotificationEvent::BalanceReminder => "balance_reminder",
+            NotificationEvent::FeeChange => "fee_change",
+        }
+    }
+}
+
+impl fmt::Display for NotificationEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for NotificationEvent {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.replace('-', "_").as_str() {
+            "welcome" => Ok(NotificationEvent::Welcome),
+            "balance_reminder" => Ok(NotificationEvent::BalanceReminder),
+            "fee_change" => Ok(NotificationEvent::FeeChange),
+            _ => Err(anyhow!("unknown notification event: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct NotificationFilter {
+    pub id: Option<u32>,
+    pub member_id: Option<u32>,
+    pub event: Option<NotificationEvent>,
+    pub dedup_key: Option<String>,
+    pub sent: Option<bool>,
+}
+
+/// A rendered email in the outbox. The dedup key
+/// makes sure the same notification is never queued
+/// twice.
+#[derive(Debug, Clone, Default, FromRow, Serialize, Deserialize)]
+pub struct Notification {
+    #[sqlx(try_from = "i64")]
+    pub id: u32,
+    #[sqlx(try_from = "i64")]
+    pub member_id: u32,
+    pub event: NotificationEvent,
+    pub dedup_key: String,
+    pub recipient: String,
+    pub subject: String,
+    pub body: String,
+    pub created_at: NaiveDateTime,
+    pub sent_at: Option<NaiveDateTime>,
+    #[sqlx(try_from = "i64")]
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    /// When a delivery started sending the notification
+    pub claimed_at: Option<NaiveDateTime>,
+}
+
+/// Claim a pending notification before sending it and count
+/// the attempt. A notification is claimed only if it was not
+/// changed since it was read and is not claimed by another
+/// delivery after `stale_before`. Returns the claimed
+/// notification, or None if it was not claimed.
+#[async_trait]
+pub trait ClaimNotification {
+    async fn claim(
+        &self,
+        notification: &Notification,
+        now: NaiveDateTime,
+        stale_before: NaiveDateTime,
+    ) -> Result<Option<Notification>>;
+}
@@ -0,0 +1,152 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use crate::{Member, Retrieve};
+
+/// The reason for paying money to a member.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type,
+)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+pub enum PayoutKind {
+    /// Pay back a positive account balance
+    #[default]
+    Refund,
+    /// Pay back expenses made on behalf of the association
+    Reimbursement,
+}
+
+impl fmt::Display for PayoutKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PayoutKind::Refund => write!(f, "refund"),
+            PayoutKind::Reimbursement => write!(f, "reimbursement"),
+        }
+    }
+}
+
+impl FromStr for PayoutKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "refund" => Ok(PayoutKind::Refund),
+            "reimbursement" => Ok(PayoutKind::Reimbursement),
+            _ => Err(anyhow!("unknown payout kind: {}", s)),
+        }
+    }
+}
+
+/// A payout is queued until it is exported in a credit
+/// transfer batch. It stays pending until the outgoing
+/// payment shows up in a bank import.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PayoutState {
+    Queued,
+    Pending,
+    Settled,
+}
+
+impl fmt::Display for PayoutState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PayoutState::Queued => write!(f, "queued"),
+            PayoutState::Pending => write!(f, "pending"),
+            PayoutState::Settled => write!(f, "settled"),
+        }
+    }
+}
+
+impl FromStr for PayoutState {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "queued" => Ok(PayoutState::Queued),
+            "pending" => Ok(PayoutState::Pending),
+            "settled" => Ok(PayoutState::Settled),
+            _ => Err(anyhow!("unknown payout state: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PayoutFilter {
+    pub id: Option<u32>,
+    pub member_id: Option<u32>,
+    pub iban: Option<String>,
+    pub batch_id: Option<String>,
+    pub state: Option<PayoutState>,
+}
+
+#[derive(Debug, Clone, Default, FromRow, Serialize, Deserialize)]
+pub struct Payout {
+    #[sqlx(try_from = "i64")]
+    pub id: u32,
+    #[sqlx(try_from = "i64")]
+    pub member_id: u32,
+    pub kind: PayoutKind,
+    pub name: String,
+    pub iban: String,
+    pub amount: f64,
+    pub subject: String,
+    pub created_at: NaiveDate,
+    pub batch_id: Option<String>,
+    pub exported_at: Option<NaiveDate>,
+    pub settled_at: Option<NaiveDate>,
+}
+
+impl Payout {
+    /// Get the state of the payout
+    pub fn state(&self) -> PayoutState {
+        if self.settled_at.is_some() {
+            PayoutState::Settled
+        } else if self.exported_at.is_some() {
+            PayoutState::Pending
+        } else {
+            PayoutState::Queued
+        }
+    }
+
+    /// The end to end id is passed along with the
+    /// credit transfer to the recipient.
+    pub fn end_to_end_id(&self) -> String {
+        format!("ERIS-PAYOUT-{:06}", self.id)
+    }
+
+    /// Get associated member
+    pub async fn get_member<DB>(&self, db: &DB) -> Result<Member>
+    where
+        DB: Retrieve<Member, Key = u32>,
+    {
+        db.retrieve(self.member_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payout_state() {
+        let mut payout = Payout::default();
+        assert_eq!(payout.state(), PayoutState::Queued);
+        payout.exported_at = NaiveDate::from_ymd_opt(2023, 5, 1);
+        assert_eq!(payout.state(), PayoutState::Pending);
+        payout.settled_at = NaiveDate::from_ymd_opt(2023, 5, 3);
+        assert_eq!(payout.state(), PayoutState::Settled);
+    }
+
+    #[test]
+    fn test_payout_kind_from_str() {
+        let kind: PayoutKind = "reimbursement".parse().unwrap();
+        assert_eq!(kind, PayoutKind::Reimbursement);
+        assert!("gift".parse::<PayoutKind>().is_err());
+    }
+}
@@ -0,0 +1,71 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::Member;
+
+/// Replace the personal data of a member with a pseudonym.
+/// The ledger is kept, so the balance and reports do
+/// not change.
+#[derive(Debug, Clone, Default)]
+pub struct Pseudonymisation {
+    pub member_id: u32,
+    /// Replaces the name of the member and account holders
+    pub pseudonym: String,
+    /// Names, email addresses, IBANs and other text which is
+    /// replaced with the pseudonym wherever it appears in
+    /// transactions, payouts and the commands of the audit log.
+    pub personal_data: Vec<String>,
+}
+
+impl Pseudonymisation {
+    /// Get the values to replace. Longer values come first,
+    /// so a name is not replaced within a longer account name.
+    pub fn replaced_values(&self) -> Vec<String> {
+        let mut values: Vec<String> = self.personal_data.iter()
+            .filter(|value| !value.is_empty())
+            .flat_map(|value| {
+                // Values are also stored escaped in JSON
+                let escaped = serde_json::to_string(value)
+                    .map(|s| s[1..s.len() - 1].to_string())
+                    .unwrap_or_default();
+                [value.clone(), escaped]
+            })
+            .collect();
+        values.sort_by_key(|value| std::cmp::Reverse(value.len()));
+        values.dedup();
+        values
+    }
+}
+
+/// Pseudonymise a member. IBAN rules and notifications are
+/// deleted; the member, mandates, payouts and receipts
+/// keep only the pseudonym. The audit log keeps which
+/// changes were made, but not the recorded values.
+#[async_trait]
+pub trait Pseudonymise {
+    async fn pseudonymise(&self, p: &Pseudonymisation) -> Result<Member>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replaced_values() {
+        let p = Pseudonymisation {
+            personal_data: vec![
+                "Eris".to_string(),
+                "".to_string(),
+                "Eris Discordia".to_string(),
+                "Says \"hail\"".to_string(),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(p.replaced_values(), vec![
+            "Eris Discordia",
+            "Says \\\"hail\\\"",
+            "Says \"hail\"",
+            "Eris",
+        ]);
+    }
+}
@@ -0,0 +1,34 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct DonationReceiptFilter {
+    pub id: Option<u32>,
+    pub member_id: Option<u32>,
+    pub year: Option<i32>,
+}
+
+/// An issued donation receipt (Zuwendungsbestätigung).
+/// Receipts are numbered sequentially per year and are
+/// kept, so the same year is never confirmed twice.
+#[derive(Debug, Clone, Default, FromRow, Serialize, Deserialize)]
+pub struct DonationReceipt {
+    #[sqlx(try_from = "i64")]
+    pub id: u32,
+    #[sqlx(try_from = "i64")]
+    pub member_id: u32,
+    pub year: i32,
+    #[sqlx(try_from = "i64")]
+    pub sequence: u32,
+    pub name: String,
+    pub amount: f64,
+    pub issued_at: NaiveDate,
+}
+
+impl DonationReceipt {
+    /// The receipt number printed on the document
+    pub fn number(&self) -> String {
+        format!("{}-{:04}", self.year, self.sequence)
+    }
+}
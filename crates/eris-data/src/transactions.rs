@@ -1,6 +1,17 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
 use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use thiserror::Error as ThisError;
+
+use crate::Member;
+
+/// The account name used for membership fee transactions.
+/// (The spelling is kept for existing databases.)
+pub const MEMBERSHIP_FEE_ACCOUNT: &str = "memberhip fee";
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct TransactionFilter {
@@ -9,14 +20,219 @@ pub struct TransactionFilter {
     pub date: Option<NaiveDate>,
     pub date_before: Option<NaiveDate>,
     pub date_after: Option<NaiveDate>,
+    /// Transactions of at least this amount
+    pub amount_min: Option<f64>,
+    /// Transactions of at most this amount
+    pub amount_max: Option<f64>,
+    /// The account name, where `%` matches any text.
+    /// The case is ignored.
+    pub account_name: Option<String>,
+    /// Part of the description, ignoring the case
+    pub description: Option<String>,
+    pub kind: Option<TransactionKind>,
 }
 
 #[derive(Debug, Default, Clone, FromRow, Serialize, Deserialize)]
 pub struct Transaction {
+    #[sqlx(try_from = "i64")]
+    pub id: u32,
+    #[sqlx(try_from = "i64")]
+    pub member_id: u32,
+    pub date: NaiveDate,
+    pub account_name: String,
+    pub amount: f64,
+    pub description: String,
+    pub kind: TransactionKind,
+}
+
+/// A transaction with the name of its member, which
+/// is listed with a single query.
+#[derive(Debug, Default, Clone, FromRow, Serialize, Deserialize)]
+pub struct MemberTransaction {
+    #[sqlx(try_from = "i64")]
     pub id: u32,
+    #[sqlx(try_from = "i64")]
     pub member_id: u32,
+    pub member_name: String,
     pub date: NaiveDate,
     pub account_name: String,
     pub amount: f64,
     pub description: String,
+    pub kind: TransactionKind,
+}
+
+impl From<MemberTransaction> for Transaction {
+    fn from(tx: MemberTransaction) -> Self {
+        Transaction {
+            id: tx.id,
+            member_id: tx.member_id,
+            date: tx.date,
+            account_name: tx.account_name,
+            amount: tx.amount,
+            description: tx.description,
+            kind: tx.kind,
+        }
+    }
+}
+
+/// How transactions are grouped for the totals
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionGroup {
+    /// The month of the date, as `YYYY-MM`
+    #[default]
+    Month,
+    /// The member id
+    Member,
+    /// The kind of the transaction
+    Kind,
+}
+
+impl fmt::Display for TransactionGroup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransactionGroup::Month => write!(f, "month"),
+            TransactionGroup::Member => write!(f, "member"),
+            TransactionGroup::Kind => write!(f, "kind"),
+        }
+    }
+}
+
+impl FromStr for TransactionGroup {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "month" => Ok(TransactionGroup::Month),
+            "member" => Ok(TransactionGroup::Member),
+            "kind" => Ok(TransactionGroup::Kind),
+            _ => Err(anyhow!("unknown transaction group: {}", s)),
+        }
+    }
+}
+
+/// Sum up the transactions matching the filter
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TransactionTotalFilter {
+    pub group: TransactionGroup,
+    pub transactions: TransactionFilter,
+}
+
+/// The number and sum of the transactions in a group
+#[derive(Debug, Default, Clone, PartialEq, FromRow, Serialize, Deserialize)]
+pub struct TransactionTotal {
+    /// The month, member id or kind
+    pub label: String,
+    #[sqlx(try_from = "i64")]
+    pub count: u32,
+    pub sum: f64,
+}
+
+/// The origin of a transaction. It is stored with the
+/// transaction when it is booked.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum TransactionKind {
+    /// A calculated membership fee
+    Fee,
+    /// A payment imported from the bank
+    Bank,
+    /// The part of a bank payment split between members
+    Split,
+    /// The left-over of a split bank payment
+    Overflow,
+    /// A reimbursement of expenses paid out to the member,
+    /// and the credit of the expenses
+    Payout,
+    /// The balance refunded to the member
+    Refund,
+    /// A manual correction of the balance
+    #[default]
+    Manual,
+}
+
+impl fmt::Display for TransactionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransactionKind::Fee => write!(f, "fee"),
+            TransactionKind::Bank => write!(f, "bank"),
+            TransactionKind::Split => write!(f, "split"),
+            TransactionKind::Overflow => write!(f, "overflow"),
+            TransactionKind::Payout => write!(f, "payout"),
+            TransactionKind::Refund => write!(f, "refund"),
+            TransactionKind::Manual => write!(f, "manual"),
+        }
+    }
+}
+
+impl FromStr for TransactionKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "fee" => Ok(TransactionKind::Fee),
+            "bank" => Ok(TransactionKind::Bank),
+            "split" => Ok(TransactionKind::Split),
+            "overflow" => Ok(TransactionKind::Overflow),
+            "payout" => Ok(TransactionKind::Payout),
+            "refund" => Ok(TransactionKind::Refund),
+            "manual" => Ok(TransactionKind::Manual),
+            _ => Err(anyhow!("unknown transaction kind: {}", s)),
+        }
+    }
+}
+
+/// Transactions booked on the account of a member
+#[derive(Debug, Clone, Default)]
+pub struct Booking {
+    pub member_id: u32,
+    pub transactions: Vec<Transaction>,
+    /// The date the account is calculated until after the
+    /// booking. Fees are not booked twice, so the booking
+    /// fails if the account is calculated until then already.
+    pub account_calculated_at: Option<NaiveDate>,
+    /// The date and number of the imported bank transaction,
+    /// recorded as the last bank transaction of the member.
+    pub last_bank_transaction: Option<(NaiveDate, u32)>,
+}
+
+#[derive(ThisError, Debug)]
+#[error("the account of member {member_id} is calculated until {date} already")]
+pub struct AlreadyCalculated {
+    pub member_id: u32,
+    pub date: NaiveDate,
+}
+
+/// Insert the transactions of a booking and add them to
+/// the account balance of the member. Both are changed
+/// together, so concurrent bookings are not lost.
+/// Returns the updated member and the booked transactions.
+#[async_trait]
+pub trait Book {
+    async fn book(&self, booking: Booking) -> Result<(Member, Vec<Transaction>)>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transaction_kind() {
+        for kind in [
+            TransactionKind::Fee,
+            TransactionKind::Bank,
+            TransactionKind::Split,
+            TransactionKind::Overflow,
+            TransactionKind::Payout,
+            TransactionKind::Refund,
+            TransactionKind::Manual,
+        ] {
+            assert_eq!(kind.to_string().parse::<TransactionKind>().unwrap(), kind);
+        }
+        assert!("reimbursement".parse::<TransactionKind>().is_err());
+        assert_eq!(Transaction::default().kind, TransactionKind::Manual);
+    }
 }
@@ -0,0 +1,135 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use crate::{
+    access::{Access, Resource},
+    OptionalId,
+};
+
+/// What a user may do
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum Role {
+    /// Manages members and accounts
+    Treasurer,
+    /// Reads members and accounts, but not the audit log
+    Board,
+    /// Reads everything, including the audit log
+    Auditor,
+    /// Reads their own records
+    #[default]
+    Member,
+}
+
+impl Role {
+    /// Check if the role grants the access. Members are
+    /// further limited to their own records.
+    pub fn allows(&self, resource: Resource, access: Access) -> bool {
+        match self {
+            Role::Treasurer => true,
+            Role::Auditor => access == Access::Read,
+            Role::Board => access == Access::Read
+                && !matches!(resource, Resource::AuditLog | Resource::User),
+            Role::Member => access == Access::Read && resource.is_personal(),
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Role::Treasurer => write!(f, "treasurer"),
+            Role::Board => write!(f, "board"),
+            Role::Auditor => write!(f, "auditor"),
+            Role::Member => write!(f, "member"),
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "treasurer" => Ok(Role::Treasurer),
+            "board" => Ok(Role::Board),
+            "auditor" => Ok(Role::Auditor),
+            "member" => Ok(Role::Member),
+            _ => Err(anyhow!("unknown role: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct UserFilter {
+    pub id: Option<u32>,
+    pub name: Option<String>,
+    pub role: Option<Role>,
+    pub member_id: Option<u32>,
+}
+
+/// A person working with the database. Users with the
+/// member role are linked to their member record.
+#[derive(Debug, Clone, Default, FromRow, Serialize, Deserialize)]
+pub struct User {
+    #[sqlx(try_from = "i64")]
+    pub id: u32,
+    pub name: String,
+    pub role: Role,
+    #[sqlx(try_from = "OptionalId")]
+    pub member_id: Option<u32>,
+    pub created_at: NaiveDateTime,
+}
+
+impl User {
+    /// Check the user is complete: members must be
+    /// linked to their record.
+    pub fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            return Err(anyhow!("user name must not be empty"));
+        }
+        if self.role == Role::Member && self.member_id.is_none() {
+            return Err(anyhow!(
+                "user {} has the member role but no member id", self.name));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_allows() {
+        use Access::{Read, Write};
+
+        assert!(Role::Treasurer.allows(Resource::User, Write));
+        assert!(Role::Auditor.allows(Resource::AuditLog, Read));
+        assert!(!Role::Auditor.allows(Resource::Member, Write));
+        assert!(Role::Board.allows(Resource::Transaction, Read));
+        assert!(!Role::Board.allows(Resource::AuditLog, Read));
+        assert!(!Role::Board.allows(Resource::Member, Write));
+        assert!(Role::Member.allows(Resource::DonationReceipt, Read));
+        assert!(!Role::Member.allows(Resource::User, Read));
+        assert!(!Role::Member.allows(Resource::Transaction, Write));
+    }
+
+    #[test]
+    fn test_validate() {
+        let user = User {
+            name: "eris".to_string(),
+            role: Role::Member,
+            ..Default::default()
+        };
+        assert!(user.validate().is_err());
+        assert!(User { member_id: Some(23), ..user }.validate().is_ok());
+    }
+}
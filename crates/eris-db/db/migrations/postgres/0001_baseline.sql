@@ -0,0 +1,46 @@
+-- Ids are BIGINT, as PostgreSQL has no unsigned integers,
+-- and enums are stored as TEXT.
+
+CREATE TABLE members (
+    id                BIGINT            GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    name              VARCHAR(100)      NOT NULL,
+    email             VARCHAR(100)      NOT NULL,
+    notes             TEXT              NOT NULL,
+    membership_start  DATE              NOT NULL,
+    membership_end    DATE              NULL     DEFAULT NULL,
+    fee               DECIMAL(10, 2)    NOT NULL,
+    interval          BIGINT            NOT NULL DEFAULT 1,
+    last_payment_at   DATE              NOT NULL,
+    last_bank_transaction_at DATE       NOT NULL,
+    last_bank_transaction_number BIGINT NOT NULL,
+    account_calculated_at DATE          NOT NULL,
+    account           DECIMAL(10, 2)    NOT NULL DEFAULT '0.00'
+);
+
+
+CREATE TABLE bank_import_member_ibans (
+    member_id         BIGINT            NOT NULL,
+    iban              VARCHAR(100)      NOT NULL,
+
+    match_subject     VARCHAR(255)      NULL,
+
+    split_amount      DECIMAL(10, 2)    NULL,
+
+    FOREIGN KEY (member_id) REFERENCES members(id)
+      ON DELETE CASCADE,
+
+    PRIMARY KEY (member_id, iban)
+);
+
+
+CREATE TABLE transactions (
+    id                BIGINT            GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    member_id         BIGINT            NOT NULL,
+    date              DATE              NOT NULL,
+    account_name      VARCHAR(100)      NOT NULL,
+    amount            DECIMAL(10, 2)    NOT NULL,
+    description       TEXT              NOT NULL,
+
+    FOREIGN KEY (member_id) REFERENCES members(id)
+      ON DELETE CASCADE
+);
@@ -0,0 +1,15 @@
+CREATE TABLE mandates (
+    id                BIGINT            GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    member_id         BIGINT            NOT NULL,
+    reference         VARCHAR(35)       NOT NULL UNIQUE,
+    iban              VARCHAR(100)      NOT NULL,
+    account_holder    VARCHAR(100)      NOT NULL,
+    signed_at         DATE              NOT NULL,
+    amended_at        DATE              NULL     DEFAULT NULL,
+    original_iban     VARCHAR(100)      NULL     DEFAULT NULL,
+    last_used_at      DATE              NULL     DEFAULT NULL,
+    revoked_at        DATE              NULL     DEFAULT NULL,
+
+    FOREIGN KEY (member_id) REFERENCES members(id)
+      ON DELETE CASCADE
+);
@@ -0,0 +1,16 @@
+CREATE TABLE payouts (
+    id                BIGINT            GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    member_id         BIGINT            NOT NULL,
+    kind              TEXT              NOT NULL, -- refund, reimbursement
+    name              VARCHAR(100)      NOT NULL,
+    iban              VARCHAR(100)      NOT NULL,
+    amount            DECIMAL(10, 2)    NOT NULL,
+    subject           VARCHAR(140)      NOT NULL,
+    created_at        DATE              NOT NULL,
+    batch_id          VARCHAR(35)       NULL     DEFAULT NULL,
+    exported_at       DATE              NULL     DEFAULT NULL,
+    settled_at        DATE              NULL     DEFAULT NULL,
+
+    FOREIGN KEY (member_id) REFERENCES members(id)
+      ON DELETE CASCADE
+);
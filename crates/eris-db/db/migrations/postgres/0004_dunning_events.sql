@@ -0,0 +1,10 @@
+CREATE TABLE dunning_events (
+    id                BIGINT            GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    member_id         BIGINT            NOT NULL,
+    level             TEXT              NOT NULL,
+    date              DATE              NOT NULL,
+    balance           DECIMAL(10, 2)    NOT NULL,
+
+    FOREIGN KEY (member_id) REFERENCES members(id)
+      ON DELETE CASCADE
+);
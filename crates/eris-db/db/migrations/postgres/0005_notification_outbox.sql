@@ -0,0 +1,16 @@
+CREATE TABLE notification_outbox (
+    id                BIGINT            GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    member_id         BIGINT            NOT NULL,
+    event             TEXT              NOT NULL,
+    dedup_key         VARCHAR(255)      NOT NULL UNIQUE,
+    recipient         VARCHAR(100)      NOT NULL,
+    subject           VARCHAR(255)      NOT NULL,
+    body              TEXT              NOT NULL,
+    created_at        TIMESTAMP         NOT NULL,
+    sent_at           TIMESTAMP         NULL     DEFAULT NULL,
+    attempts          BIGINT            NOT NULL DEFAULT 0,
+    last_error        TEXT              NULL     DEFAULT NULL,
+
+    FOREIGN KEY (member_id) REFERENCES members(id)
+      ON DELETE CASCADE
+);
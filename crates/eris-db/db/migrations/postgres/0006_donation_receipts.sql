@@ -0,0 +1,16 @@
+CREATE TABLE donation_receipts (
+    id                BIGINT            GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    member_id         BIGINT            NOT NULL,
+    year              INTEGER           NOT NULL,
+    sequence          BIGINT            NOT NULL,
+    name              VARCHAR(100)      NOT NULL,
+    amount            DECIMAL(10, 2)    NOT NULL,
+    issued_at         DATE              NOT NULL,
+
+    UNIQUE (year, sequence),
+    UNIQUE (member_id, year),
+
+    -- Issued receipts must be kept
+    FOREIGN KEY (member_id) REFERENCES members(id)
+      ON DELETE RESTRICT
+);
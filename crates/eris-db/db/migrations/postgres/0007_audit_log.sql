@@ -0,0 +1,16 @@
+-- Changes to members, rules and transactions. Entries are
+-- only appended and kept when the member is deleted.
+CREATE TABLE audit_log (
+    id                BIGINT            GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    created_at        TIMESTAMP         NOT NULL,
+    actor             VARCHAR(100)      NOT NULL,
+    command           TEXT              NOT NULL,
+    action            TEXT              NOT NULL, -- insert, update, delete
+    entity            TEXT              NOT NULL,
+    entity_id         VARCHAR(100)      NOT NULL,
+    member_id         BIGINT            NULL,
+    old_value         TEXT              NULL, -- JSON
+    new_value         TEXT              NULL  -- JSON
+);
+
+CREATE INDEX audit_log_member_id ON audit_log(member_id);
@@ -0,0 +1,2 @@
+ALTER TABLE members
+    ADD COLUMN archived_at DATE NULL DEFAULT NULL;
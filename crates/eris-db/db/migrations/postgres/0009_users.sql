@@ -0,0 +1,12 @@
+-- People working with the database and their roles.
+-- Members are linked to their member record.
+CREATE TABLE users (
+    id                BIGINT            GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    name              VARCHAR(100)      NOT NULL UNIQUE,
+    role              TEXT              NOT NULL, -- treasurer, board, auditor, member
+    member_id         BIGINT            NULL,
+    created_at        TIMESTAMP         NOT NULL,
+
+    FOREIGN KEY (member_id) REFERENCES members(id)
+      ON DELETE CASCADE
+);
@@ -0,0 +1,20 @@
+-- The kind of a transaction is stored when it is booked.
+-- Existing transactions get the kind derived from the
+-- account name and description; other payments are
+-- bank transactions.
+ALTER TABLE transactions
+    ADD COLUMN kind TEXT NOT NULL DEFAULT 'manual'; -- fee, bank, split, overflow, payout, refund, manual
+
+UPDATE transactions SET kind = CASE
+    WHEN account_name = 'memberhip fee' THEN 'fee'
+    WHEN account_name = '' THEN 'manual'
+    WHEN LEFT(description, 12) = 'ERIS-PAYOUT-'
+      AND POSITION(' refund: ' IN description) > 0
+        THEN 'refund'
+    WHEN LEFT(description, 12) = 'ERIS-PAYOUT-'
+      OR LEFT(description, 25) = 'Reimbursement of expenses'
+        THEN 'payout'
+    WHEN RIGHT(description, 8) = ' (split)' THEN 'split'
+    WHEN RIGHT(description, 11) = ' (overflow)' THEN 'overflow'
+    ELSE 'bank'
+END;
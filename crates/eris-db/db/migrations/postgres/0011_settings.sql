@@ -0,0 +1,6 @@
+-- Settings which belong to the database rather than
+-- to the configuration of a program using it.
+CREATE TABLE settings (
+    name              VARCHAR(100)      PRIMARY KEY,
+    value             TEXT              NOT NULL
+);
@@ -0,0 +1,4 @@
+-- A delivery claims a notification before sending it,
+-- so concurrent deliveries do not send it twice.
+ALTER TABLE notification_outbox
+    ADD COLUMN claimed_at TIMESTAMP NULL DEFAULT NULL;
@@ -1,4 +1,3 @@
-
 CREATE TABLE members (
     id                INTEGER           PRIMARY KEY AUTOINCREMENT,
     name              VARCHAR(100)      NOT NULL,
@@ -42,4 +41,3 @@ CREATE TABLE transactions (
     FOREIGN KEY (member_id) REFERENCES members(id)
       ON DELETE CASCADE
 );
-
@@ -0,0 +1,15 @@
+CREATE TABLE mandates (
+    id                INTEGER           PRIMARY KEY AUTOINCREMENT,
+    member_id         INTEGER           NOT NULL,
+    reference         VARCHAR(35)       NOT NULL UNIQUE,
+    iban              VARCHAR(100)      NOT NULL,
+    account_holder    VARCHAR(100)      NOT NULL,
+    signed_at         TEXT              NOT NULL, -- DATE
+    amended_at        TEXT              NULL     DEFAULT NULL,
+    original_iban     VARCHAR(100)      NULL     DEFAULT NULL,
+    last_used_at      TEXT              NULL     DEFAULT NULL,
+    revoked_at        TEXT              NULL     DEFAULT NULL,
+
+    FOREIGN KEY (member_id) REFERENCES members(id)
+      ON DELETE CASCADE
+);
@@ -0,0 +1,16 @@
+CREATE TABLE payouts (
+    id                INTEGER           PRIMARY KEY AUTOINCREMENT,
+    member_id         INTEGER           NOT NULL,
+    kind              VARCHAR(20)       NOT NULL, -- refund, reimbursement
+    name              VARCHAR(100)      NOT NULL,
+    iban              VARCHAR(100)      NOT NULL,
+    amount            DECIMAL(10, 2)    NOT NULL,
+    subject           VARCHAR(140)      NOT NULL,
+    created_at        TEXT              NOT NULL, -- DATE
+    batch_id          VARCHAR(35)       NULL     DEFAULT NULL,
+    exported_at       TEXT              NULL     DEFAULT NULL,
+    settled_at        TEXT              NULL     DEFAULT NULL,
+
+    FOREIGN KEY (member_id) REFERENCES members(id)
+      ON DELETE CASCADE
+);
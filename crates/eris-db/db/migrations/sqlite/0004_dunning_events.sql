@@ -0,0 +1,10 @@
+CREATE TABLE dunning_events (
+    id                INTEGER           PRIMARY KEY AUTOINCREMENT,
+    member_id         INTEGER           NOT NULL,
+    level             VARCHAR(20)       NOT NULL,
+    date              TEXT              NOT NULL, -- DATE
+    balance           DECIMAL(10, 2)    NOT NULL,
+
+    FOREIGN KEY (member_id) REFERENCES members(id)
+      ON DELETE CASCADE
+);
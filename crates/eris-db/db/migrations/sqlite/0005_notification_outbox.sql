@@ -0,0 +1,16 @@
+CREATE TABLE notification_outbox (
+    id                INTEGER           PRIMARY KEY AUTOINCREMENT,
+    member_id         INTEGER           NOT NULL,
+    event             VARCHAR(40)       NOT NULL,
+    dedup_key         VARCHAR(255)      NOT NULL UNIQUE,
+    recipient         VARCHAR(100)      NOT NULL,
+    subject           VARCHAR(255)      NOT NULL,
+    body              TEXT              NOT NULL,
+    created_at        TEXT              NOT NULL, -- DATETIME
+    sent_at           TEXT              NULL     DEFAULT NULL,
+    attempts          INTEGER           NOT NULL DEFAULT 0,
+    last_error        TEXT              NULL     DEFAULT NULL,
+
+    FOREIGN KEY (member_id) REFERENCES members(id)
+      ON DELETE CASCADE
+);
@@ -0,0 +1,16 @@
+CREATE TABLE donation_receipts (
+    id                INTEGER           PRIMARY KEY AUTOINCREMENT,
+    member_id         INTEGER           NOT NULL,
+    year              INTEGER           NOT NULL,
+    sequence          INTEGER           NOT NULL,
+    name              VARCHAR(100)      NOT NULL,
+    amount            DECIMAL(10, 2)    NOT NULL,
+    issued_at         TEXT              NOT NULL, -- DATE
+
+    UNIQUE (year, sequence),
+    UNIQUE (member_id, year),
+
+    -- Issued receipts must be kept
+    FOREIGN KEY (member_id) REFERENCES members(id)
+      ON DELETE RESTRICT
+);
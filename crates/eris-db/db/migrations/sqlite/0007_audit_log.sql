@@ -0,0 +1,16 @@
+-- Changes to members, rules and transactions. Entries are
+-- only appended and kept when the member is deleted.
+CREATE TABLE audit_log (
+    id                INTEGER           PRIMARY KEY AUTOINCREMENT,
+    created_at        TEXT              NOT NULL, -- DATETIME
+    actor             VARCHAR(100)      NOT NULL,
+    command           TEXT              NOT NULL,
+    action            VARCHAR(10)       NOT NULL, -- insert, update, delete
+    entity            VARCHAR(30)       NOT NULL,
+    entity_id         VARCHAR(100)      NOT NULL,
+    member_id         INTEGER           NULL,
+    old_value         TEXT              NULL, -- JSON
+    new_value         TEXT              NULL  -- JSON
+);
+
+CREATE INDEX audit_log_member_id ON audit_log(member_id);
@@ -0,0 +1,2 @@
+ALTER TABLE members
+    ADD COLUMN archived_at TEXT NULL DEFAULT NULL; -- DATE
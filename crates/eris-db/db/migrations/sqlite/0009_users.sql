@@ -0,0 +1,12 @@
+-- People working with the database and their roles.
+-- Members are linked to their member record.
+CREATE TABLE users (
+    id                INTEGER           PRIMARY KEY AUTOINCREMENT,
+    name              VARCHAR(100)      NOT NULL UNIQUE,
+    role              VARCHAR(20)       NOT NULL, -- treasurer, board, auditor, member
+    member_id         INTEGER           NULL,
+    created_at        TEXT              NOT NULL, -- DATETIME
+
+    FOREIGN KEY (member_id) REFERENCES members(id)
+      ON DELETE CASCADE
+);
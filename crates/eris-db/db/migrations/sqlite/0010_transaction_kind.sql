@@ -0,0 +1,20 @@
+-- The kind of a transaction is stored when it is booked.
+-- Existing transactions get the kind derived from the
+-- account name and description; other payments are
+-- bank transactions.
+ALTER TABLE transactions
+    ADD COLUMN kind VARCHAR(20) NOT NULL DEFAULT 'manual'; -- fee, bank, split, overflow, payout, refund, manual
+
+UPDATE transactions SET kind = CASE
+    WHEN account_name = 'memberhip fee' THEN 'fee'
+    WHEN account_name = '' THEN 'manual'
+    WHEN SUBSTR(description, 1, 12) = 'ERIS-PAYOUT-'
+      AND INSTR(description, ' refund: ') > 0
+        THEN 'refund'
+    WHEN SUBSTR(description, 1, 12) = 'ERIS-PAYOUT-'
+      OR SUBSTR(description, 1, 25) = 'Reimbursement of expenses'
+        THEN 'payout'
+    WHEN SUBSTR(description, -8) = ' (split)' THEN 'split'
+    WHEN SUBSTR(description, -11) = ' (overflow)' THEN 'overflow'
+    ELSE 'bank'
+END;
@@ -0,0 +1,271 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Serialize;
+use sqlx::QueryBuilder;
+
+use eris_data::{
+    AuditAction,
+    AuditEntity,
+    AuditEntry,
+    AuditEntryFilter,
+    BankImportRule,
+    Member,
+    Query,
+    Transaction,
+    User,
+};
+
+use crate::{connection::with_conn, Connection};
+
+/// Records which are written to the audit log
+pub(crate) trait Audited: Serialize {
+    const ENTITY: AuditEntity;
+
+    fn entity_id(&self) -> String;
+    fn member_id(&self) -> Option<u32>;
+}
+
+impl Audited for Member {
+    const ENTITY: AuditEntity = AuditEntity::Member;
+
+    fn entity_id(&self) -> String {
+        self.id.to_string()
+    }
+    fn member_id(&self) -> Option<u32> {
+        Some(self.id)
+    }
+}
+
+impl Audited for BankImportRule {
+    const ENTITY: AuditEntity = AuditEntity::BankImportRule;
+
+    fn entity_id(&self) -> String {
+        format!("{}:{}", self.member_id, self.iban)
+    }
+    fn member_id(&self) -> Option<u32> {
+        Some(self.member_id)
+    }
+}
+
+impl Audited for Transaction {
+    const ENTITY: AuditEntity = AuditEntity::Transaction;
+
+    fn entity_id(&self) -> String {
+        self.id.to_string()
+    }
+    fn member_id(&self) -> Option<u32> {
+        Some(self.member_id)
+    }
+}
+
+impl Audited for User {
+    const ENTITY: AuditEntity = AuditEntity::User;
+
+    fn entity_id(&self) -> String {
+        self.name.clone()
+    }
+    fn member_id(&self) -> Option<u32> {
+        self.member_id
+    }
+}
+
+/// The login name of the user
+pub(crate) fn default_actor() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("LOGNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// The command line of the process with secrets removed
+pub(crate) fn command_line() -> String {
+    redact_args(std::env::args())
+}
+
+/// Replace the values of options like passwords and keys
+fn redact_args<I: Iterator<Item = String>>(args: I) -> String {
+    let is_secret = |arg: &str| {
+        arg.starts_with("--")
+            && ["key", "password", "secret"].iter().any(|s| arg.contains(s))
+            && !arg.contains("keyfile")
+    };
+    let mut redacted = vec![];
+    let mut redact_next = false;
+    for arg in args {
+        if redact_next {
+            redacted.push("***".to_string());
+            redact_next = false;
+            continue;
+        }
+        match arg.split_once('=') {
+            Some((name, _)) if is_secret(name) => {
+                redacted.push(format!("{}=***", name));
+            }
+            None if is_secret(&arg) => {
+                redact_next = true;
+                redacted.push(arg);
+            }
+            _ => redacted.push(arg),
+        }
+    }
+    redacted.join(" ")
+}
+
+impl Connection {
+    /// Append a change of a record to the audit log
+    pub(crate) async fn audit<T: Audited>(
+        &self,
+        action: AuditAction,
+        old: Option<&T>,
+        new: Option<&T>,
+    ) -> Result<()> {
+        let Some(record) = new.or(old) else {
+            return Ok(());
+        };
+        let old_value = old.map(serde_json::to_string).transpose()?;
+        let new_value = new.map(serde_json::to_string).transpose()?;
+
+        with_conn!(self, |conn: DB| {
+            let mut qry = QueryBuilder::<DB>::new(
+                r#"INSERT INTO audit_log (
+                    created_at,
+                    actor,
+                    command,
+                    action,
+                    entity,
+                    entity_id,
+                    member_id,
+                    old_value,
+                    new_value
+                ) VALUES (
+                "#,
+            );
+            qry.separated(", ")
+                .push_bind(chrono::Local::now().naive_local())
+                .push_bind(&self.actor)
+                .push_bind(&self.command)
+                .push_bind(action)
+                .push_bind(T::ENTITY)
+                .push_bind(record.entity_id())
+                .push_bind(record.member_id().map(i64::from))
+                .push_bind(old_value)
+                .push_bind(new_value);
+            qry.push(")")
+                .build()
+                .execute(&mut *conn)
+                .await?;
+        });
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Query<AuditEntry> for Connection {
+    type Filter = AuditEntryFilter;
+
+    /// Fetch audit log entries, oldest first
+    async fn query(&self, filter: &Self::Filter) -> Result<Vec<AuditEntry>> {
+        let entries: Vec<AuditEntry> = with_conn!(self, |conn: DB| {
+            let mut qry = QueryBuilder::<DB>::new(
+                r#"
+                SELECT
+                    id,
+                    created_at,
+                    actor,
+                    command,
+                    action,
+                    entity,
+                    entity_id,
+                    member_id,
+                    old_value,
+                    new_value
+                FROM audit_log
+                WHERE TRUE
+                "#,
+            );
+            if let Some(member_id) = filter.member_id {
+                qry.push(" AND member_id = ").push_bind(i64::from(member_id));
+            }
+            if let Some(entity) = filter.entity {
+                qry.push(" AND entity = ").push_bind(entity);
+            }
+            if let Some(entity_id) = filter.entity_id.clone() {
+                qry.push(" AND entity_id = ").push_bind(entity_id);
+            }
+            if let Some(action) = filter.action {
+                qry.push(" AND action = ").push_bind(action);
+            }
+            qry.push(" ORDER BY id");
+
+            qry.build_query_as().fetch_all(&mut *conn).await?
+        });
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use eris_data::{Delete, Insert, Update};
+
+    #[test]
+    fn test_redact_args() {
+        let args = [
+            "eris-cli", "--db-key", "s3cret", "--smtp-password=hunter2",
+            "--db-keyfile", "/etc/eris.key", "members", "list",
+        ].map(String::from);
+        assert_eq!(
+            redact_args(args.into_iter()),
+            "eris-cli --db-key *** --smtp-password=*** \
+             --db-keyfile /etc/eris.key members list",
+        );
+    }
+
+    #[tokio::test]
+    async fn test_audit_log() {
+        let db = Connection::open_test().await.with_actor("treasurer");
+        let member = db.insert(Member {
+            name: "Eris".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        db.update(Member {
+            account: 23.0,
+            ..member.clone()
+        }).await.unwrap();
+        let rule = db.insert(BankImportRule {
+            member_id: member.id,
+            iban: "DE2342".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        db.delete(rule).await.unwrap();
+
+        let entries: Vec<AuditEntry> = db.query(&AuditEntryFilter {
+            member_id: Some(member.id),
+            ..Default::default()
+        }).await.unwrap();
+        let actions: Vec<(AuditAction, AuditEntity)> = entries.iter()
+            .map(|e| (e.action, e.entity))
+            .collect();
+        assert_eq!(actions, vec![
+            (AuditAction::Insert, AuditEntity::Member),
+            (AuditAction::Update, AuditEntity::Member),
+            (AuditAction::Insert, AuditEntity::BankImportRule),
+            (AuditAction::Delete, AuditEntity::BankImportRule),
+        ]);
+        assert_eq!(entries[0].actor, "treasurer");
+        assert_eq!(entries[1].changes()[0].field, "account");
+        assert_eq!(entries[1].changes()[0].new, "23.0");
+        assert_eq!(entries[3].entity_id, format!("{}:DE2342", member.id));
+        assert!(entries[3].new_value.is_none());
+
+        // Entries are kept when the member is deleted
+        db.delete(member.clone()).await.unwrap();
+        let entries: Vec<AuditEntry> = db.query(&AuditEntryFilter {
+            member_id: Some(member.id),
+            entity: Some(AuditEntity::Member),
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[2].action, AuditAction::Delete);
+    }
+}
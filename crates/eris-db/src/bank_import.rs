@@ -1,8 +1,9 @@
 use anyhow::Result;
 use async_trait::async_trait;
-use sqlx::{QueryBuilder, Sqlite};
+use sqlx::QueryBuilder;
 
 use eris_data::{
+    AuditAction,
     BankImportRule,
     BankImportRuleFilter,
     Retrieve,
@@ -13,6 +14,7 @@ use eris_data::{
 };
 
 use crate::{
+    connection::with_conn,
     Connection,
     QueryError,
 };
@@ -26,27 +28,28 @@ impl Query<BankImportRule> for Connection {
     async fn query(
         &self, filter: &BankImportRuleFilter,
     ) -> Result<Vec<BankImportRule>> {
-        let mut conn = self.lock().await;
-        let mut qry = QueryBuilder::new(
-            r#"
-            SELECT 
-                member_id,
-                iban,
-                match_subject,
-                ROUND(split_amount, 10) AS split_amount
-            FROM bank_import_member_ibans
-            WHERE 1
-            "#,
-        );
-        if let Some(id) = filter.member_id {
-            qry.push(" AND member_id = ").push_bind(id);
-        }
-        if let Some(iban) = filter.iban.clone() {
-            qry.push(" AND iban = ").push_bind(iban);
-        }
-        let rules: Vec<BankImportRule> = qry.build_query_as()
-            .fetch_all(&mut *conn)
-            .await?;
+        let rules: Vec<BankImportRule> = with_conn!(self, |conn: DB| {
+            let mut qry = QueryBuilder::<DB>::new(
+                r#"
+                SELECT 
+                    member_id,
+                    iban,
+                    match_subject,
+                    CAST(ROUND(CAST(split_amount AS NUMERIC), 10) AS DOUBLE PRECISION) AS split_amount
+                FROM bank_import_member_ibans
+                WHERE TRUE
+                "#,
+            );
+            if let Some(id) = filter.member_id {
+                qry.push(" AND member_id = ").push_bind(i64::from(id));
+            }
+            if let Some(iban) = filter.iban.clone() {
+                qry.push(" AND iban = ").push_bind(iban);
+            }
+            qry.push(" ORDER BY member_id, iban");
+
+            qry.build_query_as().fetch_all(&mut *conn).await?
+        });
         Ok(rules)
     }
 }
@@ -65,7 +68,7 @@ impl Retrieve<BankImportRule> for Connection {
             iban: Some(iban),
         };
         let rules: Vec<BankImportRule> = self.query(&filter).await?;
-        if rules.len() == 0 {
+        if rules.is_empty() {
             return Err(QueryError::NotFound.into());
         }
         if rules.len() > 1 {
@@ -79,28 +82,25 @@ impl Retrieve<BankImportRule> for Connection {
 impl Update<BankImportRule> for Connection {
     /// Update member IBAN
     async fn update(&self, rule: BankImportRule) -> Result<BankImportRule> {
-        {
-            let mut conn = self.lock().await;
-            let mut split_amount: Option<String> = None;
-            if let Some(amount) = rule.split_amount {
-                split_amount = Some(format!("{}", amount)); 
-            }
-
-            QueryBuilder::<Sqlite>::new(
+        let old = self.find_rule(&rule).await?;
+        with_conn!(self, |conn: DB| {
+            QueryBuilder::<DB>::new(
                 "UPDATE bank_import_member_ibans SET")
                 .push(" split_amount = ")
-                .push_bind(&split_amount)
+                .push_bind(rule.split_amount)
                 .push(", match_subject = ")
                 .push_bind(&rule.match_subject)
                 .push(" WHERE member_id = ")
-                .push_bind(rule.member_id)
+                .push_bind(i64::from(rule.member_id))
                 .push(" AND iban = ")
                 .push_bind(&rule.iban)
                 .build()
                 .execute(&mut *conn)
                 .await?;
-        }
-        self.retrieve((rule.member_id, rule.iban.clone())).await
+        });
+        let rule = self.retrieve((rule.member_id, rule.iban.clone())).await?;
+        self.audit(AuditAction::Update, old.as_ref(), Some(&rule)).await?;
+        Ok(rule)
     }
 
 }
@@ -112,14 +112,8 @@ impl Insert<BankImportRule> for Connection {
         &self,
         rule: BankImportRule,
     ) -> Result<BankImportRule> {
-        {
-            let mut split_amount: Option<String> = None;
-            if let Some(amount) = rule.split_amount {
-                split_amount = Some(format!("{}", amount)); 
-            }
-
-            let mut conn = self.lock().await;
-            let mut qry = QueryBuilder::<Sqlite>::new(
+        with_conn!(self, |conn: DB| {
+            let mut qry = QueryBuilder::<DB>::new(
                 r#"INSERT INTO bank_import_member_ibans (
                     member_id,
                     iban,
@@ -129,15 +123,17 @@ impl Insert<BankImportRule> for Connection {
             );
             qry.push(" ) VALUES ( ");
             qry.separated(", ")
-                .push_bind(rule.member_id)
+                .push_bind(i64::from(rule.member_id))
                 .push_bind(&rule.iban)
                 .push_bind(&rule.match_subject)
-                .push_bind(&split_amount);
+                .push_bind(rule.split_amount);
             qry.push(") ");
             qry.build()
                 .execute(&mut *conn).await?;
-        }
-        self.retrieve((rule.member_id, rule.iban.clone())).await
+        });
+        let rule = self.retrieve((rule.member_id, rule.iban.clone())).await?;
+        self.audit(AuditAction::Insert, None, Some(&rule)).await?;
+        Ok(rule)
     }
 
 }
@@ -147,21 +143,38 @@ impl Delete<BankImportRule> for Connection {
 
     /// Delete a member IBAN rule
     async fn delete(&self, rule: BankImportRule) -> Result<()> {
-        let mut conn = self.lock().await;
-        QueryBuilder::<Sqlite>::new(
-            "DELETE FROM bank_import_member_ibans WHERE")
-            .push(" member_id = ")
-            .push_bind(rule.member_id)
-            .push(" AND iban = ")
-            .push_bind(&rule.iban)
-            .build()
-            .execute(&mut *conn)
-            .await?;
+        let old = self.find_rule(&rule).await?;
+        with_conn!(self, |conn: DB| {
+            QueryBuilder::<DB>::new(
+                "DELETE FROM bank_import_member_ibans WHERE")
+                .push(" member_id = ")
+                .push_bind(i64::from(rule.member_id))
+                .push(" AND iban = ")
+                .push_bind(&rule.iban)
+                .build()
+                .execute(&mut *conn)
+                .await?;
+        });
+        self.audit(AuditAction::Delete, old.as_ref(), None).await?;
 
         Ok(())
     }
 }
 
+impl Connection {
+    /// Get the stored state of a rule if it exists
+    async fn find_rule(
+        &self,
+        rule: &BankImportRule,
+    ) -> Result<Option<BankImportRule>> {
+        let mut rules: Vec<BankImportRule> = self.query(&BankImportRuleFilter{
+            member_id: Some(rule.member_id),
+            iban: Some(rule.iban.clone()),
+        }).await?;
+        Ok(rules.pop())
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -1,79 +1,365 @@
+use std::env;
 use std::fs;
 use std::path::Path;
 use std::str::FromStr;
-use std::sync::Arc;
-use std::ops::Deref;
+use std::thread;
+use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use sqlx::{
-    sqlite::{SqliteConnectOptions, SqliteConnection},
+    postgres::{PgConnectOptions, PgConnection, PgPool, PgPoolOptions},
+    sqlite::{
+        SqliteConnectOptions,
+        SqliteConnection,
+        SqliteJournalMode,
+        SqlitePool,
+        SqlitePoolOptions,
+    },
     Connection as SqlConnection,
+    Executor,
 };
-use tokio::sync::Mutex;
 
-use crate::schema;
+use crate::{
+    audit,
+    encryption::{self, DatabaseKey},
+    schema,
+};
 
-/// A thread safe connection to the database
-pub struct Connection {
-    filename: String,
-    conn: Arc<Mutex<SqliteConnection>>,
-    test: bool,
+/// Connections kept open to the database
+const MAX_CONNECTIONS: u32 = 16;
+
+/// How long to wait for a write lock held by another
+/// connection or process
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Run the tests against a PostgreSQL server instead of
+/// SQLite, e.g. `postgres://postgres@localhost/postgres`.
+/// Each test creates and drops its own database.
+pub const TEST_DATABASE_URL: &str = "ERIS_TEST_DATABASE_URL";
+
+/// The pool of the database backend. SQLite databases
+/// are files, PostgreSQL databases are given by a
+/// `postgres://` URL.
+pub enum Pool {
+    Sqlite(SqlitePool),
+    Postgres(PgPool),
 }
 
-impl Deref for Connection {
-    type Target = Mutex<SqliteConnection>;
-    fn deref(&self) -> &Self::Target {
-        &self.conn
-    }
+/// Run the body with a connection from the pool. The body
+/// is compiled for each backend with `$db` naming its sqlx
+/// database, so the queries are written once.
+macro_rules! with_conn {
+    ($connection:expr, |$conn:ident: $db:ident| $body:expr) => {
+        match $connection.pool() {
+            $crate::connection::Pool::Sqlite(pool) => {
+                #[allow(dead_code)]
+                type $db = ::sqlx::Sqlite;
+                #[allow(unused_mut)]
+                let mut $conn = pool.acquire().await?;
+                $body
+            }
+            $crate::connection::Pool::Postgres(pool) => {
+                #[allow(dead_code)]
+                type $db = ::sqlx::Postgres;
+                #[allow(unused_mut)]
+                let mut $conn = pool.acquire().await?;
+                $body
+            }
+        }
+    };
+}
+pub(crate) use with_conn;
+
+/// Test databases are removed when the connection is dropped
+enum TestDatabase {
+    File(String),
+    Postgres { url: String, name: String },
+}
+
+/// A pool of connections to the database.
+///
+/// SQLite databases are opened in WAL mode, so reads do not
+/// block each other and are not blocked by a write. Inserts
+/// read the returned id with `fetch_all`: a statement which is
+/// not stepped to the end keeps its write uncommitted and
+/// invisible to the other connections.
+pub struct Connection {
+    pool: Pool,
+    test: Option<TestDatabase>,
+    /// Changes are recorded in the audit log with
+    /// the actor and command
+    pub(crate) actor: String,
+    pub(crate) command: String,
 }
 
 impl Drop for Connection {
     fn drop(&mut self) {
-        if !self.test {
-            return;
-        }
-        let path = Path::new(&self.filename);
-        if path.exists() {
-            fs::remove_file(path).unwrap();
+        match &self.test {
+            None => {}
+            Some(TestDatabase::File(filename)) => {
+                for suffix in ["", "-wal", "-shm"] {
+                    let filename = format!("{}{}", filename, suffix);
+                    let path = Path::new(&filename);
+                    if path.exists() {
+                        fs::remove_file(path).unwrap();
+                    }
+                }
+            }
+            Some(TestDatabase::Postgres { url, name }) => {
+                // Dropping happens outside of an async context,
+                // so the database is dropped from another runtime.
+                let (url, db_name) = (url.clone(), name.clone());
+                let dropped = thread::spawn(move || {
+                    tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()?
+                        .block_on(drop_test_database(&url, &db_name))
+                }).join();
+                // Panicking in drop would abort the test run
+                match dropped {
+                    Ok(Ok(())) => {}
+                    Ok(Err(err)) => {
+                        eprintln!("can not drop test database {}: {}", name, err);
+                    }
+                    Err(_) => {
+                        eprintln!("can not drop test database {}", name);
+                    }
+                }
+            }
         }
     }
 }
 
+/// Check if the database is a PostgreSQL URL
+pub fn is_postgres_url(url: &str) -> bool {
+    url.starts_with("postgres://") || url.starts_with("postgresql://")
+}
+
+/// Open the pool with WAL mode and busy timeouts
+async fn connect_sqlite_pool(opts: SqliteConnectOptions) -> Result<Pool> {
+    let opts = opts
+        .journal_mode(SqliteJournalMode::Wal)
+        .busy_timeout(BUSY_TIMEOUT);
+    let pool = SqlitePoolOptions::new()
+        .max_connections(MAX_CONNECTIONS)
+        .connect_with(opts)
+        .await?;
+    Ok(Pool::Sqlite(pool))
+}
+
+async fn connect_postgres_pool(opts: PgConnectOptions) -> Result<Pool> {
+    let pool = PgPoolOptions::new()
+        .max_connections(MAX_CONNECTIONS)
+        .connect_with(opts)
+        .await?;
+    Ok(Pool::Postgres(pool))
+}
+
+/// Create an empty database for a test on the server
+async fn create_test_database(url: &str) -> Result<(PgConnectOptions, String)> {
+    let name = format!("discordia_test_{}", rand::random::<u64>());
+    let mut conn = PgConnection::connect(url).await?;
+    conn.execute(format!("CREATE DATABASE {}", name).as_str()).await?;
+    conn.close().await?;
+    let opts = PgConnectOptions::from_str(url)?.database(&name);
+    Ok((opts, name))
+}
+
+async fn drop_test_database(url: &str, name: &str) -> Result<()> {
+    let mut conn = PgConnection::connect(url).await?;
+    conn.execute(format!("DROP DATABASE {} WITH (FORCE)", name).as_str())
+        .await?;
+    conn.close().await?;
+    Ok(())
+}
+
 impl Connection {
-    /// Open a connection to the database
-    pub async fn open(filename: &str) -> Result<Self> {
-        let conn = SqliteConnectOptions::from_str(filename)?.foreign_keys(true);
-        let conn = SqliteConnection::connect_with(&conn).await?;
+    /// Open a connection to the database, which is a
+    /// SQLite file or a PostgreSQL URL. Missing schema
+    /// migrations are applied.
+    pub async fn open(url: &str) -> Result<Self> {
+        Self::open_with_key(url, None).await
+    }
+
+    /// Open a connection to an encrypted database. New
+    /// databases are created encrypted.
+    pub async fn open_encrypted(
+        filename: &str,
+        key: &DatabaseKey,
+    ) -> Result<Self> {
+        Self::open_with_key(filename, Some(key)).await
+    }
+
+    /// Open a connection to the database, which is
+    /// encrypted if a key is given. Only SQLite databases
+    /// can be encrypted.
+    pub async fn open_with_key(
+        url: &str,
+        key: Option<&DatabaseKey>,
+    ) -> Result<Self> {
+        let pool = if is_postgres_url(url) {
+            if key.is_some() {
+                return Err(anyhow!(
+                    "only SQLite databases can be encrypted"));
+            }
+            connect_postgres_pool(PgConnectOptions::from_str(url)?).await?
+        } else {
+            let opts = encryption::connect_options(url, key)?;
+            // The key is checked before the journal mode is set,
+            // as this fails with a wrong key.
+            let mut conn = SqliteConnection::connect_with(&opts).await?;
+            encryption::check_readable(&mut conn).await?;
+            conn.close().await?;
+            connect_sqlite_pool(opts).await?
+        };
         let conn = Connection{
-            filename: filename.to_string(),
-            conn: Arc::new(Mutex::new(conn)),
-            test: false,
+            pool,
+            test: None,
+            actor: audit::default_actor(),
+            command: audit::command_line(),
         };
+        schema::migrate(&conn).await?;
         Ok(conn)
     }
 
+    /// The pool of the database backend
+    pub fn pool(&self) -> &Pool {
+        &self.pool
+    }
+
+    /// Close all connections. With SQLite, the last
+    /// connection moves the write-ahead log into the
+    /// database file.
+    pub async fn close(&self) {
+        match &self.pool {
+            Pool::Sqlite(pool) => pool.close().await,
+            Pool::Postgres(pool) => pool.close().await,
+        }
+    }
+
+    /// Set the actor recorded in the audit log. It defaults
+    /// to the login name.
+    pub fn with_actor(mut self, actor: &str) -> Self {
+        self.actor = actor.to_string();
+        self
+    }
+
     /// Open a new test database connection.
-    /// The database will be created on each open.
+    /// The database will be created on each open, on the
+    /// PostgreSQL server given by `ERIS_TEST_DATABASE_URL`
+    /// or as SQLite file.
     pub async fn open_test() -> Self {
-        let filename = format!(
-            "/tmp/discordia_test_{}.sqlite3",
-            rand::random::<u64>());
-
-        let conn = SqliteConnectOptions::from_str(&filename)
-            .unwrap()
-            .create_if_missing(true)
-            .foreign_keys(true);
-        let conn = SqliteConnection::connect_with(&conn).await.unwrap();
+        let (pool, test) = match env::var(TEST_DATABASE_URL) {
+            Ok(url) => {
+                let (opts, name) = create_test_database(&url).await.unwrap();
+                let pool = connect_postgres_pool(opts).await.unwrap();
+                (pool, TestDatabase::Postgres { url, name })
+            }
+            Err(_) => {
+                let filename = format!(
+                    "/tmp/discordia_test_{}.sqlite3",
+                    rand::random::<u64>());
+                let opts = SqliteConnectOptions::from_str(&filename)
+                    .unwrap()
+                    .create_if_missing(true)
+                    .foreign_keys(true);
+                let pool = connect_sqlite_pool(opts).await.unwrap();
+                (pool, TestDatabase::File(filename))
+            }
+        };
         let conn = Connection {
-            filename: filename.clone(),
-            conn: Arc::new(Mutex::new(conn)),
-            test: true,
+            pool,
+            test: Some(test),
+            actor: "test".to_string(),
+            command: audit::command_line(),
         };
 
-        // Install the schema
-        schema::install(&conn).await.unwrap();
+        schema::migrate(&conn).await.unwrap();
 
         conn
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::Barrier;
+
+    use eris_data::{Insert, Member, MemberFilter, Query};
+
+    use super::*;
+
+    /// Readers holding a connection for a while, like a
+    /// request streaming a response, run side by side.
+    #[tokio::test]
+    async fn test_concurrent_reads() {
+        const READERS: usize = 8;
+
+        let db = Connection::open_test().await;
+        // WAL mode is specific to SQLite
+        let Pool::Sqlite(pool) = db.pool() else {
+            return;
+        };
+        db.insert(Member {
+            name: "Eris".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+
+        // Every reader waits for the others while holding its
+        // transaction, which only passes if all are open at once.
+        let barrier = Arc::new(Barrier::new(READERS));
+        let readers: Vec<_> = (0..READERS).map(|_| {
+            let pool = pool.clone();
+            let barrier = barrier.clone();
+            tokio::spawn(async move {
+                let mut tx = pool.begin().await.unwrap();
+                let (count,): (i64,) = sqlx::query_as(
+                    "SELECT count(*) FROM members")
+                    .fetch_one(&mut tx)
+                    .await
+                    .unwrap();
+                barrier.wait().await;
+                tx.commit().await.unwrap();
+                count
+            })
+        }).collect();
+        for reader in readers {
+            let count = tokio::time::timeout(Duration::from_secs(5), reader)
+                .await
+                .expect("the readers did not overlap")
+                .unwrap();
+            assert_eq!(count, 1);
+        }
+    }
+
+    /// Reads are not blocked by an open write transaction
+    #[tokio::test]
+    async fn test_read_during_write() {
+        let db = Connection::open_test().await;
+        let Pool::Sqlite(pool) = db.pool() else {
+            return;
+        };
+        let mut writer = pool.acquire().await.unwrap();
+        writer.execute("BEGIN IMMEDIATE").await.unwrap();
+        writer.execute("INSERT INTO members (name, email, notes, \
+            membership_start, last_payment_at, last_bank_transaction_at, \
+            last_bank_transaction_number, account_calculated_at, \
+            interval, fee, account) VALUES ('Eris', '', '', '2023-01-01', \
+            '2023-01-01', '2023-01-01', 0, '2023-01-01', 1, 20, 0)")
+            .await.unwrap();
+
+        let filter = MemberFilter::default();
+        let read = db.query(&filter);
+        let members: Vec<Member> = tokio::time::timeout(
+            Duration::from_secs(1), read).await.unwrap().unwrap();
+        // The write is not committed yet
+        assert!(members.is_empty());
+
+        writer.execute("COMMIT").await.unwrap();
+        let members: Vec<Member> = db.query(&MemberFilter::default())
+            .await.unwrap();
+        assert_eq!(members.len(), 1);
+    }
+}
@@ -0,0 +1,170 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use sqlx::QueryBuilder;
+
+use eris_data::{
+    DunningEvent,
+    DunningEventFilter,
+    Insert,
+    Query,
+    Retrieve,
+};
+
+use crate::{
+    connection::with_conn,
+    results::{Id, QueryError},
+    Connection,
+};
+
+#[async_trait]
+impl Query<DunningEvent> for Connection {
+    type Filter = DunningEventFilter;
+
+    /// Fetch dunning events in chronological order
+    async fn query(
+        &self,
+        filter: &Self::Filter,
+    ) -> Result<Vec<DunningEvent>> {
+        let events: Vec<DunningEvent> = with_conn!(self, |conn: DB| {
+            let mut qry = QueryBuilder::<DB>::new(
+                r#"
+                SELECT
+                    id,
+                    member_id,
+                    level,
+                    date,
+                    CAST(balance AS DOUBLE PRECISION) AS balance
+                FROM dunning_events
+                WHERE TRUE
+                "#,
+            );
+            if let Some(id) = filter.id {
+                qry.push(" AND id = ").push_bind(i64::from(id));
+            }
+            if let Some(member_id) = filter.member_id {
+                qry.push(" AND member_id = ").push_bind(i64::from(member_id));
+            }
+            if let Some(level) = filter.level {
+                qry.push(" AND level = ").push_bind(level);
+            }
+            if let Some(date_before) = filter.date_before {
+                qry.push(" AND date <= ").push_bind(date_before);
+            }
+            if let Some(date_after) = filter.date_after {
+                qry.push(" AND date >= ").push_bind(date_after);
+            }
+            qry.push(" ORDER BY date, id");
+
+            qry.build_query_as().fetch_all(&mut *conn).await?
+        });
+        Ok(events)
+    }
+}
+
+#[async_trait]
+impl Retrieve<DunningEvent> for Connection {
+    type Key = u32;
+
+    /// Get a single dunning event
+    async fn retrieve(&self, id: Self::Key) -> Result<DunningEvent> {
+        let filter = DunningEventFilter {
+            id: Some(id),
+            ..Default::default()
+        };
+        let event = self
+            .query(&filter)
+            .await?
+            .pop()
+            .ok_or(QueryError::NotFound)?;
+        Ok(event)
+    }
+}
+
+#[async_trait]
+impl Insert<DunningEvent> for Connection {
+    /// Record a dunning event. Events are never
+    /// changed afterwards.
+    async fn insert(&self, event: DunningEvent) -> Result<DunningEvent> {
+        let insert: Id<i64> = with_conn!(self, |conn: DB| {
+            let mut qry = QueryBuilder::<DB>::new(
+                r#"INSERT INTO dunning_events (
+                    member_id,
+                    level,
+                    date,
+                    balance
+                ) VALUES (
+                "#,
+            );
+            qry.separated(", ")
+                .push_bind(i64::from(event.member_id))
+                .push_bind(event.level)
+                .push_bind(event.date)
+                .push_bind(event.balance);
+
+            qry.push(") RETURNING id ")
+                .build_query_as()
+                .fetch_all(&mut *conn)
+                .await?
+                .pop()
+                .ok_or(QueryError::NotFound)?
+        });
+        self.retrieve(insert.id.try_into()?).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::NaiveDate;
+
+    use eris_data::{DunningLevel, Member};
+
+    #[tokio::test]
+    async fn test_dunning_event_insert() {
+        let db = Connection::open_test().await;
+        let m = db.insert(Member {
+            name: "Testmember".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+
+        let date = NaiveDate::from_ymd_opt(2023, 5, 1).unwrap();
+        let event = db.insert(DunningEvent {
+            member_id: m.id,
+            level: DunningLevel::FirstNotice,
+            date,
+            balance: -60.0,
+            ..Default::default()
+        }).await.unwrap();
+
+        assert!(event.id > 0);
+        assert_eq!(event.level, DunningLevel::FirstNotice);
+        assert_eq!(event.date, date);
+        assert_eq!(event.balance, -60.0);
+    }
+
+    #[tokio::test]
+    async fn test_dunning_event_filter() {
+        let db = Connection::open_test().await;
+        let m = db.insert(Member {
+            name: "Testmember".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        for level in [DunningLevel::Reminder, DunningLevel::FirstNotice] {
+            db.insert(DunningEvent {
+                member_id: m.id,
+                level,
+                ..Default::default()
+            }).await.unwrap();
+        }
+
+        let events = m.get_dunning_events(&db).await.unwrap();
+        assert_eq!(events.len(), 2);
+
+        let events: Vec<DunningEvent> = db.query(&DunningEventFilter {
+            level: Some(DunningLevel::Reminder),
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(events.len(), 1);
+    }
+}
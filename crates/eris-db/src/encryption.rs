@@ -0,0 +1,204 @@
+use std::{fmt, fs, path::Path, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqliteConnection},
+    Connection as SqlConnection,
+    Executor,
+};
+
+use crate::connection::is_postgres_url;
+
+/// The passphrase of an encrypted database. Databases
+/// are encrypted with SQLCipher, so they can also be opened
+/// with the sqlcipher shell.
+#[derive(Clone, PartialEq, Eq)]
+pub struct DatabaseKey(String);
+
+impl fmt::Debug for DatabaseKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DatabaseKey(***)")
+    }
+}
+
+impl DatabaseKey {
+    pub fn new(passphrase: &str) -> Result<Self> {
+        if passphrase.is_empty() {
+            return Err(anyhow!("the database key must not be empty"));
+        }
+        Ok(Self(passphrase.to_string()))
+    }
+
+    /// Read the key from a keyfile. A trailing newline
+    /// is not part of the key.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let key = fs::read_to_string(path).map_err(|e| {
+            anyhow!("can not read keyfile {}: {}", path.display(), e)
+        })?;
+        Self::new(key.trim_end_matches(['\r', '\n']))
+    }
+
+    /// Take the key from a keyfile or passphrase, if any.
+    /// The keyfile takes precedence.
+    pub fn resolve(
+        passphrase: Option<&str>,
+        keyfile: Option<&Path>,
+    ) -> Result<Option<Self>> {
+        match (keyfile, passphrase) {
+            (Some(path), _) => Ok(Some(Self::from_file(path)?)),
+            (None, Some(passphrase)) => Ok(Some(Self::new(passphrase)?)),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// The key as SQL string literal
+    fn literal(&self) -> String {
+        format!("'{}'", self.0.replace('\'', "''"))
+    }
+}
+
+/// Get the connect options for a database, encrypted with
+/// the key if given.
+pub(crate) fn connect_options(
+    filename: &str,
+    key: Option<&DatabaseKey>,
+) -> Result<SqliteConnectOptions> {
+    let mut opts = SqliteConnectOptions::from_str(filename)?
+        .foreign_keys(true);
+    if let Some(key) = key {
+        opts = opts.pragma("key", key.literal());
+    }
+    Ok(opts)
+}
+
+/// Check that the database can be read. With a wrong key
+/// or a missing key for an encrypted database this fails.
+pub(crate) async fn check_readable(conn: &mut SqliteConnection) -> Result<()> {
+    conn.execute("SELECT count(*) FROM sqlite_master").await.map_err(|_| {
+        anyhow!("can not read the database: the key is wrong or missing")
+    })?;
+    Ok(())
+}
+
+/// Encrypt, decrypt or change the key of a database. The
+/// database is exported to a new file with the new key,
+/// which then replaces the database.
+pub async fn rekey(
+    filename: &str,
+    key: Option<&DatabaseKey>,
+    new_key: Option<&DatabaseKey>,
+) -> Result<()> {
+    if is_postgres_url(filename) {
+        return Err(anyhow!("only SQLite databases can be encrypted"));
+    }
+    let opts = connect_options(filename, key)?;
+    let mut conn = SqliteConnection::connect_with(&opts).await?;
+    check_readable(&mut conn).await?;
+    // Move the write-ahead log into the database file
+    conn.execute("PRAGMA wal_checkpoint(TRUNCATE)").await?;
+
+    let (path,): (String,) = sqlx::query_as(
+        "SELECT file FROM pragma_database_list WHERE name = 'main'")
+        .fetch_one(&mut conn)
+        .await?;
+    if path.is_empty() {
+        return Err(anyhow!("only database files can be rekeyed"));
+    }
+    // The file is created beforehand, as the database may
+    // have been opened without permission to create files.
+    let rekeyed = format!("{}.rekey", path);
+    fs::File::create(&rekeyed)?;
+
+    // An empty key exports a plain database
+    let new_key = new_key.map(|k| k.0.clone()).unwrap_or_default();
+    sqlx::query("ATTACH DATABASE ? AS rekeyed KEY ?")
+        .bind(&rekeyed)
+        .bind(&new_key)
+        .execute(&mut conn)
+        .await?;
+    conn.execute("SELECT sqlcipher_export('rekeyed')").await?;
+    conn.execute("DETACH DATABASE rekeyed").await?;
+    conn.close().await?;
+
+    fs::rename(&rekeyed, &path)?;
+    // The log of the replaced database must not be applied
+    // to the new one.
+    for suffix in ["-wal", "-shm"] {
+        let log = format!("{}{}", path, suffix);
+        if Path::new(&log).exists() {
+            fs::remove_file(&log)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use eris_data::{Insert, Member, MemberFilter, Query};
+
+    use crate::Connection;
+
+    fn test_file() -> String {
+        format!("/tmp/discordia_test_{}.sqlite3", rand::random::<u64>())
+    }
+
+    async fn count_members(db: &Connection) -> usize {
+        let members: Vec<Member> = db.query(&MemberFilter::default())
+            .await.unwrap();
+        members.len()
+    }
+
+    #[test]
+    fn test_key_literal() {
+        let key = DatabaseKey::new("fnord's").unwrap();
+        assert_eq!(key.literal(), "'fnord''s'");
+        assert_eq!(format!("{:?}", key), "DatabaseKey(***)");
+        assert!(DatabaseKey::new("").is_err());
+        assert_eq!(DatabaseKey::resolve(None, None).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_database() {
+        let path = test_file();
+        let filename = format!("sqlite://{}", path);
+        let key = DatabaseKey::new("hail eris").unwrap();
+        {
+            let create = format!("{}?mode=rwc", filename);
+            let db = Connection::open_encrypted(&create, &key)
+                .await.unwrap();
+            db.insert(Member {
+                name: "Eris".to_string(),
+                ..Default::default()
+            }).await.unwrap();
+            db.close().await;
+        }
+
+        // The file does not contain the plain text
+        let data = fs::read(&path).unwrap();
+        assert!(!data.windows(4).any(|w| w == b"Eris"));
+
+        // The database can not be opened without the key
+        assert!(Connection::open(&filename).await.is_err());
+        let wrong = DatabaseKey::new("fnord").unwrap();
+        assert!(Connection::open_encrypted(&filename, &wrong).await.is_err());
+
+        // Change the key
+        let new_key = DatabaseKey::new("all hail discordia").unwrap();
+        rekey(&filename, Some(&key), Some(&new_key)).await.unwrap();
+        assert!(Connection::open_encrypted(&filename, &key).await.is_err());
+        let db = Connection::open_encrypted(&filename, &new_key)
+            .await.unwrap();
+        assert_eq!(count_members(&db).await, 1);
+        db.close().await;
+
+        // Decrypt the database
+        rekey(&filename, Some(&new_key), None).await.unwrap();
+        let db = Connection::open(&filename).await.unwrap();
+        assert_eq!(count_members(&db).await, 1);
+        db.close().await;
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,504 @@
+use std::{collections::HashMap, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
+use serde::Serialize;
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqliteConnection},
+    Connection as SqlConnection,
+    FromRow,
+    QueryBuilder,
+};
+
+use eris_data::{
+    AuditAction,
+    BankImportRule,
+    BankImportRuleFilter,
+    Member,
+    MemberFilter,
+    Query,
+    Transaction,
+    TransactionFilter,
+    TransactionKind,
+    MEMBERSHIP_FEE_ACCOUNT,
+};
+
+use crate::{connection::with_conn, results::Id, Connection};
+
+/// The schema of the legacy Python implementation, which
+/// is the unversioned baseline schema
+pub const LEGACY_SCHEMA: &str = include_str!("../db/legacy_schema.sql");
+
+#[derive(Debug, Clone, FromRow)]
+struct LegacyMember {
+    id: u32,
+    name: String,
+    email: String,
+    notes: String,
+    membership_start: NaiveDate,
+    membership_end: Option<NaiveDate>,
+    fee: f64,
+    interval: u8,
+    last_payment_at: NaiveDate,
+    last_bank_transaction_at: NaiveDate,
+    last_bank_transaction_number: u32,
+    account_calculated_at: NaiveDate,
+    account: f64,
+}
+
+#[derive(Debug, Clone, FromRow)]
+struct LegacyIbanRule {
+    member_id: u32,
+    iban: String,
+    match_subject: Option<String>,
+    split_amount: Option<f64>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+struct LegacyTransaction {
+    id: u32,
+    member_id: u32,
+    date: NaiveDate,
+    account_name: String,
+    amount: f64,
+    description: String,
+}
+
+/// A read only connection to a legacy database
+pub struct LegacyDatabase {
+    conn: SqliteConnection,
+}
+
+impl LegacyDatabase {
+    /// Open the legacy database read only
+    pub async fn open(filename: &str) -> Result<Self> {
+        let opts = SqliteConnectOptions::from_str(filename)?.read_only(true);
+        let conn = SqliteConnection::connect_with(&opts).await?;
+        Ok(Self { conn })
+    }
+
+    async fn members(&mut self) -> Result<Vec<LegacyMember>> {
+        let members = sqlx::query_as(
+            r#"
+            SELECT
+                id,
+                name,
+                email,
+                notes,
+                membership_start,
+                membership_end,
+                ROUND(fee, 10) AS fee,
+                interval,
+                last_payment_at,
+                last_bank_transaction_at,
+                last_bank_transaction_number,
+                account_calculated_at,
+                ROUND(account, 10) AS account
+            FROM members
+            ORDER BY id
+            "#)
+            .fetch_all(&mut self.conn)
+            .await?;
+        Ok(members)
+    }
+
+    async fn iban_rules(&mut self) -> Result<Vec<LegacyIbanRule>> {
+        let rules = sqlx::query_as(
+            r#"
+            SELECT
+                member_id,
+                iban,
+                match_subject,
+                ROUND(split_amount, 10) AS split_amount
+            FROM bank_import_member_ibans
+            "#)
+            .fetch_all(&mut self.conn)
+            .await?;
+        Ok(rules)
+    }
+
+    async fn transactions(&mut self) -> Result<Vec<LegacyTransaction>> {
+        let transactions = sqlx::query_as(
+            r#"
+            SELECT
+                id,
+                member_id,
+                date,
+                account_name,
+                ROUND(amount, 10) AS amount,
+                description
+            FROM transactions
+            ORDER BY date, id
+            "#)
+            .fetch_all(&mut self.conn)
+            .await?;
+        Ok(transactions)
+    }
+}
+
+/// Balances of a member before and after the migration
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MemberReconciliation {
+    pub legacy_id: u32,
+    pub member_id: u32,
+    pub name: String,
+    pub balance_before: f64,
+    pub balance_after: f64,
+    /// Sum of the legacy transactions
+    pub transactions_before: f64,
+    /// Sum of the migrated transactions
+    pub transactions_after: f64,
+}
+
+impl MemberReconciliation {
+    /// Balance and transactions were migrated completely
+    pub fn is_reconciled(&self) -> bool {
+        (self.balance_before - self.balance_after).abs() < 0.005
+            && (self.transactions_before - self.transactions_after).abs()
+                < 0.005
+    }
+}
+
+/// The outcome of a migration
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MigrationReport {
+    pub members: Vec<MemberReconciliation>,
+    pub iban_rules: usize,
+    pub transactions: usize,
+    /// Legacy records which could not be migrated
+    pub skipped: Vec<String>,
+    /// The migration is kept only if it is reconciled
+    pub committed: bool,
+}
+
+impl MigrationReport {
+    /// All members are reconciled and nothing was skipped
+    pub fn is_reconciled(&self) -> bool {
+        self.skipped.is_empty()
+            && self.members.iter().all(|m| m.is_reconciled())
+    }
+
+    /// Sum of all balances before the migration
+    pub fn total_before(&self) -> f64 {
+        round(self.members.iter().map(|m| m.balance_before).sum())
+    }
+
+    /// Sum of all balances after the migration
+    pub fn total_after(&self) -> f64 {
+        round(self.members.iter().map(|m| m.balance_after).sum())
+    }
+}
+
+/// Migrate members, hashed IBAN rules and transactions
+/// of a legacy database. The target database must not
+/// contain any members. As the legacy balances already
+/// include all transactions, the transactions are copied
+/// without being applied to the accounts again.
+///
+/// The migration is made in a single database transaction,
+/// which is rolled back unless the report is reconciled.
+pub async fn migrate(
+    legacy: &mut LegacyDatabase,
+    db: &Connection,
+) -> Result<MigrationReport> {
+    let existing: Vec<Member> = db.query(&MemberFilter {
+        include_archived: true,
+        ..Default::default()
+    }).await?;
+    if !existing.is_empty() {
+        return Err(anyhow!(
+            "the database already contains {} members", existing.len()));
+    }
+
+    let legacy_members = legacy.members().await?;
+    let legacy_rules = legacy.iban_rules().await?;
+    let legacy_transactions = legacy.transactions().await?;
+
+    let mut report = MigrationReport::default();
+    let mut member_ids: HashMap<u32, u32> = HashMap::new();
+    let mut sums_before: HashMap<u32, f64> = HashMap::new();
+    with_conn!(db, |conn: DB| {
+        let mut tx = conn.begin().await?;
+
+        for legacy_member in &legacy_members {
+            let mut qry = QueryBuilder::<DB>::new(
+                r#"INSERT INTO members (
+                    name,
+                    email,
+                    notes,
+                    membership_start,
+                    membership_end,
+                    last_payment_at,
+                    last_bank_transaction_at,
+                    last_bank_transaction_number,
+                    account_calculated_at,
+                    interval,
+                    fee,
+                    account
+                ) VALUES (
+                "#,
+            );
+            qry.separated(", ")
+                .push_bind(&legacy_member.name)
+                .push_bind(&legacy_member.email)
+                .push_bind(&legacy_member.notes)
+                .push_bind(legacy_member.membership_start)
+                .push_bind(legacy_member.membership_end)
+                .push_bind(legacy_member.last_payment_at)
+                .push_bind(legacy_member.last_bank_transaction_at)
+                .push_bind(i64::from(legacy_member.last_bank_transaction_number))
+                .push_bind(legacy_member.account_calculated_at)
+                .push_bind(i64::from(legacy_member.interval))
+                .push_bind(legacy_member.fee)
+                .push_bind(legacy_member.account);
+            let id: Id<i64> = qry.push(") RETURNING id ")
+                .build_query_as()
+                .fetch_one(&mut tx)
+                .await?;
+            member_ids.insert(legacy_member.id, id.id.try_into()?);
+        }
+
+        for rule in &legacy_rules {
+            let Some(member_id) = member_ids.get(&rule.member_id) else {
+                report.skipped.push(format!(
+                    "IBAN rule {} of unknown member {}",
+                    rule.iban, rule.member_id));
+                continue;
+            };
+            let mut qry = QueryBuilder::<DB>::new(
+                r#"INSERT INTO bank_import_member_ibans (
+                    member_id,
+                    iban,
+                    match_subject,
+                    split_amount
+                ) VALUES (
+                "#,
+            );
+            qry.separated(", ")
+                .push_bind(i64::from(*member_id))
+                .push_bind(&rule.iban)
+                .push_bind(&rule.match_subject)
+                .push_bind(rule.split_amount);
+            qry.push(")").build().execute(&mut tx).await?;
+            report.iban_rules += 1;
+        }
+
+        for legacy_tx in &legacy_transactions {
+            *sums_before.entry(legacy_tx.member_id).or_default() += legacy_tx.amount;
+            let Some(member_id) = member_ids.get(&legacy_tx.member_id) else {
+                report.skipped.push(format!(
+                    "transaction {} of unknown member {}",
+                    legacy_tx.id, legacy_tx.member_id));
+                continue;
+            };
+            // The legacy implementation booked fees and bank payments
+            let kind = if legacy_tx.account_name == MEMBERSHIP_FEE_ACCOUNT {
+                TransactionKind::Fee
+            } else if legacy_tx.account_name.is_empty() {
+                TransactionKind::Manual
+            } else {
+                TransactionKind::Bank
+            };
+            let mut qry = QueryBuilder::<DB>::new(
+                r#"INSERT INTO transactions (
+                    member_id,
+                    date,
+                    account_name,
+                    amount,
+                    description,
+                    kind
+                ) VALUES (
+                "#,
+            );
+            qry.separated(", ")
+                .push_bind(i64::from(*member_id))
+                .push_bind(legacy_tx.date)
+                .push_bind(&legacy_tx.account_name)
+                .push_bind(legacy_tx.amount)
+                .push_bind(&legacy_tx.description)
+                .push_bind(kind);
+            qry.push(")").build().execute(&mut tx).await?;
+            report.transactions += 1;
+        }
+
+        // Reconcile with the migrated state
+        for legacy_member in &legacy_members {
+            let member_id = member_ids[&legacy_member.id];
+            let (balance_after, transactions_after): (f64, f64) =
+                QueryBuilder::<DB>::new(
+                    r#"SELECT
+                        CAST(account AS DOUBLE PRECISION),
+                        CAST(COALESCE((
+                            SELECT SUM(amount) FROM transactions
+                            WHERE member_id = members.id
+                        ), 0) AS DOUBLE PRECISION)
+                    FROM members WHERE id = "#)
+                    .push_bind(i64::from(member_id))
+                    .build_query_as()
+                    .fetch_one(&mut tx)
+                    .await?;
+            report.members.push(MemberReconciliation {
+                legacy_id: legacy_member.id,
+                member_id,
+                name: legacy_member.name.clone(),
+                balance_before: legacy_member.account,
+                balance_after,
+                transactions_before: round(
+                    *sums_before.get(&legacy_member.id).unwrap_or(&0.0)),
+                transactions_after: round(transactions_after),
+            });
+        }
+
+        if report.is_reconciled() {
+            tx.commit().await?;
+            report.committed = true;
+        } else {
+            tx.rollback().await?;
+        }
+    });
+
+    if report.committed {
+        audit_migrated(db).await?;
+    }
+    Ok(report)
+}
+
+/// Record the migrated records in the audit log
+async fn audit_migrated(db: &Connection) -> Result<()> {
+    let members: Vec<Member> = db.query(&MemberFilter {
+        include_archived: true,
+        ..Default::default()
+    }).await?;
+    for member in &members {
+        db.audit(AuditAction::Insert, None, Some(member)).await?;
+    }
+    let rules: Vec<BankImportRule> =
+        db.query(&BankImportRuleFilter::default()).await?;
+    for rule in &rules {
+        db.audit(AuditAction::Insert, None, Some(rule)).await?;
+    }
+    let transactions: Vec<Transaction> =
+        db.query(&TransactionFilter::default()).await?;
+    for transaction in &transactions {
+        db.audit(AuditAction::Insert, None, Some(transaction)).await?;
+    }
+    Ok(())
+}
+
+fn round(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{fs, path::Path};
+
+    use sqlx::Executor;
+
+    use eris_data::{hash_iban, Retrieve};
+
+    async fn legacy_db(filename: &str, orphans: bool) -> LegacyDatabase {
+        let opts = SqliteConnectOptions::from_str(filename)
+            .unwrap()
+            .create_if_missing(true)
+            .foreign_keys(false);
+        let mut conn = SqliteConnection::connect_with(&opts).await.unwrap();
+        conn.execute(LEGACY_SCHEMA).await.unwrap();
+        let data = format!(r#"
+            INSERT INTO members VALUES
+                (7, 'Eris', 'eris@discordia.ccc', '', '2020-01-01', NULL,
+                 '20.00', 1, '2023-01-05', '2023-01-05', 23, '2023-02-01',
+                 '-20.00'),
+                (9, 'Kallisti', 'k@discordia.ccc', 'apple', '2021-03-01',
+                 '2022-12-31', '15.00', 3, '2022-12-01', '2022-12-01', 5,
+                 '2023-01-01', '0.00');
+            INSERT INTO bank_import_member_ibans VALUES
+                (7, '{}', NULL, NULL);
+            INSERT INTO transactions VALUES
+                (1, 7, '2023-01-01', 'memberhip fee', '-20.00', 'Fee'),
+                (2, 7, '2023-01-05', 'Eris', '20.00', 'Beitrag'),
+                (3, 7, '2023-02-01', 'memberhip fee', '-20.00', 'Fee');
+            "#, hash_iban("DE12345678901234567890", "Eris"));
+        conn.execute(data.as_str()).await.unwrap();
+        if orphans {
+            conn.execute(r#"
+                INSERT INTO bank_import_member_ibans VALUES
+                    (8, 'deadbeef0000', NULL, NULL);
+                INSERT INTO transactions VALUES
+                    (4, 8, '2023-02-01', 'Ghost', '5.00', 'Spende');
+                "#).await.unwrap();
+        }
+        conn.close().await.unwrap();
+
+        LegacyDatabase::open(filename).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_migrate() {
+        let filename = format!(
+            "/tmp/discordia_legacy_{}.sqlite3", rand::random::<u64>());
+        let mut legacy = legacy_db(&filename, false).await;
+        let db = Connection::open_test().await;
+
+        let report = migrate(&mut legacy, &db).await.unwrap();
+        assert_eq!(report.members.len(), 2);
+        assert_eq!(report.iban_rules, 1);
+        assert_eq!(report.transactions, 3);
+        assert!(report.skipped.is_empty());
+        assert!(report.is_reconciled());
+        assert!(report.committed);
+        assert_eq!(report.total_before(), -20.0);
+        assert_eq!(report.total_after(), -20.0);
+
+        let eris = &report.members[0];
+        assert_eq!(eris.legacy_id, 7);
+        assert_eq!(eris.transactions_after, -20.0);
+        let member: Member = db.retrieve(eris.member_id).await.unwrap();
+        assert_eq!(member.last_bank_transaction_number, 23);
+
+        let rules: Vec<BankImportRule> = db.query(&BankImportRuleFilter {
+            member_id: Some(eris.member_id),
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(
+            rules[0].iban, hash_iban("DE12345678901234567890", "Eris"));
+
+        // Migrating twice is refused
+        assert!(migrate(&mut legacy, &db).await.is_err());
+
+        if Path::new(&filename).exists() {
+            fs::remove_file(&filename).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_migrate_rollback() {
+        let filename = format!(
+            "/tmp/discordia_legacy_{}.sqlite3", rand::random::<u64>());
+        let mut legacy = legacy_db(&filename, true).await;
+        let db = Connection::open_test().await;
+
+        // The orphaned transaction is missing in the total
+        let report = migrate(&mut legacy, &db).await.unwrap();
+        assert_eq!(report.skipped.len(), 2);
+        assert!(report.members.iter().all(|m| m.is_reconciled()));
+        assert!(!report.is_reconciled());
+        assert!(!report.committed);
+
+        // Nothing was migrated, so a retry is possible
+        let members: Vec<Member> = db.query(&MemberFilter {
+            include_archived: true,
+            ..Default::default()
+        }).await.unwrap();
+        assert!(members.is_empty());
+        let transactions: Vec<Transaction> =
+            db.query(&TransactionFilter::default()).await.unwrap();
+        assert!(transactions.is_empty());
+
+        if Path::new(&filename).exists() {
+            fs::remove_file(&filename).unwrap();
+        }
+    }
+}
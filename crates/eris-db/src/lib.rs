@@ -1,11 +1,27 @@
 pub mod connection;
 pub use connection::Connection;
 
+pub mod encryption;
+pub use encryption::DatabaseKey;
+
 pub mod results;
 pub use results::{Id, QueryError};
 
 pub mod schema;
 
+mod like;
+
+pub mod audit;
+
 pub mod bank_import;
+pub mod dunning;
+pub mod legacy;
+pub mod mandates;
 pub mod members;
+pub mod notifications;
+pub mod payouts;
+pub mod pseudonymise;
+pub mod receipts;
+pub mod settings;
 pub mod transactions;
+pub mod users;
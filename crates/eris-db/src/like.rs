@@ -0,0 +1,24 @@
+/// Escape clause for patterns made with `contains`
+pub(crate) const ESCAPE: &str = r" ESCAPE '\'";
+
+/// Make a `LIKE` pattern for values containing the text.
+/// `%` and `_` in the text are matched literally, so the
+/// pattern must be used with `ESCAPE`.
+pub(crate) fn contains(text: &str) -> String {
+    let escaped = text
+        .replace('\\', r"\\")
+        .replace('%', r"\%")
+        .replace('_', r"\_");
+    format!("%{}%", escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contains() {
+        assert_eq!(contains("eris"), "%eris%");
+        assert_eq!(contains("100%_sure\\"), r"%100\%\_sure\\%");
+    }
+}
@@ -0,0 +1,249 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use sqlx::QueryBuilder;
+
+use eris_data::{
+    Delete,
+    Insert,
+    Mandate,
+    MandateFilter,
+    Query,
+    Retrieve,
+    Update,
+};
+
+use crate::{
+    connection::with_conn,
+    results::{Id, QueryError},
+    Connection,
+};
+
+#[async_trait]
+impl Query<Mandate> for Connection {
+    type Filter = MandateFilter;
+
+    /// Fetch direct debit mandates
+    async fn query(&self, filter: &Self::Filter) -> Result<Vec<Mandate>> {
+        let mandates: Vec<Mandate> = with_conn!(self, |conn: DB| {
+            let mut qry = QueryBuilder::<DB>::new(
+                r#"
+                SELECT
+                    id,
+                    member_id,
+                    reference,
+                    iban,
+                    account_holder,
+                    signed_at,
+                    amended_at,
+                    original_iban,
+                    last_used_at,
+                    revoked_at
+                FROM mandates
+                WHERE TRUE
+                "#,
+            );
+            if let Some(id) = filter.id {
+                qry.push(" AND id = ").push_bind(i64::from(id));
+            }
+            if let Some(member_id) = filter.member_id {
+                qry.push(" AND member_id = ").push_bind(i64::from(member_id));
+            }
+            if let Some(reference) = filter.reference.clone() {
+                qry.push(" AND reference = ").push_bind(reference);
+            }
+            qry.push(" ORDER BY id");
+
+            qry.build_query_as().fetch_all(&mut *conn).await?
+        });
+        Ok(mandates)
+    }
+}
+
+#[async_trait]
+impl Retrieve<Mandate> for Connection {
+    type Key = u32;
+
+    /// Get a single mandate by id
+    async fn retrieve(&self, id: Self::Key) -> Result<Mandate> {
+        let filter = MandateFilter {
+            id: Some(id),
+            ..Default::default()
+        };
+        let mandate = self
+            .query(&filter)
+            .await?
+            .pop()
+            .ok_or(QueryError::NotFound)?;
+        Ok(mandate)
+    }
+}
+
+#[async_trait]
+impl Insert<Mandate> for Connection {
+    /// Create a mandate
+    async fn insert(&self, mandate: Mandate) -> Result<Mandate> {
+        let insert: Id<i64> = with_conn!(self, |conn: DB| {
+            let mut qry = QueryBuilder::<DB>::new(
+                r#"INSERT INTO mandates (
+                    member_id,
+                    reference,
+                    iban,
+                    account_holder,
+                    signed_at,
+                    amended_at,
+                    original_iban,
+                    last_used_at,
+                    revoked_at
+                ) VALUES (
+                "#,
+            );
+            qry.separated(", ")
+                .push_bind(i64::from(mandate.member_id))
+                .push_bind(&mandate.reference)
+                .push_bind(&mandate.iban)
+                .push_bind(&mandate.account_holder)
+                .push_bind(mandate.signed_at)
+                .push_bind(mandate.amended_at)
+                .push_bind(&mandate.original_iban)
+                .push_bind(mandate.last_used_at)
+                .push_bind(mandate.revoked_at);
+
+            qry.push(") RETURNING id ")
+                .build_query_as()
+                .fetch_all(&mut *conn)
+                .await?
+                .pop()
+                .ok_or(QueryError::NotFound)?
+        });
+        self.retrieve(insert.id.try_into()?).await
+    }
+}
+
+#[async_trait]
+impl Update<Mandate> for Connection {
+    /// Update a mandate. The reference and member
+    /// can not be changed.
+    async fn update(&self, mandate: Mandate) -> Result<Mandate> {
+        with_conn!(self, |conn: DB| {
+            QueryBuilder::<DB>::new("UPDATE mandates SET")
+                .push(" iban = ")
+                .push_bind(&mandate.iban)
+                .push(", account_holder = ")
+                .push_bind(&mandate.account_holder)
+                .push(", signed_at = ")
+                .push_bind(mandate.signed_at)
+                .push(", amended_at = ")
+                .push_bind(mandate.amended_at)
+                .push(", original_iban = ")
+                .push_bind(&mandate.original_iban)
+                .push(", last_used_at = ")
+                .push_bind(mandate.last_used_at)
+                .push(", revoked_at = ")
+                .push_bind(mandate.revoked_at)
+                .push(" WHERE id = ")
+                .push_bind(i64::from(mandate.id))
+                .build()
+                .execute(&mut *conn)
+                .await?;
+        });
+        self.retrieve(mandate.id).await
+    }
+}
+
+#[async_trait]
+impl Delete<Mandate> for Connection {
+    /// Delete a mandate
+    async fn delete(&self, mandate: Mandate) -> Result<()> {
+        with_conn!(self, |conn: DB| {
+            QueryBuilder::<DB>::new("DELETE FROM mandates WHERE id = ")
+                .push_bind(i64::from(mandate.id))
+                .build()
+                .execute(&mut *conn)
+                .await?;
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::NaiveDate;
+
+    use eris_data::Member;
+
+    #[tokio::test]
+    async fn test_mandate_insert() {
+        let db = Connection::open_test().await;
+        let m = db.insert(Member {
+            name: "Testmember".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+
+        let signed_at = NaiveDate::from_ymd_opt(2023, 5, 1).unwrap();
+        let mandate = db.insert(Mandate {
+            member_id: m.id,
+            reference: Mandate::make_reference(m.id, 1),
+            iban: "DE2342".to_string(),
+            account_holder: "Testmember".to_string(),
+            signed_at,
+            ..Default::default()
+        }).await.unwrap();
+
+        assert!(mandate.id > 0);
+        assert_eq!(mandate.member_id, m.id);
+        assert_eq!(mandate.reference, Mandate::make_reference(m.id, 1));
+        assert_eq!(mandate.iban, "DE2342");
+        assert_eq!(mandate.signed_at, signed_at);
+        assert_eq!(mandate.last_used_at, None);
+        assert_eq!(mandate.revoked_at, None);
+    }
+
+    #[tokio::test]
+    async fn test_mandate_update() {
+        let db = Connection::open_test().await;
+        let m = db.insert(Member {
+            name: "Testmember".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        let mut mandate = db.insert(Mandate {
+            member_id: m.id,
+            reference: Mandate::make_reference(m.id, 1),
+            iban: "DE2342".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+
+        let date = NaiveDate::from_ymd_opt(2023, 7, 1).unwrap();
+        mandate.original_iban = Some(mandate.iban.clone());
+        mandate.iban = "DE4223".to_string();
+        mandate.amended_at = Some(date);
+        mandate.revoked_at = Some(date);
+
+        let mandate = db.update(mandate).await.unwrap();
+        assert_eq!(mandate.iban, "DE4223");
+        assert_eq!(mandate.original_iban, Some("DE2342".to_string()));
+        assert_eq!(mandate.amended_at, Some(date));
+        assert_eq!(mandate.revoked_at, Some(date));
+    }
+
+    #[tokio::test]
+    async fn test_member_get_mandates() {
+        let db = Connection::open_test().await;
+        let m = db.insert(Member {
+            name: "Testmember".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        for rev in 1..=2 {
+            db.insert(Mandate {
+                member_id: m.id,
+                reference: Mandate::make_reference(m.id, rev),
+                ..Default::default()
+            }).await.unwrap();
+        }
+
+        let mandates = m.get_mandates(&db).await.unwrap();
+        assert_eq!(mandates.len(), 2);
+        assert_eq!(mandates[1].reference, Mandate::make_reference(m.id, 2));
+    }
+}
@@ -1,19 +1,28 @@
 use anyhow::Result;
 use async_trait::async_trait;
-use sqlx::{QueryBuilder, Sqlite};
+use sqlx::{Connection as SqlConnection, QueryBuilder};
 
 use eris_data::{
+    AddMember,
+    AuditAction,
+    BankImportRule,
+    BankImportRuleFilter,
+    ChangeMember,
     Delete,
     Update,
     Insert,
     Query,
     Retrieve,
     Member,
+    MemberChanges,
     MemberFilter,
+    MemberSort,
 };
 
 use crate::{
     results::{Id, QueryError},
+    connection::with_conn,
+    like,
     Connection,
 };
 
@@ -21,39 +30,110 @@ use crate::{
 impl Query<Member> for Connection {
     type Filter = MemberFilter;
     async fn query(&self, filter: &Self::Filter) -> Result<Vec<Member>> {
-        let mut conn = self.lock().await;
-        let mut qry = QueryBuilder::new(
-            r#"
-            SELECT 
-                id,
-                name,
-                email,
-                notes,
-                membership_start,
-                membership_end,
-                last_payment_at,
-                last_bank_transaction_at,
-                last_bank_transaction_number,
-                account_calculated_at,
-                interval,
-                ROUND(fee, 10) AS fee,
-                ROUND(account, 10) AS account
-            FROM members
-            WHERE 1
-            "#,
-        );
-
-        if let Some(id) = filter.id {
-            qry.push(" AND id = ").push_bind(id);
-        }
-        if let Some(name) = filter.name.clone() {
-            qry.push(" AND name LIKE ").push_bind(format!("%{}%", name));
-        }
-        if let Some(email) = filter.email.clone() {
-            qry.push(" AND email LIKE ").push_bind(email);
-        }
+        let members: Vec<Member> = with_conn!(self, |conn: DB| {
+            let mut qry = QueryBuilder::<DB>::new(
+                r#"
+                SELECT 
+                    id,
+                    name,
+                    email,
+                    notes,
+                    membership_start,
+                    membership_end,
+                    last_payment_at,
+                    last_bank_transaction_at,
+                    last_bank_transaction_number,
+                    account_calculated_at,
+                    interval,
+                    CAST(ROUND(CAST(fee AS NUMERIC), 10) AS DOUBLE PRECISION) AS fee,
+                    CAST(ROUND(CAST(account AS NUMERIC), 10) AS DOUBLE PRECISION) AS account,
+                    archived_at
+                FROM members
+                WHERE TRUE
+                "#,
+            );
+
+            if let Some(id) = filter.id {
+                qry.push(" AND id = ").push_bind(i64::from(id));
+            }
+            if let Some(name) = filter.name.clone() {
+                qry.push(" AND LOWER(name) LIKE LOWER(")
+                    .push_bind(like::contains(&name))
+                    .push(")")
+                    .push(like::ESCAPE);
+            }
+            if let Some(email) = filter.email.clone() {
+                qry.push(" AND LOWER(email) LIKE LOWER(")
+                    .push_bind(email)
+                    .push(")");
+            }
+            if !filter.include_archived {
+                qry.push(" AND archived_at IS NULL");
+            }
+            if let Some(date) = filter.active_at {
+                qry.push(" AND membership_start <= ").push_bind(date)
+                    .push(" AND (membership_end IS NULL OR membership_end >= ")
+                    .push_bind(date)
+                    .push(")");
+            }
+            if let Some(date) = filter.inactive_at {
+                qry.push(" AND (membership_start > ").push_bind(date)
+                    .push(" OR membership_end < ").push_bind(date)
+                    .push(")");
+            }
+            if let Some(amount) = filter.balance_below {
+                qry.push(" AND account < ").push_bind(amount);
+            }
+            if let Some(amount) = filter.balance_above {
+                qry.push(" AND account > ").push_bind(amount);
+            }
+            if let Some(fee) = filter.fee {
+                qry.push(" AND fee = ").push_bind(fee);
+            }
+            if let Some(interval) = filter.interval {
+                qry.push(" AND interval = ").push_bind(i64::from(interval));
+            }
+            if let Some(date) = filter.membership_start_after {
+                qry.push(" AND membership_start >= ").push_bind(date);
+            }
+            if let Some(date) = filter.membership_start_before {
+                qry.push(" AND membership_start <= ").push_bind(date);
+            }
+            if let Some(date) = filter.membership_end_after {
+                qry.push(" AND membership_end >= ").push_bind(date);
+            }
+            if let Some(date) = filter.membership_end_before {
+                qry.push(" AND membership_end <= ").push_bind(date);
+            }
+            if let Some(has_iban_rule) = filter.has_iban_rule {
+                qry.push(" AND ");
+                if !has_iban_rule {
+                    qry.push("NOT ");
+                }
+                qry.push("EXISTS (SELECT 1 FROM bank_import_member_ibans r \
+                          WHERE r.member_id = members.id)");
+            }
+            if let Some(notes) = filter.notes.clone() {
+                qry.push(" AND LOWER(notes) LIKE LOWER(")
+                    .push_bind(like::contains(&notes))
+                    .push(")")
+                    .push(like::ESCAPE);
+            }
+
+            qry.push(" ORDER BY ").push(order_by(filter.sort));
+            if filter.descending {
+                qry.push(" DESC");
+            }
+            qry.push(", id");
+            if filter.limit.is_some() || filter.offset.is_some() {
+                let limit = filter.limit.map_or(i64::MAX, i64::from);
+                let offset = filter.offset.map_or(0, i64::from);
+                qry.push(" LIMIT ").push_bind(limit)
+                    .push(" OFFSET ").push_bind(offset);
+            }
 
-        let members: Vec<Member> = qry.build_query_as().fetch_all(&mut *conn).await?;
+            qry.build_query_as().fetch_all(&mut *conn).await?
+        });
         Ok(members)
     }
 }
@@ -64,13 +144,14 @@ impl Retrieve<Member> for Connection {
     async fn retrieve(&self, member_id: Self::Key) -> Result<Member> {
         let filter = MemberFilter {
             id: Some(member_id),
+            include_archived: true,
             ..Default::default()
         };
         let member = self
             .query(&filter)
             .await?
             .pop()
-            .ok_or_else(|| QueryError::NotFound)?;
+            .ok_or(QueryError::NotFound)?;
         Ok(member)
     }
 }
@@ -78,9 +159,8 @@ impl Retrieve<Member> for Connection {
 #[async_trait]
 impl Insert<Member> for Connection {
     async fn insert(&self, member: Member) -> Result<Member> {
-        let insert: Id<u32> = {
-            let mut conn = self.lock().await;
-            let mut qry = QueryBuilder::<Sqlite>::new(
+        let insert: Id<i64> = with_conn!(self, |conn: DB| {
+            let mut qry = QueryBuilder::<DB>::new(
                 r#"INSERT INTO members (
                     name,
                     email,
@@ -93,7 +173,8 @@ impl Insert<Member> for Connection {
                     account_calculated_at,
                     interval,
                     fee,
-                    account
+                    account,
+                    archived_at
                 ) VALUES (
                 "#,
             );
@@ -105,18 +186,23 @@ impl Insert<Member> for Connection {
                 .push_bind(member.membership_end)
                 .push_bind(member.last_payment_at)
                 .push_bind(member.last_bank_transaction_at)
-                .push_bind(member.last_bank_transaction_number)
+                .push_bind(i64::from(member.last_bank_transaction_number))
                 .push_bind(member.account_calculated_at)
-                .push_bind(member.interval)
-                .push_bind(format!("{}", member.fee))
-                .push_bind(format!("{}", member.account));
+                .push_bind(i64::from(member.interval))
+                .push_bind(member.fee)
+                .push_bind(member.account)
+                .push_bind(member.archived_at);
 
             qry.push(") RETURNING id ")
                 .build_query_as()
-                .fetch_one(&mut *conn)
+                .fetch_all(&mut *conn)
                 .await?
-        };
-        self.retrieve(insert.id).await
+                .pop()
+                .ok_or(QueryError::NotFound)?
+        });
+        let member = self.retrieve(insert.id.try_into()?).await?;
+        self.audit(AuditAction::Insert, None, Some(&member)).await?;
+        Ok(member)
     }
 }
 
@@ -125,9 +211,9 @@ impl Insert<Member> for Connection {
 impl Update<Member> for Connection {
     /// Update member
     async fn update(&self, member: Member) -> Result<Member> {
-        {
-            let mut conn = self.lock().await;
-            QueryBuilder::<Sqlite>::new("UPDATE members SET")
+        let old = self.find_member(member.id).await?;
+        with_conn!(self, |conn: DB| {
+            QueryBuilder::<DB>::new("UPDATE members SET")
                 .push(" name = ")
                 .push_bind(&member.name)
                 .push(", email = ")
@@ -143,46 +229,203 @@ impl Update<Member> for Connection {
                 .push(", last_bank_transaction_at = ")
                 .push_bind(member.last_bank_transaction_at)
                 .push(", last_bank_transaction_number = ")
-                .push_bind(member.last_bank_transaction_number)
+                .push_bind(i64::from(member.last_bank_transaction_number))
                 .push(", account_calculated_at = ")
                 .push_bind(member.account_calculated_at)
                 .push(", interval = ")
-                .push_bind(member.interval)
+                .push_bind(i64::from(member.interval))
                 .push(", fee = ")
-                .push_bind(format!("{}", member.fee))
+                .push_bind(member.fee)
                 .push(", account = ")
-                .push_bind(format!("{}", member.account))
+                .push_bind(member.account)
+                .push(", archived_at = ")
+                .push_bind(member.archived_at)
                 .push(" WHERE id = ")
-                .push_bind(member.id)
+                .push_bind(i64::from(member.id))
                 .build()
                 .execute(&mut *conn)
                 .await?;
+        });
+        let member = self.retrieve(member.id).await?;
+        self.audit(AuditAction::Update, old.as_ref(), Some(&member)).await?;
+        Ok(member)
+    }
+}
+
+#[async_trait]
+impl AddMember for Connection {
+    /// Insert the member and the rules in a single
+    /// database transaction.
+    async fn add_member(
+        &self,
+        member: Member,
+        rules: Vec<BankImportRule>,
+    ) -> Result<Member> {
+        let insert: Id<i64> = with_conn!(self, |conn: DB| {
+            let mut tx = conn.begin().await?;
+            let mut qry = QueryBuilder::<DB>::new(
+                r#"INSERT INTO members (
+                    name,
+                    email,
+                    notes,
+                    membership_start,
+                    membership_end,
+                    last_payment_at,
+                    last_bank_transaction_at,
+                    last_bank_transaction_number,
+                    account_calculated_at,
+                    interval,
+                    fee,
+                    account,
+                    archived_at
+                ) VALUES (
+                "#,
+            );
+            qry.separated(", ")
+                .push_bind(&member.name)
+                .push_bind(&member.email)
+                .push_bind(&member.notes)
+                .push_bind(member.membership_start)
+                .push_bind(member.membership_end)
+                .push_bind(member.last_payment_at)
+                .push_bind(member.last_bank_transaction_at)
+                .push_bind(i64::from(member.last_bank_transaction_number))
+                .push_bind(member.account_calculated_at)
+                .push_bind(i64::from(member.interval))
+                .push_bind(member.fee)
+                .push_bind(member.account)
+                .push_bind(member.archived_at);
+            let insert: Id<i64> = qry.push(") RETURNING id ")
+                .build_query_as()
+                .fetch_one(&mut tx)
+                .await?;
+
+            for rule in &rules {
+                let mut qry = QueryBuilder::<DB>::new(
+                    r#"INSERT INTO bank_import_member_ibans (
+                        member_id,
+                        iban,
+                        match_subject,
+                        split_amount
+                    ) VALUES (
+                    "#,
+                );
+                qry.separated(", ")
+                    .push_bind(insert.id)
+                    .push_bind(&rule.iban)
+                    .push_bind(&rule.match_subject)
+                    .push_bind(rule.split_amount);
+                qry.push(")").build().execute(&mut tx).await?;
+            }
+            tx.commit().await?;
+            insert
+        });
+
+        let member: Member = self.retrieve(insert.id.try_into()?).await?;
+        self.audit(AuditAction::Insert, None, Some(&member)).await?;
+        let rules: Vec<BankImportRule> = self.query(&BankImportRuleFilter {
+            member_id: Some(member.id),
+            ..Default::default()
+        }).await?;
+        for rule in &rules {
+            self.audit(AuditAction::Insert, None, Some(rule)).await?;
         }
-        self.retrieve(member.id).await  
+        Ok(member)
+    }
+}
+
+#[async_trait]
+impl ChangeMember for Connection {
+    /// Update only the changed columns, leaving the balance
+    /// to concurrent bookings.
+    async fn change_member(&self, changes: MemberChanges) -> Result<Member> {
+        let old: Member = self.retrieve(changes.id).await?;
+        with_conn!(self, |conn: DB| {
+            let mut qry = QueryBuilder::<DB>::new("UPDATE members SET id = id");
+            if let Some(name) = &changes.name {
+                qry.push(", name = ").push_bind(name);
+            }
+            if let Some(email) = &changes.email {
+                qry.push(", email = ").push_bind(email);
+            }
+            if let Some(notes) = &changes.notes {
+                qry.push(", notes = ").push_bind(notes);
+            }
+            if let Some(date) = changes.membership_start {
+                qry.push(", membership_start = ").push_bind(date);
+            }
+            if let Some(date) = changes.membership_end {
+                qry.push(", membership_end = ").push_bind(date);
+            }
+            if let Some(fee) = changes.fee {
+                qry.push(", fee = ").push_bind(fee);
+            }
+            if let Some(interval) = changes.interval {
+                qry.push(", interval = ").push_bind(i64::from(interval));
+            }
+            qry.push(" WHERE id = ")
+                .push_bind(i64::from(changes.id))
+                .build()
+                .execute(&mut *conn)
+                .await?;
+        });
+        let member = self.retrieve(changes.id).await?;
+        self.audit(AuditAction::Update, Some(&old), Some(&member)).await?;
+        Ok(member)
     }
 }
 
 #[async_trait]
 impl Delete<Member> for Connection {
-    /// Delete member
+    /// Delete member. Rules and transactions are deleted
+    /// with the member, which is recorded as a single change.
+    /// Former members should be archived instead; they are
+    /// only deleted when purged after the retention period.
     async fn delete(&self, member: Member) -> Result<()> {
-        let mut conn = self.lock().await;
-        QueryBuilder::<Sqlite>::new("DELETE FROM members WHERE id = ")
-            .push_bind(member.id)
-            .build()
-            .execute(&mut *conn)
-            .await?;
+        let old = self.find_member(member.id).await?;
+        with_conn!(self, |conn: DB| {
+            QueryBuilder::<DB>::new("DELETE FROM members WHERE id = ")
+                .push_bind(i64::from(member.id))
+                .build()
+                .execute(&mut *conn)
+                .await?;
+        });
+        self.audit(AuditAction::Delete, old.as_ref(), None).await?;
         Ok(())
     }
 }
 
+/// The column members are sorted by
+fn order_by(sort: MemberSort) -> &'static str {
+    match sort {
+        MemberSort::Id => "id",
+        MemberSort::Name => "LOWER(name)",
+        MemberSort::Balance => "account",
+        MemberSort::Fee => "fee",
+        MemberSort::MembershipStart => "membership_start",
+        MemberSort::LastPayment => "last_payment_at",
+    }
+}
+
+impl Connection {
+    /// Get a member if it exists
+    async fn find_member(&self, id: u32) -> Result<Option<Member>> {
+        let mut members: Vec<Member> = self.query(&MemberFilter {
+            id: Some(id),
+            include_archived: true,
+            ..Default::default()
+        }).await?;
+        Ok(members.pop())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use chrono::NaiveDate;
 
     use super::*;
 
-    use eris_data::Transaction;
+    use eris_data::{BankImportRule, Transaction};
 
     #[tokio::test]
     async fn test_member_insert() {
@@ -244,6 +487,64 @@ mod tests {
         assert_eq!(member.fee, 123.42);
         assert_eq!(member.account, 23.0);
         assert_eq!(member.notes, "was not very nice");
+
+        // Float errors are rounded away on read
+        let mut member = member;
+        member.account = 0.1 + 0.2;
+        let member = db.update(member).await.unwrap();
+        assert_eq!(member.account, 0.3);
+    }
+
+    #[tokio::test]
+    async fn test_add_member() {
+        let db = Connection::open_test().await;
+        let rule = |iban: &str| BankImportRule {
+            iban: iban.to_string(),
+            ..Default::default()
+        };
+        let member = db.add_member(Member {
+            name: "Eris".to_string(),
+            ..Member::default()
+        }, vec![rule("DE1111"), rule("DE2222")]).await.unwrap();
+        let rules = member.get_bank_import_rules(&db).await.unwrap();
+        assert_eq!(rules.len(), 2);
+        assert!(rules.iter().all(|r| r.member_id == member.id));
+
+        // A failing rule does not leave the member behind
+        let result = db.add_member(Member {
+            name: "Kallisti".to_string(),
+            ..Member::default()
+        }, vec![rule("DE3333"), rule("DE3333")]).await;
+        assert!(result.is_err());
+        let members: Vec<Member> = db.query(&MemberFilter {
+            include_archived: true,
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(members.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_change_member() {
+        let db = Connection::open_test().await;
+        let member = db.insert(Member {
+            name: "Test Member".to_string(),
+            email: "eris@discordia.ccc".to_string(),
+            membership_end: Some(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()),
+            ..Member::default()
+        }).await.unwrap();
+
+        // A booking after the member was read is kept
+        db.update(Member { account: 23.0, ..member.clone() }).await.unwrap();
+        let member = db.change_member(MemberChanges {
+            id: member.id,
+            name: Some("Changed".to_string()),
+            membership_end: Some(None),
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(member.name, "Changed");
+        assert_eq!(member.email, "eris@discordia.ccc");
+        assert_eq!(member.membership_end, None);
+        assert_eq!(member.account, 23.0);
     }
 
     #[tokio::test]
@@ -295,6 +596,169 @@ mod tests {
         };
         let members: Vec<Member> = db.query(&result).await.unwrap();
         assert_eq!(members.len(), 0);
+        // Wildcards are matched literally
+        for name in ["T_st", "%"] {
+            let result = MemberFilter {
+                name: Some(name.to_string()),
+                ..MemberFilter::default()
+            };
+            let members: Vec<Member> = db.query(&result).await.unwrap();
+            assert_eq!(members.len(), 0);
+        }
+    }
+
+    /// Insert members for the filter tests
+    async fn insert_members(db: &Connection) -> Vec<Member> {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        let members = vec![
+            Member {
+                name: "Eris".to_string(),
+                notes: "Founding member".to_string(),
+                membership_start: date(2020, 1, 1),
+                fee: 20.0,
+                interval: 1,
+                account: -40.0,
+                ..Default::default()
+            },
+            Member {
+                name: "aneris".to_string(),
+                membership_start: date(2021, 6, 1),
+                membership_end: Some(date(2022, 12, 31)),
+                fee: 10.0,
+                interval: 12,
+                account: 5.0,
+                ..Default::default()
+            },
+            Member {
+                name: "Discordia".to_string(),
+                notes: "Pays in advance, FOUNDING member".to_string(),
+                membership_start: date(2023, 1, 1),
+                fee: 20.0,
+                interval: 1,
+                account: 60.0,
+                ..Default::default()
+            },
+        ];
+        let mut inserted = vec![];
+        for member in members {
+            inserted.push(db.insert(member).await.unwrap());
+        }
+        db.insert(BankImportRule::new(&inserted[2], "DE2342")).await.unwrap();
+        inserted
+    }
+
+    #[tokio::test]
+    async fn test_member_query_filters() {
+        let db = Connection::open_test().await;
+        let members = insert_members(&db).await;
+        let ids = |filter: MemberFilter| {
+            let db = &db;
+            async move {
+                let result: Vec<Member> = db.query(&filter).await.unwrap();
+                result.iter().map(|m| m.id).collect::<Vec<u32>>()
+            }
+        };
+        let (eris, aneris, discordia) = (members[0].id, members[1].id, members[2].id);
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d);
+
+        assert_eq!(ids(MemberFilter {
+            active_at: date(2022, 1, 1),
+            ..Default::default()
+        }).await, vec![eris, aneris]);
+        assert_eq!(ids(MemberFilter {
+            inactive_at: date(2022, 1, 1),
+            ..Default::default()
+        }).await, vec![discordia]);
+        assert_eq!(ids(MemberFilter {
+            balance_below: Some(0.0),
+            ..Default::default()
+        }).await, vec![eris]);
+        assert_eq!(ids(MemberFilter {
+            balance_above: Some(0.0),
+            fee: Some(20.0),
+            ..Default::default()
+        }).await, vec![discordia]);
+        assert_eq!(ids(MemberFilter {
+            interval: Some(12),
+            ..Default::default()
+        }).await, vec![aneris]);
+        assert_eq!(ids(MemberFilter {
+            membership_start_after: date(2021, 1, 1),
+            membership_start_before: date(2022, 1, 1),
+            ..Default::default()
+        }).await, vec![aneris]);
+        assert_eq!(ids(MemberFilter {
+            membership_end_before: date(2023, 1, 1),
+            ..Default::default()
+        }).await, vec![aneris]);
+        assert!(ids(MemberFilter {
+            membership_end_after: date(2023, 1, 1),
+            ..Default::default()
+        }).await.is_empty());
+        assert_eq!(ids(MemberFilter {
+            has_iban_rule: Some(true),
+            ..Default::default()
+        }).await, vec![discordia]);
+        assert_eq!(ids(MemberFilter {
+            has_iban_rule: Some(false),
+            ..Default::default()
+        }).await, vec![eris, aneris]);
+        assert_eq!(ids(MemberFilter {
+            notes: Some("founding".to_string()),
+            ..Default::default()
+        }).await, vec![eris, discordia]);
+        assert!(ids(MemberFilter {
+            notes: Some("f_unding".to_string()),
+            ..Default::default()
+        }).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_member_query_sort() {
+        let db = Connection::open_test().await;
+        let members = insert_members(&db).await;
+        let ids = |filter: MemberFilter| {
+            let db = &db;
+            async move {
+                let result: Vec<Member> = db.query(&filter).await.unwrap();
+                result.iter().map(|m| m.id).collect::<Vec<u32>>()
+            }
+        };
+        let (eris, aneris, discordia) = (members[0].id, members[1].id, members[2].id);
+
+        // Names are sorted ignoring the case
+        assert_eq!(ids(MemberFilter {
+            sort: MemberSort::Name,
+            ..Default::default()
+        }).await, vec![aneris, discordia, eris]);
+        assert_eq!(ids(MemberFilter {
+            sort: MemberSort::Balance,
+            descending: true,
+            ..Default::default()
+        }).await, vec![discordia, aneris, eris]);
+
+        // Members with the same fee are ordered by id
+        assert_eq!(ids(MemberFilter {
+            sort: MemberSort::Fee,
+            descending: true,
+            ..Default::default()
+        }).await, vec![eris, discordia, aneris]);
+
+        assert_eq!(ids(MemberFilter {
+            limit: Some(2),
+            ..Default::default()
+        }).await, vec![eris, aneris]);
+        assert_eq!(ids(MemberFilter {
+            offset: Some(1),
+            ..Default::default()
+        }).await, vec![aneris, discordia]);
+        assert_eq!(ids(MemberFilter {
+            sort: MemberSort::MembershipStart,
+            descending: true,
+            limit: Some(1),
+            offset: Some(1),
+            ..Default::default()
+        }).await, vec![aneris]);
     }
 
     #[tokio::test]
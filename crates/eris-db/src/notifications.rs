@@ -0,0 +1,291 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use sqlx::QueryBuilder;
+
+use eris_data::{
+    ClaimNotification,
+    Insert,
+    Notification,
+    NotificationFilter,
+    Query,
+    Retrieve,
+    Update,
+};
+
+use crate::{
+    connection::with_conn,
+    results::{Id, QueryError},
+    Connection,
+};
+
+#[async_trait]
+impl Query<Notification> for Connection {
+    type Filter = NotificationFilter;
+
+    /// Fetch notifications from the outbox
+    async fn query(
+        &self,
+        filter: &Self::Filter,
+    ) -> Result<Vec<Notification>> {
+        let notifications: Vec<Notification> = with_conn!(self, |conn: DB| {
+            let mut qry = QueryBuilder::<DB>::new(
+                r#"
+                SELECT
+                    id,
+                    member_id,
+                    event,
+                    dedup_key,
+                    recipient,
+                    subject,
+                    body,
+                    created_at,
+                    sent_at,
+                    attempts,
+                    last_error,
+                    claimed_at
+                FROM notification_outbox
+                WHERE TRUE
+                "#,
+            );
+            if let Some(id) = filter.id {
+                qry.push(" AND id = ").push_bind(i64::from(id));
+            }
+            if let Some(member_id) = filter.member_id {
+                qry.push(" AND member_id = ").push_bind(i64::from(member_id));
+            }
+            if let Some(event) = filter.event {
+                qry.push(" AND event = ").push_bind(event);
+            }
+            if let Some(dedup_key) = filter.dedup_key.clone() {
+                qry.push(" AND dedup_key = ").push_bind(dedup_key);
+            }
+            match filter.sent {
+                Some(true) => {
+                    qry.push(" AND sent_at IS NOT NULL");
+                }
+                Some(false) => {
+                    qry.push(" AND sent_at IS NULL");
+                }
+                None => {}
+            }
+            qry.push(" ORDER BY id");
+
+            qry.build_query_as().fetch_all(&mut *conn).await?
+        });
+        Ok(notifications)
+    }
+}
+
+#[async_trait]
+impl Retrieve<Notification> for Connection {
+    type Key = u32;
+
+    /// Get a single notification
+    async fn retrieve(&self, id: Self::Key) -> Result<Notification> {
+        let filter = NotificationFilter {
+            id: Some(id),
+            ..Default::default()
+        };
+        let notification = self
+            .query(&filter)
+            .await?
+            .pop()
+            .ok_or(QueryError::NotFound)?;
+        Ok(notification)
+    }
+}
+
+#[async_trait]
+impl Insert<Notification> for Connection {
+    /// Queue a notification. This fails if a notification
+    /// with the same dedup key exists.
+    async fn insert(
+        &self,
+        notification: Notification,
+    ) -> Result<Notification> {
+        let insert: Id<i64> = with_conn!(self, |conn: DB| {
+            let mut qry = QueryBuilder::<DB>::new(
+                r#"INSERT INTO notification_outbox (
+                    member_id,
+                    event,
+                    dedup_key,
+                    recipient,
+                    subject,
+                    body,
+                    created_at,
+                    sent_at,
+                    attempts,
+                    last_error
+                ) VALUES (
+                "#,
+            );
+            qry.separated(", ")
+                .push_bind(i64::from(notification.member_id))
+                .push_bind(notification.event)
+                .push_bind(&notification.dedup_key)
+                .push_bind(&notification.recipient)
+                .push_bind(&notification.subject)
+                .push_bind(&notification.body)
+                .push_bind(notification.created_at)
+                .push_bind(notification.sent_at)
+                .push_bind(i64::from(notification.attempts))
+                .push_bind(&notification.last_error);
+
+            qry.push(") RETURNING id ")
+                .build_query_as()
+                .fetch_all(&mut *conn)
+                .await?
+                .pop()
+                .ok_or(QueryError::NotFound)?
+        });
+        self.retrieve(insert.id.try_into()?).await
+    }
+}
+
+#[async_trait]
+impl Update<Notification> for Connection {
+    /// Update the delivery state of a notification.
+    /// The content can not be changed.
+    async fn update(
+        &self,
+        notification: Notification,
+    ) -> Result<Notification> {
+        with_conn!(self, |conn: DB| {
+            QueryBuilder::<DB>::new("UPDATE notification_outbox SET")
+                .push(" sent_at = ")
+                .push_bind(notification.sent_at)
+                .push(", attempts = ")
+                .push_bind(i64::from(notification.attempts))
+                .push(", last_error = ")
+                .push_bind(&notification.last_error)
+                .push(", claimed_at = ")
+                .push_bind(notification.claimed_at)
+                .push(" WHERE id = ")
+                .push_bind(i64::from(notification.id))
+                .build()
+                .execute(&mut *conn)
+                .await?;
+        });
+        self.retrieve(notification.id).await
+    }
+}
+
+#[async_trait]
+impl ClaimNotification for Connection {
+    /// Claim the notification with a single conditional
+    /// update, so only one delivery succeeds.
+    async fn claim(
+        &self,
+        notification: &Notification,
+        now: NaiveDateTime,
+        stale_before: NaiveDateTime,
+    ) -> Result<Option<Notification>> {
+        let claimed = with_conn!(self, |conn: DB| {
+            QueryBuilder::<DB>::new(
+                "UPDATE notification_outbox SET attempts = attempts + 1")
+                .push(", claimed_at = ")
+                .push_bind(now)
+                .push(" WHERE id = ")
+                .push_bind(i64::from(notification.id))
+                .push(" AND sent_at IS NULL AND attempts = ")
+                .push_bind(i64::from(notification.attempts))
+                .push(" AND (claimed_at IS NULL OR claimed_at < ")
+                .push_bind(stale_before)
+                .push(")")
+                .build()
+                .execute(&mut *conn)
+                .await?
+                .rows_affected()
+        });
+        if claimed == 0 {
+            return Ok(None);
+        }
+        Ok(Some(self.retrieve(notification.id).await?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use eris_data::{Member, NotificationEvent};
+
+    #[tokio::test]
+    async fn test_notification_insert_dedup() {
+        let db = Connection::open_test().await;
+        let m = db.insert(Member {
+            name: "Testmember".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+
+        let notification = Notification {
+            member_id: m.id,
+            event: NotificationEvent::BalanceReminder,
+            dedup_key: "balance_reminder:1:2023-05".to_string(),
+            recipient: "test@eris.discordia".to_string(),
+            subject: "Your balance".to_string(),
+            ..Default::default()
+        };
+        let n = db.insert(notification.clone()).await.unwrap();
+        assert!(n.id > 0);
+        assert_eq!(n.event, NotificationEvent::BalanceReminder);
+        assert_eq!(n.sent_at, None);
+
+        // The same notification can not be queued twice
+        assert!(db.insert(notification).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_notification_update_sent() {
+        let db = Connection::open_test().await;
+        let m = db.insert(Member {
+            name: "Testmember".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        let mut n = db.insert(Notification {
+            member_id: m.id,
+            dedup_key: "welcome:1".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+
+        n.attempts = 1;
+        n.sent_at = Some(chrono::Local::now().naive_local());
+        db.update(n).await.unwrap();
+
+        let pending: Vec<Notification> = db.query(&NotificationFilter {
+            sent: Some(false),
+            ..Default::default()
+        }).await.unwrap();
+        assert!(pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_notification_claim() {
+        let db = Connection::open_test().await;
+        let m = db.insert(Member {
+            name: "Testmember".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        let n = db.insert(Notification {
+            member_id: m.id,
+            dedup_key: "welcome:1".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        let now = chrono::Local::now().naive_local();
+        let stale = now - chrono::Duration::hours(1);
+
+        let claimed = db.claim(&n, now, stale).await.unwrap().unwrap();
+        assert_eq!(claimed.attempts, 1);
+        assert!(claimed.claimed_at.is_some());
+
+        // Neither the stale copy nor the claimed one can be claimed again
+        assert!(db.claim(&n, now, stale).await.unwrap().is_none());
+        assert!(db.claim(&claimed, now, stale).await.unwrap().is_none());
+
+        // Unless the claim is stale
+        let later = now + chrono::Duration::hours(2);
+        let stale = later - chrono::Duration::hours(1);
+        assert!(db.claim(&claimed, later, stale).await.unwrap().is_some());
+    }
+}
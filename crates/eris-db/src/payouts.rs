@@ -0,0 +1,266 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use sqlx::QueryBuilder;
+
+use eris_data::{
+    Delete,
+    Insert,
+    Payout,
+    PayoutFilter,
+    PayoutState,
+    Query,
+    Retrieve,
+    Update,
+};
+
+use crate::{
+    connection::with_conn,
+    results::{Id, QueryError},
+    Connection,
+};
+
+#[async_trait]
+impl Query<Payout> for Connection {
+    type Filter = PayoutFilter;
+
+    /// Fetch payouts
+    async fn query(&self, filter: &Self::Filter) -> Result<Vec<Payout>> {
+        let payouts: Vec<Payout> = with_conn!(self, |conn: DB| {
+            let mut qry = QueryBuilder::<DB>::new(
+                r#"
+                SELECT
+                    id,
+                    member_id,
+                    kind,
+                    name,
+                    iban,
+                    CAST(amount AS DOUBLE PRECISION) AS amount,
+                    subject,
+                    created_at,
+                    batch_id,
+                    exported_at,
+                    settled_at
+                FROM payouts
+                WHERE TRUE
+                "#,
+            );
+            if let Some(id) = filter.id {
+                qry.push(" AND id = ").push_bind(i64::from(id));
+            }
+            if let Some(member_id) = filter.member_id {
+                qry.push(" AND member_id = ").push_bind(i64::from(member_id));
+            }
+            if let Some(iban) = filter.iban.clone() {
+                qry.push(" AND iban = ").push_bind(iban);
+            }
+            if let Some(batch_id) = filter.batch_id.clone() {
+                qry.push(" AND batch_id = ").push_bind(batch_id);
+            }
+            match filter.state {
+                Some(PayoutState::Queued) => {
+                    qry.push(" AND exported_at IS NULL");
+                }
+                Some(PayoutState::Pending) => {
+                    qry.push(" AND exported_at IS NOT NULL");
+                    qry.push(" AND settled_at IS NULL");
+                }
+                Some(PayoutState::Settled) => {
+                    qry.push(" AND settled_at IS NOT NULL");
+                }
+                None => {}
+            }
+            qry.push(" ORDER BY id");
+
+            qry.build_query_as().fetch_all(&mut *conn).await?
+        });
+        Ok(payouts)
+    }
+}
+
+#[async_trait]
+impl Retrieve<Payout> for Connection {
+    type Key = u32;
+
+    /// Get a single payout
+    async fn retrieve(&self, id: Self::Key) -> Result<Payout> {
+        let filter = PayoutFilter {
+            id: Some(id),
+            ..Default::default()
+        };
+        let payout = self
+            .query(&filter)
+            .await?
+            .pop()
+            .ok_or(QueryError::NotFound)?;
+        Ok(payout)
+    }
+}
+
+#[async_trait]
+impl Insert<Payout> for Connection {
+    /// Queue a payout
+    async fn insert(&self, payout: Payout) -> Result<Payout> {
+        let insert: Id<i64> = with_conn!(self, |conn: DB| {
+            let mut qry = QueryBuilder::<DB>::new(
+                r#"INSERT INTO payouts (
+                    member_id,
+                    kind,
+                    name,
+                    iban,
+                    amount,
+                    subject,
+                    created_at,
+                    batch_id,
+                    exported_at,
+                    settled_at
+                ) VALUES (
+                "#,
+            );
+            qry.separated(", ")
+                .push_bind(i64::from(payout.member_id))
+                .push_bind(payout.kind)
+                .push_bind(&payout.name)
+                .push_bind(&payout.iban)
+                .push_bind(payout.amount)
+                .push_bind(&payout.subject)
+                .push_bind(payout.created_at)
+                .push_bind(&payout.batch_id)
+                .push_bind(payout.exported_at)
+                .push_bind(payout.settled_at);
+
+            qry.push(") RETURNING id ")
+                .build_query_as()
+                .fetch_all(&mut *conn)
+                .await?
+                .pop()
+                .ok_or(QueryError::NotFound)?
+        });
+        self.retrieve(insert.id.try_into()?).await
+    }
+}
+
+#[async_trait]
+impl Update<Payout> for Connection {
+    /// Update a payout
+    async fn update(&self, payout: Payout) -> Result<Payout> {
+        with_conn!(self, |conn: DB| {
+            QueryBuilder::<DB>::new("UPDATE payouts SET")
+                .push(" kind = ")
+                .push_bind(payout.kind)
+                .push(", name = ")
+                .push_bind(&payout.name)
+                .push(", iban = ")
+                .push_bind(&payout.iban)
+                .push(", amount = ")
+                .push_bind(payout.amount)
+                .push(", subject = ")
+                .push_bind(&payout.subject)
+                .push(", batch_id = ")
+                .push_bind(&payout.batch_id)
+                .push(", exported_at = ")
+                .push_bind(payout.exported_at)
+                .push(", settled_at = ")
+                .push_bind(payout.settled_at)
+                .push(" WHERE id = ")
+                .push_bind(i64::from(payout.id))
+                .build()
+                .execute(&mut *conn)
+                .await?;
+        });
+        self.retrieve(payout.id).await
+    }
+}
+
+#[async_trait]
+impl Delete<Payout> for Connection {
+    /// Delete a payout
+    async fn delete(&self, payout: Payout) -> Result<()> {
+        with_conn!(self, |conn: DB| {
+            QueryBuilder::<DB>::new("DELETE FROM payouts WHERE id = ")
+                .push_bind(i64::from(payout.id))
+                .build()
+                .execute(&mut *conn)
+                .await?;
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::NaiveDate;
+
+    use eris_data::{Member, PayoutKind};
+
+    #[tokio::test]
+    async fn test_payout_insert() {
+        let db = Connection::open_test().await;
+        let m = db.insert(Member {
+            name: "Testmember".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+
+        let payout = db.insert(Payout {
+            member_id: m.id,
+            kind: PayoutKind::Reimbursement,
+            name: "Testmember".to_string(),
+            iban: "DE2342".to_string(),
+            amount: 42.23,
+            subject: "Mate".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+
+        assert!(payout.id > 0);
+        assert_eq!(payout.kind, PayoutKind::Reimbursement);
+        assert_eq!(payout.amount, 42.23);
+        assert_eq!(payout.state(), PayoutState::Queued);
+    }
+
+    #[tokio::test]
+    async fn test_payout_filter_state() {
+        let db = Connection::open_test().await;
+        let m = db.insert(Member {
+            name: "Testmember".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+
+        let date = NaiveDate::from_ymd_opt(2023, 5, 1).unwrap();
+        db.insert(Payout {
+            member_id: m.id,
+            ..Default::default()
+        }).await.unwrap();
+        let pending = db.insert(Payout {
+            member_id: m.id,
+            exported_at: Some(date),
+            ..Default::default()
+        }).await.unwrap();
+        let mut settled = db.insert(Payout {
+            member_id: m.id,
+            exported_at: Some(date),
+            ..Default::default()
+        }).await.unwrap();
+        settled.settled_at = Some(date);
+        db.update(settled).await.unwrap();
+
+        for state in [
+            PayoutState::Queued,
+            PayoutState::Pending,
+            PayoutState::Settled,
+        ] {
+            let payouts: Vec<Payout> = db.query(&PayoutFilter {
+                state: Some(state),
+                ..Default::default()
+            }).await.unwrap();
+            assert_eq!(payouts.len(), 1);
+            assert_eq!(payouts[0].state(), state);
+        }
+
+        let payouts: Vec<Payout> = db.query(&PayoutFilter {
+            state: Some(PayoutState::Pending),
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(payouts[0].id, pending.id);
+    }
+}
@@ -0,0 +1,211 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use sqlx::{Connection as SqlConnection, QueryBuilder};
+
+use eris_data::{
+    AuditAction,
+    AuditEntity,
+    Member,
+    Pseudonymisation,
+    Pseudonymise,
+    Retrieve,
+};
+
+use crate::{connection::with_conn, Connection};
+
+/// The columns personal data is replaced in
+const SCRUBBED_COLUMNS: &[(&str, &[&str])] = &[
+    ("transactions", &["account_name", "description"]),
+    ("payouts", &["subject"]),
+    ("audit_log", &["command", "entity_id"]),
+];
+
+#[async_trait]
+impl Pseudonymise for Connection {
+    /// Pseudonymise a member. All changes are made in a
+    /// single database transaction. The recorded values of
+    /// the audit log are removed, as they hold earlier names,
+    /// email addresses and IBANs as well.
+    async fn pseudonymise(&self, p: &Pseudonymisation) -> Result<Member> {
+        let personal_data = p.replaced_values();
+
+        with_conn!(self, |conn: DB| {
+            let mut tx = conn.begin().await?;
+
+            QueryBuilder::<DB>::new("UPDATE members SET name = ")
+                .push_bind(&p.pseudonym)
+                .push(", email = '', notes = '' WHERE id = ")
+                .push_bind(i64::from(p.member_id))
+                .build()
+                .execute(&mut tx)
+                .await?;
+            let tables = ["bank_import_member_ibans", "notification_outbox", "users"];
+            for table in tables {
+                QueryBuilder::<DB>::new(format!("DELETE FROM {}", table))
+                    .push(" WHERE member_id = ")
+                    .push_bind(i64::from(p.member_id))
+                    .build()
+                    .execute(&mut tx)
+                    .await?;
+            }
+            QueryBuilder::<DB>::new("UPDATE mandates SET account_holder = ")
+                .push_bind(&p.pseudonym)
+                .push(", iban = '', original_iban = NULL WHERE member_id = ")
+                .push_bind(i64::from(p.member_id))
+                .build()
+                .execute(&mut tx)
+                .await?;
+            QueryBuilder::<DB>::new("UPDATE payouts SET name = ")
+                .push_bind(&p.pseudonym)
+                .push(", iban = '' WHERE member_id = ")
+                .push_bind(i64::from(p.member_id))
+                .build()
+                .execute(&mut tx)
+                .await?;
+            QueryBuilder::<DB>::new("UPDATE donation_receipts SET name = ")
+                .push_bind(&p.pseudonym)
+                .push(" WHERE member_id = ")
+                .push_bind(i64::from(p.member_id))
+                .build()
+                .execute(&mut tx)
+                .await?;
+            QueryBuilder::<DB>::new(
+                "UPDATE audit_log SET old_value = NULL, new_value = NULL")
+                .push(" WHERE member_id = ")
+                .push_bind(i64::from(p.member_id))
+                .build()
+                .execute(&mut tx)
+                .await?;
+            // Rules are logged by their IBAN, including rules
+            // removed before and not part of the personal data
+            QueryBuilder::<DB>::new("UPDATE audit_log SET entity_id = ")
+                .push_bind(p.member_id.to_string())
+                .push(" WHERE entity = ")
+                .push_bind(AuditEntity::BankImportRule)
+                .push(" AND member_id = ")
+                .push_bind(i64::from(p.member_id))
+                .build()
+                .execute(&mut tx)
+                .await?;
+
+            for value in &personal_data {
+                for (table, columns) in SCRUBBED_COLUMNS {
+                    let mut qry = QueryBuilder::<DB>::new(
+                        format!("UPDATE {} SET ", table));
+                    let mut assignments = qry.separated(", ");
+                    for column in columns.iter() {
+                        assignments
+                            .push(format!("{0} = replace({0}, ", column))
+                            .push_bind_unseparated(value)
+                            .push_unseparated(", ")
+                            .push_bind_unseparated(&p.pseudonym)
+                            .push_unseparated(")");
+                    }
+                    qry.push(" WHERE member_id = ")
+                        .push_bind(i64::from(p.member_id))
+                        .build()
+                        .execute(&mut tx)
+                        .await?;
+                }
+            }
+            tx.commit().await?;
+        });
+
+        let member: Member = self.retrieve(p.member_id).await?;
+        self.audit(AuditAction::Update, None, Some(&member)).await?;
+        Ok(member)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::NaiveDate;
+    use eris_data::{
+        AuditEntry,
+        AuditEntryFilter,
+        BankImportRule,
+        Delete,
+        Insert,
+        Query,
+        Transaction,
+        TransactionFilter,
+        Update,
+    };
+
+    #[tokio::test]
+    async fn test_pseudonymise() {
+        let db = Connection::open_test().await;
+        let member = db.insert(Member {
+            name: "Kallisti".to_string(),
+            email: "kallisti@discordia.example".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        // Earlier names and addresses are not part of the personal data
+        let member = db.update(Member {
+            name: "Eris".to_string(),
+            email: "eris@discordia.example".to_string(),
+            notes: "Says \"hail\"".to_string(),
+            ..member
+        }).await.unwrap();
+        db.insert(BankImportRule {
+            member_id: member.id,
+            iban: "DE2342".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        // A rule removed before is not part of the personal data
+        let removed = db.insert(BankImportRule {
+            member_id: member.id,
+            iban: "DE4223".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        db.delete(removed).await.unwrap();
+        db.insert(Transaction {
+            member_id: member.id,
+            date: NaiveDate::from_ymd_opt(2023, 5, 10).unwrap(),
+            account_name: "Eris Discordia".to_string(),
+            description: "Beitrag Eris (split)".to_string(),
+            amount: 20.0,
+            ..Default::default()
+        }).await.unwrap();
+
+        let member = db.pseudonymise(&Pseudonymisation {
+            member_id: member.id,
+            pseudonym: "Member 1".to_string(),
+            personal_data: vec![
+                "Eris".to_string(),
+                "Eris Discordia".to_string(),
+                "eris@discordia.example".to_string(),
+                "Says \"hail\"".to_string(),
+                "DE2342".to_string(),
+            ],
+        }).await.unwrap();
+        assert_eq!(member.name, "Member 1");
+        assert_eq!(member.email, "");
+        assert!(member.get_bank_import_rules(&db).await.unwrap().is_empty());
+
+        let transactions: Vec<Transaction> = db.query(&TransactionFilter {
+            member_id: Some(member.id),
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(transactions[0].account_name, "Member 1");
+        assert_eq!(transactions[0].description, "Beitrag Member 1 (split)");
+        assert_eq!(transactions[0].amount, 20.0);
+
+        let entries: Vec<AuditEntry> = db.query(&AuditEntryFilter {
+            member_id: Some(member.id),
+            ..Default::default()
+        }).await.unwrap();
+        for entry in entries {
+            let values = format!(
+                "{} {:?} {:?}", entry.entity_id, entry.old_value, entry.new_value);
+            assert!(!values.contains("Eris"), "{}", values);
+            assert!(!values.contains("hail"), "{}", values);
+            assert!(!values.contains("DE2342"), "{}", values);
+            assert!(!values.contains("DE4223"), "{}", values);
+            assert!(!values.contains("Kallisti"), "{}", values);
+            assert!(!values.contains("kallisti@"), "{}", values);
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use sqlx::QueryBuilder;
+
+use eris_data::{
+    Delete,
+    DonationReceipt,
+    DonationReceiptFilter,
+    Insert,
+    Query,
+    Retrieve,
+};
+
+use crate::{
+    connection::with_conn,
+    results::{Id, QueryError},
+    Connection,
+};
+
+#[async_trait]
+impl Query<DonationReceipt> for Connection {
+    type Filter = DonationReceiptFilter;
+
+    /// Fetch donation receipts ordered by number
+    async fn query(
+        &self,
+        filter: &Self::Filter,
+    ) -> Result<Vec<DonationReceipt>> {
+        let receipts: Vec<DonationReceipt> = with_conn!(self, |conn: DB| {
+            let mut qry = QueryBuilder::<DB>::new(
+                r#"
+                SELECT
+                    id,
+                    member_id,
+                    year,
+                    sequence,
+                    name,
+                    CAST(amount AS DOUBLE PRECISION) AS amount,
+                    issued_at
+                FROM donation_receipts
+                WHERE TRUE
+                "#,
+            );
+            if let Some(id) = filter.id {
+                qry.push(" AND id = ").push_bind(i64::from(id));
+            }
+            if let Some(member_id) = filter.member_id {
+                qry.push(" AND member_id = ").push_bind(i64::from(member_id));
+            }
+            if let Some(year) = filter.year {
+                qry.push(" AND year = ").push_bind(year);
+            }
+            qry.push(" ORDER BY year, sequence");
+
+            qry.build_query_as().fetch_all(&mut *conn).await?
+        });
+        Ok(receipts)
+    }
+}
+
+#[async_trait]
+impl Retrieve<DonationReceipt> for Connection {
+    type Key = u32;
+
+    /// Get a single donation receipt
+    async fn retrieve(&self, id: Self::Key) -> Result<DonationReceipt> {
+        let filter = DonationReceiptFilter {
+            id: Some(id),
+            ..Default::default()
+        };
+        let receipt = self
+            .query(&filter)
+            .await?
+            .pop()
+            .ok_or(QueryError::NotFound)?;
+        Ok(receipt)
+    }
+}
+
+#[async_trait]
+impl Insert<DonationReceipt> for Connection {
+    /// Issue a donation receipt. The sequence number is
+    /// assigned as the next free number of the year; a
+    /// second receipt for the same member and year fails.
+    async fn insert(
+        &self,
+        receipt: DonationReceipt,
+    ) -> Result<DonationReceipt> {
+        let insert: Id<i64> = with_conn!(self, |conn: DB| {
+            let mut qry = QueryBuilder::<DB>::new(
+                r#"INSERT INTO donation_receipts (
+                    member_id,
+                    year,
+                    sequence,
+                    name,
+                    amount,
+                    issued_at
+                ) VALUES (
+                "#,
+            );
+            qry.push_bind(i64::from(receipt.member_id))
+                .push(", ")
+                .push_bind(receipt.year)
+                .push(", (SELECT COALESCE(MAX(sequence), 0) + 1 \
+                      FROM donation_receipts WHERE year = ")
+                .push_bind(receipt.year)
+                .push("), ");
+            qry.separated(", ")
+                .push_bind(&receipt.name)
+                .push_bind(receipt.amount)
+                .push_bind(receipt.issued_at);
+
+            qry.push(") RETURNING id ")
+                .build_query_as()
+                .fetch_all(&mut *conn)
+                .await?
+                .pop()
+                .ok_or(QueryError::NotFound)?
+        });
+        self.retrieve(insert.id.try_into()?).await
+    }
+}
+
+#[async_trait]
+impl Delete<DonationReceipt> for Connection {
+    /// Delete a receipt. Issued receipts must be kept for
+    /// the retention period.
+    async fn delete(&self, receipt: DonationReceipt) -> Result<()> {
+        with_conn!(self, |conn: DB| {
+            QueryBuilder::<DB>::new("DELETE FROM donation_receipts WHERE id = ")
+                .push_bind(i64::from(receipt.id))
+                .build()
+                .execute(&mut *conn)
+                .await?;
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::NaiveDate;
+
+    use eris_data::Member;
+
+    #[tokio::test]
+    async fn test_donation_receipt_sequence() {
+        let db = Connection::open_test().await;
+        let m1 = db.insert(Member {
+            name: "Eris".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        let m2 = db.insert(Member {
+            name: "Discordia".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+
+        let receipt = |member_id, year| DonationReceipt {
+            member_id,
+            year,
+            amount: 240.0,
+            issued_at: NaiveDate::from_ymd_opt(2024, 1, 15).unwrap(),
+            ..Default::default()
+        };
+        let r1 = db.insert(receipt(m1.id, 2023)).await.unwrap();
+        let r2 = db.insert(receipt(m2.id, 2023)).await.unwrap();
+        let r3 = db.insert(receipt(m1.id, 2024)).await.unwrap();
+        assert_eq!(r1.number(), "2023-0001");
+        assert_eq!(r2.number(), "2023-0002");
+        assert_eq!(r3.number(), "2024-0001");
+        assert_eq!(r2.amount, 240.0);
+
+        // A year is confirmed only once per member
+        assert!(db.insert(receipt(m1.id, 2023)).await.is_err());
+    }
+}
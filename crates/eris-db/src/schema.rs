@@ -1,14 +1,353 @@
-
 use anyhow::Result;
-use sqlx::Executor;
+use sqlx::{Connection as SqlConnection, Executor, QueryBuilder};
+
+use crate::connection::{with_conn, Connection, Pool};
+
+/// A change of the database schema. Each backend has its
+/// own script in `db/migrations/<backend>/`.
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    pub sqlite: &'static str,
+    pub postgres: &'static str,
+    /// The table, or column of a table, the migration creates.
+    /// Databases installed before the schema was versioned
+    /// may have it already.
+    pub creates: (&'static str, Option<&'static str>),
+}
+
+macro_rules! migration {
+    ($version:literal, $name:literal, $table:literal) => {
+        migration!($version, $name, ($table, None))
+    };
+    ($version:literal, $name:literal, $table:literal, $column:literal) => {
+        migration!($version, $name, ($table, Some($column)))
+    };
+    ($version:literal, $name:literal, $creates:expr) => {
+        Migration {
+            version: $version,
+            name: $name,
+            sqlite: include_str!(
+                concat!("../db/migrations/sqlite/", $name, ".sql")),
+            postgres: include_str!(
+                concat!("../db/migrations/postgres/", $name, ".sql")),
+            creates: $creates,
+        }
+    };
+}
+
+/// All migrations in the order they are applied
+pub const MIGRATIONS: &[Migration] = &[
+    migration!(1, "0001_baseline", "members"),
+    migration!(2, "0002_mandates", "mandates"),
+    migration!(3, "0003_payouts", "payouts"),
+    migration!(4, "0004_dunning_events", "dunning_events"),
+    migration!(5, "0005_notification_outbox", "notification_outbox"),
+    migration!(6, "0006_donation_receipts", "donation_receipts"),
+    migration!(7, "0007_audit_log", "audit_log"),
+    migration!(8, "0008_archived_members", "members", "archived_at"),
+    migration!(9, "0009_users", "users"),
+    migration!(10, "0010_transaction_kind", "transactions", "kind"),
+    migration!(11, "0011_settings", "settings"),
+    migration!(12, "0012_notification_claims", "notification_outbox", "claimed_at"),
+];
+
+/// The applied migrations
+const SQLITE_VERSIONS: &str = r#"
+    CREATE TABLE IF NOT EXISTS schema_migrations (
+        version           INTEGER           PRIMARY KEY,
+        name              VARCHAR(100)      NOT NULL,
+        applied_at        TEXT              NOT NULL -- DATETIME
+    )
+"#;
+
+const POSTGRES_VERSIONS: &str = r#"
+    CREATE TABLE IF NOT EXISTS schema_migrations (
+        version           BIGINT            PRIMARY KEY,
+        name              VARCHAR(100)      NOT NULL,
+        applied_at        TIMESTAMP         NOT NULL
+    )
+"#;
+
+/// Check if a table, or a column of the table, exists
+async fn exists(
+    conn: &Connection,
+    table: &str,
+    column: Option<&str>,
+) -> Result<bool> {
+    let sqlite = matches!(conn.pool(), Pool::Sqlite(_));
+    let (count,): (i64,) = with_conn!(conn, |c: DB| {
+        let mut qry = QueryBuilder::<DB>::new("SELECT COUNT(*) FROM ");
+        if sqlite {
+            qry.push("pragma_table_info(").push_bind(table).push(") WHERE TRUE");
+        } else {
+            qry.push(
+                r#"information_schema.columns
+                WHERE table_schema = current_schema()
+                  AND table_name = "#,
+            ).push_bind(table);
+        }
+        if let Some(column) = column {
+            qry.push(if sqlite { " AND name = " } else { " AND column_name = " })
+                .push_bind(column);
+        }
+        qry.build_query_as().fetch_one(&mut *c).await?
+    });
+    Ok(count > 0)
+}
+
+/// Get the versions of the applied migrations
+async fn applied_versions(conn: &Connection) -> Result<Vec<u32>> {
+    let versions: Vec<(i64,)> = with_conn!(conn, |c: DB| {
+        QueryBuilder::<DB>::new(
+            "SELECT version FROM schema_migrations ORDER BY version")
+            .build_query_as()
+            .fetch_all(&mut *c)
+            .await?
+    });
+    let versions = versions.into_iter()
+        .map(|(version,)| u32::try_from(version))
+        .collect::<Result<_, _>>()?;
+    Ok(versions)
+}
+
+/// Apply the missing migrations. Each migration runs in a
+/// transaction with recording its version, so a failed
+/// migration is applied again on the next open.
+///
+/// Databases installed before the schema was versioned
+/// have no versions recorded; migrations of tables and
+/// columns which exist are only recorded.
+///
+/// Returns the version of the schema.
+pub async fn migrate(conn: &Connection) -> Result<u32> {
+    let versioned = exists(conn, "schema_migrations", None).await?;
+    match conn.pool() {
+        Pool::Sqlite(pool) => {
+            pool.execute(SQLITE_VERSIONS).await?;
+        }
+        Pool::Postgres(pool) => {
+            pool.execute(POSTGRES_VERSIONS).await?;
+        }
+    }
+    let applied = applied_versions(conn).await?;
+
+    for migration in MIGRATIONS {
+        if applied.contains(&migration.version) {
+            continue;
+        }
+        let (table, column) = migration.creates;
+        let installed = !versioned && exists(conn, table, column).await?;
+        let script = match conn.pool() {
+            Pool::Sqlite(_) => migration.sqlite,
+            Pool::Postgres(_) => migration.postgres,
+        };
+        if !installed {
+            eprintln!("applying schema migration {}", migration.name);
+        }
+        with_conn!(conn, |c: DB| {
+            let mut tx = c.begin().await?;
+            if !installed {
+                tx.execute(script).await?;
+            }
+            let mut qry = QueryBuilder::<DB>::new(
+                "INSERT INTO schema_migrations (version, name, applied_at) VALUES (");
+            qry.separated(", ")
+                .push_bind(i64::from(migration.version))
+                .push_bind(migration.name)
+                .push_bind(chrono::Local::now().naive_local());
+            qry.push(")").build().execute(&mut *tx).await?;
+            tx.commit().await?;
+        });
+    }
+
+    Ok(MIGRATIONS.last().map_or(0, |m| m.version))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use super::*;
+
+    type Columns = BTreeMap<String, BTreeSet<String>>;
+
+    /// Get the tables and columns created by the scripts
+    fn script_columns(scripts: &[&str]) -> Columns {
+        let mut columns = Columns::new();
+        let mut table: Option<String> = None;
+        for line in scripts.iter().flat_map(|script| script.lines()) {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["CREATE", "TABLE", name, ..] | ["ALTER", "TABLE", name, ..] => {
+                    table = Some(name.to_string());
+                    columns.entry(name.to_string()).or_default();
+                }
+                [")", ..] | [");", ..] => table = None,
+                ["ADD", "COLUMN", column, ..] | [column, ..]
+                    if table.is_some()
+                        && !column.starts_with("--")
+                        && column.chars().all(|c| c.is_ascii_lowercase() || c == '_') =>
+                {
+                    columns.get_mut(table.as_ref().unwrap())
+                        .unwrap()
+                        .insert(column.to_string());
+                }
+                _ => {}
+            }
+        }
+        columns
+    }
+
+    /// Get the tables and columns of the database
+    async fn database_columns(db: &Connection) -> Columns {
+        let sqlite = matches!(db.pool(), Pool::Sqlite(_));
+        let rows: Vec<(String, String)> = async {
+            let rows = with_conn!(db, |c: DB| {
+                let qry = if sqlite {
+                    r#"SELECT m.name, p.name
+                       FROM sqlite_master m, pragma_table_info(m.name) p
+                       WHERE m.type = 'table'
+                         AND m.name NOT LIKE 'sqlite_%'"#
+                } else {
+                    r#"SELECT CAST(table_name AS TEXT), CAST(column_name AS TEXT)
+                       FROM information_schema.columns
+                       WHERE table_schema = current_schema()"#
+                };
+                QueryBuilder::<DB>::new(qry)
+                    .build_query_as()
+                    .fetch_all(&mut *c)
+                    .await?
+            });
+            anyhow::Ok(rows)
+        }.await.unwrap();
+        let mut columns = Columns::new();
+        for (table, column) in rows {
+            if table != "schema_migrations" {
+                columns.entry(table).or_default().insert(column);
+            }
+        }
+        columns
+    }
+
+    #[test]
+    fn test_migration_versions() {
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version as usize, i + 1);
+            assert!(migration.name.starts_with(
+                &format!("{:04}_", migration.version)));
+        }
+    }
+
+    #[test]
+    fn test_migration_parity() {
+        // Both backends have the same tables and columns
+        // after each migration.
+        for migration in MIGRATIONS {
+            assert_eq!(
+                script_columns(&[migration.sqlite]),
+                script_columns(&[migration.postgres]),
+                "migration {} differs", migration.name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_migrated_schema() {
+        let db = Connection::open_test().await;
+        let scripts: Vec<&str> = MIGRATIONS.iter()
+            .map(|m| match db.pool() {
+                Pool::Sqlite(_) => m.sqlite,
+                Pool::Postgres(_) => m.postgres,
+            })
+            .collect();
+        let expected = script_columns(&scripts);
+        assert!(expected["members"].contains("archived_at"));
+        assert_eq!(database_columns(&db).await, expected);
+    }
+
+    #[tokio::test]
+    async fn test_migrate_transaction_kind() {
+        use eris_data::{
+            BankImportRule,
+            Insert,
+            Member,
+            Query,
+            Transaction,
+            TransactionFilter,
+            TransactionKind,
+            MEMBERSHIP_FEE_ACCOUNT,
+        };
+
+        let db = Connection::open_test().await;
+        let member = db.insert(Member::default()).await.unwrap();
+        db.insert(BankImportRule {
+            member_id: member.id,
+            iban: "DE2342".to_string(),
+            split_amount: Some(10.0),
+            ..Default::default()
+        }).await.unwrap();
+        let txs = [
+            (MEMBERSHIP_FEE_ACCOUNT, "Monthly fee", -20.0),
+            ("", "Manual account balance update", 5.0),
+            ("Eris", "ERIS-PAYOUT-000001 refund: x", -5.0),
+            ("Eris", "Reimbursement of expenses: Mate", 5.0),
+            ("Eris", "Beitrag (split)", 10.0),
+            // Matches the split amount, but is a plain payment
+            ("Eris", "Beitrag", 10.0),
+            ("Eris", "Beitrag (overflow)", 2.0),
+            ("Eris", "Beitrag", 20.0),
+        ];
+        for (account_name, description, amount) in txs {
+            db.insert(Transaction {
+                member_id: member.id,
+                account_name: account_name.to_string(),
+                description: description.to_string(),
+                amount,
+                ..Default::default()
+            }).await.unwrap();
+        }
+
+        // Transactions booked before the kind was stored
+        async {
+            with_conn!(db, |c: DB| {
+                c.execute("ALTER TABLE transactions DROP COLUMN kind").await?;
+                c.execute("DELETE FROM schema_migrations WHERE version = 10").await?;
+            });
+            anyhow::Ok(())
+        }.await.unwrap();
+        migrate(&db).await.unwrap();
+
+        use TransactionKind::*;
+        let txs: Vec<Transaction> =
+            db.query(&TransactionFilter::default()).await.unwrap();
+        let kinds: Vec<TransactionKind> = txs.iter().map(|tx| tx.kind).collect();
+        assert_eq!(kinds, vec![
+            Fee, Manual, Refund, Payout, Split, Bank, Overflow, Bank,
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_migrate_unversioned() {
+        let db = Connection::open_test().await;
+
+        // A database installed before the schema was versioned,
+        // without the archived_at column.
+        async {
+            with_conn!(db, |c: DB| {
+                c.execute("DROP TABLE schema_migrations").await?;
+                c.execute("ALTER TABLE members DROP COLUMN archived_at").await?;
+            });
+            anyhow::Ok(())
+        }.await.unwrap();
+        assert!(!exists(&db, "members", Some("archived_at")).await.unwrap());
 
-use crate::Connection;
+        let version = migrate(&db).await.unwrap();
+        assert_eq!(version, MIGRATIONS.len() as u32);
+        assert!(exists(&db, "members", Some("archived_at")).await.unwrap());
+        let applied = applied_versions(&db).await.unwrap();
+        assert_eq!(applied.len(), MIGRATIONS.len());
 
-/// Install the database schema.
-pub async fn install(conn: &Connection) -> Result<()> {
-    let mut conn = conn.lock().await;
-    let schema_data = include_str!("../db/schema.sql");
-    println!("installing database schema");
-    (*conn).execute(schema_data).await?;
-    Ok(())
+        // Migrating again changes nothing
+        migrate(&db).await.unwrap();
+    }
 }
@@ -0,0 +1,105 @@
+use anyhow::Result;
+use sqlx::QueryBuilder;
+
+use anyhow::anyhow;
+
+use eris_data::{IbanHasher, IbanStorage};
+
+use crate::{connection::with_conn, Connection};
+
+/// How the IBANs of bank import rules are stored
+const IBAN_STORAGE: &str = "iban_storage";
+
+impl Connection {
+    /// Get a setting if it was set
+    pub async fn setting(&self, name: &str) -> Result<Option<String>> {
+        let value: Option<(String,)> = with_conn!(self, |conn: DB| {
+            QueryBuilder::<DB>::new("SELECT value FROM settings WHERE name = ")
+                .push_bind(name)
+                .build_query_as()
+                .fetch_optional(&mut *conn)
+                .await?
+        });
+        Ok(value.map(|(value,)| value))
+    }
+
+    /// Set or replace a setting
+    pub async fn set_setting(&self, name: &str, value: &str) -> Result<()> {
+        with_conn!(self, |conn: DB| {
+            let mut qry = QueryBuilder::<DB>::new(
+                "INSERT INTO settings (name, value) VALUES (");
+            qry.separated(", ")
+                .push_bind(name)
+                .push_bind(value);
+            qry.push(") ON CONFLICT (name) DO UPDATE SET value = excluded.value")
+                .build()
+                .execute(&mut *conn)
+                .await?;
+        });
+        Ok(())
+    }
+
+    /// Get how the IBANs of new rules are stored. IBANs
+    /// are stored in plain text until protected.
+    pub async fn iban_storage(&self) -> Result<IbanStorage> {
+        match self.setting(IBAN_STORAGE).await? {
+            Some(storage) => storage.parse(),
+            None => Ok(IbanStorage::default()),
+        }
+    }
+
+    pub async fn set_iban_storage(&self, storage: IbanStorage) -> Result<()> {
+        self.set_setting(IBAN_STORAGE, &storage.to_string()).await
+    }
+
+    /// Create the hasher for new rules. Without a storage
+    /// the one of the database is used; once IBANs are
+    /// protected, a different storage is rejected so rules
+    /// are not stored in mixed ways.
+    pub async fn iban_hasher(
+        &self,
+        storage: Option<IbanStorage>,
+        secret: Option<&str>,
+    ) -> Result<IbanHasher> {
+        let stored = self.iban_storage().await?;
+        let storage = match storage {
+            Some(storage) if stored != IbanStorage::Plain
+                && storage != stored => {
+                return Err(anyhow!(
+                    "IBANs are stored as {}, not {}", stored, storage));
+            }
+            Some(storage) => storage,
+            None => stored,
+        };
+        IbanHasher::new(storage, secret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_iban_storage() {
+        let db = Connection::open_test().await;
+        assert_eq!(db.iban_storage().await.unwrap(), IbanStorage::Plain);
+        db.set_iban_storage(IbanStorage::Hash).await.unwrap();
+        db.set_iban_storage(IbanStorage::Hmac).await.unwrap();
+        assert_eq!(db.iban_storage().await.unwrap(), IbanStorage::Hmac);
+        assert_eq!(db.setting("fnord").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_iban_hasher() {
+        let db = Connection::open_test().await;
+        let hasher = db.iban_hasher(Some(IbanStorage::Hash), None)
+            .await.unwrap();
+        assert_eq!(hasher.storage(), IbanStorage::Hash);
+
+        db.set_iban_storage(IbanStorage::Hash).await.unwrap();
+        let hasher = db.iban_hasher(None, None).await.unwrap();
+        assert_eq!(hasher.storage(), IbanStorage::Hash);
+        assert!(db.iban_hasher(Some(IbanStorage::Plain), None)
+            .await.is_err());
+    }
+}
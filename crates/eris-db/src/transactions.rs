@@ -1,56 +1,187 @@
 use anyhow::Result;
 use async_trait::async_trait;
-use sqlx::{QueryBuilder, Sqlite};
+use sqlx::{Connection as SqlConnection, QueryBuilder};
 
-use eris_data::{Delete, Insert, Query, Retrieve, Transaction, TransactionFilter};
+use eris_data::{
+    AlreadyCalculated,
+    AuditAction,
+    Book,
+    Booking,
+    Delete,
+    Insert,
+    Member,
+    MemberTransaction,
+    Query,
+    Retrieve,
+    Transaction,
+    TransactionFilter,
+    TransactionGroup,
+    TransactionTotal,
+    TransactionTotalFilter,
+};
 
 use crate::{
     results::{Id, QueryError},
+    connection::with_conn,
+    like,
     Connection,
 };
 
-#[async_trait]
-impl Query<Transaction> for Connection {
-    type Filter = TransactionFilter;
-
-    async fn query(&self, filter: &Self::Filter) -> Result<Vec<Transaction>> {
-        let mut conn = self.lock().await;
-        let mut qry = QueryBuilder::<Sqlite>::new(
-            r#"
-            SELECT 
-                id,
-                member_id,
-                date,
-                account_name,
-                ROUND(amount, 10) AS amount,
-                description
-            FROM transactions
-            WHERE 1
-            "#,
-        );
+/// Add the conditions of a transaction filter to a query
+/// ending in a `WHERE` clause. Columns are qualified, so
+/// the transactions can be joined.
+macro_rules! push_filter {
+    ($qry:ident, $filter:expr) => {
+        let filter: &TransactionFilter = $filter;
         if let Some(id) = filter.id {
-            qry.push(" AND id = ").push_bind(id);
+            $qry.push(" AND transactions.id = ").push_bind(i64::from(id));
         }
         if let Some(member_id) = filter.member_id {
-            qry.push(" AND member_id = ").push_bind(member_id);
+            $qry.push(" AND transactions.member_id = ")
+                .push_bind(i64::from(member_id));
+        }
+        if let Some(date) = filter.date {
+            $qry.push(" AND transactions.date = ").push_bind(date);
+        }
+        if let Some(date_before) = filter.date_before {
+            $qry.push(" AND transactions.date <= ").push_bind(date_before);
+        }
+        if let Some(date_after) = filter.date_after {
+            $qry.push(" AND transactions.date >= ").push_bind(date_after);
+        }
+        if let Some(amount_min) = filter.amount_min {
+            $qry.push(" AND transactions.amount >= ").push_bind(amount_min);
+        }
+        if let Some(amount_max) = filter.amount_max {
+            $qry.push(" AND transactions.amount <= ").push_bind(amount_max);
         }
-        if let Some(date) = filter.date.clone() {
-            qry.push(" AND date = ").push_bind(date);
+        if let Some(account_name) = &filter.account_name {
+            $qry.push(" AND LOWER(transactions.account_name) LIKE LOWER(")
+                .push_bind(account_name)
+                .push(")");
         }
-        if let Some(date_before) = filter.date_before.clone() {
-            qry.push(" AND date <= ").push_bind(date_before);
+        if let Some(description) = &filter.description {
+            $qry.push(" AND LOWER(transactions.description) LIKE LOWER(")
+                .push_bind(like::contains(description))
+                .push(")")
+                .push(like::ESCAPE);
         }
-        if let Some(date_after) = filter.date_after.clone() {
-            qry.push(" AND date >= ").push_bind(date_after);
+        if let Some(kind) = filter.kind {
+            $qry.push(" AND transactions.kind = ").push_bind(kind);
         }
+    };
+}
 
-        let transactions: Vec<Transaction> = qry.build_query_as()
-            .fetch_all(&mut *conn)
-            .await?;
+#[async_trait]
+impl Query<Transaction> for Connection {
+    type Filter = TransactionFilter;
+
+    async fn query(&self, filter: &Self::Filter) -> Result<Vec<Transaction>> {
+        let transactions: Vec<Transaction> = with_conn!(self, |conn: DB| {
+            let mut qry = QueryBuilder::<DB>::new(
+                r#"
+                SELECT 
+                    id,
+                    member_id,
+                    date,
+                    account_name,
+                    CAST(ROUND(CAST(amount AS NUMERIC), 10) AS DOUBLE PRECISION) AS amount,
+                    description,
+                    kind
+                FROM transactions
+                WHERE TRUE
+                "#,
+            );
+            push_filter!(qry, filter);
+            qry.push(" ORDER BY id");
+
+            qry.build_query_as().fetch_all(&mut *conn).await?
+        });
         Ok(transactions)
     }
 }
 
+#[async_trait]
+impl Query<MemberTransaction> for Connection {
+    type Filter = TransactionFilter;
+
+    /// List transactions joined with the name of the member
+    async fn query(
+        &self,
+        filter: &Self::Filter,
+    ) -> Result<Vec<MemberTransaction>> {
+        let transactions: Vec<MemberTransaction> = with_conn!(self, |conn: DB| {
+            let mut qry = QueryBuilder::<DB>::new(
+                r#"
+                SELECT 
+                    transactions.id,
+                    transactions.member_id,
+                    members.name AS member_name,
+                    transactions.date,
+                    transactions.account_name,
+                    CAST(ROUND(CAST(transactions.amount AS NUMERIC), 10) AS DOUBLE PRECISION) AS amount,
+                    transactions.description,
+                    transactions.kind
+                FROM transactions
+                JOIN members ON members.id = transactions.member_id
+                WHERE TRUE
+                "#,
+            );
+            push_filter!(qry, filter);
+            qry.push(" ORDER BY transactions.id");
+
+            qry.build_query_as().fetch_all(&mut *conn).await?
+        });
+        Ok(transactions)
+    }
+}
+
+#[async_trait]
+impl Query<TransactionTotal> for Connection {
+    type Filter = TransactionTotalFilter;
+
+    /// Sum up the transactions per group. Members are
+    /// ordered by id, months and kinds by their label.
+    async fn query(
+        &self,
+        filter: &Self::Filter,
+    ) -> Result<Vec<TransactionTotal>> {
+        // The label and the expression the groups are ordered by
+        let (label, group) = match filter.group {
+            TransactionGroup::Month => {
+                let month = "SUBSTR(CAST(transactions.date AS TEXT), 1, 7)";
+                (month.to_string(), month.to_string())
+            }
+            TransactionGroup::Member => (
+                "CAST(transactions.member_id AS TEXT)".to_string(),
+                "transactions.member_id".to_string(),
+            ),
+            TransactionGroup::Kind => (
+                "transactions.kind".to_string(),
+                "transactions.kind".to_string(),
+            ),
+        };
+        let totals: Vec<TransactionTotal> = with_conn!(self, |conn: DB| {
+            let mut qry = QueryBuilder::<DB>::new(format!(
+                r#"
+                SELECT 
+                    {} AS label,
+                    COUNT(*) AS count,
+                    CAST(SUM(transactions.amount) AS DOUBLE PRECISION) AS sum
+                FROM transactions
+                WHERE TRUE
+                "#,
+                label,
+            ));
+            push_filter!(qry, &filter.transactions);
+            qry.push(format!(" GROUP BY {} ORDER BY {}", group, group));
+
+            qry.build_query_as().fetch_all(&mut *conn).await?
+        });
+        Ok(totals)
+    }
+}
+
 #[async_trait]
 impl Retrieve<Transaction> for Connection {
     type Key = u32;
@@ -63,7 +194,7 @@ impl Retrieve<Transaction> for Connection {
             .query(&filter)
             .await?
             .pop()
-            .ok_or_else(|| QueryError::NotFound)?;
+            .ok_or(QueryError::NotFound)?;
         Ok(transaction)
     }
 }
@@ -71,31 +202,117 @@ impl Retrieve<Transaction> for Connection {
 #[async_trait]
 impl Insert<Transaction> for Connection {
     async fn insert(&self, transaction: Transaction) -> Result<Transaction> {
-        let insert: Id<u32> = {
-            let mut conn = self.lock().await;
-            let mut qry = QueryBuilder::<Sqlite>::new(
+        let insert: Id<i64> = with_conn!(self, |conn: DB| {
+            let mut qry = QueryBuilder::<DB>::new(
                 r#"INSERT INTO transactions (
                     member_id,
                     date,
                     account_name,
                     amount,
-                    description
+                    description,
+                    kind
                 ) VALUES (
                 "#,
             );
             qry.separated(", ")
-                .push_bind(transaction.member_id)
+                .push_bind(i64::from(transaction.member_id))
                 .push_bind(transaction.date)
                 .push_bind(&transaction.account_name)
                 .push_bind(transaction.amount)
-                .push_bind(&transaction.description);
+                .push_bind(&transaction.description)
+                .push_bind(transaction.kind);
 
             qry.push(") RETURNING id ")
                 .build_query_as()
-                .fetch_one(&mut *conn)
+                .fetch_all(&mut *conn)
                 .await?
-        };
-        self.retrieve(insert.id).await
+                .pop()
+                .ok_or(QueryError::NotFound)?
+        });
+        let transaction = self.retrieve(insert.id.try_into()?).await?;
+        self.audit(AuditAction::Insert, None, Some(&transaction)).await?;
+        Ok(transaction)
+    }
+}
+
+#[async_trait]
+impl Book for Connection {
+    /// Book the transactions in a single database transaction.
+    /// The amounts are added to the stored balance, which may
+    /// have changed since the member was read.
+    async fn book(&self, booking: Booking) -> Result<(Member, Vec<Transaction>)> {
+        let old: Member = self.retrieve(booking.member_id).await?;
+        let sum: f64 = booking.transactions.iter().map(|tx| tx.amount).sum();
+        let ids: Vec<Id<i64>> = with_conn!(self, |conn: DB| {
+            let mut tx = conn.begin().await?;
+
+            let mut qry = QueryBuilder::<DB>::new("UPDATE members SET account = account + ");
+            qry.push_bind(sum);
+            if let Some(date) = booking.account_calculated_at {
+                qry.push(", account_calculated_at = ").push_bind(date);
+            }
+            if let Some((date, number)) = booking.last_bank_transaction {
+                qry.push(", last_bank_transaction_at = ").push_bind(date)
+                    .push(", last_bank_transaction_number = ")
+                    .push_bind(i64::from(number));
+            }
+            qry.push(" WHERE id = ").push_bind(i64::from(booking.member_id));
+            if let Some(date) = booking.account_calculated_at {
+                qry.push(" AND account_calculated_at < ").push_bind(date);
+            }
+            let updated = qry.build().execute(&mut tx).await?.rows_affected();
+            if updated == 0 {
+                let (date,): (chrono::NaiveDate,) = QueryBuilder::<DB>::new(
+                    "SELECT account_calculated_at FROM members WHERE id = ")
+                    .push_bind(i64::from(booking.member_id))
+                    .build_query_as()
+                    .fetch_one(&mut tx)
+                    .await?;
+                return Err(AlreadyCalculated {
+                    member_id: booking.member_id,
+                    date,
+                }.into());
+            }
+
+            let mut ids = vec![];
+            for transaction in &booking.transactions {
+                let mut qry = QueryBuilder::<DB>::new(
+                    r#"INSERT INTO transactions (
+                        member_id,
+                        date,
+                        account_name,
+                        amount,
+                        description,
+                        kind
+                    ) VALUES (
+                    "#,
+                );
+                qry.separated(", ")
+                    .push_bind(i64::from(booking.member_id))
+                    .push_bind(transaction.date)
+                    .push_bind(&transaction.account_name)
+                    .push_bind(transaction.amount)
+                    .push_bind(&transaction.description)
+                    .push_bind(transaction.kind);
+                let id: Id<i64> = qry.push(") RETURNING id ")
+                    .build_query_as()
+                    .fetch_one(&mut tx)
+                    .await?;
+                ids.push(id);
+            }
+            tx.commit().await?;
+            ids
+        });
+
+        let mut booked = vec![];
+        for id in ids {
+            let transaction: Transaction = self.retrieve(id.id.try_into()?).await?;
+            self.audit(AuditAction::Insert, None, Some(&transaction)).await?;
+            booked.push(transaction);
+        }
+        let member: Member = self.retrieve(booking.member_id).await?;
+        self.audit(AuditAction::Update, Some(&old), Some(&member)).await?;
+        Ok((member, booked))
     }
 }
 
@@ -103,11 +320,17 @@ impl Insert<Transaction> for Connection {
 impl Delete<Transaction> for Connection {
     /// Delete a transaction
     async fn delete(&self, tx: Transaction) -> Result<()> {
-        let mut conn = self.lock().await;
-        QueryBuilder::<Sqlite>::new("DELETE FROM transactions WHERE id = ")
-           .push_bind(tx.id)
-           .build()
-           .execute(&mut *conn).await?;
+        let old: Option<Transaction> = self.query(&TransactionFilter {
+            id: Some(tx.id),
+            ..Default::default()
+        }).await?.pop();
+        with_conn!(self, |conn: DB| {
+            QueryBuilder::<DB>::new("DELETE FROM transactions WHERE id = ")
+               .push_bind(i64::from(tx.id))
+               .build()
+               .execute(&mut *conn).await?;
+        });
+        self.audit(AuditAction::Delete, old.as_ref(), None).await?;
         Ok(())
     }
 
@@ -119,7 +342,7 @@ mod tests {
 
     use chrono::NaiveDate;
 
-    use eris_data::Member;
+    use eris_data::{TransactionKind, MEMBERSHIP_FEE_ACCOUNT};
 
     #[tokio::test]
     async fn test_transaction_insert() {
@@ -138,7 +361,7 @@ mod tests {
         // Create transaction for member
         let tx = Transaction {
             member_id: m.id,
-            date: date,
+            date,
             account_name: "Testmember AccountName".to_string(),
             amount: 23.0,
             description: "Mitgliedsbeitrag".to_string(),
@@ -219,4 +442,208 @@ mod tests {
         assert_eq!(txs.len(), 1);
     }
 
+    /// Insert two members with transactions of each kind
+    async fn insert_transactions(db: &Connection) -> (Member, Member) {
+        let m1 = db.insert(Member {
+            name: "Eris".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        let m2 = db.insert(Member {
+            name: "Discordia".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        let date = |m, d| NaiveDate::from_ymd_opt(2023, m, d).unwrap();
+        let txs = [
+            (m1.id, date(1, 1), MEMBERSHIP_FEE_ACCOUNT, -23.0, "Monthly fee", TransactionKind::Fee),
+            (m1.id, date(1, 5), "Eris Discordia", 23.0, "Mitgliedsbeitrag", TransactionKind::Bank),
+            (m2.id, date(1, 31), MEMBERSHIP_FEE_ACCOUNT, -42.0, "Monthly fee", TransactionKind::Fee),
+            (m2.id, date(2, 3), "Eris Discordia", 40.0, "Beitrag", TransactionKind::Split),
+            (m2.id, date(2, 3), "Eris Discordia", 2.0, "Beitrag (overflow)", TransactionKind::Overflow),
+            (m1.id, date(2, 10), "", 5.0, "Manual account balance update", TransactionKind::Manual),
+            (m1.id, date(3, 1), "Eris", -5.0, "ERIS-PAYOUT-000001 refund", TransactionKind::Refund),
+            (m2.id, date(3, 2), "Eris", 1.0, "eris-payout-000002", TransactionKind::Bank),
+            (m2.id, date(3, 3), "Discordia", 0.5, "Reimbursement of expenses: Mate", TransactionKind::Payout),
+        ];
+        for (member_id, date, account_name, amount, description, kind) in txs {
+            db.insert(Transaction {
+                member_id,
+                date,
+                account_name: account_name.to_string(),
+                amount,
+                description: description.to_string(),
+                kind,
+                ..Default::default()
+            }).await.unwrap();
+        }
+        (m1, m2)
+    }
+
+    #[tokio::test]
+    async fn test_transaction_query_filters() {
+        let db = Connection::open_test().await;
+        insert_transactions(&db).await;
+
+        let ids = |filter: TransactionFilter| {
+            let db = &db;
+            async move {
+                let txs: Vec<Transaction> = db.query(&filter).await.unwrap();
+                txs.iter().map(|tx| tx.id).collect::<Vec<u32>>()
+            }
+        };
+        assert_eq!(ids(TransactionFilter {
+            amount_min: Some(2.0),
+            amount_max: Some(23.0),
+            ..Default::default()
+        }).await, vec![2, 5, 6]);
+        assert_eq!(ids(TransactionFilter {
+            account_name: Some("eris%".to_string()),
+            ..Default::default()
+        }).await, vec![2, 4, 5, 7, 8]);
+        assert_eq!(ids(TransactionFilter {
+            description: Some("BEITRAG".to_string()),
+            ..Default::default()
+        }).await, vec![2, 4, 5]);
+
+        // Wildcards in the description are matched literally
+        assert_eq!(ids(TransactionFilter {
+            description: Some("eris_payout".to_string()),
+            ..Default::default()
+        }).await, Vec::<u32>::new());
+        assert_eq!(ids(TransactionFilter {
+            description: Some("%".to_string()),
+            ..Default::default()
+        }).await, Vec::<u32>::new());
+
+        // Transactions are filtered by their stored kind
+        let all: Vec<Transaction> =
+            db.query(&TransactionFilter::default()).await.unwrap();
+        for kind in [
+            TransactionKind::Fee,
+            TransactionKind::Bank,
+            TransactionKind::Split,
+            TransactionKind::Overflow,
+            TransactionKind::Payout,
+            TransactionKind::Refund,
+            TransactionKind::Manual,
+        ] {
+            let expected: Vec<u32> = all.iter()
+                .filter(|tx| tx.kind == kind)
+                .map(|tx| tx.id)
+                .collect();
+            assert!(!expected.is_empty());
+            assert_eq!(ids(TransactionFilter {
+                kind: Some(kind),
+                ..Default::default()
+            }).await, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_book() {
+        let db = Connection::open_test().await;
+        let member = db.insert(Member {
+            account: 10.0,
+            ..Default::default()
+        }).await.unwrap();
+        let date = NaiveDate::from_ymd_opt(2023, 3, 1).unwrap();
+        let booking = Booking {
+            member_id: member.id,
+            transactions: vec![Transaction {
+                amount: -23.0,
+                kind: TransactionKind::Fee,
+                ..Default::default()
+            }; 2],
+            account_calculated_at: Some(date),
+            last_bank_transaction: None,
+        };
+
+        // The balance is changed by the stored amount
+        let (member, booked) = db.book(booking.clone()).await.unwrap();
+        assert_eq!(member.account, -36.0);
+        assert_eq!(member.account_calculated_at, date);
+        assert_eq!(booked.len(), 2);
+        assert!(booked.iter().all(|tx| tx.member_id == member.id));
+
+        // Fees are not booked twice
+        let err = db.book(booking).await.unwrap_err();
+        assert!(err.is::<AlreadyCalculated>());
+        let txs: Vec<Transaction> = db.query(&TransactionFilter {
+            member_id: Some(member.id),
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(txs.len(), 2);
+        let member: Member = db.retrieve(member.id).await.unwrap();
+        assert_eq!(member.account, -36.0);
+
+        // Imports record the last bank transaction
+        let (member, _) = db.book(Booking {
+            member_id: member.id,
+            transactions: vec![Transaction {
+                date,
+                amount: 46.0,
+                kind: TransactionKind::Bank,
+                ..Default::default()
+            }],
+            last_bank_transaction: Some((date, 23)),
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(member.account, 10.0);
+        assert_eq!(member.last_bank_transaction_at, date);
+        assert_eq!(member.last_bank_transaction_number, 23);
+        assert_eq!(member.account_calculated_at, date);
+    }
+
+    #[tokio::test]
+    async fn test_member_transactions() {
+        let db = Connection::open_test().await;
+        let (_, m2) = insert_transactions(&db).await;
+
+        let txs: Vec<MemberTransaction> = db.query(&TransactionFilter {
+            member_id: Some(m2.id),
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(txs.len(), 5);
+        assert!(txs.iter().all(|tx| tx.member_name == "Discordia"));
+        assert_eq!(txs[1].kind, TransactionKind::Split);
+        assert_eq!(txs[1].amount, 40.0);
+    }
+
+    #[tokio::test]
+    async fn test_transaction_totals() {
+        let db = Connection::open_test().await;
+        let (m1, m2) = insert_transactions(&db).await;
+
+        let totals = |group, transactions| {
+            let db = &db;
+            async move {
+                let totals: Vec<TransactionTotal> = db.query(&TransactionTotalFilter {
+                    group,
+                    transactions,
+                }).await.unwrap();
+                totals.into_iter()
+                    .map(|t| (t.label, t.count, t.sum))
+                    .collect::<Vec<(String, u32, f64)>>()
+            }
+        };
+        let total = |label: &str, count, sum| (label.to_string(), count, sum);
+
+        assert_eq!(totals(TransactionGroup::Month, Default::default()).await, vec![
+            total("2023-01", 3, -42.0),
+            total("2023-02", 3, 47.0),
+            total("2023-03", 3, -3.5),
+        ]);
+        assert_eq!(totals(TransactionGroup::Member, Default::default()).await, vec![
+            total(&m1.id.to_string(), 4, 0.0),
+            total(&m2.id.to_string(), 5, 1.5),
+        ]);
+        assert_eq!(totals(TransactionGroup::Kind, TransactionFilter {
+            member_id: Some(m1.id),
+            ..Default::default()
+        }).await, vec![
+            total("bank", 1, 23.0),
+            total("fee", 1, -23.0),
+            total("manual", 1, 5.0),
+            total("refund", 1, -5.0),
+        ]);
+    }
 }
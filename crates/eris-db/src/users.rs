@@ -0,0 +1,264 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use sqlx::QueryBuilder;
+
+use eris_data::{
+    AuditAction,
+    Delete,
+    Insert,
+    Query,
+    Retrieve,
+    Update,
+    User,
+    UserFilter,
+};
+
+use crate::{
+    connection::with_conn,
+    results::{Id, QueryError},
+    Connection,
+};
+
+#[async_trait]
+impl Query<User> for Connection {
+    type Filter = UserFilter;
+
+    /// Fetch users ordered by name
+    async fn query(&self, filter: &Self::Filter) -> Result<Vec<User>> {
+        let users: Vec<User> = with_conn!(self, |conn: DB| {
+            let mut qry = QueryBuilder::<DB>::new(
+                r#"
+                SELECT
+                    id,
+                    name,
+                    role,
+                    member_id,
+                    created_at
+                FROM users
+                WHERE TRUE
+                "#,
+            );
+            if let Some(id) = filter.id {
+                qry.push(" AND id = ").push_bind(i64::from(id));
+            }
+            if let Some(name) = &filter.name {
+                qry.push(" AND name = ").push_bind(name);
+            }
+            if let Some(role) = filter.role {
+                qry.push(" AND role = ").push_bind(role);
+            }
+            if let Some(member_id) = filter.member_id {
+                qry.push(" AND member_id = ").push_bind(i64::from(member_id));
+            }
+            qry.push(" ORDER BY name");
+
+            qry.build_query_as().fetch_all(&mut *conn).await?
+        });
+        Ok(users)
+    }
+}
+
+#[async_trait]
+impl Retrieve<User> for Connection {
+    type Key = String;
+
+    /// Get a user by name
+    async fn retrieve(&self, name: Self::Key) -> Result<User> {
+        let filter = UserFilter {
+            name: Some(name),
+            ..Default::default()
+        };
+        let user = self
+            .query(&filter)
+            .await?
+            .pop()
+            .ok_or(QueryError::NotFound)?;
+        Ok(user)
+    }
+}
+
+#[async_trait]
+impl Insert<User> for Connection {
+    /// Create a user. Names are unique.
+    async fn insert(&self, user: User) -> Result<User> {
+        user.validate()?;
+        with_conn!(self, |conn: DB| {
+            let mut qry = QueryBuilder::<DB>::new(
+                r#"INSERT INTO users (
+                    name,
+                    role,
+                    member_id,
+                    created_at
+                ) VALUES (
+                "#,
+            );
+            qry.separated(", ")
+                .push_bind(&user.name)
+                .push_bind(user.role)
+                .push_bind(user.member_id.map(i64::from))
+                .push_bind(chrono::Local::now().naive_local());
+            let _: Id<i64> = qry.push(") RETURNING id ")
+                .build_query_as()
+                .fetch_all(&mut *conn)
+                .await?
+                .pop()
+                .ok_or(QueryError::NotFound)?;
+        });
+        let user = self.retrieve(user.name).await?;
+        self.audit(AuditAction::Insert, None, Some(&user)).await?;
+        Ok(user)
+    }
+}
+
+#[async_trait]
+impl Update<User> for Connection {
+    /// Change the role or member of a user. The name
+    /// can not be changed.
+    async fn update(&self, user: User) -> Result<User> {
+        user.validate()?;
+        let old: User = self.retrieve(user.name.clone()).await?;
+        with_conn!(self, |conn: DB| {
+            QueryBuilder::<DB>::new("UPDATE users SET")
+                .push(" role = ")
+                .push_bind(user.role)
+                .push(", member_id = ")
+                .push_bind(user.member_id.map(i64::from))
+                .push(" WHERE id = ")
+                .push_bind(i64::from(old.id))
+                .build()
+                .execute(&mut *conn)
+                .await?;
+        });
+        let user = self.retrieve(user.name).await?;
+        self.audit(AuditAction::Update, Some(&old), Some(&user)).await?;
+        Ok(user)
+    }
+}
+
+#[async_trait]
+impl Delete<User> for Connection {
+    /// Delete a user
+    async fn delete(&self, user: User) -> Result<()> {
+        let old: User = self.retrieve(user.name).await?;
+        with_conn!(self, |conn: DB| {
+            QueryBuilder::<DB>::new("DELETE FROM users WHERE id = ")
+                .push_bind(i64::from(old.id))
+                .build()
+                .execute(&mut *conn)
+                .await?;
+        });
+        self.audit(AuditAction::Delete, Some(&old), None).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    use eris_data::{
+        Authorized,
+        Member,
+        MemberFilter,
+        PermissionDenied,
+        Principal,
+        Role,
+        Transaction,
+        TransactionFilter,
+    };
+
+    #[tokio::test]
+    async fn test_users() {
+        let db = Connection::open_test().await;
+        let user = db.insert(User {
+            name: "kallisti".to_string(),
+            role: Role::Board,
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(user.role, Role::Board);
+        // Names are unique
+        assert!(db.insert(user.clone()).await.is_err());
+        // Members must be linked to their record
+        assert!(db.update(User {
+            role: Role::Member,
+            ..user.clone()
+        }).await.is_err());
+
+        let user = db.update(User {
+            role: Role::Auditor,
+            ..user
+        }).await.unwrap();
+        assert_eq!(user.role, Role::Auditor);
+        let users: Vec<User> = db.query(&UserFilter {
+            role: Some(Role::Auditor),
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(users.len(), 1);
+
+        db.delete(user).await.unwrap();
+        let users: Vec<User> = db.query(&UserFilter::default()).await.unwrap();
+        assert!(users.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_authorized() {
+        let db = Arc::new(Connection::open_test().await);
+        let eris = db.insert(Member {
+            name: "Eris".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        let other = db.insert(Member {
+            name: "Discordia".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        for member in [&eris, &other] {
+            db.insert(Transaction {
+                member_id: member.id,
+                amount: 23.0,
+                ..Default::default()
+            }).await.unwrap();
+        }
+
+        let is_denied = |result: Result<()>| match result {
+            Err(err) => err.is::<PermissionDenied>(),
+            Ok(_) => false,
+        };
+
+        // Members only see their own records
+        let member = Authorized::new(db.clone(), Principal::member(eris.id));
+        let members: Vec<Member> = member.query(&MemberFilter::default())
+            .await.unwrap();
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].id, eris.id);
+        let transactions: Vec<Transaction> = member
+            .query(&TransactionFilter::default()).await.unwrap();
+        assert_eq!(transactions.len(), 1);
+        let retrieved: Result<Member> = member.retrieve(other.id).await;
+        assert!(is_denied(retrieved.map(|_| ())));
+        assert!(is_denied(member.update(eris.clone()).await.map(|_| ())));
+        let users: Result<Vec<User>> = member.query(&UserFilter::default()).await;
+        assert!(is_denied(users.map(|_| ())));
+
+        // The board reads everything, but changes nothing
+        let board = Authorized::new(db.clone(), Principal {
+            role: Role::Board,
+            member_id: None,
+        });
+        let members: Vec<Member> = board.query(&MemberFilter::default())
+            .await.unwrap();
+        assert_eq!(members.len(), 2);
+        assert!(is_denied(board.update(eris.clone()).await.map(|_| ())));
+
+        let treasurer = Authorized::new(db.clone(), Principal {
+            role: Role::Treasurer,
+            member_id: None,
+        });
+        let eris = treasurer.update(Member {
+            notes: "Goddess of chaos".to_string(),
+            ..eris
+        }).await.unwrap();
+        assert_eq!(eris.notes, "Goddess of chaos");
+    }
+}
@@ -0,0 +1,6 @@
+pub mod mailer;
+pub mod outbox;
+pub mod templates;
+
+pub use mailer::{Mailer, SmtpConfig, TlsMode};
+pub use templates::Templates;
@@ -0,0 +1,223 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use lettre::{
+    message::Mailbox,
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport,
+    AsyncTransport,
+    Message,
+    Tokio1Executor,
+};
+
+use eris_data::Notification;
+
+/// How to secure the connection to the SMTP server
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TlsMode {
+    /// Plain connection, only for local servers
+    None,
+    /// Upgrade the connection with STARTTLS
+    #[default]
+    StartTls,
+    /// Connect with TLS
+    Tls,
+}
+
+impl fmt::Display for TlsMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TlsMode::None => write!(f, "none"),
+            TlsMode::StartTls => write!(f, "starttls"),
+            TlsMode::Tls => write!(f, "tls"),
+        }
+    }
+}
+
+impl FromStr for TlsMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "none" => Ok(TlsMode::None),
+            "starttls" => Ok(TlsMode::StartTls),
+            "tls" => Ok(TlsMode::Tls),
+            _ => Err(anyhow!("unknown tls mode: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub tls: TlsMode,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+}
+
+/// Send notifications via SMTP
+pub struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl Mailer {
+    /// Create a new mailer from the SMTP configuration
+    pub fn new(config: &SmtpConfig) -> Result<Self> {
+        let builder = match config.tls {
+            TlsMode::None => AsyncSmtpTransport::<Tokio1Executor>
+                ::builder_dangerous(&config.host),
+            TlsMode::StartTls => AsyncSmtpTransport::<Tokio1Executor>
+                ::starttls_relay(&config.host)?,
+            TlsMode::Tls => AsyncSmtpTransport::<Tokio1Executor>
+                ::relay(&config.host)?,
+        };
+        let mut builder = builder.port(config.port);
+        if let Some(username) = config.username.clone() {
+            let password = config.password.clone().unwrap_or_default();
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+        Ok(Self {
+            transport: builder.build(),
+            from: config.from.parse()?,
+        })
+    }
+
+    /// Send a single notification
+    pub async fn send(&self, notification: &Notification) -> Result<()> {
+        self.send_message(
+            &notification.recipient,
+            &notification.subject,
+            notification.body.clone(),
+        ).await
+    }
+
+    /// Send a plain text message, which is not kept in
+    /// the outbox.
+    pub async fn send_message(
+        &self,
+        recipient: &str,
+        subject: &str,
+        body: String,
+    ) -> Result<()> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(recipient.parse()?)
+            .subject(subject)
+            .body(body)?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+}
+
+/// A minimal SMTP server accepting all mail, used in tests
+/// in place of a real mail server.
+#[cfg(any(test, feature = "testing"))]
+pub mod testing {
+    use std::sync::Arc;
+
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+        sync::Mutex,
+    };
+
+    use super::*;
+
+    /// Messages received by the server, as raw DATA.
+    pub type Inbox = Arc<Mutex<Vec<String>>>;
+
+    /// Start the server on a random port. Recipients
+    /// containing `reject` are refused.
+    pub async fn smtp_server() -> (SmtpConfig, Inbox) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let inbox: Inbox = Arc::new(Mutex::new(vec![]));
+        let received = inbox.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let received = received.clone();
+                tokio::spawn(async move {
+                    let (read, mut write) = stream.into_split();
+                    let mut lines = BufReader::new(read).lines();
+                    write.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+                    let mut data: Option<String> = None;
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        if let Some(msg) = data.as_mut() {
+                            if line == "." {
+                                received.lock().await.push(msg.clone());
+                                data = None;
+                                write.write_all(b"250 OK\r\n").await.unwrap();
+                            } else {
+                                msg.push_str(&line);
+                                msg.push('\n');
+                            }
+                            continue;
+                        }
+                        let reply: &[u8] = match line.get(..4) {
+                            Some("EHLO") | Some("HELO") => b"250 localhost\r\n",
+                            Some("RCPT") if line.contains("reject") => {
+                                b"550 No such user\r\n"
+                            }
+                            Some("DATA") => {
+                                data = Some(String::new());
+                                b"354 Go ahead\r\n"
+                            }
+                            Some("QUIT") => {
+                                write.write_all(b"221 Bye\r\n").await.unwrap();
+                                break;
+                            }
+                            _ => b"250 OK\r\n",
+                        };
+                        write.write_all(reply).await.unwrap();
+                    }
+                });
+            }
+        });
+        let config = SmtpConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            tls: TlsMode::None,
+            from: "Treasurer <treasurer@eris.discordia>".to_string(),
+            ..Default::default()
+        };
+        (config, inbox)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{testing::smtp_server, *};
+
+    #[tokio::test]
+    async fn test_mailer_send() {
+        let (config, inbox) = smtp_server().await;
+        let mailer = Mailer::new(&config).unwrap();
+        let notification = Notification {
+            recipient: "Eris <eris@discordia.ccc>".to_string(),
+            subject: "Hail Eris".to_string(),
+            body: "All hail discordia".to_string(),
+            ..Default::default()
+        };
+        mailer.send(&notification).await.unwrap();
+
+        let inbox = inbox.lock().await;
+        assert_eq!(inbox.len(), 1);
+        assert!(inbox[0].contains("Subject: Hail Eris"));
+        assert!(inbox[0].contains("All hail discordia"));
+    }
+
+    #[tokio::test]
+    async fn test_mailer_send_rejected() {
+        let (config, _) = smtp_server().await;
+        let mailer = Mailer::new(&config).unwrap();
+        let notification = Notification {
+            recipient: "reject@discordia.ccc".to_string(),
+            ..Default::default()
+        };
+        assert!(mailer.send(&notification).await.is_err());
+    }
+}
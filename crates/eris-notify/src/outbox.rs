@@ -0,0 +1,287 @@
+use anyhow::{anyhow, Result};
+use chrono::{Duration, NaiveDate};
+
+use eris_data::{
+    ClaimNotification,
+    Insert,
+    Member,
+    Notification,
+    NotificationEvent,
+    NotificationFilter,
+    Query,
+    Transaction,
+    TransactionFilter,
+    Update,
+};
+
+use crate::{
+    mailer::Mailer,
+    templates::{TemplateContext, Templates},
+};
+
+/// Notifications are retried this many times by default
+pub const MAX_ATTEMPTS: u32 = 5;
+
+/// A delivery which did not finish sending a notification
+/// within this time is assumed to have failed.
+const CLAIM_TIMEOUT_MINUTES: i64 = 60;
+
+/// Get the key identifying a notification: A member is
+/// welcomed once, reminded once per month and notified
+/// once per date their fee is changed at.
+pub fn dedup_key(
+    event: NotificationEvent,
+    member: &Member,
+    date: NaiveDate,
+) -> String {
+    match event {
+        NotificationEvent::Welcome => format!("welcome:{}", member.id),
+        NotificationEvent::BalanceReminder => format!(
+            "balance_reminder:{}:{}", member.id, date.format("%Y-%m")),
+        NotificationEvent::FeeChange => format!(
+            "fee_change:{}:{}", member.id, date),
+    }
+}
+
+/// Render a notification for a member and put it into the
+/// outbox. Returns None if the notification was queued before.
+pub async fn queue<DB>(
+    db: &DB,
+    templates: &Templates,
+    event: NotificationEvent,
+    member: &Member,
+    date: NaiveDate,
+) -> Result<Option<Notification>>
+where
+    DB: Query<Notification, Filter = NotificationFilter>
+        + Query<Transaction, Filter = TransactionFilter>
+        + Insert<Notification>,
+{
+    if member.email.is_empty() {
+        return Err(anyhow!("member {} has no email address", member.id));
+    }
+    let dedup_key = dedup_key(event, member, date);
+    let queued: Vec<Notification> = db.query(&NotificationFilter {
+        dedup_key: Some(dedup_key.clone()),
+        ..Default::default()
+    }).await?;
+    if !queued.is_empty() {
+        return Ok(None);
+    }
+
+    let transactions: Vec<Transaction> = db.query(&TransactionFilter {
+        member_id: Some(member.id),
+        date_after: member.last_payment_at.succ_opt(),
+        ..Default::default()
+    }).await?;
+    let context = TemplateContext {
+        member: member.clone(),
+        transactions,
+        date,
+    };
+    let message = templates.render(event, &context)?;
+
+    let notification = db.insert(Notification {
+        member_id: member.id,
+        event,
+        dedup_key,
+        recipient: format!("{} <{}>", member.name, member.email),
+        subject: message.subject,
+        body: message.body,
+        created_at: chrono::Local::now().naive_local(),
+        ..Default::default()
+    }).await?;
+    Ok(Some(notification))
+}
+
+/// The outcome of a delivery run
+#[derive(Debug, Default)]
+pub struct DeliveryReport {
+    pub sent: Vec<Notification>,
+    pub failed: Vec<Notification>,
+}
+
+/// Send all pending notifications which have been tried
+/// less than `max_attempts` times. Each notification is
+/// claimed before it is sent, so concurrent deliveries do
+/// not send it twice.
+pub async fn deliver<DB>(
+    db: &DB,
+    mailer: &Mailer,
+    max_attempts: u32,
+) -> Result<DeliveryReport>
+where
+    DB: Query<Notification, Filter = NotificationFilter>
+        + Update<Notification>
+        + ClaimNotification,
+{
+    let pending: Vec<Notification> = db.query(&NotificationFilter {
+        sent: Some(false),
+        ..Default::default()
+    }).await?;
+
+    let mut report = DeliveryReport::default();
+    for notification in pending {
+        if notification.attempts >= max_attempts {
+            continue;
+        }
+        let now = chrono::Local::now().naive_local();
+        let stale_before = now - Duration::minutes(CLAIM_TIMEOUT_MINUTES);
+        let Some(notification) = db.claim(&notification, now, stale_before).await? else {
+            continue;
+        };
+        match mailer.send(&notification).await {
+            Ok(()) => {
+                let notification = db.update(Notification {
+                    sent_at: Some(chrono::Local::now().naive_local()),
+                    last_error: None,
+                    ..notification
+                }).await?;
+                report.sent.push(notification);
+            }
+            Err(err) => {
+                let notification = db.update(Notification {
+                    last_error: Some(err.to_string()),
+                    claimed_at: None,
+                    ..notification
+                }).await?;
+                report.failed.push(notification);
+            }
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mailer::testing::smtp_server;
+
+    use eris_db::Connection;
+
+    fn date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2023, 5, 1).unwrap()
+    }
+
+    #[test]
+    fn test_dedup_key() {
+        let member = Member {
+            id: 23,
+            fee: 20.0,
+            interval: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            dedup_key(NotificationEvent::BalanceReminder, &member, date()),
+            "balance_reminder:23:2023-05"
+        );
+        assert_eq!(
+            dedup_key(NotificationEvent::FeeChange, &member, date()),
+            "fee_change:23:2023-05-01"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_queue_and_deliver() {
+        let db = Connection::open_test().await;
+        let templates = Templates::builtin().unwrap();
+        let (config, inbox) = smtp_server().await;
+        let mailer = Mailer::new(&config).unwrap();
+
+        let member = db.insert(Member {
+            name: "Eris".to_string(),
+            email: "eris@discordia.ccc".to_string(),
+            fee: 20.0,
+            account: -40.0,
+            ..Default::default()
+        }).await.unwrap();
+        let event = NotificationEvent::BalanceReminder;
+
+        // Queueing twice does not duplicate the notification
+        let queued = queue(&db, &templates, event, &member, date())
+            .await.unwrap();
+        assert!(queued.is_some());
+        let queued = queue(&db, &templates, event, &member, date())
+            .await.unwrap();
+        assert!(queued.is_none());
+
+        let report = deliver(&db, &mailer, MAX_ATTEMPTS).await.unwrap();
+        assert_eq!(report.sent.len(), 1);
+        assert_eq!(inbox.lock().await.len(), 1);
+
+        // Sent notifications are not sent again
+        let report = deliver(&db, &mailer, MAX_ATTEMPTS).await.unwrap();
+        assert!(report.sent.is_empty());
+        assert_eq!(inbox.lock().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_deliveries() {
+        let db = Connection::open_test().await;
+        let templates = Templates::builtin().unwrap();
+        let (config, inbox) = smtp_server().await;
+        let mailer = Mailer::new(&config).unwrap();
+
+        for n in 0..5 {
+            let member = db.insert(Member {
+                name: format!("Eris {}", n),
+                email: format!("eris{}@discordia.ccc", n),
+                ..Default::default()
+            }).await.unwrap();
+            queue(&db, &templates, NotificationEvent::Welcome, &member, date())
+                .await.unwrap();
+        }
+
+        let (a, b) = tokio::join!(
+            deliver(&db, &mailer, MAX_ATTEMPTS),
+            deliver(&db, &mailer, MAX_ATTEMPTS),
+        );
+        let sent = a.unwrap().sent.len() + b.unwrap().sent.len();
+        assert_eq!(sent, 5);
+        assert_eq!(inbox.lock().await.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_queue_in_memory() {
+        let db = eris_data::MemoryDb::new();
+        let templates = Templates::builtin().unwrap();
+        let member = db.insert(Member {
+            name: "Eris".to_string(),
+            email: "eris@discordia.ccc".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        let event = NotificationEvent::Welcome;
+        assert!(queue(&db, &templates, event, &member, date())
+            .await.unwrap().is_some());
+        assert!(queue(&db, &templates, event, &member, date())
+            .await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_deliver_retry() {
+        let db = Connection::open_test().await;
+        let templates = Templates::builtin().unwrap();
+        let (config, inbox) = smtp_server().await;
+        let mailer = Mailer::new(&config).unwrap();
+
+        let member = db.insert(Member {
+            name: "Eris".to_string(),
+            email: "reject@discordia.ccc".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        queue(&db, &templates, NotificationEvent::Welcome, &member, date())
+            .await.unwrap();
+
+        for attempt in 1..=2 {
+            let report = deliver(&db, &mailer, 2).await.unwrap();
+            assert_eq!(report.failed.len(), 1);
+            assert_eq!(report.failed[0].attempts, attempt);
+            assert!(report.failed[0].last_error.is_some());
+        }
+
+        // Give up after max attempts
+        let report = deliver(&db, &mailer, 2).await.unwrap();
+        assert!(report.failed.is_empty());
+        assert!(inbox.lock().await.is_empty());
+    }
+}
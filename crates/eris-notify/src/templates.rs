@@ -0,0 +1,178 @@
+use std::{fs, path::Path};
+
+use anyhow::Result;
+use chrono::NaiveDate;
+use handlebars::{handlebars_helper, Handlebars};
+use serde::Serialize;
+
+use eris_data::{Member, NotificationEvent, Transaction};
+
+/// All events a template is required for
+pub const EVENTS: [NotificationEvent; 3] = [
+    NotificationEvent::Welcome,
+    NotificationEvent::BalanceReminder,
+    NotificationEvent::FeeChange,
+];
+
+/// Built-in templates: (event, subject, body)
+const DEFAULT_TEMPLATES: [(&str, &str, &str); 3] = [
+    (
+        "welcome",
+        include_str!("../templates/welcome.subject.hbs"),
+        include_str!("../templates/welcome.body.hbs"),
+    ),
+    (
+        "balance_reminder",
+        include_str!("../templates/balance_reminder.subject.hbs"),
+        include_str!("../templates/balance_reminder.body.hbs"),
+    ),
+    (
+        "fee_change",
+        include_str!("../templates/fee_change.subject.hbs"),
+        include_str!("../templates/fee_change.body.hbs"),
+    ),
+];
+
+handlebars_helper!(euro: |amount: f64| format!("{:.2} €", amount));
+
+/// The data available in templates.
+#[derive(Debug, Clone, Serialize)]
+pub struct TemplateContext {
+    pub member: Member,
+    pub transactions: Vec<Transaction>,
+    pub date: NaiveDate,
+}
+
+/// A rendered email
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub subject: String,
+    pub body: String,
+}
+
+/// Email templates for every notification event
+pub struct Templates {
+    registry: Handlebars<'static>,
+}
+
+impl Templates {
+    fn new_registry() -> Handlebars<'static> {
+        let mut registry = Handlebars::new();
+        registry.set_strict_mode(true);
+        registry.register_escape_fn(handlebars::no_escape);
+        registry.register_helper("euro", Box::new(euro));
+        registry
+    }
+
+    /// Use the built-in templates
+    pub fn builtin() -> Result<Self> {
+        let mut registry = Self::new_registry();
+        for (name, subject, body) in DEFAULT_TEMPLATES {
+            registry.register_template_string(
+                &format!("{}.subject", name), subject)?;
+            registry.register_template_string(
+                &format!("{}.body", name), body)?;
+        }
+        Ok(Self { registry })
+    }
+
+    /// Load templates from a directory. Templates are named
+    /// `<event>.subject.hbs` and `<event>.body.hbs`; missing
+    /// templates fall back to the built-in ones.
+    pub fn from_dir(path: &Path) -> Result<Self> {
+        let mut templates = Self::builtin()?;
+        for event in EVENTS {
+            for part in ["subject", "body"] {
+                let name = format!("{}.{}", event.name(), part);
+                let file = path.join(format!("{}.hbs", name));
+                if file.exists() {
+                    let template = fs::read_to_string(file)?;
+                    templates.registry
+                        .register_template_string(&name, template)?;
+                }
+            }
+        }
+        Ok(templates)
+    }
+
+    /// Render subject and body for an event
+    pub fn render(
+        &self,
+        event: NotificationEvent,
+        context: &TemplateContext,
+    ) -> Result<Message> {
+        let subject = self.registry.render(
+            &format!("{}.subject", event.name()), context)?;
+        let body = self.registry.render(
+            &format!("{}.body", event.name()), context)?;
+        Ok(Message {
+            subject: subject.trim().to_string(),
+            body,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> TemplateContext {
+        TemplateContext {
+            member: Member {
+                id: 23,
+                name: "Eris Discordia".to_string(),
+                fee: 23.0,
+                account: -46.0,
+                ..Default::default()
+            },
+            transactions: vec![Transaction {
+                date: NaiveDate::from_ymd_opt(2023, 4, 1).unwrap(),
+                amount: -23.0,
+                description: "Monthly member fee for April 2023".to_string(),
+                ..Default::default()
+            }],
+            date: NaiveDate::from_ymd_opt(2023, 5, 1).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_render_builtin() {
+        let templates = Templates::builtin().unwrap();
+        for event in EVENTS {
+            templates.render(event, &context()).unwrap();
+        }
+
+        let msg = templates
+            .render(NotificationEvent::BalanceReminder, &context())
+            .unwrap();
+        assert_eq!(msg.subject, "Your account balance is -46.00 €");
+        assert!(msg.body.contains("Hi Eris Discordia,"));
+        assert!(msg.body.contains("-23.00 €  Monthly member fee for April"));
+    }
+
+    #[test]
+    fn test_render_from_dir() {
+        let dir = std::env::temp_dir()
+            .join(format!("eris_templates_{}", rand_suffix()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("welcome.subject.hbs"), "Hello {{member.name}}")
+            .unwrap();
+
+        let templates = Templates::from_dir(&dir).unwrap();
+        let msg = templates
+            .render(NotificationEvent::Welcome, &context())
+            .unwrap();
+        assert_eq!(msg.subject, "Hello Eris Discordia");
+        // The body falls back to the builtin template
+        assert!(msg.body.contains("welcome to the club"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn rand_suffix() -> u128 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos()
+    }
+}
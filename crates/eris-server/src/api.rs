@@ -0,0 +1,551 @@
+use axum::{
+    extract::{self, Path, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::NaiveDate;
+use serde::{Deserialize, Deserializer};
+
+use eris_accounting::{
+    archive,
+    datetime,
+    transactions::{calculate_account, AccountCalculation},
+};
+use eris_data::{
+    BankImportRule,
+    BankImportRuleFilter,
+    Member,
+    MemberFilter,
+    Transaction,
+    TransactionFilter,
+};
+
+use crate::{error::ApiError, AppState, Database};
+
+type ApiResult<T> = Result<Json<T>, ApiError>;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct MemberParams {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub include_archived: bool,
+}
+
+/// GET /api/members
+pub async fn list_members<DB: Database>(
+    State(state): State<AppState<DB>>,
+    extract::Query(params): extract::Query<MemberParams>,
+) -> ApiResult<Vec<Member>> {
+    let members: Vec<Member> = state.db.query(&MemberFilter {
+        name: params.name,
+        email: params.email,
+        include_archived: params.include_archived,
+        ..Default::default()
+    }).await?;
+    Ok(Json(members))
+}
+
+/// GET /api/members/:id
+pub async fn get_member<DB: Database>(
+    State(state): State<AppState<DB>>,
+    Path(id): Path<u32>,
+) -> ApiResult<Member> {
+    let member: Member = state.db.retrieve(id).await?;
+    Ok(Json(member))
+}
+
+fn default_fee() -> f64 {
+    20.0
+}
+
+fn default_interval() -> u8 {
+    1
+}
+
+/// A new member, as added with `members add`
+#[derive(Debug, Deserialize)]
+pub struct NewMember {
+    pub name: String,
+    pub email: String,
+    #[serde(default)]
+    pub notes: String,
+    pub membership_start: Option<NaiveDate>,
+    #[serde(default = "default_fee")]
+    pub fee: f64,
+    #[serde(default = "default_interval")]
+    pub interval: u8,
+    #[serde(default)]
+    pub account: f64,
+}
+
+/// Fail if another member uses the email address
+async fn check_email<DB: Database>(
+    db: &DB,
+    email: &str,
+    member_id: Option<u32>,
+) -> Result<(), ApiError> {
+    let members: Vec<Member> = db.query(&MemberFilter {
+        email: Some(email.to_string()),
+        include_archived: true,
+        ..Default::default()
+    }).await?;
+    if members.iter().any(|m| Some(m.id) != member_id) {
+        return Err(ApiError::conflict(&format!(
+            "member with email {} already exists", email)));
+    }
+    Ok(())
+}
+
+/// POST /api/members
+pub async fn create_member<DB: Database>(
+    State(state): State<AppState<DB>>,
+    Json(new): Json<NewMember>,
+) -> Result<(StatusCode, Json<Member>), ApiError> {
+    if new.name.trim().is_empty() {
+        return Err(ApiError::bad_request("name must not be empty"));
+    }
+    check_email(&*state.db, &new.email, None).await?;
+    let membership_start = new.membership_start
+        .unwrap_or(datetime::today());
+    let member = state.db.insert(Member {
+        name: new.name,
+        email: new.email,
+        notes: new.notes,
+        membership_start,
+        fee: new.fee,
+        interval: new.interval,
+        account: new.account,
+        ..Default::default()
+    }).await?;
+    Ok((StatusCode::CREATED, Json(member)))
+}
+
+/// Tell a missing value from an explicit null
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
+}
+
+/// The attributes changed by an update, like `members set`.
+/// A `membership_end` of null clears the end of membership.
+#[derive(Debug, Default, Deserialize)]
+pub struct MemberChanges {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub notes: Option<String>,
+    pub membership_start: Option<NaiveDate>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub membership_end: Option<Option<NaiveDate>>,
+    pub fee: Option<f64>,
+    pub interval: Option<u8>,
+}
+
+/// PUT /api/members/:id
+pub async fn update_member<DB: Database>(
+    State(state): State<AppState<DB>>,
+    Path(id): Path<u32>,
+    Json(changes): Json<MemberChanges>,
+) -> ApiResult<Member> {
+    let _: Member = state.db.retrieve(id).await?;
+    if let Some(email) = &changes.email {
+        check_email(&*state.db, email, Some(id)).await?;
+    }
+    let member = state.db.change_member(eris_data::MemberChanges {
+        id,
+        name: changes.name,
+        email: changes.email,
+        notes: changes.notes,
+        membership_start: changes.membership_start,
+        membership_end: changes.membership_end,
+        fee: changes.fee,
+        interval: changes.interval,
+    }).await?;
+    Ok(Json(member))
+}
+
+/// DELETE /api/members/:id
+///
+/// Members are archived, so their ledger is kept.
+pub async fn archive_member<DB: Database>(
+    State(state): State<AppState<DB>>,
+    Path(id): Path<u32>,
+) -> ApiResult<Member> {
+    let member = archive::archive(&*state.db, id, datetime::today()).await?;
+    Ok(Json(member))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct TransactionParams {
+    pub member_id: Option<u32>,
+    pub after_date: Option<NaiveDate>,
+    pub before_date: Option<NaiveDate>,
+}
+
+/// GET /api/transactions
+pub async fn list_transactions<DB: Database>(
+    State(state): State<AppState<DB>>,
+    extract::Query(params): extract::Query<TransactionParams>,
+) -> ApiResult<Vec<Transaction>> {
+    let transactions: Vec<Transaction> = state.db.query(&TransactionFilter {
+        member_id: params.member_id,
+        date_after: params.after_date,
+        date_before: params.before_date,
+        ..Default::default()
+    }).await?;
+    Ok(Json(transactions))
+}
+
+/// GET /api/transactions/:id
+pub async fn get_transaction<DB: Database>(
+    State(state): State<AppState<DB>>,
+    Path(id): Path<u32>,
+) -> ApiResult<Transaction> {
+    let transaction: Transaction = state.db.retrieve(id).await?;
+    Ok(Json(transaction))
+}
+
+/// GET /api/members/:id/transactions
+pub async fn list_member_transactions<DB: Database>(
+    State(state): State<AppState<DB>>,
+    Path(id): Path<u32>,
+) -> ApiResult<Vec<Transaction>> {
+    let member: Member = state.db.retrieve(id).await?;
+    let transactions = member.get_transactions(&*state.db).await?;
+    Ok(Json(transactions))
+}
+
+/// GET /api/members/:id/rules
+pub async fn list_rules<DB: Database>(
+    State(state): State<AppState<DB>>,
+    Path(id): Path<u32>,
+) -> ApiResult<Vec<BankImportRule>> {
+    let rules: Vec<BankImportRule> = state.db.query(&BankImportRuleFilter {
+        member_id: Some(id),
+        ..Default::default()
+    }).await?;
+    Ok(Json(rules))
+}
+
+/// GET /api/members/:id/rules/:iban
+pub async fn get_rule<DB: Database>(
+    State(state): State<AppState<DB>>,
+    Path((id, iban)): Path<(u32, String)>,
+) -> ApiResult<BankImportRule> {
+    let rule: BankImportRule = state.db.retrieve((id, iban)).await?;
+    Ok(Json(rule))
+}
+
+/// A new IBAN rule, like `bank iban add`
+#[derive(Debug, Deserialize)]
+pub struct NewRule {
+    pub iban: String,
+    pub split_amount: Option<f64>,
+    pub match_subject: Option<String>,
+    /// The account holder the IBAN is hashed with,
+    /// defaults to the member name
+    pub account_name: Option<String>,
+}
+
+/// POST /api/members/:id/rules
+pub async fn create_rule<DB: Database>(
+    State(state): State<AppState<DB>>,
+    Path(id): Path<u32>,
+    Json(new): Json<NewRule>,
+) -> Result<(StatusCode, Json<BankImportRule>), ApiError> {
+    let member: Member = state.db.retrieve(id).await?;
+    let account_name = new.account_name.unwrap_or(member.name);
+    let iban = state.hasher.protect(&new.iban, &account_name);
+    let existing: Vec<BankImportRule> = state.db.query(&BankImportRuleFilter {
+        member_id: Some(id),
+        iban: Some(iban.clone()),
+    }).await?;
+    if !existing.is_empty() {
+        return Err(ApiError::conflict("the rule already exists"));
+    }
+    let rule = state.db.insert(BankImportRule {
+        member_id: id,
+        iban,
+        split_amount: new.split_amount,
+        match_subject: new.match_subject,
+    }).await?;
+    Ok((StatusCode::CREATED, Json(rule)))
+}
+
+/// DELETE /api/members/:id/rules/:iban
+pub async fn delete_rule<DB: Database>(
+    State(state): State<AppState<DB>>,
+    Path((id, iban)): Path<(u32, String)>,
+) -> Result<StatusCode, ApiError> {
+    let rule: BankImportRule = state.db.retrieve((id, iban)).await?;
+    state.db.delete(rule).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CalculateRequest {
+    pub member_id: Option<u32>,
+    /// Fees are booked until this month, defaults
+    /// to the last month
+    pub until: Option<NaiveDate>,
+}
+
+/// POST /api/accounts/calculate
+pub async fn calculate_accounts<DB: Database>(
+    State(state): State<AppState<DB>>,
+    Json(request): Json<CalculateRequest>,
+) -> ApiResult<Vec<AccountCalculation>> {
+    let until = request.until.unwrap_or(datetime::last_month());
+    let members: Vec<Member> = match request.member_id {
+        Some(id) => vec![state.db.retrieve(id).await?],
+        None => state.db.query(&MemberFilter::default()).await?,
+    };
+    let mut calculations = vec![];
+    for member in members {
+        let calculation = calculate_account(&*state.db, member, until).await?;
+        if !calculation.fees.is_empty() {
+            calculations.push(calculation);
+        }
+    }
+    Ok(Json(calculations))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{
+        body::Body,
+        http::{header, Method, Request},
+        Router,
+    };
+    use serde::de::DeserializeOwned;
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    use eris_data::{Authorized, IbanHasher, Insert, Principal, Role};
+    use eris_db::Connection;
+
+    use super::*;
+    use crate::{auth::ApiTokens, router};
+
+    const TOKEN: &str = "hail-eris";
+
+    async fn test_app() -> (Arc<Connection>, Router) {
+        let db = Arc::new(Connection::open_test().await);
+        let app = router(AppState {
+            db: db.clone(),
+            tokens: Arc::new(ApiTokens::new(vec![format!("{}:write", TOKEN)]).unwrap()),
+            hasher: Arc::new(IbanHasher::default()),
+        });
+        (db, app)
+    }
+
+    async fn request(
+        app: &Router,
+        method: Method,
+        uri: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", TOKEN))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(match body {
+                Some(body) => Body::from(body.to_string()),
+                None => Body::empty(),
+            })
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body = serde_json::from_slice(&body).unwrap_or(Value::Null);
+        (status, body)
+    }
+
+    fn parse<T: DeserializeOwned>(value: Value) -> T {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_requires_token() {
+        let (_db, app) = test_app().await;
+        for token in [None, Some("Bearer fnord"), Some(TOKEN)] {
+            let mut request = Request::builder().uri("/api/members");
+            if let Some(token) = token {
+                request = request.header(header::AUTHORIZATION, token);
+            }
+            let response = app.clone()
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await.unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+        let (status, _) = request(&app, Method::GET, "/api/members", None).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_read_only_token() {
+        let db = Arc::new(Connection::open_test().await);
+        let app = router(AppState {
+            db,
+            tokens: Arc::new(ApiTokens::new(vec![TOKEN.to_string()]).unwrap()),
+            hasher: Arc::new(IbanHasher::default()),
+        });
+        let (status, _) = request(&app, Method::GET, "/api/members", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = request(&app, Method::POST, "/api/members", Some(json!({
+            "name": "Eris",
+        }))).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = request(
+            &app, Method::POST, "/api/accounts/calculate", Some(json!({}))).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_members() {
+        let (_db, app) = test_app().await;
+        let (status, body) = request(&app, Method::POST, "/api/members", Some(json!({
+            "name": "Eris",
+            "email": "eris@discordia.example",
+            "membership_start": "2023-01-01",
+        }))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let member: Member = parse(body);
+        assert_eq!(member.fee, 20.0);
+
+        // Email addresses are unique
+        let (status, _) = request(&app, Method::POST, "/api/members", Some(json!({
+            "name": "Eris 2",
+            "email": "eris@discordia.example",
+        }))).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let uri = format!("/api/members/{}", member.id);
+        let (status, body) = request(&app, Method::PUT, &uri, Some(json!({
+            "notes": "Goddess of chaos",
+        }))).await;
+        assert_eq!(status, StatusCode::OK);
+        let updated: Member = parse(body);
+        assert_eq!(updated.notes, "Goddess of chaos");
+        assert_eq!(updated.name, "Eris");
+
+        // The end of membership can be cleared
+        let (_, body) = request(&app, Method::PUT, &uri, Some(json!({
+            "membership_end": "2023-12-31",
+        }))).await;
+        assert!(parse::<Member>(body).membership_end.is_some());
+        let (_, body) = request(&app, Method::PUT, &uri, Some(json!({
+            "notes": "Still here",
+        }))).await;
+        assert!(parse::<Member>(body).membership_end.is_some());
+        let (_, body) = request(&app, Method::PUT, &uri, Some(json!({
+            "membership_end": null,
+        }))).await;
+        assert_eq!(parse::<Member>(body).membership_end, None);
+
+        let (status, _) = request(&app, Method::GET, "/api/members/23", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // Deleting archives the member
+        let (status, _) = request(&app, Method::DELETE, &uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, body) = request(&app, Method::GET, "/api/members", None).await;
+        assert_eq!(parse::<Vec<Member>>(body).len(), 0);
+        let (_, body) = request(
+            &app, Method::GET, "/api/members?include_archived=true", None).await;
+        assert_eq!(parse::<Vec<Member>>(body).len(), 1);
+        let (status, _) = request(&app, Method::DELETE, &uri, None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_rules_and_transactions() {
+        let (db, app) = test_app().await;
+        let member = db.insert(Member {
+            name: "Eris".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        db.insert(Transaction {
+            member_id: member.id,
+            date: NaiveDate::from_ymd_opt(2023, 5, 10).unwrap(),
+            account_name: "Eris".to_string(),
+            description: "Mitgliedsbeitrag".to_string(),
+            amount: 20.0,
+            ..Default::default()
+        }).await.unwrap();
+
+        let uri = format!("/api/members/{}/rules", member.id);
+        let (status, _) = request(&app, Method::POST, &uri, Some(json!({
+            "iban": "DE2342",
+            "split_amount": 10.0,
+        }))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (_, body) = request(&app, Method::GET, &uri, None).await;
+        let rules: Vec<BankImportRule> = parse(body);
+        assert_eq!(rules[0].split_amount, Some(10.0));
+        let (status, _) = request(
+            &app, Method::DELETE, &format!("{}/DE2342", uri), None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (_, body) = request(&app, Method::GET, &uri, None).await;
+        assert_eq!(parse::<Vec<BankImportRule>>(body).len(), 0);
+
+        let (_, body) = request(
+            &app, Method::GET, "/api/transactions?after_date=2023-05-01", None).await;
+        let transactions: Vec<Transaction> = parse(body);
+        assert_eq!(transactions.len(), 1);
+        let (status, body) = request(&app, Method::GET,
+            &format!("/api/transactions/{}", transactions[0].id), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(parse::<Transaction>(body).amount, 20.0);
+    }
+
+    #[tokio::test]
+    async fn test_calculate_accounts() {
+        let (db, app) = test_app().await;
+        let member = db.insert(Member {
+            name: "Eris".to_string(),
+            fee: 20.0,
+            membership_start: NaiveDate::from_ymd_opt(2023, 1, 1).unwrap(),
+            ..Default::default()
+        }).await.unwrap();
+
+        let (status, body) = request(
+            &app, Method::POST, "/api/accounts/calculate", Some(json!({
+                "until": "2023-03-01",
+            }))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body[0]["fees"].as_array().unwrap().len(), 3);
+        assert_eq!(body[0]["member"]["account"], -60.0);
+
+        let (_, body) = request(&app, Method::GET,
+            &format!("/api/members/{}/transactions", member.id), None).await;
+        assert_eq!(parse::<Vec<Transaction>>(body).len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_read_only_user() {
+        let db = Arc::new(Connection::open_test().await);
+        let board = Authorized::new(db.clone(), Principal {
+            role: Role::Board,
+            member_id: None,
+        });
+        let app = router(AppState {
+            db: Arc::new(board),
+            tokens: Arc::new(ApiTokens::new(vec![format!("{}:write", TOKEN)]).unwrap()),
+            hasher: Arc::new(IbanHasher::default()),
+        });
+        let (status, _) = request(&app, Method::POST, "/api/members", Some(json!({
+            "name": "Eris",
+            "email": "eris@discordia.example",
+        }))).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = request(&app, Method::GET, "/api/members", None).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
@@ -0,0 +1,108 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use axum::{
+    extract::State,
+    http::{header, Method, Request},
+    middleware::Next,
+    response::Response,
+};
+
+use eris_data::Access;
+
+use crate::error::ApiError;
+
+/// The tokens which grant access to the API. A token is
+/// given as `TOKEN:read` or `TOKEN:write`; tokens without
+/// a scope only grant read access.
+#[derive(Debug, Clone)]
+pub struct ApiTokens(Vec<(String, Access)>);
+
+impl ApiTokens {
+    pub fn new(tokens: Vec<String>) -> Result<Self> {
+        let tokens: Vec<(String, Access)> = tokens.iter()
+            .map(|t| t.trim())
+            .filter(|t| !t.is_empty())
+            .map(|t| match t.rsplit_once(':') {
+                Some((token, "read")) => (token.to_string(), Access::Read),
+                Some((token, "write")) => (token.to_string(), Access::Write),
+                _ => (t.to_string(), Access::Read),
+            })
+            .collect();
+        if tokens.is_empty() {
+            return Err(anyhow!("at least one API token is required"));
+        }
+        Ok(Self(tokens))
+    }
+
+    /// Get the access granted by a token. Tokens are compared
+    /// in constant time, so they can not be guessed from the
+    /// timing.
+    pub fn scope(&self, token: &str) -> Option<Access> {
+        self.0.iter().fold(None, |scope, (t, access)| {
+            if constant_time_eq(t, token) {
+                scope.max(Some(*access))
+            } else {
+                scope
+            }
+        })
+    }
+
+    /// Check a token grants the access
+    pub fn accepts(&self, token: &str, access: Access) -> bool {
+        self.scope(token).is_some_and(|scope| scope >= access)
+    }
+}
+
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.bytes().zip(b.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Reject requests without a valid bearer token. Requests
+/// other than GET need a token with write access.
+pub async fn require_token<B>(
+    State(tokens): State<Arc<ApiTokens>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Result<Response, ApiError> {
+    let token = request.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let Some(scope) = token.and_then(|token| tokens.scope(token)) else {
+        return Err(ApiError::unauthorized());
+    };
+    let access = match *request.method() {
+        Method::GET | Method::HEAD => Access::Read,
+        _ => Access::Write,
+    };
+    if scope < access {
+        return Err(ApiError::forbidden("the API token is read only"));
+    }
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens() {
+        assert!(ApiTokens::new(vec![" ".to_string()]).is_err());
+        let tokens = ApiTokens::new(vec![
+            "fnord".to_string(),
+            "kallisti:write".to_string(),
+            "hail:eris:read".to_string(),
+        ]).unwrap();
+        assert!(tokens.accepts("kallisti", Access::Write));
+        assert!(!tokens.accepts("kallisti:write", Access::Read));
+        assert!(!tokens.accepts("kallist", Access::Read));
+        assert!(!tokens.accepts("", Access::Read));
+        assert!(tokens.accepts("fnord", Access::Read));
+        assert!(!tokens.accepts("fnord", Access::Write));
+        assert_eq!(tokens.scope("hail:eris"), Some(Access::Read));
+    }
+}
@@ -0,0 +1,127 @@
+use std::{net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use clap::Parser;
+
+use eris_db::{Connection, DatabaseKey};
+use eris_notify::{Mailer, SmtpConfig, TlsMode};
+use eris_server::{
+    portal::{router, LoginLimits, Payee, PortalConfig, PortalState},
+    rate_limit::RateLimit,
+    sessions::Sessions,
+};
+
+/// The member portal, where members log in with a link
+/// sent by email to see their account.
+#[derive(Parser, Debug)]
+#[clap(name = "eris-portal", version = env!("CARGO_PKG_VERSION"))]
+struct Cli {
+    /// The SQLite database file or a postgres:// URL
+    #[clap(long, default_value = "members.sqlite3")]
+    pub members_db: String,
+
+    /// Passphrase of an encrypted database
+    #[clap(long, env = "ERIS_DB_KEY", hide = true, hide_env_values = true)]
+    pub db_key: Option<String>,
+
+    /// Read the passphrase of an encrypted database from a file
+    #[clap(long, env = "ERIS_DB_KEYFILE")]
+    pub db_keyfile: Option<PathBuf>,
+
+    /// Address to listen on
+    #[clap(long, env = "ERIS_PORTAL_LISTEN", default_value = "127.0.0.1:8081")]
+    pub listen: SocketAddr,
+
+    /// The public URL of the portal, used in login links
+    #[clap(long, env = "ERIS_PORTAL_URL")]
+    pub base_url: String,
+
+    /// Minutes a login link can be used
+    #[clap(long, default_value_t = 15)]
+    pub link_minutes: u64,
+
+    /// Minutes until members are logged out
+    #[clap(long, default_value_t = 60)]
+    pub session_minutes: u64,
+
+    /// Login links which can be requested for an email
+    /// address per hour
+    #[clap(long, default_value_t = 5)]
+    pub links_per_address: usize,
+
+    /// Login links which can be requested from a client
+    /// IP address per hour
+    #[clap(long, default_value_t = 20)]
+    pub links_per_client: usize,
+
+    /// Account holder members transfer their fees to
+    #[clap(long, env = "ERIS_PAYEE_NAME")]
+    pub payee_name: String,
+
+    #[clap(long, env = "ERIS_PAYEE_IBAN")]
+    pub payee_iban: String,
+
+    #[clap(long, env = "ERIS_PAYEE_BIC")]
+    pub payee_bic: Option<String>,
+
+    #[clap(long, env = "ERIS_SMTP_HOST")]
+    pub smtp_host: String,
+    #[clap(long, env = "ERIS_SMTP_PORT", default_value_t = 587)]
+    pub smtp_port: u16,
+    /// One of none, starttls or tls
+    #[clap(long, env = "ERIS_SMTP_TLS", default_value_t = TlsMode::StartTls)]
+    pub smtp_tls: TlsMode,
+    #[clap(long, env = "ERIS_SMTP_USER")]
+    pub smtp_user: Option<String>,
+    #[clap(long, env = "ERIS_SMTP_PASSWORD", hide_env_values = true)]
+    pub smtp_password: Option<String>,
+    /// The sender address, e.g. "Treasurer <kasse@example.org>"
+    #[clap(long, env = "ERIS_SMTP_FROM")]
+    pub smtp_from: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    let key = DatabaseKey::resolve(
+        cli.db_key.as_deref(), cli.db_keyfile.as_deref())?;
+    let db = Connection::open_with_key(&cli.members_db, key.as_ref()).await?
+        .with_actor("eris-portal");
+    let mailer = Mailer::new(&SmtpConfig {
+        host: cli.smtp_host,
+        port: cli.smtp_port,
+        tls: cli.smtp_tls,
+        username: cli.smtp_user,
+        password: cli.smtp_password,
+        from: cli.smtp_from,
+    })?;
+    let state = PortalState {
+        db: Arc::new(db),
+        mailer: Arc::new(mailer),
+        sessions: Arc::new(Sessions::new(
+            Duration::from_secs(cli.link_minutes * 60),
+            Duration::from_secs(cli.session_minutes * 60))),
+        limits: Arc::new(LoginLimits {
+            per_address: RateLimit::new(
+                cli.links_per_address, Duration::from_secs(3600)),
+            per_client: RateLimit::new(
+                cli.links_per_client, Duration::from_secs(3600)),
+        }),
+        config: Arc::new(PortalConfig {
+            base_url: cli.base_url,
+            payee: Payee {
+                name: cli.payee_name,
+                iban: cli.payee_iban,
+                bic: cli.payee_bic,
+            },
+        }),
+    };
+
+    println!("Listening on http://{}", cli.listen);
+    axum::Server::bind(&cli.listen)
+        .serve(router(state)
+            .into_make_service_with_connect_info::<SocketAddr>())
+        .await?;
+    Ok(())
+}
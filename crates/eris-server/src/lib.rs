@@ -0,0 +1,114 @@
+use std::sync::Arc;
+
+use axum::{middleware, routing::{get, post}, Router};
+
+use eris_data::{
+    BankImportRule,
+    BankImportRuleFilter,
+    Book,
+    ChangeMember,
+    Delete,
+    IbanHasher,
+    Insert,
+    Member,
+    MemberFilter,
+    Query,
+    Retrieve,
+    Transaction,
+    TransactionFilter,
+    Update,
+};
+
+pub mod api;
+pub mod auth;
+pub mod error;
+pub mod portal;
+pub mod rate_limit;
+pub mod sessions;
+
+use auth::ApiTokens;
+
+/// The operations the API needs from the database. This is
+/// implemented by every type providing the data traits, so
+/// the API does not depend on a database backend.
+pub trait Database:
+    Query<Member, Filter = MemberFilter>
+    + Retrieve<Member, Key = u32>
+    + Insert<Member>
+    + Update<Member>
+    + ChangeMember
+    + Query<Transaction, Filter = TransactionFilter>
+    + Retrieve<Transaction, Key = u32>
+    + Insert<Transaction>
+    + Query<BankImportRule, Filter = BankImportRuleFilter>
+    + Retrieve<BankImportRule, Key = (u32, String)>
+    + Insert<BankImportRule>
+    + Delete<BankImportRule>
+    + Book
+    + Send
+    + Sync
+    + 'static
+{
+}
+
+impl<T> Database for T where
+    T: Query<Member, Filter = MemberFilter>
+        + Retrieve<Member, Key = u32>
+        + Insert<Member>
+        + Update<Member>
+        + ChangeMember
+        + Query<Transaction, Filter = TransactionFilter>
+        + Retrieve<Transaction, Key = u32>
+        + Insert<Transaction>
+        + Query<BankImportRule, Filter = BankImportRuleFilter>
+        + Retrieve<BankImportRule, Key = (u32, String)>
+        + Insert<BankImportRule>
+        + Delete<BankImportRule>
+        + Book
+        + Send
+        + Sync
+        + 'static
+{
+}
+
+/// Shared by all requests
+pub struct AppState<DB> {
+    pub db: Arc<DB>,
+    pub tokens: Arc<ApiTokens>,
+    /// Stores the IBANs of new rules
+    pub hasher: Arc<IbanHasher>,
+}
+
+impl<DB> Clone for AppState<DB> {
+    fn clone(&self) -> Self {
+        Self {
+            db: self.db.clone(),
+            tokens: self.tokens.clone(),
+            hasher: self.hasher.clone(),
+        }
+    }
+}
+
+/// Build the routes of the API. All routes require a token.
+pub fn router<DB: Database>(state: AppState<DB>) -> Router {
+    Router::new()
+        .route("/api/members",
+            get(api::list_members::<DB>).post(api::create_member::<DB>))
+        .route("/api/members/:id",
+            get(api::get_member::<DB>)
+                .put(api::update_member::<DB>)
+                .delete(api::archive_member::<DB>))
+        .route("/api/members/:id/transactions",
+            get(api::list_member_transactions::<DB>))
+        .route("/api/members/:id/rules",
+            get(api::list_rules::<DB>).post(api::create_rule::<DB>))
+        .route("/api/members/:id/rules/:iban",
+            get(api::get_rule::<DB>).delete(api::delete_rule::<DB>))
+        .route("/api/transactions", get(api::list_transactions::<DB>))
+        .route("/api/transactions/:id", get(api::get_transaction::<DB>))
+        .route("/api/accounts/calculate",
+            post(api::calculate_accounts::<DB>))
+        .route_layer(middleware::from_fn_with_state(
+            state.tokens.clone(), auth::require_token))
+        .with_state(state)
+}
@@ -0,0 +1,81 @@
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+
+use anyhow::Result;
+use clap::Parser;
+
+use eris_data::{Authorized, IbanStorage, Retrieve, User};
+use eris_db::{Connection, DatabaseKey};
+use eris_server::{auth::ApiTokens, router, AppState, Database};
+
+#[derive(Parser, Debug)]
+#[clap(name = "eris-server", version = env!("CARGO_PKG_VERSION"))]
+struct Cli {
+    /// The SQLite database file or a postgres:// URL
+    #[clap(long, default_value = "members.sqlite3")]
+    pub members_db: String,
+
+    /// Passphrase of an encrypted database
+    #[clap(long, env = "ERIS_DB_KEY", hide = true, hide_env_values = true)]
+    pub db_key: Option<String>,
+
+    /// Read the passphrase of an encrypted database from a file
+    #[clap(long, env = "ERIS_DB_KEYFILE")]
+    pub db_keyfile: Option<PathBuf>,
+
+    /// Address to listen on
+    #[clap(long, env = "ERIS_LISTEN", default_value = "127.0.0.1:8080")]
+    pub listen: SocketAddr,
+
+    /// API tokens granting access, separated by commas. Tokens
+    /// are given as TOKEN:read or TOKEN:write; without a scope
+    /// they only grant read access.
+    #[clap(long = "token", env = "ERIS_API_TOKENS", hide_env_values = true,
+           value_delimiter = ',', required = true)]
+    pub tokens: Vec<String>,
+
+    /// Run with the permissions of this user. Without a
+    /// user, the API has full access.
+    #[clap(long, env = "ERIS_USER")]
+    pub user: Option<String>,
+
+    /// How IBANs of new rules are stored: plain, hash or hmac.
+    /// Defaults to the storage of the database.
+    #[clap(long, env = "ERIS_IBAN_STORAGE")]
+    pub iban_storage: Option<IbanStorage>,
+
+    /// Secret for storing IBANs as HMAC
+    #[clap(long, env = "ERIS_IBAN_SECRET", hide_env_values = true)]
+    pub iban_secret: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    let key = DatabaseKey::resolve(
+        cli.db_key.as_deref(), cli.db_keyfile.as_deref())?;
+    let actor = cli.user.as_deref().unwrap_or("eris-server");
+    let db = Connection::open_with_key(&cli.members_db, key.as_ref()).await?
+        .with_actor(actor);
+    let hasher = Arc::new(db.iban_hasher(
+        cli.iban_storage, cli.iban_secret.as_deref()).await?);
+    let db = Arc::new(db);
+    let tokens = Arc::new(ApiTokens::new(cli.tokens)?);
+
+    match cli.user {
+        Some(name) => {
+            let user: User = db.retrieve(name).await?;
+            let db = Authorized::new(db, (&user).into());
+            serve(cli.listen, AppState { db: Arc::new(db), tokens, hasher }).await
+        }
+        None => serve(cli.listen, AppState { db, tokens, hasher }).await,
+    }
+}
+
+async fn serve<DB: Database>(listen: SocketAddr, state: AppState<DB>) -> Result<()> {
+    println!("Listening on http://{}", listen);
+    axum::Server::bind(&listen)
+        .serve(router(state).into_make_service())
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,548 @@
+use std::{fmt::Write, net::SocketAddr, sync::Arc};
+
+use anyhow::Result;
+use axum::{
+    extract::{ConnectInfo, Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form,
+    Router,
+};
+use chrono::Months;
+use serde::Deserialize;
+
+use eris_accounting::{datetime, html::escape, statements::Statement};
+use eris_data::{Authorized, Member, MemberFilter, Principal, Retrieve};
+use eris_notify::Mailer;
+
+use crate::{rate_limit::RateLimit, sessions::Sessions, Database};
+
+const SESSION_COOKIE: &str = "eris_session";
+
+/// Months shown on the statement in the portal
+const STATEMENT_MONTHS: u32 = 12;
+
+/// Where members send their fees
+#[derive(Debug, Clone, Default)]
+pub struct Payee {
+    pub name: String,
+    pub iban: String,
+    pub bic: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PortalConfig {
+    /// The URL of the portal as seen by members, used
+    /// for login links
+    pub base_url: String,
+    pub payee: Payee,
+}
+
+impl PortalConfig {
+    fn login_link(&self, token: &str) -> String {
+        format!("{}/login/{}", self.base_url.trim_end_matches('/'), token)
+    }
+
+    fn secure_cookies(&self) -> bool {
+        self.base_url.starts_with("https://")
+    }
+}
+
+/// How often login links can be requested
+#[derive(Debug)]
+pub struct LoginLimits {
+    /// Requests for the same email address
+    pub per_address: RateLimit,
+    /// Requests from the same client IP address
+    pub per_client: RateLimit,
+}
+
+/// Shared by all requests to the portal
+pub struct PortalState<DB> {
+    pub db: Arc<DB>,
+    pub mailer: Arc<Mailer>,
+    pub sessions: Arc<Sessions>,
+    pub limits: Arc<LoginLimits>,
+    pub config: Arc<PortalConfig>,
+}
+
+impl<DB> Clone for PortalState<DB> {
+    fn clone(&self) -> Self {
+        Self {
+            db: self.db.clone(),
+            mailer: self.mailer.clone(),
+            sessions: self.sessions.clone(),
+            limits: self.limits.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// Build the routes of the member portal
+pub fn router<DB: Database>(state: PortalState<DB>) -> Router {
+    Router::new()
+        .route("/", get(index::<DB>))
+        .route("/login", post(request_link::<DB>))
+        .route("/login/:token", get(login::<DB>))
+        .route("/logout", post(logout::<DB>))
+        .route("/account", get(account::<DB>))
+        .route("/account/statement", get(statement::<DB>))
+        .with_state(state)
+}
+
+/// An unexpected error, rendered as an error page
+pub struct PortalError(anyhow::Error);
+
+impl From<anyhow::Error> for PortalError {
+    fn from(err: anyhow::Error) -> Self {
+        Self(err)
+    }
+}
+
+impl IntoResponse for PortalError {
+    fn into_response(self) -> Response {
+        eprintln!("Error: {:#}", self.0);
+        let body = page("Error", "<p>Something went wrong. \
+            Please try again later.</p>");
+        (StatusCode::INTERNAL_SERVER_ERROR, body).into_response()
+    }
+}
+
+type PortalResult = Result<Response, PortalError>;
+
+#[derive(Debug, Deserialize)]
+pub struct LoginRequest {
+    pub email: String,
+}
+
+/// GET /
+pub async fn index<DB: Database>(
+    State(state): State<PortalState<DB>>,
+    headers: HeaderMap,
+) -> Response {
+    if current_member_id(&state, &headers).is_some() {
+        return Redirect::to("/account").into_response();
+    }
+    page("Member portal", LOGIN_FORM).into_response()
+}
+
+/// POST /login sends a login link to active members. The
+/// response is the same for unknown addresses, so it does
+/// not reveal who is a member. The mail is sent in the
+/// background, so the response time does not reveal it
+/// either. Requests are limited per address and per client.
+pub async fn request_link<DB: Database>(
+    State(state): State<PortalState<DB>>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    Form(request): Form<LoginRequest>,
+) -> PortalResult {
+    let address = request.email.trim().to_lowercase();
+    if !state.limits.per_client.allow(&client.ip().to_string())
+        || !state.limits.per_address.allow(&address)
+    {
+        let body = page("Too many requests", "<p>Too many login links \
+            were requested. Please try again later.</p>");
+        return Ok((StatusCode::TOO_MANY_REQUESTS, body).into_response());
+    }
+
+    if let Some(member) = find_member(&*state.db, &request.email).await? {
+        let token = state.sessions.issue_link(member.id);
+        let minutes = state.sessions.link_ttl().as_secs() / 60;
+        let body = format!(
+            "Hello {},\n\n\
+            use this link to log in to the member portal:\n\n\
+            {}\n\n\
+            The link can be used once within {} minutes. If you did \
+            not ask for it, you can ignore this message.\n",
+            member.name, state.config.login_link(&token), minutes);
+        let mailer = state.mailer.clone();
+        tokio::spawn(async move {
+            // Failures are not shown, as they would reveal
+            // that the address belongs to a member.
+            if let Err(err) = mailer.send_message(
+                &member.email, "Your login link", body).await
+            {
+                eprintln!("Error: could not send login link to member {}: {:#}",
+                    member.id, err);
+            }
+        });
+    }
+    Ok(page("Check your email", "<p>If the address belongs to a \
+        member, we sent you a link to log in.</p>").into_response())
+}
+
+/// Find the active member with exactly this email address
+async fn find_member<DB: Database>(db: &DB, email: &str) -> Result<Option<Member>> {
+    let email = email.trim();
+    if email.is_empty() {
+        return Ok(None);
+    }
+    let members: Vec<Member> = db.query(&MemberFilter {
+        email: Some(email.to_string()),
+        ..Default::default()
+    }).await?;
+    let today = datetime::today();
+    Ok(members.into_iter()
+        .find(|m| m.email.eq_ignore_ascii_case(email) && m.is_active(today)))
+}
+
+/// GET /login/:token
+pub async fn login<DB: Database>(
+    State(state): State<PortalState<DB>>,
+    Path(token): Path<String>,
+) -> Response {
+    let session = match state.sessions.redeem_link(&token) {
+        Some(session) => session,
+        None => {
+            let body = page("Login failed", "<p>The link is invalid or \
+                expired.</p><p><a href=\"/\">Request a new link</a></p>");
+            return (StatusCode::FORBIDDEN, body).into_response();
+        }
+    };
+    let mut cookie = format!(
+        "{}={}; Path=/; HttpOnly; SameSite=Lax; Max-Age={}",
+        SESSION_COOKIE, session, state.sessions.session_ttl().as_secs());
+    if state.config.secure_cookies() {
+        cookie += "; Secure";
+    }
+    ([(header::SET_COOKIE, cookie)], Redirect::to("/account")).into_response()
+}
+
+/// POST /logout
+pub async fn logout<DB: Database>(
+    State(state): State<PortalState<DB>>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(session) = session_cookie(&headers) {
+        state.sessions.end(session);
+    }
+    let cookie = format!("{}=; Path=/; HttpOnly; Max-Age=0", SESSION_COOKIE);
+    ([(header::SET_COOKIE, cookie)], Redirect::to("/")).into_response()
+}
+
+/// GET /account shows the member record, the balance and
+/// how to pay.
+pub async fn account<DB: Database>(
+    State(state): State<PortalState<DB>>,
+    headers: HeaderMap,
+) -> PortalResult {
+    let member_id = match current_member_id(&state, &headers) {
+        Some(id) => id,
+        None => return Ok(Redirect::to("/").into_response()),
+    };
+    let db = Authorized::new(state.db.clone(), Principal::member(member_id));
+    let member: Member = db.retrieve(member_id).await?;
+    let title = format!("Welcome, {}", escape(&member.name));
+    let body = account_html(&member, &state.config.payee);
+    Ok(page(&title, &body).into_response())
+}
+
+/// GET /account/statement
+pub async fn statement<DB: Database>(
+    State(state): State<PortalState<DB>>,
+    headers: HeaderMap,
+) -> PortalResult {
+    let member_id = match current_member_id(&state, &headers) {
+        Some(id) => id,
+        None => return Ok(Redirect::to("/").into_response()),
+    };
+    let db = Authorized::new(state.db.clone(), Principal::member(member_id));
+    let member: Member = db.retrieve(member_id).await?;
+    let to = datetime::today();
+    let from = to.checked_sub_months(Months::new(STATEMENT_MONTHS))
+        .unwrap_or(member.membership_start)
+        .max(member.membership_start);
+    let statement = Statement::fetch(&db, member, from, to).await?;
+    Ok(Html(statement.to_html()).into_response())
+}
+
+fn session_cookie(headers: &HeaderMap) -> Option<&str> {
+    headers.get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, value)| value)
+}
+
+fn current_member_id<DB>(
+    state: &PortalState<DB>,
+    headers: &HeaderMap,
+) -> Option<u32> {
+    session_cookie(headers).and_then(|s| state.sessions.member_id(s))
+}
+
+fn account_html(member: &Member, payee: &Payee) -> String {
+    let mut out = String::new();
+    writeln!(out, "<h2>Membership</h2>\n<table>").unwrap();
+    let mut row = |label: &str, value: &str| {
+        writeln!(out, "<tr><th>{}</th><td>{}</td></tr>", label, value).unwrap();
+    };
+    row("Member number", &member.id.to_string());
+    row("Name", &escape(&member.name));
+    row("Email", &escape(&member.email));
+    row("Member since", &member.membership_start.to_string());
+    if let Some(end) = member.membership_end {
+        row("Member until", &end.to_string());
+    }
+    row("Fee", &format!("{:.2} EUR every {} month(s)",
+        member.fee, member.interval));
+    row("Balance", &format!("{:.2} EUR", member.account));
+    row("Calculated on", &member.account_calculated_at.to_string());
+    writeln!(out, "</table>").unwrap();
+    writeln!(out, "<p><a href=\"/account/statement\">Account statement \
+        of the last {} months</a></p>", STATEMENT_MONTHS).unwrap();
+
+    writeln!(out, "<h2>Payment</h2>").unwrap();
+    if member.account < 0.0 {
+        writeln!(out, "<p>Please transfer <strong>{:.2} EUR</strong>.</p>",
+            -member.account).unwrap();
+    } else {
+        writeln!(out, "<p>Your account is settled.</p>").unwrap();
+    }
+    writeln!(out, "<table>").unwrap();
+    writeln!(out, "<tr><th>Recipient</th><td>{}</td></tr>",
+        escape(&payee.name)).unwrap();
+    writeln!(out, "<tr><th>IBAN</th><td>{}</td></tr>",
+        escape(&payee.iban)).unwrap();
+    if let Some(bic) = &payee.bic {
+        writeln!(out, "<tr><th>BIC</th><td>{}</td></tr>", escape(bic)).unwrap();
+    }
+    writeln!(out, "<tr><th>Subject</th><td><code>{}</code></td></tr>",
+        escape(&member.transfer_subject())).unwrap();
+    writeln!(out, "</table>").unwrap();
+    writeln!(out, "<p>Please keep the reference {} in the subject, so \
+        we can assign the payment to your account.</p>",
+        member.payment_reference()).unwrap();
+    writeln!(out, "<form method=\"post\" action=\"/logout\">\
+        <button>Log out</button></form>").unwrap();
+    out
+}
+
+fn page(title: &str, body: &str) -> Html<String> {
+    Html(format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+        <title>{title}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n\
+        <h1>{title}</h1>\n{body}\n</body>\n</html>\n"))
+}
+
+const LOGIN_FORM: &str = "\
+<p>Enter the email address of your membership to get a login link.</p>
+<form method=\"post\" action=\"/login\">
+<input type=\"email\" name=\"email\" required>
+<button>Send link</button>
+</form>";
+
+const STYLE: &str = "\
+body { font-family: sans-serif; max-width: 40em; margin: 2em auto; }
+table { border-collapse: collapse; }
+th { text-align: left; padding-right: 2em; }
+td, th { padding: 0.2em 0; }";
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::{body::Body, http::{Method, Request}};
+    use tokio::time::sleep;
+    use chrono::NaiveDate;
+    use tower::ServiceExt;
+
+    use eris_data::{Insert, Transaction, Update};
+    use eris_db::Connection;
+    use eris_notify::mailer::testing::{smtp_server, Inbox};
+
+    use super::*;
+
+    async fn test_portal() -> (Arc<Connection>, Inbox, Router) {
+        let db = Arc::new(Connection::open_test().await);
+        let (smtp, inbox) = smtp_server().await;
+        let app = router(PortalState {
+            db: db.clone(),
+            mailer: Arc::new(Mailer::new(&smtp).unwrap()),
+            sessions: Arc::new(Sessions::new(
+                Duration::from_secs(900), Duration::from_secs(3600))),
+            limits: Arc::new(LoginLimits {
+                per_address: RateLimit::new(2, Duration::from_secs(3600)),
+                per_client: RateLimit::new(5, Duration::from_secs(3600)),
+            }),
+            config: Arc::new(PortalConfig {
+                base_url: "http://portal.example/".to_string(),
+                payee: Payee {
+                    name: "Discordia e.V.".to_string(),
+                    iban: "DE23100000001234567890".to_string(),
+                    bic: None,
+                },
+            }),
+        });
+        (db, inbox, app)
+    }
+
+    async fn request(
+        app: &Router,
+        method: Method,
+        uri: &str,
+        cookie: Option<&str>,
+        form: Option<&str>,
+    ) -> (StatusCode, HeaderMap, String) {
+        let client: SocketAddr = "192.0.2.23:4242".parse().unwrap();
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .extension(ConnectInfo(client));
+        if let Some(cookie) = cookie {
+            request = request.header(header::COOKIE, cookie);
+        }
+        let body = match form {
+            Some(form) => {
+                request = request.header(
+                    header::CONTENT_TYPE, "application/x-www-form-urlencoded");
+                Body::from(form.to_string())
+            }
+            None => Body::empty(),
+        };
+        let response = app.clone()
+            .oneshot(request.body(body).unwrap())
+            .await.unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, headers, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    /// Wait for the mails sent in the background
+    async fn received(inbox: &Inbox, count: usize) -> Vec<String> {
+        for _ in 0..100 {
+            let messages = inbox.lock().await.clone();
+            if messages.len() >= count {
+                return messages;
+            }
+            sleep(Duration::from_millis(20)).await;
+        }
+        panic!("expected {} messages", count);
+    }
+
+    fn login_token(message: &str) -> String {
+        // Undo the soft line breaks of quoted-printable
+        let message = message.replace("=\n", "");
+        let start = message.find("http://portal.example/login/").unwrap()
+            + "http://portal.example/login/".len();
+        message[start..].chars()
+            .take_while(|c| c.is_ascii_hexdigit())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_login_and_account() {
+        let (db, inbox, app) = test_portal().await;
+        let member = db.insert(Member {
+            name: "Eris".to_string(),
+            email: "eris@discordia.example".to_string(),
+            membership_start: NaiveDate::from_ymd_opt(2020, 1, 1).unwrap(),
+            fee: 23.0,
+            interval: 1,
+            ..Default::default()
+        }).await.unwrap();
+        db.insert(Transaction {
+            member_id: member.id,
+            date: datetime::today(),
+            account_name: "Eris".to_string(),
+            description: "Mitgliedsbeitrag".to_string(),
+            amount: -23.0,
+            ..Default::default()
+        }).await.unwrap();
+        db.update(Member { account: -23.0, ..member }).await.unwrap();
+
+        // Without a session the account is not shown
+        let (status, headers, _) = request(
+            &app, Method::GET, "/account", None, None).await;
+        assert_eq!(status, StatusCode::SEE_OTHER);
+        assert_eq!(headers[header::LOCATION], "/");
+
+        // Unknown addresses get the same answer, but no mail
+        let (status, _, unknown) = request(&app, Method::POST, "/login",
+            None, Some("email=fnord%40discordia.example")).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, _, known) = request(&app, Method::POST, "/login",
+            None, Some("email=ERIS%40discordia.example")).await;
+        assert_eq!(unknown, known);
+        let messages = received(&inbox, 1).await;
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("To: eris@discordia.example"));
+        let token = login_token(&messages[0]);
+
+        let uri = format!("/login/{}", token);
+        let (status, headers, _) = request(
+            &app, Method::GET, &uri, None, None).await;
+        assert_eq!(status, StatusCode::SEE_OTHER);
+        let cookie = headers[header::SET_COOKIE].to_str().unwrap();
+        let cookie = cookie.split(';').next().unwrap().to_string();
+
+        // The link can only be used once
+        let (status, _, _) = request(&app, Method::GET, &uri, None, None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (status, _, body) = request(
+            &app, Method::GET, "/account", Some(&cookie), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("Welcome, Eris"));
+        assert!(body.contains("-23.00 EUR"));
+        assert!(body.contains("Please transfer <strong>23.00 EUR</strong>"));
+        assert!(body.contains("DE23100000001234567890"));
+        assert!(body.contains("ERIS-M-000001 Mitgliedsbeitrag"));
+
+        let (status, _, body) = request(
+            &app, Method::GET, "/account/statement", Some(&cookie), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("Mitgliedsbeitrag"));
+
+        request(&app, Method::POST, "/logout", Some(&cookie), None).await;
+        let (status, _, _) = request(
+            &app, Method::GET, "/account", Some(&cookie), None).await;
+        assert_eq!(status, StatusCode::SEE_OTHER);
+    }
+
+    #[tokio::test]
+    async fn test_former_members_get_no_link() {
+        let (db, inbox, app) = test_portal().await;
+        db.insert(Member {
+            name: "Eris".to_string(),
+            email: "eris@discordia.example".to_string(),
+            membership_start: NaiveDate::from_ymd_opt(2020, 1, 1).unwrap(),
+            membership_end: NaiveDate::from_ymd_opt(2021, 1, 1),
+            ..Default::default()
+        }).await.unwrap();
+        let (status, _, _) = request(&app, Method::POST, "/login",
+            None, Some("email=eris%40discordia.example")).await;
+        assert_eq!(status, StatusCode::OK);
+        sleep(Duration::from_millis(100)).await;
+        assert!(inbox.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_login_rate_limit() {
+        let (_, _, app) = test_portal().await;
+        let login = |email: &'static str| {
+            let app = app.clone();
+            async move {
+                let form = format!("email={}", email);
+                request(&app, Method::POST, "/login", None, Some(&form)).await.0
+            }
+        };
+
+        // Per address, regardless of the case
+        assert_eq!(login("eris%40discordia.example").await, StatusCode::OK);
+        assert_eq!(login("ERIS%40discordia.example").await, StatusCode::OK);
+        assert_eq!(
+            login("eris%40discordia.example").await,
+            StatusCode::TOO_MANY_REQUESTS);
+
+        // Per client
+        assert_eq!(login("a%40discordia.example").await, StatusCode::OK);
+        assert_eq!(login("b%40discordia.example").await, StatusCode::OK);
+        assert_eq!(
+            login("c%40discordia.example").await,
+            StatusCode::TOO_MANY_REQUESTS);
+    }
+}
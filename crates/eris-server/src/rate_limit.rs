@@ -0,0 +1,68 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Limits how often something, e.g. an email address,
+/// is used within a sliding time window. The uses are
+/// kept in memory, so a restart resets the limits.
+#[derive(Debug)]
+pub struct RateLimit {
+    max: usize,
+    window: Duration,
+    uses: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl RateLimit {
+    pub fn new(max: usize, window: Duration) -> Self {
+        Self {
+            max,
+            window,
+            uses: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record a use of the key. Returns false without
+    /// recording it, if the key was used too often.
+    pub fn allow(&self, key: &str) -> bool {
+        let now = Instant::now();
+        let mut uses = self.uses.lock().unwrap();
+        uses.retain(|_, times| {
+            while times.front()
+                .is_some_and(|t| now.duration_since(*t) >= self.window)
+            {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+        let times = uses.entry(key.to_string()).or_default();
+        if times.len() >= self.max {
+            return false;
+        }
+        times.push_back(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit() {
+        let limit = RateLimit::new(2, Duration::from_secs(60));
+        assert!(limit.allow("eris"));
+        assert!(limit.allow("eris"));
+        assert!(!limit.allow("eris"));
+        assert!(limit.allow("kallisti"));
+    }
+
+    #[test]
+    fn test_rate_limit_window() {
+        let limit = RateLimit::new(1, Duration::ZERO);
+        assert!(limit.allow("eris"));
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(limit.allow("eris"));
+    }
+}
@@ -0,0 +1,128 @@
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// A token granting access to the account of a member
+/// until it expires.
+#[derive(Debug, Clone)]
+struct Grant {
+    member_id: u32,
+    expires_at: Instant,
+}
+
+/// Magic login links and sessions of the member portal.
+/// They are kept in memory, so a restart logs out everyone.
+#[derive(Debug)]
+pub struct Sessions {
+    links: Mutex<HashMap<String, Grant>>,
+    sessions: Mutex<HashMap<String, Grant>>,
+    link_ttl: Duration,
+    session_ttl: Duration,
+}
+
+impl Sessions {
+    pub fn new(link_ttl: Duration, session_ttl: Duration) -> Self {
+        Self {
+            links: Mutex::new(HashMap::new()),
+            sessions: Mutex::new(HashMap::new()),
+            link_ttl,
+            session_ttl,
+        }
+    }
+
+    /// How long a login link can be used
+    pub fn link_ttl(&self) -> Duration {
+        self.link_ttl
+    }
+
+    /// How long a session lasts after the login
+    pub fn session_ttl(&self) -> Duration {
+        self.session_ttl
+    }
+
+    /// Create the token of a login link for the member
+    pub fn issue_link(&self, member_id: u32) -> String {
+        issue(&self.links, member_id, self.link_ttl)
+    }
+
+    /// Exchange the token of a login link for a session
+    /// token. Links can only be used once.
+    pub fn redeem_link(&self, token: &str) -> Option<String> {
+        let grant = self.links.lock().unwrap().remove(token)?;
+        if grant.expires_at < Instant::now() {
+            return None;
+        }
+        Some(issue(&self.sessions, grant.member_id, self.session_ttl))
+    }
+
+    /// Get the member logged in with the session
+    pub fn member_id(&self, session: &str) -> Option<u32> {
+        self.sessions.lock().unwrap()
+            .get(session)
+            .filter(|grant| grant.expires_at >= Instant::now())
+            .map(|grant| grant.member_id)
+    }
+
+    /// End a session
+    pub fn end(&self, session: &str) {
+        self.sessions.lock().unwrap().remove(session);
+    }
+}
+
+fn issue(
+    grants: &Mutex<HashMap<String, Grant>>,
+    member_id: u32,
+    ttl: Duration,
+) -> String {
+    let now = Instant::now();
+    let token = new_token();
+    let mut grants = grants.lock().unwrap();
+    grants.retain(|_, grant| grant.expires_at >= now);
+    grants.insert(token.clone(), Grant {
+        member_id,
+        expires_at: now + ttl,
+    });
+    token
+}
+
+/// Make a random token with 256 bits
+fn new_token() -> String {
+    let bytes: [u8; 32] = rand::random();
+    bytes.iter().fold(String::new(), |mut token, b| {
+        write!(token, "{:02x}", b).unwrap();
+        token
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sessions() {
+        let sessions = Sessions::new(
+            Duration::from_secs(60), Duration::from_secs(60));
+        let link = sessions.issue_link(23);
+        assert_eq!(link.len(), 64);
+        assert!(sessions.redeem_link("fnord").is_none());
+
+        let session = sessions.redeem_link(&link).unwrap();
+        assert_eq!(sessions.member_id(&session), Some(23));
+        // Links can only be used once
+        assert!(sessions.redeem_link(&link).is_none());
+
+        sessions.end(&session);
+        assert_eq!(sessions.member_id(&session), None);
+    }
+
+    #[test]
+    fn test_expired_link() {
+        let sessions = Sessions::new(Duration::ZERO, Duration::from_secs(60));
+        let link = sessions.issue_link(23);
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(sessions.redeem_link(&link).is_none());
+    }
+}
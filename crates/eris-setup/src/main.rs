@@ -1,37 +1,184 @@
-use anyhow::Result;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
 
 use clap::{Subcommand, Parser};
+use inquire::Password;
 
-use eris_db::{Connection, schema};
+use eris_db::{
+    Connection,
+    DatabaseKey,
+    encryption,
+    legacy::{self, LegacyDatabase, MigrationReport},
+    schema,
+};
 
 #[derive(Parser, Debug)]
 #[clap(name="eris-setup")]
 struct Cli {
+    /// The SQLite database file or a postgres:// URL
     #[clap(default_value="members.sqlite3")]
     pub members_db: String,
 
+    /// Passphrase of an encrypted database
+    #[clap(long, env = "ERIS_DB_KEY", hide = true, hide_env_values = true)]
+    pub db_key: Option<String>,
+
+    /// Read the passphrase of an encrypted database from a file
+    #[clap(long, env = "ERIS_DB_KEYFILE")]
+    pub db_keyfile: Option<PathBuf>,
+
+    /// Ask for the passphrase of an encrypted database
+    #[clap(long)]
+    pub encrypted: bool,
+
     #[clap(subcommand)]
     pub command: Command,
 }
 
+impl Cli {
+    /// Get the database key from the keyfile, the
+    /// environment or a prompt.
+    fn database_key(&self) -> Result<Option<DatabaseKey>> {
+        let key = DatabaseKey::resolve(
+            self.db_key.as_deref(), self.db_keyfile.as_deref())?;
+        if key.is_some() || !self.encrypted {
+            return Ok(key);
+        }
+        let passphrase = Password::new("Database passphrase:")
+            .without_confirmation()
+            .prompt()?;
+        Ok(Some(DatabaseKey::new(&passphrase)?))
+    }
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Command{
+    /// Create the schema or apply missing migrations
     Init,
+    /// Migrate a database of the legacy Python implementation
+    #[clap(name="import-legacy")]
+    ImportLegacy {
+        /// The legacy database file
+        #[clap(long)]
+        from: String,
+    },
+    /// Encrypt the database, change its key or decrypt it
+    Rekey {
+        /// New passphrase, asked for if not given
+        #[clap(long, env = "ERIS_DB_NEW_KEY", hide = true,
+               hide_env_values = true)]
+        new_key: Option<String>,
+        /// Read the new passphrase from a file
+        #[clap(long)]
+        new_keyfile: Option<PathBuf>,
+        /// Store the database unencrypted
+        #[clap(long, conflicts_with_all = ["new_key", "new_keyfile"])]
+        decrypt: bool,
+    },
 }
 
-/// Initialize the database
-async fn db_init(filename: &str) -> Result<()> {
-    let conn = Connection::open(&filename).await?;
-    schema::install(&conn).await?;
+/// Initialize the database or apply missing migrations
+async fn db_init(filename: &str, key: Option<&DatabaseKey>) -> Result<()> {
+    let conn = Connection::open_with_key(filename, key).await?;
+    let version = schema::migrate(&conn).await?;
+    conn.close().await;
+    println!("The database schema is at version {}.", version);
     Ok(())
 }
 
+/// Migrate the legacy database and print the
+/// reconciliation report
+async fn import_legacy(
+    filename: &str,
+    key: Option<&DatabaseKey>,
+    from: &str,
+) -> Result<()> {
+    let mut legacy = LegacyDatabase::open(from).await?;
+    let conn = Connection::open_with_key(filename, key).await?;
+    let report = legacy::migrate(&mut legacy, &conn).await?;
+    conn.close().await;
+    print_report(&report);
+    if !report.committed {
+        return Err(anyhow!(
+            "the migration is not reconciled and was rolled back, \
+             see the report above"));
+    }
+    Ok(())
+}
+
+fn print_report(report: &MigrationReport) {
+    println!(
+        "{} {} members, {} IBAN rules and {} transactions.",
+        if report.committed { "Migrated" } else { "Rolled back" },
+        report.members.len(),
+        report.iban_rules,
+        report.transactions);
+    println!();
+    println!("{:>6}  {:>6}  {:<30}  {:>10}  {:>10}  {:>10}  {:>10}",
+        "Legacy", "ID", "Name", "Before", "After", "Tx before", "Tx after");
+    println!("{:-<102}", "-");
+    for m in &report.members {
+        println!(
+            "{:>6}  {:>6}  {:<30}  {:>10.2}  {:>10.2}  {:>10.2}  {:>10.2}{}",
+            m.legacy_id,
+            m.member_id,
+            m.name,
+            m.balance_before,
+            m.balance_after,
+            m.transactions_before,
+            m.transactions_after,
+            if m.is_reconciled() { "" } else { "  MISMATCH" });
+    }
+    println!("{:-<102}", "-");
+    println!("{:>6}  {:>6}  {:<30}  {:>10.2}  {:>10.2}",
+        "", "", "Total", report.total_before(), report.total_after());
+
+    if !report.skipped.is_empty() {
+        println!();
+        println!("Skipped:");
+        for skipped in &report.skipped {
+            println!("  {}", skipped);
+        }
+    }
+}
+
+/// Get the new key for rekeying the database
+fn new_database_key(
+    new_key: Option<String>,
+    new_keyfile: Option<PathBuf>,
+    decrypt: bool,
+) -> Result<Option<DatabaseKey>> {
+    if decrypt {
+        return Ok(None);
+    }
+    let key = DatabaseKey::resolve(new_key.as_deref(), new_keyfile.as_deref())?;
+    if key.is_some() {
+        return Ok(key);
+    }
+    let passphrase = Password::new("New database passphrase:").prompt()?;
+    Ok(Some(DatabaseKey::new(&passphrase)?))
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse(); 
+    let key = cli.database_key()?;
     match cli.command {
-        Command::Init => db_init(&cli.members_db).await?,
+        Command::Init => db_init(&cli.members_db, key.as_ref()).await?,
+        Command::ImportLegacy { from } => {
+            import_legacy(&cli.members_db, key.as_ref(), &from).await?
+        }
+        Command::Rekey { new_key, new_keyfile, decrypt } => {
+            let new_key = new_database_key(new_key, new_keyfile, decrypt)?;
+            encryption::rekey(
+                &cli.members_db, key.as_ref(), new_key.as_ref()).await?;
+            if new_key.is_some() {
+                println!("The database is encrypted with the new key.");
+            } else {
+                println!("The database is decrypted.");
+            }
+        }
     }
     Ok(())
 }